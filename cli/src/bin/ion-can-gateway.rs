@@ -0,0 +1,77 @@
+use clap::error::ErrorKind;
+use clap::{CommandFactory, Parser};
+use ecu_diag::hardware::pcan_usb::PcanUsbScanner;
+#[cfg(target_os = "linux")]
+use ecu_diag::hardware::socketcan::SocketCanDevice;
+use ecu_diag::hardware::udp_can::{UdpCanGateway, DEFAULT_UDP_PORT};
+use ecu_diag::hardware::{Hardware, HardwareResult};
+use std::net::SocketAddr;
+use std::sync::atomic::AtomicBool;
+
+#[derive(Parser)]
+#[command(author, version)]
+#[command(
+    about = "Bridges a local CAN adapter to UDP (cannelloni framing), so a tester on another \
+machine can reach the bus with `ion-diagnostic-cli --udp`"
+)]
+struct Cli {
+    /// Address to receive datagrams on
+    #[arg(long, default_value_t = SocketAddr::from(([0, 0, 0, 0], DEFAULT_UDP_PORT)))]
+    listen: SocketAddr,
+
+    /// Address to send frames to. Defaults to the tester that sent the last datagram
+    #[arg(long)]
+    remote: Option<SocketAddr>,
+
+    /// PCAN adapter to use, by name or index. Defaults to the first adapter found
+    #[arg(long)]
+    adapter: Option<String>,
+
+    /// SocketCAN interface to use instead of a PCAN adapter (EG: can0)
+    #[cfg(target_os = "linux")]
+    #[arg(long, conflicts_with = "adapter")]
+    socketcan: Option<String>,
+
+    /// CAN baud rate
+    #[arg(long, default_value_t = 500_000)]
+    baud: u32,
+}
+
+fn main() {
+    let cli = Cli::parse();
+    let mut gateway = match open_gateway(&cli) {
+        Ok(g) => g,
+        Err(e) => Cli::command().error(ErrorKind::Io, e).exit(),
+    };
+    eprintln!("Forwarding CAN at {} bps <-> udp {}", cli.baud, cli.listen);
+    if let Err(e) = gateway.run(&AtomicBool::new(false)) {
+        Cli::command()
+            .error(ErrorKind::Io, format!("Gateway stopped: {e}"))
+            .exit();
+    }
+}
+
+fn open_gateway(cli: &Cli) -> Result<UdpCanGateway, String> {
+    #[cfg(target_os = "linux")]
+    if let Some(iface) = &cli.socketcan {
+        return start_gateway("SocketCAN interface", SocketCanDevice::new(iface), cli);
+    }
+    let scanner = PcanUsbScanner::default();
+    start_gateway(
+        "PCAN adapter",
+        scanner.open_adapter(cli.adapter.as_deref()),
+        cli,
+    )
+}
+
+fn start_gateway<H: Hardware>(
+    source: &str,
+    device: HardwareResult<H>,
+    cli: &Cli,
+) -> Result<UdpCanGateway, String> {
+    let channel = device
+        .and_then(|mut d| d.create_can_channel())
+        .map_err(|e| format!("Cannot open {source}: {e}"))?;
+    UdpCanGateway::new(channel, cli.baud, cli.listen, cli.remote)
+        .map_err(|e| format!("Cannot start gateway on {source}: {e}"))
+}
@@ -1,5 +1,5 @@
 use clap::Args;
-use ecu_diag::api::UdsServiceProvider;
+use ecu_diag::api::{DiagServerResult, UdsServiceProvider};
 use ecu_diag::uds::diagnostic_session_control::UdsSessionType;
 use ecu_diag::uds::UDSClientSession;
 
@@ -7,7 +7,7 @@ use ecu_diag::uds::UDSClientSession;
 pub struct DefaultModeCmd {}
 
 impl DefaultModeCmd {
-    pub fn run(&self, client: &mut UDSClientSession) {
-        client.invoke_set_session_mode(UdsSessionType::Default);
+    pub fn run(&self, client: &mut UDSClientSession) -> DiagServerResult<()> {
+        client.invoke_set_session_mode(UdsSessionType::Default)
     }
 }
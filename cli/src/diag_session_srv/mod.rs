@@ -1,6 +1,8 @@
 use clap::{Args, Subcommand};
 use ecu_diag::uds::UDSClientSession;
 
+use crate::output::print_status;
+
 mod default_mode;
 
 pub(crate) use default_mode::DefaultModeCmd;
@@ -20,8 +22,8 @@ pub enum DiagnosticSessionServiceSubCmd {
 #[allow(dead_code)]
 impl DiagnosticSessionServiceCmd {
     pub fn run(self, client: &mut UDSClientSession) {
-        match self.subcommand {
+        print_status(match self.subcommand {
             DiagnosticSessionServiceSubCmd::DefaultMode(c) => c.run(client),
-        }
+        });
     }
 }
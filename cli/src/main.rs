@@ -1,7 +1,22 @@
-#[allow(unused_imports)]
+use clap::error::ErrorKind;
 use clap::{CommandFactory, Parser, Subcommand};
+use ecu_diag::hardware::isotp::{
+    BusRecoveryPolicy, CanBusEvent, IsoTpAddressing, IsoTpProtocol, COMMON_CAN_BAUD_RATES,
+};
+use ecu_diag::hardware::pcan_usb::PcanUsbScanner;
+use ecu_diag::hardware::replay::{ReplayDevice, ReplayTiming};
+use ecu_diag::hardware::udp_can::UdpCanDevice;
+use ecu_diag::hardware::HardwareScanner;
+use ecu_diag::uds::routine_control::{ServiceRequest, ServiceResponse};
+use ecu_diag::uds::UDSClientSession;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::mpsc::Receiver;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc::unbounded_channel;
 
 pub(crate) mod diag_session_srv;
+pub(crate) mod output;
 pub(crate) mod read_data_by_id_srv;
 pub(crate) mod reset_ecu_srv;
 pub(crate) mod routine_control_srv;
@@ -33,6 +48,48 @@ struct Cli {
     /// Stream output continuously
     #[arg(short, long, default_value_t = false)]
     stream: bool,
+
+    /// PCAN adapter to use, by name or index as listed by `adapters`.
+    /// Defaults to the first adapter found
+    #[arg(long)]
+    adapter: Option<String>,
+
+    /// Detect the CAN baud rate by listening to the bus before connecting
+    #[arg(long, default_value_t = false)]
+    auto_baud: bool,
+
+    /// Replay a recorded CAN trace (candump .log, PCAN .trc or Vector .asc)
+    /// instead of using a PCAN adapter
+    #[arg(long, value_name = "TRACE")]
+    replay: Option<PathBuf>,
+
+    /// Replay the trace as fast as possible, instead of with its original timing
+    #[arg(long, default_value_t = false, requires = "replay")]
+    replay_fast: bool,
+
+    /// Reach the CAN bus over UDP through `ion-can-gateway` (or cannelloni) at this address,
+    /// instead of using a PCAN adapter
+    #[arg(long, value_name = "ADDR", conflicts_with = "replay")]
+    udp: Option<SocketAddr>,
+
+    /// Local address to receive datagrams on. Defaults to any free port
+    #[arg(long, value_name = "ADDR", requires = "udp")]
+    udp_local: Option<SocketAddr>,
+
+    /// Record all CAN frames sent and received to a trace file.
+    /// The format is picked from the extension: candump (.log), PCAN (.trc) or Vector (.asc)
+    #[arg(long, value_name = "FILE")]
+    trace: Option<PathBuf>,
+
+    /// Automatically recover the CAN channel when the bus goes bus-off
+    #[arg(long, default_value_t = false)]
+    bus_recovery: bool,
+
+    /// ISO-TP addressing of the VCU, with hex CAN IDs and addresses:
+    /// normal:TX_ID:RX_ID, normal-fixed:TARGET:SOURCE,
+    /// extended:TX_ID:RX_ID:TX_EXT:RX_EXT or mixed:TARGET:SOURCE:EXTENSION
+    #[arg(long, value_name = "MODE", default_value_t = IsoTpAddressing::default())]
+    addressing: IsoTpAddressing,
 }
 
 #[derive(Subcommand)]
@@ -45,47 +102,153 @@ enum UDSService {
     Routine(RoutineControlServiceCmd),
     /// Set Diagnostic Session Mode
     SetMode(DiagnosticSessionServiceCmd),
+    /// List the PCAN adapters attached to this PC
+    Adapters,
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() {
+    let cli = Cli::parse();
+    let scanner = PcanUsbScanner::default();
+    if let UDSService::Adapters = cli.command {
+        print_adapters(&scanner);
+        return;
+    }
+
+    let (tx_req, _rx_req) = unbounded_channel::<ServiceRequest>();
+    let (_tx_res, rx_res) = unbounded_channel::<ServiceResponse>();
+    let (source, protocol) = match (&cli.replay, cli.udp) {
+        (Some(trace), _) => {
+            let timing = if cli.replay_fast {
+                ReplayTiming::AsFastAsPossible
+            } else {
+                ReplayTiming::Original
+            };
+            (
+                "trace",
+                ReplayDevice::open_file(trace, timing).and_then(IsoTpProtocol::new_from_hardware),
+            )
+        }
+        (None, Some(remote)) => {
+            let local = cli
+                .udp_local
+                .unwrap_or_else(|| SocketAddr::from(([0, 0, 0, 0], 0)));
+            (
+                "UDP gateway",
+                IsoTpProtocol::new_from_hardware(UdpCanDevice::new(local, Some(remote))),
+            )
+        }
+        (None, None) => (
+            "PCAN adapter",
+            scanner
+                .open_adapter(cli.adapter.as_deref())
+                .and_then(IsoTpProtocol::new_from_hardware),
+        ),
+    };
+    let mut protocol = match protocol {
+        Ok(p) => p,
+        Err(e) => {
+            let mut cmd = Cli::command();
+            cmd.error(ErrorKind::Io, format!("Cannot open {source}: {e}"))
+                .exit();
+        }
+    };
+    if cli.bus_recovery {
+        protocol.set_bus_recovery(BusRecoveryPolicy::Automatic {
+            max_attempts: 3,
+            delay_ms: 500,
+        });
+    }
+    if cli.auto_baud {
+        match protocol.detect_can_speed(COMMON_CAN_BAUD_RATES, 500) {
+            Ok(baud) => eprintln!("Detected CAN baud rate: {baud}"),
+            Err(e) => eprintln!("CAN baud rate detection failed: {e}"),
+        }
+    }
+    if let Some(trace) = &cli.trace {
+        if let Err(e) = protocol.start_trace(trace) {
+            let mut cmd = Cli::command();
+            cmd.error(ErrorKind::Io, format!("Cannot record trace: {e}"))
+                .exit();
+        }
+    }
+    let bus_events = protocol.subscribe_bus_events();
+    let mut client = UDSClientSession::new(protocol, tx_req, rx_res);
+    if let Err(e) = client.set_addressing(cli.addressing) {
+        let mut cmd = Cli::command();
+        cmd.error(ErrorKind::InvalidValue, format!("Invalid addressing: {e}"))
+            .exit();
+    }
+    client.register_waiting_hook(|| eprintln!("ECU busy, waiting for its response"));
+    client.init();
+
+    if cli.stream {
+        let start_time = Instant::now();
+        loop {
+            let cli = &cli;
+            match &cli.command {
+                UDSService::Read(c) => c.clone().run(&mut client),
+                UDSService::Reset(_c) => {
+                    let mut cmd = Cli::command();
+                    cmd.error(
+                        ErrorKind::ArgumentConflict,
+                        "Reset ECU Service does not support streaming output.",
+                    )
+                    .exit();
+                }
+                UDSService::Routine(c) => c.clone().run(&mut client).await,
+                UDSService::SetMode(_c) => {
+                    let mut cmd = Cli::command();
+                    cmd.error(
+                        ErrorKind::ArgumentConflict,
+                        "Set Diagnostic Service does not support streaming output.",
+                    )
+                    .exit();
+                }
+                UDSService::Adapters => unreachable!("Adapters are listed before connecting"),
+            }
+            print_bus_events(&bus_events);
+
+            tokio::time::sleep(Duration::from_secs(1)).await;
+            if let Err(e) = client.uds_tester_present(start_time.elapsed().as_millis()) {
+                eprintln!("FAIL: {e}");
+            }
+        }
+    } else {
+        match cli.command {
+            UDSService::Read(c) => c.run(&mut client),
+            UDSService::Reset(c) => c.run(&mut client),
+            UDSService::Routine(c) => c.run(&mut client).await,
+            UDSService::SetMode(c) => c.run(&mut client),
+            UDSService::Adapters => unreachable!("Adapters are listed before connecting"),
+        }
+        print_bus_events(&bus_events);
+    }
+    if let Some(mut protocol) = client.protocol() {
+        protocol.stop_trace();
+    };
+}
+
+fn print_adapters(scanner: &PcanUsbScanner) {
+    let adapters = scanner.list_devices();
+    if adapters.is_empty() {
+        println!("No PCAN adapters found");
+    }
+    for (idx, info) in adapters.iter().enumerate() {
+        let mut line = format!("{idx}: {}", info.name);
+        if let Some(id) = info.device_id {
+            line.push_str(&format!(", Device ID: {id}"));
+        }
+        if let Some(fw) = &info.device_fw_version {
+            line.push_str(&format!(", Firmware: {fw}"));
+        }
+        println!("{line}");
+    }
 }
 
-fn main() {
-    // let cli = Cli::parse();
-
-    // let mut client = UDSClientSession::new_uds_client();
-
-    // if cli.stream {
-    //     let start_time = Instant::now(); // Record the starting time{
-    //     loop {
-    //         let cli = &cli;
-    //         match &cli.command {
-    //             UDSService::Read(c) => c.clone().run(&mut client),
-    //             UDSService::Reset(_c) => {
-    //                 let mut cmd = Cli::command();
-    //                 cmd.error(
-    //                     ErrorKind::ArgumentConflict,
-    //                     "Reset ECU Service does not support streaming output.",
-    //                 )
-    //                 .exit();
-    //             }
-    //             UDSService::Routine(c) => c.clone().run(&mut client),
-    //             UDSService::SetMode(_c) => {
-    //                 let mut cmd = Cli::command();
-    //                 cmd.error(
-    //                     ErrorKind::ArgumentConflict,
-    //                     "Set Diagnostic Service does not support streaming output.",
-    //                 )
-    //                 .exit();
-    //             }
-    //         }
-
-    //         thread::sleep(Duration::from_secs(1));
-    //         client.uds_tester_present(start_time.elapsed().as_millis());
-    //     }
-    // } else {
-    //     match cli.command {
-    //         UDSService::Read(c) => c.run(&mut client),
-    //         UDSService::Reset(c) => c.run(&mut client),
-    //         UDSService::Routine(c) => c.run(&mut client),
-    //         UDSService::SetMode(c) => c.run(&mut client),
-    //     }
-    // }
+/// Reports CAN bus state changes, overruns and recoveries seen while running a command
+fn print_bus_events(events: &Receiver<CanBusEvent>) {
+    for event in events.try_iter() {
+        eprintln!("{event}");
+    }
 }
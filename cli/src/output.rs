@@ -0,0 +1,24 @@
+//! Console output of the UDS services
+
+use ecu_diag::api::DiagServerResult;
+use ecu_diag::uds::read_data_by_id::DataField;
+
+/// Prints a data record, one field per line
+pub(crate) fn print_data(result: DiagServerResult<Vec<DataField>>) {
+    match result {
+        Ok(fields) => {
+            for field in fields {
+                println!("{field}");
+            }
+        }
+        Err(e) => eprintln!("FAIL: {e}"),
+    }
+}
+
+/// Prints the outcome of a service that has no data to show
+pub(crate) fn print_status(result: DiagServerResult<()>) {
+    match result {
+        Ok(()) => println!("SUCCESS"),
+        Err(e) => eprintln!("FAIL: {e}"),
+    }
+}
@@ -1,13 +1,13 @@
 use clap::Args;
-use ecu_diag::api::UdsServiceProvider;
-use ecu_diag::uds::read_data_by_id::DataId;
+use ecu_diag::api::{DiagServerResult, UdsServiceProvider};
+use ecu_diag::uds::read_data_by_id::{DataField, DataId};
 use ecu_diag::uds::UDSClientSession;
 
 #[derive(Args, Clone, Debug)]
 pub struct BikeStateCmd {}
 
 impl BikeStateCmd {
-    pub fn run(&self, client: &mut UDSClientSession) {
-        client.invoke_read_data_by_id_service(DataId::BikeState);
+    pub fn run(&self, client: &mut UDSClientSession) -> DiagServerResult<Vec<DataField>> {
+        client.invoke_read_data_by_id_service(DataId::BikeState)
     }
 }
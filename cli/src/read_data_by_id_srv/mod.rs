@@ -1,6 +1,8 @@
 use clap::{Args, Subcommand};
 use ecu_diag::uds::UDSClientSession;
 
+use crate::output::print_data;
+
 mod adc;
 mod bike_state;
 mod bms;
@@ -52,7 +54,7 @@ pub enum ReadDataServiceSubCmd {
 #[allow(dead_code)]
 impl ReadDataServiceCmd {
     pub fn run(self, client: &mut UDSClientSession) {
-        match self.subcommand {
+        print_data(match self.subcommand {
             ReadDataServiceSubCmd::BikeState(c) => c.run(client),
             ReadDataServiceSubCmd::SwitchGear(c) => c.run(client),
             ReadDataServiceSubCmd::ErrorCode(c) => c.run(client),
@@ -62,6 +64,6 @@ impl ReadDataServiceCmd {
             ReadDataServiceSubCmd::FirmwareVersion(c) => c.run(client),
             ReadDataServiceSubCmd::Adc(c) => c.run(client),
             ReadDataServiceSubCmd::Bms(c) => c.run(client),
-        }
+        });
     }
 }
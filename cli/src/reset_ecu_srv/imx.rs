@@ -1,5 +1,5 @@
 use clap::Args;
-use ecu_diag::api::UdsServiceProvider;
+use ecu_diag::api::{DiagServerResult, UdsServiceProvider};
 use ecu_diag::uds::ecu_reset::ResetType;
 use ecu_diag::uds::UDSClientSession;
 
@@ -7,7 +7,7 @@ use ecu_diag::uds::UDSClientSession;
 pub struct ResetImxCmd {}
 
 impl ResetImxCmd {
-    pub fn run(&self, client: &mut UDSClientSession) {
-        client.invoke_reset_ecu_service(ResetType::ImxReset);
+    pub fn run(&self, client: &mut UDSClientSession) -> DiagServerResult<()> {
+        client.invoke_reset_ecu_service(ResetType::ImxReset)
     }
 }
@@ -1,6 +1,8 @@
 use clap::{Args, Subcommand};
 use ecu_diag::uds::UDSClientSession;
 
+use crate::output::print_status;
+
 mod ble;
 mod cendric;
 mod imx;
@@ -52,7 +54,7 @@ pub enum ResetEcuServiceSubCmd {
 #[allow(dead_code)]
 impl ResetEcuServiceCmd {
     pub fn run(self, client: &mut UDSClientSession) {
-        match self.subcommand {
+        print_status(match self.subcommand {
             ResetEcuServiceSubCmd::SoftReset(c) => c.run(client),
             ResetEcuServiceSubCmd::Rt(c) => c.run(client),
             ResetEcuServiceSubCmd::Tm(c) => c.run(client),
@@ -62,6 +64,6 @@ impl ResetEcuServiceCmd {
             ResetEcuServiceSubCmd::Lte(c) => c.run(client),
             ResetEcuServiceSubCmd::Lizard(c) => c.run(client),
             ResetEcuServiceSubCmd::Cendric(c) => c.run(client),
-        }
+        });
     }
 }
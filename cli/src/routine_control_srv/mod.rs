@@ -1,6 +1,8 @@
 use clap::{Args, Subcommand};
 use ecu_diag::uds::UDSClientSession;
 
+use crate::output::print_status;
+
 mod disable_imx_hmi;
 mod disable_imx_lte;
 mod enable_imx_hmi;
@@ -54,18 +56,17 @@ pub enum RoutineControlServiceSubCmd {
     TriggerOutput(TriggerOutputCmd),
 }
 
-#[allow(unused_must_use)]
 #[allow(dead_code)]
 impl RoutineControlServiceCmd {
-    pub fn run(self, client: &mut UDSClientSession) {
-        match self.subcommand {
+    pub async fn run(self, client: &mut UDSClientSession) {
+        print_status(match self.subcommand {
             // RoutineControlServiceSubCmd::EnableImxLte(c) => c.run(client),
             // RoutineControlServiceSubCmd::DisableImxLte(c) => c.run(client),
             // RoutineControlServiceSubCmd::EnableImxHmi(c) => c.run(client),
             // RoutineControlServiceSubCmd::DisableImxHmi(c) => c.run(client),
-            RoutineControlServiceSubCmd::SimulateInput(c) => c.run(client),
-            RoutineControlServiceSubCmd::TriggerOutput(c) => c.run(client),
+            RoutineControlServiceSubCmd::SimulateInput(c) => c.run(client).await,
+            RoutineControlServiceSubCmd::TriggerOutput(c) => c.run(client).await,
             // RoutineControlServiceSubCmd::SwitchUsbOtgUsbHost(c) => c.run(client),
-        }
+        });
     }
 }
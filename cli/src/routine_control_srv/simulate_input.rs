@@ -1,5 +1,5 @@
 use clap::{Args, ValueEnum};
-use ecu_diag::api::UdsServiceProvider;
+use ecu_diag::api::{DiagServerResult, UdsServiceProvider};
 use ecu_diag::uds::routine_control::SimulateInputOption;
 use ecu_diag::uds::routine_control::{RoutineControlSubfcn, RoutineId};
 use ecu_diag::uds::UDSClientSession;
@@ -12,14 +12,13 @@ pub struct SimulateInputCmd {
     input: Input,
 }
 
-#[allow(unused_must_use)]
 impl SimulateInputCmd {
-    pub fn run(&self, client: &mut UDSClientSession) {
+    pub async fn run(&self, client: &mut UDSClientSession) -> DiagServerResult<()> {
         let action = match self.action {
             Action::Enable => RoutineControlSubfcn::StartRoutine,
             Action::Disable => RoutineControlSubfcn::StopRoutine,
         };
-        match self.input {
+        let routine = match self.input {
             Input::RightBrakeSw => client.invoke_routine_control_service(
                 action,
                 RoutineId::SimulateInput,
@@ -41,11 +40,13 @@ impl SimulateInputCmd {
                 &[SimulateInputOption::PowerSwitch as u8],
             ),
             Input::PowerSwShortPress => {
-                client.invoke_routine_control_service(
-                    RoutineControlSubfcn::StartRoutine,
-                    RoutineId::SimulateInput,
-                    &[SimulateInputOption::PowerSwitch as u8],
-                );
+                client
+                    .invoke_routine_control_service(
+                        RoutineControlSubfcn::StartRoutine,
+                        RoutineId::SimulateInput,
+                        &[SimulateInputOption::PowerSwitch as u8],
+                    )
+                    .await?;
 
                 client.invoke_routine_control_service(
                     RoutineControlSubfcn::StopRoutine,
@@ -54,11 +55,13 @@ impl SimulateInputCmd {
                 )
             }
             Input::StartSwShortPress => {
-                client.invoke_routine_control_service(
-                    RoutineControlSubfcn::StartRoutine,
-                    RoutineId::SimulateInput,
-                    &[SimulateInputOption::StartSwitch as u8],
-                );
+                client
+                    .invoke_routine_control_service(
+                        RoutineControlSubfcn::StartRoutine,
+                        RoutineId::SimulateInput,
+                        &[SimulateInputOption::StartSwitch as u8],
+                    )
+                    .await?;
 
                 client.invoke_routine_control_service(
                     RoutineControlSubfcn::StopRoutine,
@@ -152,6 +155,7 @@ impl SimulateInputCmd {
                 &[SimulateInputOption::KeyfobLongPress as u8],
             ),
         };
+        routine.await
     }
 }
 
@@ -1,5 +1,5 @@
 use clap::{Args, ValueEnum};
-use ecu_diag::api::UdsServiceProvider;
+use ecu_diag::api::{DiagServerResult, UdsServiceProvider};
 use ecu_diag::uds::routine_control::TriggerOutputOption;
 use ecu_diag::uds::routine_control::{RoutineControlSubfcn, RoutineId};
 use ecu_diag::uds::UDSClientSession;
@@ -12,14 +12,13 @@ pub struct TriggerOutputCmd {
     output: Output,
 }
 
-#[allow(unused_must_use)]
 impl TriggerOutputCmd {
-    pub fn run(&self, client: &mut UDSClientSession) {
+    pub async fn run(&self, client: &mut UDSClientSession) -> DiagServerResult<()> {
         let action = match self.action {
             Action::Enable => RoutineControlSubfcn::StartRoutine,
             Action::Disable => RoutineControlSubfcn::StopRoutine,
         };
-        match self.output {
+        let routine = match self.output {
             Output::HssRearRightIndicator => client.invoke_routine_control_service(
                 action,
                 RoutineId::TriggerOutput,
@@ -106,6 +105,7 @@ impl TriggerOutputCmd {
                 &[TriggerOutputOption::HssSideStandPower as u8],
             ),
         };
+        routine.await
     }
 }
 
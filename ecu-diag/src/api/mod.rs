@@ -1,9 +1,10 @@
+use crate::hardware::isotp::IsoTpProtocol;
 use crate::uds::diagnostic_session_control::UdsSessionType;
 use crate::uds::ecu_reset::ResetType;
-use crate::uds::read_data_by_id::DataId;
+use crate::uds::read_data_by_id::{DataField, DataId};
 use crate::uds::routine_control::ServiceRequest;
 use crate::uds::routine_control::ServiceResponse;
-use crate::uds::routine_control::{RoutineControlSubfcn, RoutineId};
+use crate::uds::routine_control::{RoutineControlSubfcn, RoutineId, RoutineResult};
 use crate::uds::UDSClientSession;
 
 use tokio::sync::mpsc::UnboundedReceiver;
@@ -11,21 +12,10 @@ use tokio::sync::mpsc::UnboundedSender;
 
 use std::default::Default;
 
-// Public types
-
-pub enum UdsServiceResponse {
-    Success(UdsSericeResponseDetail),
-    Fail(UdsSericeResponseDetail),
-}
+pub use crate::core::dynamic_diag::{DiagServerEmptyLogger, DiagServerLogger, ServerEvent};
+pub use crate::core::{DiagError, DiagServerResult};
 
-pub struct UdsSericeResponseDetail {
-    pub console_output: String,
-}
-
-pub enum UdsMonitorViewResponse {
-    Success(UdsMonitorViewResponseDetail),
-    Fail,
-}
+// Public types
 
 pub struct UdsMonitorViewResponseDetail {
     pub right_brake_sw: u8,
@@ -275,28 +265,40 @@ impl Default for UdsMonitorViewResponseDetail {
 // UDS public API
 #[allow(async_fn_in_trait)]
 pub trait UdsServiceProvider {
+    /// Creates a UDS client on the first PCAN adapter found, see [IsoTpProtocol::new_pcan].
+    /// Returns [DiagError::HardwareError] if no adapter could be opened
     async fn new_uds_client(
         tx: UnboundedSender<ServiceRequest>,
         rx: UnboundedReceiver<ServiceResponse>,
+    ) -> DiagServerResult<UDSClientSession>;
+    /// Creates a UDS client on any ISOTP protocol handler, see [IsoTpProtocol::new]
+    /// and [IsoTpProtocol::new_from_hardware]
+    async fn new_uds_client_with_protocol(
+        protocol: IsoTpProtocol,
+        tx: UnboundedSender<ServiceRequest>,
+        rx: UnboundedReceiver<ServiceResponse>,
     ) -> UDSClientSession;
-    fn invoke_read_data_by_id_service(&mut self, data_id: DataId) -> UdsServiceResponse;
-    fn invoke_reset_ecu_service(&mut self, reset_mode: ResetType) -> UdsServiceResponse;
+    fn invoke_read_data_by_id_service(
+        &mut self,
+        data_id: DataId,
+    ) -> DiagServerResult<Vec<DataField>>;
+    fn invoke_reset_ecu_service(&mut self, reset_mode: ResetType) -> DiagServerResult<()>;
     async fn invoke_routine_control_service(
         &mut self,
         routine_subfcn: RoutineControlSubfcn,
         routine_id: RoutineId,
         routine_control_option: &[u8],
-    ) -> UdsServiceResponse;
+    ) -> DiagServerResult<()>;
     async fn invoke_routine_control_service_get_result(
         &mut self,
         routine_subfcn: RoutineControlSubfcn,
         routine_id: RoutineId,
         routine_control_option: &[u8],
-    ) -> UdsServiceResponse;
-    fn invoke_set_session_mode(&mut self, session_mode: UdsSessionType) -> UdsServiceResponse;
+    ) -> DiagServerResult<RoutineResult>;
+    fn invoke_set_session_mode(&mut self, session_mode: UdsSessionType) -> DiagServerResult<()>;
 
     fn invoke_read_data_by_id_service_return_struct(
         &mut self,
         data_id: DataId,
-    ) -> UdsMonitorViewResponse;
+    ) -> DiagServerResult<UdsMonitorViewResponseDetail>;
 }
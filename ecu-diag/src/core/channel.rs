@@ -6,7 +6,8 @@
 
 use std::{
     borrow::BorrowMut,
-    sync::{mpsc, Arc, Mutex, PoisonError},
+    sync::{mpsc, Arc, Mutex, OnceLock, PoisonError},
+    time::{Duration, Instant},
 };
 
 use crate::hardware::pcan_usb::pcan_types::PCANError;
@@ -60,11 +61,47 @@ pub enum ChannelError {
     /// Channel not configured prior to opening
     #[error("Channel configuration error")]
     ConfigurationError,
+    /// No error-free CAN traffic was seen at any of the candidate baud rates
+    #[error("CAN baud rate could not be detected")]
+    BaudNotDetected,
+    /// ISO-TP transfer was aborted
+    #[error("ISO-TP transfer error")]
+    IsoTpError(
+        #[from]
+        #[source]
+        IsoTpError,
+    ),
     /// Other Channel error
     #[error("Unknown channel error: {0}")]
     Other(String),
 }
 
+#[derive(Debug, Copy, Clone, PartialEq, Eq, thiserror::Error)]
+/// Reason an ISO-TP (ISO 15765-2) transfer was aborted
+pub enum IsoTpError {
+    /// A frame could not be sent within N_As
+    #[error("N_As timeout, frame was not sent in time")]
+    TimeoutAs,
+    /// The receiver did not send a flow control frame within N_Bs
+    #[error("N_Bs timeout, no flow control received")]
+    TimeoutBs,
+    /// The sender did not send the next consecutive frame within N_Cr
+    #[error("N_Cr timeout, no consecutive frame received")]
+    TimeoutCr,
+    /// The receiver sent more flow control WAIT frames in a row than N_WFTmax allows
+    #[error("Receiver sent more than {0} flow control wait frames")]
+    WaitLimitExceeded(u8),
+    /// The receiver cannot buffer the payload (Flow control OVFLW)
+    #[error("Receiver buffer overflow")]
+    Overflow,
+    /// The receiver sent a flow control frame with an unknown flow status
+    #[error("Invalid flow status 0x{0:02X}")]
+    InvalidFlowStatus(u8),
+    /// A consecutive frame had the wrong sequence number, a frame was lost
+    #[error("Wrong consecutive frame sequence number")]
+    WrongSequenceNumber,
+}
+
 impl From<PCanErrorTy> for ChannelError {
     fn from(value: PCanErrorTy) -> Self {
         match value {
@@ -215,6 +252,13 @@ pub trait PayloadChannel: Send + Sync {
     /// Tells the channel to clear its Tx buffer.
     /// This means all messages that are queued to be sent to the ECU should be wiped.
     fn clear_tx_buffer(&mut self) -> ChannelResult<()>;
+
+    /// Returns the receive timestamp (In microseconds) of the last payload returned
+    /// by [PayloadChannel::read_bytes], if the channel keeps track of it.
+    /// See [CanFrame::get_timestamp_us] for the time base
+    fn last_rx_timestamp_us(&self) -> Option<u64> {
+        None
+    }
 }
 
 /// Extended trait for [PayloadChannel] when utilizing ISO-TP to send data to the ECU
@@ -257,20 +301,109 @@ pub trait PacketChannel<T: Packet>: Send + Sync {
     fn clear_tx_buffer(&mut self) -> ChannelResult<()>;
 }
 
-#[allow(dead_code)]
 /// An extension to [PacketChannel] that allows for specified filtering of packet IDs
+///
+/// Once a filter is added, only packets allowed by at least one of the active filters
+/// are received. Removing all filters opens the channel to all traffic again.
+///
+/// By default, this returns [ChannelError::UnsupportedRequest] for adapters without hardware filtering
 pub trait FilterPacketChannel<T: Packet>: PacketChannel<T> {
     /// Allow a filter for specific packet IDs
     /// If successful, a unique ID to this filter is returned
-    fn add_filter(&mut self, allowed_ids: &[u32]) -> ChannelResult<u32>;
+    fn add_filter(&mut self, _allowed_ids: &[u32]) -> ChannelResult<u32> {
+        Err(ChannelError::UnsupportedRequest)
+    }
     /// Removes a filter created by [FilterPacketChannel::add_filter]
-    fn remove_filter(&mut self, filter_id: u32) -> ChannelResult<()>;
+    fn remove_filter(&mut self, _filter_id: u32) -> ChannelResult<()> {
+        Err(ChannelError::UnsupportedRequest)
+    }
 }
 
 /// Packet channel for sending and receiving individual CAN Frames
-pub trait CanChannel: PacketChannel<CanFrame> {
+pub trait CanChannel: FilterPacketChannel<CanFrame> {
     /// Sets the CAN network configuration
     fn set_can_cfg(&mut self, baud: u32, use_extended: bool) -> ChannelResult<()>;
+
+    /// Sets the CAN FD network configuration
+    ///
+    /// ## Parameters
+    /// * baud - Nominal (arbitration phase) baud rate
+    /// * data_baud - Data phase baud rate, used by frames sent with bit rate switching
+    /// * use_extended - Use extended (29bit) addressing
+    ///
+    /// By default, this returns [ChannelError::UnsupportedRequest] for adapters without CAN FD
+    fn set_can_fd_cfg(
+        &mut self,
+        _baud: u32,
+        _data_baud: u32,
+        _use_extended: bool,
+    ) -> ChannelResult<()> {
+        Err(ChannelError::UnsupportedRequest)
+    }
+
+    /// Returns the health of the CAN bus, as seen by the adapter's CAN controller
+    ///
+    /// By default, this returns [ChannelError::UnsupportedRequest] for adapters that do not report it
+    fn get_bus_status(&mut self) -> ChannelResult<CanBusStatus> {
+        Err(ChannelError::UnsupportedRequest)
+    }
+
+    /// Detects the baud rate of a running CAN bus. The channel must be closed.
+    ///
+    /// The adapter listens passively (listen-only mode, it never sends or acknowledges frames)
+    /// at each candidate baud rate in turn for `listen_ms`, and the first rate at which
+    /// frames are received without bus errors is returned. If there is none, this returns
+    /// [ChannelError::BaudNotDetected]. The channel is left closed, configure it
+    /// with [CanChannel::set_can_cfg] before opening.
+    ///
+    /// By default, this returns [ChannelError::UnsupportedRequest] for adapters without a listen-only mode
+    fn detect_can_baud(&mut self, _candidates: &[u32], _listen_ms: u32) -> ChannelResult<u32> {
+        Err(ChannelError::UnsupportedRequest)
+    }
+}
+
+/// Common CAN baud rates, most common first. Candidates for [CanChannel::detect_can_baud]
+pub const COMMON_CAN_BAUD_RATES: &[u32] = &[
+    500_000, 250_000, 125_000, 1_000_000, 800_000, 100_000, 83_333, 50_000, 33_333, 20_000, 10_000,
+];
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Default)]
+/// Error state of a CAN controller (ISO 11898-1 fault confinement), from healthy to off the bus
+pub enum CanBusState {
+    /// No significant errors on the bus
+    #[default]
+    ErrorActive,
+    /// An error counter reached the warning limit (96)
+    Warning,
+    /// An error counter reached the error passive limit (128).
+    /// The controller no longer signals errors it sees on the bus
+    ErrorPassive,
+    /// The transmit error counter exceeded 255, the controller left the bus.
+    /// Nothing can be sent or received until the channel is reset
+    BusOff,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+/// Health of a CAN bus, see [CanChannel::get_bus_status]
+pub struct CanBusStatus {
+    /// Error state of the CAN controller
+    pub state: CanBusState,
+    /// Transmit error counter, if the adapter reports it
+    pub tx_error_count: Option<u8>,
+    /// Receive error counter, if the adapter reports it
+    pub rx_error_count: Option<u8>,
+    /// Number of receive overruns (Frames that were lost) since the channel was created
+    pub overrun_count: u32,
+}
+
+impl std::fmt::Display for CanBusStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self.state)?;
+        if let (Some(tx), Some(rx)) = (self.tx_error_count, self.rx_error_count) {
+            write!(f, ", TEC: {tx}, REC: {rx}")?;
+        }
+        write!(f, ", Overruns: {}", self.overrun_count)
+    }
 }
 
 #[allow(dead_code)]
@@ -317,6 +450,10 @@ impl<T: PayloadChannel + ?Sized> PayloadChannel for Box<T> {
     fn clear_tx_buffer(&mut self) -> ChannelResult<()> {
         T::clear_tx_buffer(self)
     }
+
+    fn last_rx_timestamp_us(&self) -> Option<u64> {
+        T::last_rx_timestamp_us(self)
+    }
 }
 
 impl<T: IsoTPChannel + ?Sized> IsoTPChannel for Box<T> {
@@ -351,10 +488,37 @@ impl<X: Packet, T: PacketChannel<X> + ?Sized> PacketChannel<X> for Box<T> {
     }
 }
 
+impl<X: Packet, T: FilterPacketChannel<X> + ?Sized> FilterPacketChannel<X> for Box<T> {
+    fn add_filter(&mut self, allowed_ids: &[u32]) -> ChannelResult<u32> {
+        T::add_filter(self, allowed_ids)
+    }
+
+    fn remove_filter(&mut self, filter_id: u32) -> ChannelResult<()> {
+        T::remove_filter(self, filter_id)
+    }
+}
+
 impl<T: CanChannel + ?Sized> CanChannel for Box<T> {
     fn set_can_cfg(&mut self, baud: u32, use_extended: bool) -> ChannelResult<()> {
         T::set_can_cfg(self, baud, use_extended)
     }
+
+    fn set_can_fd_cfg(
+        &mut self,
+        baud: u32,
+        data_baud: u32,
+        use_extended: bool,
+    ) -> ChannelResult<()> {
+        T::set_can_fd_cfg(self, baud, data_baud, use_extended)
+    }
+
+    fn get_bus_status(&mut self) -> ChannelResult<CanBusStatus> {
+        T::get_bus_status(self)
+    }
+
+    fn detect_can_baud(&mut self, candidates: &[u32], listen_ms: u32) -> ChannelResult<u32> {
+        T::detect_can_baud(self, candidates, listen_ms)
+    }
 }
 
 impl<T: PayloadChannel + ?Sized> PayloadChannel for Arc<Mutex<T>> {
@@ -391,6 +555,10 @@ impl<T: PayloadChannel + ?Sized> PayloadChannel for Arc<Mutex<T>> {
     fn clear_tx_buffer(&mut self) -> ChannelResult<()> {
         T::clear_tx_buffer(self.lock()?.borrow_mut())
     }
+
+    fn last_rx_timestamp_us(&self) -> Option<u64> {
+        T::last_rx_timestamp_us(&*self.lock().ok()?)
+    }
 }
 
 impl<T: IsoTPChannel + ?Sized> IsoTPChannel for Arc<Mutex<T>> {
@@ -425,10 +593,37 @@ impl<X: Packet, T: PacketChannel<X> + ?Sized> PacketChannel<X> for Arc<Mutex<T>>
     }
 }
 
+impl<X: Packet, T: FilterPacketChannel<X> + ?Sized> FilterPacketChannel<X> for Arc<Mutex<T>> {
+    fn add_filter(&mut self, allowed_ids: &[u32]) -> ChannelResult<u32> {
+        T::add_filter(self.lock()?.borrow_mut(), allowed_ids)
+    }
+
+    fn remove_filter(&mut self, filter_id: u32) -> ChannelResult<()> {
+        T::remove_filter(self.lock()?.borrow_mut(), filter_id)
+    }
+}
+
 impl<T: CanChannel + ?Sized> CanChannel for Arc<Mutex<T>> {
     fn set_can_cfg(&mut self, baud: u32, use_extended: bool) -> ChannelResult<()> {
         T::set_can_cfg(self.lock()?.borrow_mut(), baud, use_extended)
     }
+
+    fn set_can_fd_cfg(
+        &mut self,
+        baud: u32,
+        data_baud: u32,
+        use_extended: bool,
+    ) -> ChannelResult<()> {
+        T::set_can_fd_cfg(self.lock()?.borrow_mut(), baud, data_baud, use_extended)
+    }
+
+    fn get_bus_status(&mut self) -> ChannelResult<CanBusStatus> {
+        T::get_bus_status(self.lock()?.borrow_mut())
+    }
+
+    fn detect_can_baud(&mut self, candidates: &[u32], listen_ms: u32) -> ChannelResult<u32> {
+        T::detect_can_baud(self.lock()?.borrow_mut(), candidates, listen_ms)
+    }
 }
 
 /// This trait is for packets that are used by [PacketChannel]
@@ -443,13 +638,65 @@ pub trait Packet: Send + Sync + Sized {
     fn set_data(&mut self, data: &[u8]);
 }
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+/// Valid data lengths of a CAN FD frame, indexed by DLC
+const CAN_FD_LENGTHS: [usize; 16] = [0, 1, 2, 3, 4, 5, 6, 7, 8, 12, 16, 20, 24, 32, 48, 64];
+
+/// Converts a CAN FD DLC (0-15) to the data length of the frame
+pub fn can_fd_dlc_to_len(dlc: u8) -> usize {
+    CAN_FD_LENGTHS[std::cmp::min(dlc as usize, 15)]
+}
+
+/// Converts a data length to the smallest CAN FD DLC that can hold it.
+/// Lengths over 64 bytes return the maximum DLC of 15
+pub fn can_fd_len_to_dlc(len: usize) -> u8 {
+    CAN_FD_LENGTHS.iter().position(|x| *x >= len).unwrap_or(15) as u8
+}
+
+/// Returns a monotonic host timestamp in microseconds, counted from the first call.
+/// This is used for received frames when the adapter provides no timestamp of its own
+pub fn host_timestamp_us() -> u64 {
+    static EPOCH: OnceLock<Instant> = OnceLock::new();
+    EPOCH.get_or_init(Instant::now).elapsed().as_micros() as u64
+}
+
+#[derive(Debug, Copy, Clone)]
 /// CAN Frame
+///
+/// Frames are compared by their content only, the receive timestamp is ignored.
 pub struct CanFrame {
     id: u32,
     dlc: u8,
-    data: [u8; 8],
+    data: [u8; 64],
     ext: bool,
+    fd: bool,
+    brs: bool,
+    timestamp_us: Option<u64>,
+}
+
+impl CanFrame {
+    fn key(&self) -> (u32, u8, &[u8; 64], bool, bool, bool) {
+        (self.id, self.dlc, &self.data, self.ext, self.fd, self.brs)
+    }
+}
+
+impl PartialEq for CanFrame {
+    fn eq(&self, other: &Self) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl Eq for CanFrame {}
+
+impl PartialOrd for CanFrame {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for CanFrame {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.key().cmp(&other.key())
+    }
 }
 
 unsafe impl Sync for CanFrame {}
@@ -468,13 +715,40 @@ impl CanFrame {
     /// Also, `data` will be limited to 8 bytes.
     pub fn new(id: u32, data: &[u8], is_ext: bool) -> Self {
         let max = std::cmp::min(8, data.len());
-        let mut tmp = [0u8; 8];
+        let mut tmp = [0u8; 64];
         tmp[0..max].copy_from_slice(&data[0..max]);
         Self {
             id,
             dlc: max as u8,
             data: tmp,
             ext: is_ext,
+            fd: false,
+            brs: false,
+            timestamp_us: None,
+        }
+    }
+
+    /// Creates a new CAN FD Frame given data and an ID.
+    /// ## Parameters
+    /// * id - The CAN ID of the packet
+    /// * data - The data of the CAN packet
+    /// * is_ext - Indication if the CAN packet shall use extended addressing
+    /// * brs - Send the data phase of the frame at the data baud rate (Bit rate switch)
+    ///
+    /// NOTE: `data` will be limited to 64 bytes, and padded with 0x00
+    /// up to the next valid CAN FD data length.
+    pub fn new_fd(id: u32, data: &[u8], is_ext: bool, brs: bool) -> Self {
+        let max = std::cmp::min(64, data.len());
+        let mut tmp = [0u8; 64];
+        tmp[0..max].copy_from_slice(&data[0..max]);
+        Self {
+            id,
+            dlc: can_fd_dlc_to_len(can_fd_len_to_dlc(max)) as u8,
+            data: tmp,
+            ext: is_ext,
+            fd: true,
+            brs,
+            timestamp_us: None,
         }
     }
 
@@ -483,6 +757,30 @@ impl CanFrame {
         self.ext
     }
 
+    /// Returns true if this is a CAN FD Frame
+    pub fn is_fd(&self) -> bool {
+        self.fd
+    }
+
+    /// Returns true if this CAN FD Frame uses bit rate switching
+    pub fn is_brs(&self) -> bool {
+        self.brs
+    }
+
+    /// Returns the receive timestamp of the frame in microseconds, or None for frames that
+    /// were not received from a channel.
+    ///
+    /// This is the adapter's hardware timestamp where available, otherwise [host_timestamp_us].
+    /// Either way it is monotonic, but only timestamps from the same channel can be compared
+    pub fn get_timestamp_us(&self) -> Option<u64> {
+        self.timestamp_us
+    }
+
+    /// Sets the receive timestamp of the frame in microseconds
+    pub fn set_timestamp_us(&mut self, timestamp_us: u64) {
+        self.timestamp_us = Some(timestamp_us)
+    }
+
     pub fn get_sid(&mut self) -> &[u8] {
         &self.data[1..2]
     }
@@ -501,9 +799,16 @@ impl Packet for CanFrame {
         self.id = address
     }
     fn set_data(&mut self, data: &[u8]) {
-        let max = std::cmp::min(8, data.len());
+        let (max, len) = if self.fd {
+            let max = std::cmp::min(64, data.len());
+            (max, can_fd_dlc_to_len(can_fd_len_to_dlc(max)))
+        } else {
+            let max = std::cmp::min(8, data.len());
+            (max, max)
+        };
+        self.data = [0; 64];
         self.data[0..max].copy_from_slice(&data[0..max]);
-        self.dlc = max as u8;
+        self.dlc = len as u8;
     }
 }
 
@@ -512,18 +817,18 @@ impl std::fmt::Display for CanFrame {
         // Format the CanFrame as a string
         write!(
             f,
-            "ID: {:#X}, DLC: {}, Data: {:02X} {:02X} {:02X} {:02X} {:02X} {:02X} {:02X} {:02X}",
+            "ID: {:#X}, {}DLC: {}, Data:",
             self.id,
-            self.dlc,
-            self.data[0],
-            self.data[1],
-            self.data[2],
-            self.data[3],
-            self.data[4],
-            self.data[5],
-            self.data[6],
-            self.data[7]
-        )
+            if self.fd { "FD, " } else { "" },
+            self.dlc
+        )?;
+        for b in self.get_data() {
+            write!(f, " {b:02X}")?;
+        }
+        if let Some(ts) = self.timestamp_us {
+            write!(f, ", Time: {ts}us")?;
+        }
+        Ok(())
     }
 }
 
@@ -558,6 +863,24 @@ pub struct IsoTPSettings {
     pub can_speed: u32,
     /// Does the CAN Network support extended addressing (29bit) or standard addressing (11bit)
     pub can_use_ext_addr: bool,
+    /// CAN FD data phase baud rate. When set, ISO-TP is sent over CAN FD frames of
+    /// up to 64 bytes (ISO 15765-2:2016), with bit rate switching if it differs from `can_speed`
+    pub can_fd_speed: Option<u32>,
+    /// Network layer timeouts
+    ///
+    /// NOTE: These might be ignored by the device's implementation of ISO-TP
+    pub timeouts: IsoTpTimeouts,
+}
+
+impl IsoTPSettings {
+    /// Returns the maximum data length of a single ISO-TP frame (TX_DL)
+    pub fn tx_dl(&self) -> usize {
+        if self.can_fd_speed.is_some() {
+            64
+        } else {
+            8
+        }
+    }
 }
 
 impl Default for IsoTPSettings {
@@ -569,6 +892,46 @@ impl Default for IsoTPSettings {
             pad_frame: true,
             can_speed: 500_000,
             can_use_ext_addr: true,
+            can_fd_speed: None,
+            timeouts: IsoTpTimeouts::default(),
         }
     }
 }
+
+/// ISO-TP network layer timeouts and limits (ISO 15765-2)
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[repr(C)]
+pub struct IsoTpTimeouts {
+    /// N_As - Time in milliseconds for a frame to be sent on the bus
+    pub n_as: u32,
+    /// N_Bs - Time in milliseconds the sender waits for a flow control frame
+    pub n_bs: u32,
+    /// N_Cr - Time in milliseconds the receiver waits for the next consecutive frame
+    pub n_cr: u32,
+    /// N_WFTmax - Number of flow control WAIT frames in a row accepted from the receiver.
+    /// A value of 0 means the receiver is not allowed to send WAIT frames
+    pub n_wft_max: u8,
+}
+
+impl Default for IsoTpTimeouts {
+    fn default() -> Self {
+        Self {
+            n_as: 1000,
+            n_bs: 1000,
+            n_cr: 1000,
+            n_wft_max: 10,
+        }
+    }
+}
+
+/// Converts an ISO-TP STmin value to the separation time it encodes.
+///
+/// 0x00-0x7F are milliseconds and 0xF1-0xF9 are 100-900 microseconds.
+/// Reserved values are treated as the longest separation time (127ms), as ISO 15765-2 requires
+pub fn st_min_duration(st_min: u8) -> Duration {
+    match st_min {
+        0x00..=0x7F => Duration::from_millis(st_min as u64),
+        0xF1..=0xF9 => Duration::from_micros((st_min - 0xF0) as u64 * 100),
+        _ => Duration::from_millis(0x7F),
+    }
+}
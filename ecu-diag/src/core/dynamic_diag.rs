@@ -16,7 +16,7 @@ use std::{
 };
 
 use crate::core::{
-    channel::{ChannelResult, IsoTPChannel, IsoTPSettings},
+    channel::{host_timestamp_us, ChannelResult, IsoTPChannel, IsoTPSettings, PayloadChannel},
     DiagError, DiagServerResult,
 };
 
@@ -38,6 +38,9 @@ pub enum DiagServerRx {
     },
     /// ECU is busy, please wait
     EcuBusy,
+    /// ECU has left the session mode of the server, which is switching it
+    /// back before repeating the request
+    SessionRecovery,
     /// Request message transmit result
     SendState {
         /// Data that was sent to be transmitted
@@ -88,6 +91,29 @@ pub struct TimeoutConfig {
     pub write_timeout_ms: u32,
 }
 
+/// Time added to the P2 and P2* timings of the ECU for the request
+/// and the response to cross the network
+pub const P2_NETWORK_MARGIN_MS: u32 = 100;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+/// Response timings of the ECU in the active diagnostic session (ISO 14229-2)
+pub struct SessionTimings {
+    /// P2, time the ECU has to start its response to a request
+    pub p2_ms: u32,
+    /// P2*, time the ECU has to start its response after a response pending NRC
+    pub p2_star_ms: u32,
+}
+
+impl From<TimeoutConfig> for SessionTimings {
+    /// Timings to use until the ECU reports its own, the read timeout for both
+    fn from(cfg: TimeoutConfig) -> Self {
+        Self {
+            p2_ms: cfg.read_timeout_ms,
+            p2_star_ms: cfg.read_timeout_ms,
+        }
+    }
+}
+
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
 #[repr(C)]
 /// Basic diagnostic server options
@@ -134,8 +160,9 @@ pub enum ServerEvent {
     ServerExit,
     /// Sent payload to ECU
     BytesSendState(u32, Vec<u8>, ChannelResult<()>),
-    /// Recv payload from ECU
-    BytesRecvState(u32, ChannelResult<Vec<u8>>),
+    /// Recv payload from ECU, with its receive timestamp in microseconds
+    /// (See [crate::core::channel::CanFrame::get_timestamp_us])
+    BytesRecvState(u32, ChannelResult<Vec<u8>>, u64),
 }
 
 /// Diag server logger
@@ -239,6 +266,11 @@ where
     fn process_ecu_response(r: &[u8]) -> Result<Vec<u8>, (u8, NRC)>;
     /// Gets a hashmap of available diagnostic session modes
     fn get_diagnostic_session_list(&self) -> HashMap<u8, DiagSessionMode>;
+    /// Reads the P2 and P2* timings of the new session from the positive response
+    /// to a session control request, if the protocol has them
+    fn get_session_timings(&self, _r: &[u8]) -> Option<SessionTimings> {
+        None
+    }
     /// Registers a new custom diagnostic session mode
     fn register_session_type(&mut self, session: DiagSessionMode);
 }
@@ -285,14 +317,13 @@ impl std::fmt::Debug for DynamicDiagSession {
 impl DynamicDiagSession {
     /// Creates a new diagnostic server with a given protocol and NRC format
     /// over an ISO-TP connection
-    #[allow(unused_must_use, unused_assignments)]
     pub fn new_over_iso_tp<P, NRC, L>(
         protocol: P,
         mut channel: Box<dyn IsoTPChannel>,
         channel_cfg: IsoTPSettings,
         basic_opts: DiagServerBasicOptions,
         advanced_opts: Option<DiagServerAdvancedOptions>,
-        mut logger: L,
+        logger: L,
     ) -> DiagServerResult<Self>
     where
         P: DiagProtocol<NRC> + 'static,
@@ -301,6 +332,30 @@ impl DynamicDiagSession {
     {
         // Create iso tp channel using provided HW interface. If this fails, we cannot setup KWP or UDS session!
         channel.set_iso_tp_cfg(channel_cfg)?;
+        Self::new_over_payload_channel(
+            protocol,
+            Box::new(channel),
+            basic_opts,
+            advanced_opts,
+            logger,
+        )
+    }
+
+    /// Creates a new diagnostic server with a given protocol and NRC format
+    /// over any payload channel, EG: [DoIpChannel](crate::hardware::doip::DoIpChannel)
+    #[allow(unused_must_use, unused_assignments)]
+    pub fn new_over_payload_channel<P, NRC, L>(
+        protocol: P,
+        mut channel: Box<dyn PayloadChannel>,
+        basic_opts: DiagServerBasicOptions,
+        advanced_opts: Option<DiagServerAdvancedOptions>,
+        mut logger: L,
+    ) -> DiagServerResult<Self>
+    where
+        P: DiagProtocol<NRC> + 'static,
+        NRC: EcuNRC,
+        L: DiagServerLogger + 'static,
+    {
         channel.set_ids(basic_opts.send_id, basic_opts.recv_id)?;
         channel.open()?;
 
@@ -324,6 +379,8 @@ impl DynamicDiagSession {
         let is_running_c = is_running.clone();
         let cooldown = advanced_opts.map(|x| x.command_cooldown_ms).unwrap_or(0) as u128;
         std::thread::spawn(move || {
+            let default_timings = SessionTimings::from(basic_opts.timeout_cfg);
+            let mut timings = default_timings;
             let mut last_tp_time = Instant::now();
             let mut last_cmd_time = Instant::now();
             logger.on_event(ServerEvent::ServerStart);
@@ -356,12 +413,18 @@ impl DynamicDiagSession {
                                     needs_response,
                                     Some(&mut tx_resp),
                                     basic_opts,
+                                    timings,
                                     0,
                                     &mut channel,
                                     &is_connected_inner,
                                     &mut logger,
                                 );
                                 if res.is_ok() {
+                                    if let DiagServerRx::EcuResponse(r) = &res {
+                                        timings = protocol
+                                            .get_session_timings(r)
+                                            .unwrap_or(default_timings);
+                                    }
                                     // Send OK! We can set diag mode in the server side
                                     requested_session_mode = Some(mode);
                                     *noti_session_mode_t.write().unwrap() =
@@ -380,6 +443,7 @@ impl DynamicDiagSession {
                                     req.response_require,
                                     Some(&mut tx_resp),
                                     basic_opts,
+                                    timings,
                                     0,
                                     &mut channel,
                                     &is_connected_inner,
@@ -393,6 +457,7 @@ impl DynamicDiagSession {
                                     *noti_session_mode_t.write().unwrap() =
                                         protocol.get_basic_session_mode();
                                     current_session_mode = protocol.get_basic_session_mode();
+                                    timings = default_timings;
                                     std::thread::sleep(Duration::from_millis(500)); // Await ECU to reboot - TODO. Maybe we should let this be configured?
                                     last_cmd_time = Instant::now();
                                 }
@@ -407,6 +472,7 @@ impl DynamicDiagSession {
                                     req.response_require,
                                     Some(&mut tx_resp),
                                     basic_opts,
+                                    timings,
                                     0,
                                     &mut channel,
                                     &is_connected_inner,
@@ -417,8 +483,8 @@ impl DynamicDiagSession {
                                         log::debug!("Trying to switch ECU modes");
                                         // Wrong diag mode. We need to see if we need to change modes
                                         // Switch modes!
-                                        tx_resp.send(DiagServerRx::EcuBusy); // Until we have a hook for this specific scenerio
-                                                                             // Now create new diag server request message
+                                        tx_resp.send(DiagServerRx::SessionRecovery);
+                                        // Now create new diag server request message
                                         let mut needs_response = true;
                                         let mut ext_id = None;
                                         if let Some(adv) = advanced_opts {
@@ -432,7 +498,7 @@ impl DynamicDiagSession {
                                                 log::warn!("Global session control is enabled but global TP ID is not specified");
                                             }
                                         }
-                                        if send_recv_ecu_req::<P, NRC, L>(
+                                        let switch_res = send_recv_ecu_req::<P, NRC, L>(
                                             tx_addr,
                                             rx_addr,
                                             ext_id,
@@ -442,13 +508,18 @@ impl DynamicDiagSession {
                                             needs_response,
                                             None, // None, internally handled
                                             basic_opts,
+                                            timings,
                                             0,
                                             &mut channel,
                                             &is_connected_inner,
                                             &mut logger,
-                                        )
-                                        .is_ok()
-                                        {
+                                        );
+                                        if switch_res.is_ok() {
+                                            if let DiagServerRx::EcuResponse(r) = &switch_res {
+                                                timings = protocol
+                                                    .get_session_timings(r)
+                                                    .unwrap_or(default_timings);
+                                            }
                                             log::debug!(
                                                 "ECU mode switch OK. Resending the request"
                                             );
@@ -464,6 +535,7 @@ impl DynamicDiagSession {
                                                 req.response_require,
                                                 Some(&mut tx_resp),
                                                 basic_opts,
+                                                timings,
                                                 0,
                                                 &mut channel,
                                                 &is_connected_inner,
@@ -473,7 +545,9 @@ impl DynamicDiagSession {
                                             // Diag session mode req failed. Set session data
                                             *noti_session_mode_t.write().unwrap() =
                                                 protocol.get_basic_session_mode();
-                                            current_session_mode = protocol.get_basic_session_mode()
+                                            current_session_mode =
+                                                protocol.get_basic_session_mode();
+                                            timings = default_timings;
                                         }
                                     }
                                 } else if let DiagServerRx::EcuResponse(_) = &resp {
@@ -511,6 +585,7 @@ impl DynamicDiagSession {
                                 aops.tester_present_require_response,
                                 None,
                                 basic_opts,
+                                timings,
                                 0,
                                 &mut channel,
                                 &is_connected_inner,
@@ -522,6 +597,7 @@ impl DynamicDiagSession {
                                 current_session_mode = protocol.get_basic_session_mode();
                                 *noti_session_mode_t.write().unwrap() =
                                     current_session_mode.clone();
+                                timings = default_timings;
                             } else {
                                 last_tp_time = Instant::now(); // OK, reset the timer
                             }
@@ -609,6 +685,7 @@ impl DynamicDiagSession {
                     })
                 }
                 DiagServerRx::EcuBusy => (self.waiting_hook)(),
+                DiagServerRx::SessionRecovery => log::debug!("Restoring the ECU session mode"),
                 DiagServerRx::SendState { p, r } => match r {
                     Ok(_) => (self.on_send_complete_hook)(&p),
                     Err(e) => return Err(e),
@@ -661,8 +738,9 @@ fn send_recv_ecu_req<P, NRC, L>(
     needs_response: bool,
     tx_resp: Option<&mut Sender<DiagServerRx>>,
     basic_opts: DiagServerBasicOptions,
+    timings: SessionTimings,
     cooldown: u32,
-    channel: &mut Box<dyn IsoTPChannel>,
+    channel: &mut Box<dyn PayloadChannel>,
     connect_state: &AtomicBool,
     logger: &mut L,
 ) -> DiagServerRx
@@ -707,9 +785,22 @@ where
                     })
                     .unwrap();
                 }
-                // Now poll for the ECU's response
-                let r_state = channel.read_bytes(basic_opts.timeout_cfg.read_timeout_ms);
-                logger.on_event(ServerEvent::BytesRecvState(rx_addr, r_state.clone()));
+                // Now poll for the ECU's response, P2 after a request or P2* after a response pending
+                let timeout_ms = match payload.is_empty() {
+                    true => timings.p2_star_ms,
+                    false => timings.p2_ms,
+                } + P2_NETWORK_MARGIN_MS;
+                let r_state = channel.read_bytes(timeout_ms);
+                let rx_time = match r_state {
+                    Ok(_) => channel.last_rx_timestamp_us(),
+                    Err(_) => None,
+                }
+                .unwrap_or_else(host_timestamp_us);
+                logger.on_event(ServerEvent::BytesRecvState(
+                    rx_addr,
+                    r_state.clone(),
+                    rx_time,
+                ));
                 match r_state {
                     Err(e) => {
                         log::error!("Error reading from channel. Request was {payload:02X?}");
@@ -718,7 +809,7 @@ where
                         DiagServerRx::RecvError(e.into())
                     }
                     Ok(bytes) => {
-                        log::debug!("ECU Response: {bytes:02X?}");
+                        log::debug!("ECU Response @ {rx_time}us: {bytes:02X?}");
                         let parsed_response = P::process_ecu_response(&bytes);
                         connect_state.store(true, Ordering::Relaxed);
                         match parsed_response {
@@ -731,6 +822,9 @@ where
                                     // ECU waiting, so poll again for the response
                                     // to do that, call this function again with no payload
                                     log::debug!("ECU is busy, awaiting response");
+                                    if let Some(s) = &tx_resp {
+                                        s.send(DiagServerRx::EcuBusy).unwrap();
+                                    }
                                     send_recv_ecu_req::<P, NRC, L>(
                                         tx_addr,
                                         rx_addr,
@@ -739,6 +833,7 @@ where
                                         needs_response,
                                         tx_resp,
                                         basic_opts,
+                                        timings,
                                         cooldown,
                                         channel,
                                         connect_state,
@@ -756,6 +851,7 @@ where
                                         needs_response,
                                         tx_resp,
                                         basic_opts,
+                                        timings,
                                         cooldown,
                                         channel,
                                         connect_state,
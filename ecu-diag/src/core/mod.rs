@@ -1,5 +1,6 @@
 use crate::core::channel::ChannelError;
 use crate::hardware::HardwareError;
+use crate::uds::errors::UdsError;
 use std::sync::Arc;
 
 pub(crate) mod channel;
@@ -16,7 +17,8 @@ pub enum DiagError {
     /// The Diagnostic server does not support the request
     #[error("Diagnostic server does not support the request")]
     NotSupported,
-    /// Diagnostic error code from the ECU itself
+    /// Diagnostic error code from the ECU itself.
+    /// The code of a UDS negative response is decoded by [DiagError::uds_error]
     #[error("ECU Negative response. Error 0x{:02X?}, definition: {:?}", code, def)]
     ECUError {
         /// Raw Negative response code from ECU
@@ -71,6 +73,16 @@ pub enum DiagError {
     },
 }
 
+impl DiagError {
+    /// Returns the UDS negative response code of an [DiagError::ECUError]
+    pub fn uds_error(&self) -> Option<UdsError> {
+        match self {
+            DiagError::ECUError { code, .. } => Some(UdsError::from(*code)),
+            _ => None,
+        }
+    }
+}
+
 #[allow(dead_code)]
 /// Converts a single byte into a BCD string
 pub fn bcd_decode(input: u8) -> String {
@@ -0,0 +1,489 @@
+//! Async (tokio) counterparts of the blocking channel traits
+//!
+//! The following channel types are defined:
+//! * [AsyncPayloadChannel] - Async version of [PayloadChannel]
+//! * [AsyncPacketChannel] - Async version of [PacketChannel]
+//!
+//! Existing blocking channels are turned into async ones with [AsyncPayloadAdapter] and
+//! [AsyncPacketAdapter], which run the blocking calls on tokio's blocking thread pool.
+//! [BlockingAdapter] goes the other way, for code that still expects a blocking channel.
+
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use tokio::runtime::Handle;
+use tokio::task::JoinHandle;
+
+use crate::core::channel::{ChannelError, ChannelResult, Packet, PacketChannel, PayloadChannel};
+
+/// Longest blocking read the async adapters start at once. A cancelled read
+/// keeps a blocking thread busy for at most this long
+const POLL_SLICE_MS: u32 = 10;
+
+/// Async version of [PayloadChannel]
+///
+/// [AsyncPayloadChannel::read] and [AsyncPayloadChannel::write] are cancellation safe.
+/// If a read future is dropped before completing, no payload that the channel received is lost,
+/// it is returned by the next read. If a write future is dropped, the payload is either not sent
+/// at all, or sent completely before the next call to the channel proceeds.
+pub trait AsyncPayloadChannel: Send + Sync {
+    /// Opens the interface. See [PayloadChannel::open]
+    fn open(&mut self) -> impl Future<Output = ChannelResult<()>> + Send;
+
+    /// Closes and destroys the channel
+    fn close(&mut self) -> impl Future<Output = ChannelResult<()>> + Send;
+
+    /// Configures the send and receive IDs of the channel. See [PayloadChannel::set_ids]
+    fn set_ids(&mut self, send: u32, recv: u32) -> impl Future<Output = ChannelResult<()>> + Send;
+
+    /// Reads a payload from the channel. See [PayloadChannel::read_bytes]
+    fn read(&mut self, timeout_ms: u32) -> impl Future<Output = ChannelResult<Vec<u8>>> + Send;
+
+    /// Writes a payload to the channel. See [PayloadChannel::write_bytes]
+    fn write(
+        &mut self,
+        addr: u32,
+        ext_id: Option<u8>,
+        buffer: &[u8],
+        timeout_ms: u32,
+    ) -> impl Future<Output = ChannelResult<()>> + Send;
+
+    /// Writes a payload to the channel, then reads the response.
+    /// See [PayloadChannel::read_write_bytes]
+    fn read_write(
+        &mut self,
+        addr: u32,
+        ext_id: Option<u8>,
+        buffer: &[u8],
+        write_timeout_ms: u32,
+        read_timeout_ms: u32,
+    ) -> impl Future<Output = ChannelResult<Vec<u8>>> + Send {
+        async move {
+            self.write(addr, ext_id, buffer, write_timeout_ms).await?;
+            self.read(read_timeout_ms).await
+        }
+    }
+
+    /// Tells the channel to clear its Rx buffer. See [PayloadChannel::clear_rx_buffer]
+    fn clear_rx_buffer(&mut self) -> impl Future<Output = ChannelResult<()>> + Send;
+
+    /// Tells the channel to clear its Tx buffer. See [PayloadChannel::clear_tx_buffer]
+    fn clear_tx_buffer(&mut self) -> impl Future<Output = ChannelResult<()>> + Send;
+}
+
+/// Async version of [PacketChannel]
+///
+/// [AsyncPacketChannel::read] and [AsyncPacketChannel::write] are cancellation safe,
+/// in the same way as the methods of [AsyncPayloadChannel]
+pub trait AsyncPacketChannel<T: Packet>: Send + Sync {
+    /// Opens the channel. See [PacketChannel::open]
+    fn open(&mut self) -> impl Future<Output = ChannelResult<()>> + Send;
+
+    /// Closes the channel. See [PacketChannel::close]
+    fn close(&mut self) -> impl Future<Output = ChannelResult<()>> + Send;
+
+    /// Writes a list of packets to the raw interface
+    fn write(
+        &mut self,
+        packets: Vec<T>,
+        timeout_ms: u32,
+    ) -> impl Future<Output = ChannelResult<()>> + Send;
+
+    /// Reads a list of packets from the raw interface
+    fn read(
+        &mut self,
+        max: usize,
+        timeout_ms: u32,
+    ) -> impl Future<Output = ChannelResult<Vec<T>>> + Send;
+
+    /// Tells the channel to clear its Rx buffer. See [PacketChannel::clear_rx_buffer]
+    fn clear_rx_buffer(&mut self) -> impl Future<Output = ChannelResult<()>> + Send;
+
+    /// Tells the channel to clear its Tx buffer. See [PacketChannel::clear_tx_buffer]
+    fn clear_tx_buffer(&mut self) -> impl Future<Output = ChannelResult<()>> + Send;
+}
+
+/// Blocking call running on tokio's blocking thread pool. The handle is kept in `slot`
+/// until the call completes, so a cancelled caller can pick up the result later on.
+/// If `slot` already holds a call, its result is returned and `f` is not run
+async fn run_in_slot<T, F>(
+    slot: &mut Option<JoinHandle<ChannelResult<T>>>,
+    f: F,
+) -> ChannelResult<T>
+where
+    T: Send + 'static,
+    F: FnOnce() -> ChannelResult<T> + Send + 'static,
+{
+    let res = slot
+        .get_or_insert_with(|| tokio::task::spawn_blocking(f))
+        .await;
+    *slot = None;
+    res.unwrap_or_else(|e| Err(ChannelError::Other(e.to_string())))
+}
+
+/// Blocking call that does not need to survive cancellation. A cancelled call still
+/// runs to completion in the background
+async fn run_detached<T, F>(f: F) -> ChannelResult<T>
+where
+    T: Send + 'static,
+    F: FnOnce() -> ChannelResult<T> + Send + 'static,
+{
+    tokio::task::spawn_blocking(f)
+        .await
+        .unwrap_or_else(|e| Err(ChannelError::Other(e.to_string())))
+}
+
+/// Waits for a write that was started by a cancelled caller to finish
+async fn finish_write(slot: &mut Option<JoinHandle<ChannelResult<()>>>) {
+    if slot.is_some() {
+        if let Err(e) = run_in_slot(slot, || Ok(())).await {
+            log::warn!("Cancelled channel write failed: {e}");
+        }
+    }
+}
+
+/// Repeats a blocking read in slices of at most [POLL_SLICE_MS] until it returns data,
+/// an error other than an empty buffer, or the timeout expires
+async fn read_sliced<T, F>(
+    slot: &mut Option<JoinHandle<ChannelResult<Vec<T>>>>,
+    timeout_ms: u32,
+    read: F,
+) -> ChannelResult<Vec<T>>
+where
+    T: Send + 'static,
+    F: Fn(u32) -> Box<dyn FnOnce() -> ChannelResult<Vec<T>> + Send>,
+{
+    let deadline = Instant::now() + Duration::from_millis(timeout_ms as u64);
+    loop {
+        let remaining = deadline
+            .saturating_duration_since(Instant::now())
+            .as_millis() as u32;
+        let res = run_in_slot(slot, read(remaining.min(POLL_SLICE_MS))).await;
+        let empty = match &res {
+            Ok(data) => data.is_empty(),
+            Err(e) => matches!(e, ChannelError::BufferEmpty | ChannelError::ReadTimeout),
+        };
+        if !empty || Instant::now() >= deadline {
+            return res;
+        }
+    }
+}
+
+/// Turns a blocking [PayloadChannel] into an [AsyncPayloadChannel].
+///
+/// The blocking calls run on tokio's blocking thread pool, so this must be used from within
+/// a tokio runtime. Reads are done in short slices, so a cancelled read releases the channel quickly.
+pub struct AsyncPayloadAdapter<C: PayloadChannel> {
+    channel: Arc<Mutex<C>>,
+    pending_read: Option<JoinHandle<ChannelResult<Vec<u8>>>>,
+    pending_write: Option<JoinHandle<ChannelResult<()>>>,
+}
+
+impl<C: PayloadChannel + 'static> AsyncPayloadAdapter<C> {
+    /// Wraps a blocking payload channel
+    pub fn new(channel: C) -> Self {
+        Self {
+            channel: Arc::new(Mutex::new(channel)),
+            pending_read: None,
+            pending_write: None,
+        }
+    }
+
+    /// Runs a blocking call on the channel
+    async fn call<T, F>(&mut self, f: F) -> ChannelResult<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut Arc<Mutex<C>>) -> ChannelResult<T> + Send + 'static,
+    {
+        let mut channel = self.channel.clone();
+        run_detached(move || f(&mut channel)).await
+    }
+}
+
+impl<C: PayloadChannel + 'static> AsyncPayloadChannel for AsyncPayloadAdapter<C> {
+    async fn open(&mut self) -> ChannelResult<()> {
+        self.call(|c| c.open()).await
+    }
+
+    async fn close(&mut self) -> ChannelResult<()> {
+        finish_write(&mut self.pending_write).await;
+        self.call(|c| c.close()).await
+    }
+
+    async fn set_ids(&mut self, send: u32, recv: u32) -> ChannelResult<()> {
+        self.call(move |c| c.set_ids(send, recv)).await
+    }
+
+    async fn read(&mut self, timeout_ms: u32) -> ChannelResult<Vec<u8>> {
+        let channel = self.channel.clone();
+        read_sliced(&mut self.pending_read, timeout_ms, |slice_ms| {
+            let mut channel = channel.clone();
+            Box::new(move || channel.read_bytes(slice_ms))
+        })
+        .await
+    }
+
+    async fn write(
+        &mut self,
+        addr: u32,
+        ext_id: Option<u8>,
+        buffer: &[u8],
+        timeout_ms: u32,
+    ) -> ChannelResult<()> {
+        finish_write(&mut self.pending_write).await;
+        let mut channel = self.channel.clone();
+        let buffer = buffer.to_vec();
+        run_in_slot(&mut self.pending_write, move || {
+            channel.write_bytes(addr, ext_id, &buffer, timeout_ms)
+        })
+        .await
+    }
+
+    async fn clear_rx_buffer(&mut self) -> ChannelResult<()> {
+        // A read that is still pending would return stale data
+        if self.pending_read.is_some() {
+            let _ = run_in_slot(&mut self.pending_read, || Ok(vec![])).await;
+        }
+        self.call(|c| c.clear_rx_buffer()).await
+    }
+
+    async fn clear_tx_buffer(&mut self) -> ChannelResult<()> {
+        self.call(|c| c.clear_tx_buffer()).await
+    }
+}
+
+/// Turns a blocking [PacketChannel] into an [AsyncPacketChannel].
+///
+/// The blocking calls run on tokio's blocking thread pool, so this must be used from within
+/// a tokio runtime. Reads are done in short slices, so a cancelled read releases the channel quickly.
+pub struct AsyncPacketAdapter<T: Packet, C: PacketChannel<T>> {
+    channel: Arc<Mutex<C>>,
+    pending_read: Option<JoinHandle<ChannelResult<Vec<T>>>>,
+    pending_write: Option<JoinHandle<ChannelResult<()>>>,
+}
+
+impl<T: Packet + 'static, C: PacketChannel<T> + 'static> AsyncPacketAdapter<T, C> {
+    /// Wraps a blocking packet channel
+    pub fn new(channel: C) -> Self {
+        Self {
+            channel: Arc::new(Mutex::new(channel)),
+            pending_read: None,
+            pending_write: None,
+        }
+    }
+
+    /// Runs a blocking call on the channel
+    async fn call<F>(&mut self, f: F) -> ChannelResult<()>
+    where
+        F: FnOnce(&mut Arc<Mutex<C>>) -> ChannelResult<()> + Send + 'static,
+    {
+        let mut channel = self.channel.clone();
+        run_detached(move || f(&mut channel)).await
+    }
+}
+
+impl<T: Packet + 'static, C: PacketChannel<T> + 'static> AsyncPacketChannel<T>
+    for AsyncPacketAdapter<T, C>
+{
+    async fn open(&mut self) -> ChannelResult<()> {
+        self.call(|c| c.open()).await
+    }
+
+    async fn close(&mut self) -> ChannelResult<()> {
+        finish_write(&mut self.pending_write).await;
+        self.call(|c| c.close()).await
+    }
+
+    async fn write(&mut self, packets: Vec<T>, timeout_ms: u32) -> ChannelResult<()> {
+        finish_write(&mut self.pending_write).await;
+        let mut channel = self.channel.clone();
+        run_in_slot(&mut self.pending_write, move || {
+            channel.write_packets(packets, timeout_ms)
+        })
+        .await
+    }
+
+    async fn read(&mut self, max: usize, timeout_ms: u32) -> ChannelResult<Vec<T>> {
+        let channel = self.channel.clone();
+        read_sliced(&mut self.pending_read, timeout_ms, |slice_ms| {
+            let mut channel = channel.clone();
+            Box::new(move || channel.read_packets(max, slice_ms))
+        })
+        .await
+    }
+
+    async fn clear_rx_buffer(&mut self) -> ChannelResult<()> {
+        // A read that is still pending would return stale data
+        if self.pending_read.is_some() {
+            let _ = run_in_slot(&mut self.pending_read, || Ok(vec![])).await;
+        }
+        self.call(|c| c.clear_rx_buffer()).await
+    }
+
+    async fn clear_tx_buffer(&mut self) -> ChannelResult<()> {
+        self.call(|c| c.clear_tx_buffer()).await
+    }
+}
+
+/// Turns an [AsyncPayloadChannel] or [AsyncPacketChannel] into its blocking counterpart,
+/// by running each call to completion on a tokio runtime.
+///
+/// The blocking methods must not be called from within an async task.
+/// Call them from a plain thread, or from [tokio::task::spawn_blocking]
+pub struct BlockingAdapter<C> {
+    channel: C,
+    runtime: Handle,
+}
+
+impl<C> BlockingAdapter<C> {
+    /// Wraps an async channel, which is driven by the runtime of `runtime`
+    pub fn new(channel: C, runtime: Handle) -> Self {
+        Self { channel, runtime }
+    }
+
+    /// Returns the wrapped async channel
+    pub fn into_inner(self) -> C {
+        self.channel
+    }
+}
+
+impl<C: AsyncPayloadChannel> PayloadChannel for BlockingAdapter<C> {
+    fn open(&mut self) -> ChannelResult<()> {
+        self.runtime.block_on(self.channel.open())
+    }
+
+    fn close(&mut self) -> ChannelResult<()> {
+        self.runtime.block_on(self.channel.close())
+    }
+
+    fn set_ids(&mut self, send: u32, recv: u32) -> ChannelResult<()> {
+        self.runtime.block_on(self.channel.set_ids(send, recv))
+    }
+
+    fn read_bytes(&mut self, timeout_ms: u32) -> ChannelResult<Vec<u8>> {
+        self.runtime.block_on(self.channel.read(timeout_ms))
+    }
+
+    fn write_bytes(
+        &mut self,
+        addr: u32,
+        ext_id: Option<u8>,
+        buffer: &[u8],
+        timeout_ms: u32,
+    ) -> ChannelResult<()> {
+        self.runtime
+            .block_on(self.channel.write(addr, ext_id, buffer, timeout_ms))
+    }
+
+    fn clear_rx_buffer(&mut self) -> ChannelResult<()> {
+        self.runtime
+            .block_on(AsyncPayloadChannel::clear_rx_buffer(&mut self.channel))
+    }
+
+    fn clear_tx_buffer(&mut self) -> ChannelResult<()> {
+        self.runtime
+            .block_on(AsyncPayloadChannel::clear_tx_buffer(&mut self.channel))
+    }
+}
+
+impl<T: Packet, C: AsyncPacketChannel<T>> PacketChannel<T> for BlockingAdapter<C> {
+    fn open(&mut self) -> ChannelResult<()> {
+        self.runtime.block_on(self.channel.open())
+    }
+
+    fn close(&mut self) -> ChannelResult<()> {
+        self.runtime.block_on(self.channel.close())
+    }
+
+    fn write_packets(&mut self, packets: Vec<T>, timeout_ms: u32) -> ChannelResult<()> {
+        self.runtime
+            .block_on(self.channel.write(packets, timeout_ms))
+    }
+
+    fn read_packets(&mut self, max: usize, timeout_ms: u32) -> ChannelResult<Vec<T>> {
+        self.runtime.block_on(self.channel.read(max, timeout_ms))
+    }
+
+    fn clear_rx_buffer(&mut self) -> ChannelResult<()> {
+        self.runtime.block_on(self.channel.clear_rx_buffer())
+    }
+
+    fn clear_tx_buffer(&mut self) -> ChannelResult<()> {
+        self.runtime.block_on(self.channel.clear_tx_buffer())
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::core::channel::{CanFrame, IsoTPChannel, IsoTPSettings};
+    use crate::hardware::virtual_bus::{VirtualBus, VirtualBusDevice};
+    use crate::hardware::Hardware;
+
+    #[tokio::test(flavor = "multi_thread")]
+    pub async fn test_async_packet_read_cancelled() {
+        let bus = VirtualBus::new();
+        let mut peer = bus.attach();
+        peer.open().unwrap();
+        let mut channel = AsyncPacketAdapter::new(bus.attach());
+        channel.open().await.unwrap();
+
+        // Cancelled reads must not lose a frame that arrives afterwards, or whilst
+        // a blocking read is still in flight
+        let mut received = vec![];
+        for i in 0..20u8 {
+            let read = tokio::time::timeout(Duration::from_millis(3), channel.read(1, 1000));
+            if let Ok(frames) = read.await {
+                received.extend(frames.unwrap().iter().map(|f| f.get_data()[0]));
+            }
+            peer.write_packets(vec![CanFrame::new(0x7E8, &[i], false)], 0)
+                .unwrap();
+        }
+        while received.len() < 20 {
+            let frames = channel.read(20, 100).await.unwrap();
+            received.extend(frames.iter().map(|f| f.get_data()[0]));
+        }
+        assert_eq!(received, (0..20).collect::<Vec<u8>>());
+
+        // A read without data times out like the blocking channel does
+        let start = Instant::now();
+        assert!(matches!(
+            channel.read(1, 50).await,
+            Err(ChannelError::BufferEmpty)
+        ));
+        assert!(start.elapsed() >= Duration::from_millis(50));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    pub async fn test_async_payload_round_trip() {
+        let bus = VirtualBus::new();
+        let cfg = IsoTPSettings::default();
+        let mut ecu = VirtualBusDevice::new(&bus, "ECU")
+            .create_iso_tp_channel(false)
+            .unwrap();
+        ecu.set_iso_tp_cfg(cfg).unwrap();
+        ecu.set_ids(0x7E8, 0x7E0).unwrap();
+        ecu.open().unwrap();
+        let mut ecu = AsyncPayloadAdapter::new(ecu);
+
+        let mut tester = VirtualBusDevice::new(&bus, "Tester")
+            .create_iso_tp_channel(false)
+            .unwrap();
+        tester.set_iso_tp_cfg(cfg).unwrap();
+        let mut tester = BlockingAdapter::new(AsyncPayloadAdapter::new(tester), Handle::current());
+
+        let request: Vec<u8> = (0..100).collect();
+        let expected = request.clone();
+        let tester_thread = tokio::task::spawn_blocking(move || {
+            tester.set_ids(0x7E0, 0x7E8).unwrap();
+            tester.open().unwrap();
+            tester.read_write_bytes(0x7E0, None, &request, 100, 1000)
+        });
+
+        let req = ecu.read(1000).await.unwrap();
+        assert_eq!(req, expected);
+        ecu.write(0x7E8, None, &[0x7E, 0x00], 100).await.unwrap();
+        assert_eq!(tester_thread.await.unwrap().unwrap(), [0x7E, 0x00]);
+    }
+}
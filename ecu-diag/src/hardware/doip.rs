@@ -0,0 +1,828 @@
+//! Diagnostics over IP (ISO 13400-2) transport
+//!
+//! [DoIpChannel] is a [PayloadChannel] over the DoIP TCP data port of a DoIP entity
+//! (EG: the IMX / VCU over the bike's Ethernet or USB-Ethernet link).
+//! Vehicles on the network can be found with [discover_vehicles], which
+//! sends a vehicle identification request over UDP.
+//! [UDSClientSession::new_doip](crate::uds::UDSClientSession::new_doip) runs a UDS client over it.
+//!
+//! The IDs used by [PayloadChannel::set_ids] are DoIP logical addresses:
+//! * send - Logical address of the target ECU
+//! * recv - Logical address the ECU responds with (Usually the same as send)
+
+use std::{
+    collections::VecDeque,
+    io::{ErrorKind, Read, Write},
+    net::{SocketAddr, TcpStream, UdpSocket},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use crate::core::channel::{ChannelError, ChannelResult, PayloadChannel};
+
+use super::HardwareError;
+
+/// UDP discovery and TCP data port of a DoIP entity
+pub const DOIP_PORT: u16 = 13400;
+
+/// Size of the generic DoIP header
+const HEADER_LEN: usize = 8;
+
+/// Largest payload accepted from a DoIP entity
+pub const MAX_PAYLOAD_LEN: usize = 0x10000;
+
+/// Generic header NACK code for a message that is larger than [MAX_PAYLOAD_LEN]
+const NACK_MESSAGE_TOO_LARGE: u8 = 0x02;
+
+/// Routing activation response code for a successful activation
+const ROUTING_SUCCESS: u8 = 0x10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u16)]
+/// DoIP payload types
+pub enum DoIpPayloadType {
+    GenericNack = 0x0000,
+    VehicleIdRequest = 0x0001,
+    VehicleIdRequestEid = 0x0002,
+    VehicleIdRequestVin = 0x0003,
+    VehicleAnnouncement = 0x0004,
+    RoutingActivationRequest = 0x0005,
+    RoutingActivationResponse = 0x0006,
+    AliveCheckRequest = 0x0007,
+    AliveCheckResponse = 0x0008,
+    EntityStatusRequest = 0x4001,
+    EntityStatusResponse = 0x4002,
+    PowerModeRequest = 0x4003,
+    PowerModeResponse = 0x4004,
+    DiagnosticMessage = 0x8001,
+    DiagnosticMessageAck = 0x8002,
+    DiagnosticMessageNack = 0x8003,
+}
+
+impl DoIpPayloadType {
+    pub fn from_u16(x: u16) -> Option<Self> {
+        let ty = match x {
+            0x0000 => Self::GenericNack,
+            0x0001 => Self::VehicleIdRequest,
+            0x0002 => Self::VehicleIdRequestEid,
+            0x0003 => Self::VehicleIdRequestVin,
+            0x0004 => Self::VehicleAnnouncement,
+            0x0005 => Self::RoutingActivationRequest,
+            0x0006 => Self::RoutingActivationResponse,
+            0x0007 => Self::AliveCheckRequest,
+            0x0008 => Self::AliveCheckResponse,
+            0x4001 => Self::EntityStatusRequest,
+            0x4002 => Self::EntityStatusResponse,
+            0x4003 => Self::PowerModeRequest,
+            0x4004 => Self::PowerModeResponse,
+            0x8001 => Self::DiagnosticMessage,
+            0x8002 => Self::DiagnosticMessageAck,
+            0x8003 => Self::DiagnosticMessageNack,
+            _ => return None,
+        };
+        Some(ty)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// A single DoIP message (Generic header + payload)
+pub struct DoIpMessage {
+    pub version: u8,
+    pub payload_type: u16,
+    pub payload: Vec<u8>,
+}
+
+impl DoIpMessage {
+    pub fn new(version: u8, payload_type: DoIpPayloadType, payload: Vec<u8>) -> Self {
+        Self {
+            version,
+            payload_type: payload_type as u16,
+            payload,
+        }
+    }
+
+    /// Returns the payload type, if it is known
+    pub fn get_type(&self) -> Option<DoIpPayloadType> {
+        DoIpPayloadType::from_u16(self.payload_type)
+    }
+
+    /// Serializes the message, including its generic header
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(HEADER_LEN + self.payload.len());
+        buf.push(self.version);
+        buf.push(!self.version);
+        buf.extend_from_slice(&self.payload_type.to_be_bytes());
+        buf.extend_from_slice(&(self.payload.len() as u32).to_be_bytes());
+        buf.extend_from_slice(&self.payload);
+        buf
+    }
+
+    /// Attempts to parse a message from the start of `buf`.
+    ///
+    /// ## Returns
+    /// * Ok(None) if `buf` does not hold a complete message yet
+    /// * Ok(Some((msg, len))) with the message and the number of bytes it used
+    /// * Err(nack_code) if the generic header is invalid, or the payload is larger than [MAX_PAYLOAD_LEN]
+    pub fn from_bytes(buf: &[u8]) -> Result<Option<(Self, usize)>, u8> {
+        if buf.len() < HEADER_LEN {
+            return Ok(None);
+        }
+        if buf[0] != !buf[1] {
+            // Incorrect pattern format
+            return Err(0x00);
+        }
+        let payload_type = u16::from_be_bytes([buf[2], buf[3]]);
+        let len = u32::from_be_bytes([buf[4], buf[5], buf[6], buf[7]]) as usize;
+        if len > MAX_PAYLOAD_LEN {
+            return Err(NACK_MESSAGE_TOO_LARGE);
+        }
+        if buf.len() < HEADER_LEN + len {
+            return Ok(None);
+        }
+        let msg = Self {
+            version: buf[0],
+            payload_type,
+            payload: buf[HEADER_LEN..HEADER_LEN + len].to_vec(),
+        };
+        Ok(Some((msg, HEADER_LEN + len)))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Vehicle announcement / identification response of a DoIP entity
+pub struct DoIpVehicleInfo {
+    /// Address the response was received from
+    pub addr: SocketAddr,
+    pub vin: String,
+    /// Logical address of the DoIP entity
+    pub logical_address: u16,
+    /// Entity ID (Usually the MAC address)
+    pub eid: [u8; 6],
+    /// Group ID
+    pub gid: [u8; 6],
+    pub further_action: u8,
+}
+
+impl DoIpVehicleInfo {
+    fn from_payload(addr: SocketAddr, p: &[u8]) -> Option<Self> {
+        if p.len() < 32 {
+            return None;
+        }
+        let mut eid = [0; 6];
+        let mut gid = [0; 6];
+        eid.copy_from_slice(&p[19..25]);
+        gid.copy_from_slice(&p[25..31]);
+        Some(Self {
+            addr,
+            vin: String::from_utf8_lossy(&p[0..17]).to_string(),
+            logical_address: u16::from_be_bytes([p[17], p[18]]),
+            eid,
+            gid,
+            further_action: p[31],
+        })
+    }
+}
+
+/// Sends a vehicle identification request to `target` (A broadcast address such as
+/// `255.255.255.255:13400`, or a single entity) and collects every response received within `timeout_ms`
+pub fn discover_vehicles(
+    target: SocketAddr,
+    timeout_ms: u32,
+) -> ChannelResult<Vec<DoIpVehicleInfo>> {
+    let socket = UdpSocket::bind("0.0.0.0:0").map_err(io_error)?;
+    socket.set_broadcast(true).map_err(io_error)?;
+    let req = DoIpMessage::new(0xFF, DoIpPayloadType::VehicleIdRequest, vec![]);
+    socket.send_to(&req.to_bytes(), target).map_err(io_error)?;
+
+    let mut res: Vec<DoIpVehicleInfo> = vec![];
+    let mut buf = [0u8; 512];
+    let start = Instant::now();
+    let timeout = Duration::from_millis(timeout_ms as u64);
+    while let Some(remaining) = timeout.checked_sub(start.elapsed()) {
+        if remaining.is_zero() {
+            break;
+        }
+        socket.set_read_timeout(Some(remaining)).map_err(io_error)?;
+        let (len, addr) = match socket.recv_from(&mut buf) {
+            Ok(x) => x,
+            Err(e) if is_timeout(&e) => break,
+            Err(e) => return Err(io_error(e)),
+        };
+        if let Ok(Some((msg, _))) = DoIpMessage::from_bytes(&buf[..len]) {
+            if msg.get_type() == Some(DoIpPayloadType::VehicleAnnouncement) {
+                if let Some(info) = DoIpVehicleInfo::from_payload(addr, &msg.payload) {
+                    if !res.contains(&info) {
+                        res.push(info);
+                    }
+                }
+            }
+        }
+    }
+    Ok(res)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// DoIP channel configuration
+pub struct DoIpSettings {
+    /// Protocol version used in the generic header (0x02 - ISO 13400-2:2012, 0x03 - ISO 13400-2:2019)
+    pub protocol_version: u8,
+    /// Logical address of this tester
+    pub tester_address: u16,
+    /// Routing activation type (0x00 - Default, 0x01 - WWH-OBD)
+    pub activation_type: u8,
+    /// Timeout for connecting and routing activation
+    pub connect_timeout_ms: u32,
+}
+
+impl Default for DoIpSettings {
+    fn default() -> Self {
+        Self {
+            protocol_version: 0x02,
+            tester_address: 0x0E00,
+            activation_type: 0x00,
+            connect_timeout_ms: 2000,
+        }
+    }
+}
+
+#[derive(Debug)]
+/// DoIP client channel to a single DoIP entity
+pub struct DoIpChannel {
+    addr: SocketAddr,
+    cfg: DoIpSettings,
+    stream: Option<TcpStream>,
+    target_address: u16,
+    source_address: u16,
+    entity_address: Option<u16>,
+    rx_buffer: Vec<u8>,
+    /// Bytes left of a message that was too large to be received
+    rx_skip: usize,
+    rx_queue: VecDeque<Vec<u8>>,
+}
+
+impl DoIpChannel {
+    /// Creates a new DoIP channel to the DoIP entity at `addr`.
+    /// The TCP connection is made when the channel is opened
+    pub fn new(addr: SocketAddr, cfg: DoIpSettings) -> Self {
+        Self {
+            addr,
+            cfg,
+            stream: None,
+            target_address: 0,
+            source_address: 0,
+            entity_address: None,
+            rx_buffer: vec![],
+            rx_skip: 0,
+            rx_queue: VecDeque::new(),
+        }
+    }
+
+    /// Returns the logical address of the DoIP entity, once routing is activated
+    pub fn get_entity_address(&self) -> Option<u16> {
+        self.entity_address
+    }
+
+    fn send_msg(&mut self, payload_type: DoIpPayloadType, payload: Vec<u8>) -> ChannelResult<()> {
+        let msg = DoIpMessage::new(self.cfg.protocol_version, payload_type, payload);
+        let stream = self.stream.as_mut().ok_or(ChannelError::InterfaceNotOpen)?;
+        stream.write_all(&msg.to_bytes()).map_err(io_error)
+    }
+
+    /// Reads the next message from the entity, answering alive checks and
+    /// queuing diagnostic messages for [PayloadChannel::read_bytes] on the way.
+    /// Returns Ok(None) once a diagnostic message is queued, or if nothing arrived before `deadline`
+    fn poll_msg(&mut self, deadline: Instant) -> ChannelResult<Option<DoIpMessage>> {
+        loop {
+            let skip = self.rx_skip.min(self.rx_buffer.len());
+            self.rx_buffer.drain(..skip);
+            self.rx_skip -= skip;
+            match DoIpMessage::from_bytes(&self.rx_buffer) {
+                Ok(Some((msg, len))) => {
+                    self.rx_buffer.drain(..len);
+                    if let Some(msg) = self.handle_msg(msg)? {
+                        return Ok(Some(msg));
+                    } else if !self.rx_queue.is_empty() {
+                        return Ok(None);
+                    }
+                    continue;
+                }
+                Ok(None) => {}
+                Err(NACK_MESSAGE_TOO_LARGE) => {
+                    // Discard the message, the connection stays usable
+                    let b = &self.rx_buffer;
+                    self.rx_skip =
+                        HEADER_LEN + u32::from_be_bytes([b[4], b[5], b[6], b[7]]) as usize;
+                    let _ =
+                        self.send_msg(DoIpPayloadType::GenericNack, vec![NACK_MESSAGE_TOO_LARGE]);
+                    return Err(doip_error(NACK_MESSAGE_TOO_LARGE, "DoIP message too large"));
+                }
+                Err(code) => {
+                    self.rx_buffer.clear();
+                    let _ = self.send_msg(DoIpPayloadType::GenericNack, vec![code]);
+                    return Err(doip_error(code, "Invalid DoIP header received"));
+                }
+            }
+
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            let stream = self.stream.as_mut().ok_or(ChannelError::InterfaceNotOpen)?;
+            // A zero duration is not a valid socket timeout
+            stream
+                .set_read_timeout(Some(remaining.max(Duration::from_millis(1))))
+                .map_err(io_error)?;
+            let mut buf = [0u8; 4096];
+            match stream.read(&mut buf) {
+                Ok(0) => {
+                    self.stream = None;
+                    return Err(io_error(std::io::Error::from(ErrorKind::ConnectionReset)));
+                }
+                Ok(len) => self.rx_buffer.extend_from_slice(&buf[..len]),
+                Err(e) if is_timeout(&e) => {
+                    if Instant::now() >= deadline {
+                        return Ok(None);
+                    }
+                }
+                Err(e) => return Err(io_error(e)),
+            }
+        }
+    }
+
+    /// Handles messages that do not need a response from the caller.
+    /// Returns the message back if it is not one of those
+    fn handle_msg(&mut self, msg: DoIpMessage) -> ChannelResult<Option<DoIpMessage>> {
+        match msg.get_type() {
+            Some(DoIpPayloadType::AliveCheckRequest) => {
+                log::debug!("DoIP alive check request");
+                let addr = self.cfg.tester_address.to_be_bytes().to_vec();
+                self.send_msg(DoIpPayloadType::AliveCheckResponse, addr)?;
+                Ok(None)
+            }
+            Some(DoIpPayloadType::GenericNack) => {
+                let code = msg.payload.first().copied().unwrap_or(0xFF);
+                Err(doip_error(code, "DoIP generic header NACK"))
+            }
+            Some(DoIpPayloadType::DiagnosticMessage) if msg.payload.len() >= 4 => {
+                let source = u16::from_be_bytes([msg.payload[0], msg.payload[1]]);
+                let target = u16::from_be_bytes([msg.payload[2], msg.payload[3]]);
+                if source == self.source_address && target == self.cfg.tester_address {
+                    log::debug!("DoIP Rx from 0x{source:04X}: {:02X?}", &msg.payload[4..]);
+                    self.rx_queue.push_back(msg.payload[4..].to_vec());
+                } else {
+                    log::debug!("DoIP ignoring message 0x{source:04X} -> 0x{target:04X}");
+                }
+                Ok(None)
+            }
+            _ => Ok(Some(msg)),
+        }
+    }
+
+    fn activate_routing(&mut self) -> ChannelResult<()> {
+        let mut req = self.cfg.tester_address.to_be_bytes().to_vec();
+        req.push(self.cfg.activation_type);
+        req.extend_from_slice(&[0; 4]); // Reserved
+        self.send_msg(DoIpPayloadType::RoutingActivationRequest, req)?;
+
+        let deadline = Instant::now() + Duration::from_millis(self.cfg.connect_timeout_ms as u64);
+        loop {
+            let msg = match self.poll_msg(deadline)? {
+                Some(msg) => msg,
+                None if Instant::now() < deadline => continue,
+                None => return Err(ChannelError::ReadTimeout),
+            };
+            if msg.get_type() != Some(DoIpPayloadType::RoutingActivationResponse) {
+                log::debug!("DoIP ignoring {msg:02X?} during routing activation");
+                continue;
+            }
+            if msg.payload.len() < 9 {
+                return Err(doip_error(0x04, "Invalid routing activation response"));
+            }
+            let code = msg.payload[4];
+            return if code == ROUTING_SUCCESS {
+                self.entity_address = Some(u16::from_be_bytes([msg.payload[2], msg.payload[3]]));
+                Ok(())
+            } else {
+                Err(doip_error(code, routing_activation_desc(code)))
+            };
+        }
+    }
+}
+
+impl PayloadChannel for DoIpChannel {
+    fn open(&mut self) -> ChannelResult<()> {
+        if self.stream.is_some() {
+            return Ok(());
+        }
+        let stream = TcpStream::connect_timeout(
+            &self.addr,
+            Duration::from_millis(self.cfg.connect_timeout_ms as u64),
+        )
+        .map_err(io_error)?;
+        stream.set_nodelay(true).map_err(io_error)?;
+        self.stream = Some(stream);
+        self.rx_buffer.clear();
+        self.rx_skip = 0;
+        self.rx_queue.clear();
+        if let Err(e) = self.activate_routing() {
+            self.stream = None;
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    fn close(&mut self) -> ChannelResult<()> {
+        if let Some(stream) = self.stream.take() {
+            let _ = stream.shutdown(std::net::Shutdown::Both);
+        }
+        self.entity_address = None;
+        Ok(())
+    }
+
+    fn set_ids(&mut self, send: u32, recv: u32) -> ChannelResult<()> {
+        self.target_address = send as u16;
+        self.source_address = recv as u16;
+        Ok(())
+    }
+
+    fn read_bytes(&mut self, timeout_ms: u32) -> ChannelResult<Vec<u8>> {
+        let deadline = Instant::now() + Duration::from_millis(timeout_ms as u64);
+        loop {
+            if let Some(data) = self.rx_queue.pop_front() {
+                return Ok(data);
+            }
+            match self.poll_msg(deadline)? {
+                Some(msg) => log::debug!("DoIP ignoring {msg:02X?}"),
+                None if !self.rx_queue.is_empty() => {}
+                None if timeout_ms == 0 => return Err(ChannelError::BufferEmpty),
+                None => return Err(ChannelError::ReadTimeout),
+            }
+        }
+    }
+
+    fn write_bytes(
+        &mut self,
+        addr: u32,
+        ext_id: Option<u8>,
+        buffer: &[u8],
+        timeout_ms: u32,
+    ) -> ChannelResult<()> {
+        if ext_id.is_some() {
+            return Err(ChannelError::UnsupportedRequest);
+        }
+        let mut payload = self.cfg.tester_address.to_be_bytes().to_vec();
+        payload.extend_from_slice(&(addr as u16).to_be_bytes());
+        payload.extend_from_slice(buffer);
+        self.send_msg(DoIpPayloadType::DiagnosticMessage, payload)?;
+        if timeout_ms == 0 {
+            return Ok(());
+        }
+
+        // Wait for the entity to acknowledge the diagnostic message
+        let deadline = Instant::now() + Duration::from_millis(timeout_ms as u64);
+        loop {
+            let msg = match self.poll_msg(deadline)? {
+                Some(msg) => msg,
+                None if Instant::now() < deadline => continue,
+                None => return Err(ChannelError::WriteTimeout),
+            };
+            match msg.get_type() {
+                Some(DoIpPayloadType::DiagnosticMessageAck) => return Ok(()),
+                Some(DoIpPayloadType::DiagnosticMessageNack) => {
+                    let code = msg.payload.get(4).copied().unwrap_or(0xFF);
+                    return Err(doip_error(code, diag_nack_desc(code)));
+                }
+                _ => log::debug!("DoIP ignoring {msg:02X?} whilst awaiting ACK"),
+            }
+        }
+    }
+
+    fn clear_rx_buffer(&mut self) -> ChannelResult<()> {
+        if self.stream.is_some() {
+            loop {
+                self.rx_queue.clear();
+                if self.poll_msg(Instant::now())?.is_none() && self.rx_queue.is_empty() {
+                    break;
+                }
+            }
+        }
+        self.rx_queue.clear();
+        Ok(())
+    }
+
+    fn clear_tx_buffer(&mut self) -> ChannelResult<()> {
+        Ok(())
+    }
+}
+
+impl Drop for DoIpChannel {
+    fn drop(&mut self) {
+        let _ = self.close();
+    }
+}
+
+fn routing_activation_desc(code: u8) -> &'static str {
+    match code {
+        0x00 => "Routing activation denied - Unknown source address",
+        0x01 => "Routing activation denied - All sockets are registered and active",
+        0x02 => "Routing activation denied - Source address already activated on another socket",
+        0x03 => "Routing activation denied - Source address already registered",
+        0x04 => "Routing activation denied - Missing authentication",
+        0x05 => "Routing activation denied - Rejected confirmation",
+        0x06 => "Routing activation denied - Unsupported activation type",
+        0x11 => "Routing activation requires confirmation",
+        _ => "Routing activation denied",
+    }
+}
+
+fn diag_nack_desc(code: u8) -> &'static str {
+    match code {
+        0x02 => "Diagnostic message NACK - Invalid source address",
+        0x03 => "Diagnostic message NACK - Unknown target address",
+        0x04 => "Diagnostic message NACK - Diagnostic message too large",
+        0x05 => "Diagnostic message NACK - Out of memory",
+        0x06 => "Diagnostic message NACK - Target unreachable",
+        0x07 => "Diagnostic message NACK - Unknown network",
+        0x08 => "Diagnostic message NACK - Transport protocol error",
+        _ => "Diagnostic message NACK",
+    }
+}
+
+fn doip_error(code: u8, desc: &str) -> ChannelError {
+    ChannelError::HardwareError(HardwareError::APIError {
+        code: code as u32,
+        desc: desc.to_string(),
+    })
+}
+
+fn io_error(e: std::io::Error) -> ChannelError {
+    ChannelError::IOError(Arc::new(e))
+}
+
+fn is_timeout(e: &std::io::Error) -> bool {
+    matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut)
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use std::net::TcpListener;
+
+    pub const ENTITY_ADDR: u16 = 0x1001;
+
+    /// Minimal DoIP entity. Answers vehicle identification requests over UDP,
+    /// and serves a single tester over TCP, echoing diagnostic requests back as positive responses
+    pub struct TestEntity {
+        pub tcp_addr: SocketAddr,
+        pub udp_addr: SocketAddr,
+    }
+
+    impl TestEntity {
+        pub fn start(routing_code: u8, nack_target: Option<u16>, alive_check: bool) -> Self {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let udp = UdpSocket::bind("127.0.0.1:0").unwrap();
+            let tcp_addr = listener.local_addr().unwrap();
+            let udp_addr = udp.local_addr().unwrap();
+
+            std::thread::spawn(move || {
+                let mut buf = [0u8; 512];
+                if let Ok((len, from)) = udp.recv_from(&mut buf) {
+                    let (req, _) = DoIpMessage::from_bytes(&buf[..len]).unwrap().unwrap();
+                    assert_eq!(req.get_type(), Some(DoIpPayloadType::VehicleIdRequest));
+                    let mut p = b"ION0TESTVIN000001".to_vec();
+                    p.extend_from_slice(&ENTITY_ADDR.to_be_bytes());
+                    p.extend_from_slice(&[1, 2, 3, 4, 5, 6]);
+                    p.extend_from_slice(&[0; 6]);
+                    p.push(0x00);
+                    let resp = DoIpMessage::new(0x02, DoIpPayloadType::VehicleAnnouncement, p);
+                    udp.send_to(&resp.to_bytes(), from).unwrap();
+                }
+            });
+
+            std::thread::spawn(move || {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut rx: Vec<u8> = vec![];
+                let mut buf = [0u8; 4096];
+                loop {
+                    let msg = match DoIpMessage::from_bytes(&rx).unwrap() {
+                        Some((msg, len)) => {
+                            rx.drain(..len);
+                            msg
+                        }
+                        None => match stream.read(&mut buf) {
+                            Ok(0) | Err(_) => return,
+                            Ok(len) => {
+                                rx.extend_from_slice(&buf[..len]);
+                                continue;
+                            }
+                        },
+                    };
+                    let p = &msg.payload;
+                    match msg.get_type().unwrap() {
+                        DoIpPayloadType::RoutingActivationRequest => {
+                            let mut resp = p[0..2].to_vec();
+                            resp.extend_from_slice(&ENTITY_ADDR.to_be_bytes());
+                            resp.push(routing_code);
+                            resp.extend_from_slice(&[0; 4]);
+                            let resp = DoIpMessage::new(
+                                0x02,
+                                DoIpPayloadType::RoutingActivationResponse,
+                                resp,
+                            );
+                            stream.write_all(&resp.to_bytes()).unwrap();
+                        }
+                        DoIpPayloadType::DiagnosticMessage => {
+                            let target = u16::from_be_bytes([p[2], p[3]]);
+                            let mut ack = p[2..4].to_vec();
+                            ack.extend_from_slice(&p[0..2]);
+                            if Some(target) == nack_target {
+                                ack.push(0x03);
+                                let nack = DoIpMessage::new(
+                                    0x02,
+                                    DoIpPayloadType::DiagnosticMessageNack,
+                                    ack,
+                                );
+                                stream.write_all(&nack.to_bytes()).unwrap();
+                                continue;
+                            }
+                            ack.push(0x00);
+                            let mut out =
+                                DoIpMessage::new(0x02, DoIpPayloadType::DiagnosticMessageAck, ack)
+                                    .to_bytes();
+                            if alive_check {
+                                out.extend_from_slice(
+                                    &DoIpMessage::new(
+                                        0x02,
+                                        DoIpPayloadType::AliveCheckRequest,
+                                        vec![],
+                                    )
+                                    .to_bytes(),
+                                );
+                            }
+                            let mut resp = p[2..4].to_vec();
+                            resp.extend_from_slice(&p[0..2]);
+                            resp.push(p[4] + 0x40);
+                            resp.extend_from_slice(&p[5..]);
+                            out.extend_from_slice(
+                                &DoIpMessage::new(0x02, DoIpPayloadType::DiagnosticMessage, resp)
+                                    .to_bytes(),
+                            );
+                            stream.write_all(&out).unwrap();
+                        }
+                        DoIpPayloadType::AliveCheckResponse => {
+                            // Report the tester address back as a diagnostic message
+                            let mut resp = ENTITY_ADDR.to_be_bytes().to_vec();
+                            resp.extend_from_slice(p);
+                            resp.extend_from_slice(p);
+                            resp.push(0xAA);
+                            let resp =
+                                DoIpMessage::new(0x02, DoIpPayloadType::DiagnosticMessage, resp);
+                            stream.write_all(&resp.to_bytes()).unwrap();
+                        }
+                        _ => {}
+                    }
+                }
+            });
+
+            Self { tcp_addr, udp_addr }
+        }
+
+        fn channel(&self) -> DoIpChannel {
+            let mut ch = DoIpChannel::new(self.tcp_addr, DoIpSettings::default());
+            ch.set_ids(ENTITY_ADDR as u32, ENTITY_ADDR as u32).unwrap();
+            ch
+        }
+    }
+
+    #[test]
+    pub fn test_header() {
+        let msg = DoIpMessage::new(0x02, DoIpPayloadType::DiagnosticMessage, vec![1, 2, 3]);
+        let bytes = msg.to_bytes();
+        assert_eq!(bytes, [0x02, 0xFD, 0x80, 0x01, 0, 0, 0, 3, 1, 2, 3]);
+        assert_eq!(DoIpMessage::from_bytes(&bytes[..10]), Ok(None));
+        assert_eq!(DoIpMessage::from_bytes(&bytes), Ok(Some((msg, 11))));
+        assert_eq!(
+            DoIpMessage::from_bytes(&[0x02, 0x02, 0, 0, 0, 0, 0, 0]),
+            Err(0x00)
+        );
+        // Rejected before the payload is received
+        assert_eq!(
+            DoIpMessage::from_bytes(&[0x02, 0xFD, 0x80, 0x01, 0xFF, 0xFF, 0xFF, 0xFF]),
+            Err(0x02)
+        );
+        let mut max = DoIpMessage::new(0x02, DoIpPayloadType::DiagnosticMessage, vec![]).to_bytes();
+        max[4..8].copy_from_slice(&(MAX_PAYLOAD_LEN as u32).to_be_bytes());
+        assert_eq!(DoIpMessage::from_bytes(&max), Ok(None));
+    }
+
+    #[test]
+    pub fn test_message_too_large() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let entity = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut buf = [0u8; 64];
+            let len = stream.read(&mut buf).unwrap();
+            let (req, _) = DoIpMessage::from_bytes(&buf[..len]).unwrap().unwrap();
+            let mut resp = req.payload[0..2].to_vec();
+            resp.extend_from_slice(&ENTITY_ADDR.to_be_bytes());
+            resp.push(ROUTING_SUCCESS);
+            resp.extend_from_slice(&[0; 4]);
+            let resp = DoIpMessage::new(0x02, DoIpPayloadType::RoutingActivationResponse, resp);
+            stream.write_all(&resp.to_bytes()).unwrap();
+
+            // Oversized message, followed by a valid response
+            let tester = DoIpSettings::default().tester_address.to_be_bytes();
+            let mut p = ENTITY_ADDR.to_be_bytes().to_vec();
+            p.extend_from_slice(&tester);
+            p.resize(MAX_PAYLOAD_LEN + 1, 0xAA);
+            let mut out = DoIpMessage::new(0x02, DoIpPayloadType::DiagnosticMessage, p).to_bytes();
+            let mut p = ENTITY_ADDR.to_be_bytes().to_vec();
+            p.extend_from_slice(&tester);
+            p.extend_from_slice(&[0x7E, 0x00]);
+            out.extend(DoIpMessage::new(0x02, DoIpPayloadType::DiagnosticMessage, p).to_bytes());
+            stream.write_all(&out).unwrap();
+
+            let len = stream.read(&mut buf).unwrap();
+            DoIpMessage::from_bytes(&buf[..len]).unwrap().unwrap().0
+        });
+
+        let mut ch = DoIpChannel::new(addr, DoIpSettings::default());
+        ch.set_ids(ENTITY_ADDR as u32, ENTITY_ADDR as u32).unwrap();
+        ch.open().unwrap();
+        assert!(matches!(
+            ch.read_bytes(1000),
+            Err(ChannelError::HardwareError(HardwareError::APIError {
+                code: 0x02,
+                ..
+            }))
+        ));
+        assert_eq!(ch.read_bytes(1000).unwrap(), [0x7E, 0x00]);
+        let nack = entity.join().unwrap();
+        assert_eq!(nack.get_type(), Some(DoIpPayloadType::GenericNack));
+        assert_eq!(nack.payload, [0x02]);
+    }
+
+    #[test]
+    pub fn test_discover_vehicles() {
+        let entity = TestEntity::start(ROUTING_SUCCESS, None, false);
+        let found = discover_vehicles(entity.udp_addr, 500).unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].vin, "ION0TESTVIN000001");
+        assert_eq!(found[0].logical_address, ENTITY_ADDR);
+        assert_eq!(found[0].eid, [1, 2, 3, 4, 5, 6]);
+    }
+
+    #[test]
+    pub fn test_diagnostic_message() {
+        let entity = TestEntity::start(ROUTING_SUCCESS, None, false);
+        let mut ch = entity.channel();
+        ch.open().unwrap();
+        assert_eq!(ch.get_entity_address(), Some(ENTITY_ADDR));
+
+        let resp = ch
+            .read_write_bytes(ENTITY_ADDR as u32, None, &[0x22, 0xF1, 0x90], 1000, 1000)
+            .unwrap();
+        assert_eq!(resp, [0x62, 0xF1, 0x90]);
+        assert!(matches!(ch.read_bytes(0), Err(ChannelError::BufferEmpty)));
+    }
+
+    #[test]
+    pub fn test_diagnostic_message_nack() {
+        let entity = TestEntity::start(ROUTING_SUCCESS, Some(0x2002), false);
+        let mut ch = entity.channel();
+        ch.open().unwrap();
+        let res = ch.write_bytes(0x2002, None, &[0x3E, 0x00], 1000);
+        assert!(matches!(
+            res,
+            Err(ChannelError::HardwareError(HardwareError::APIError {
+                code: 0x03,
+                ..
+            }))
+        ));
+    }
+
+    #[test]
+    pub fn test_alive_check() {
+        let entity = TestEntity::start(ROUTING_SUCCESS, None, true);
+        let mut ch = entity.channel();
+        ch.open().unwrap();
+        let resp = ch
+            .read_write_bytes(ENTITY_ADDR as u32, None, &[0x3E, 0x00], 1000, 1000)
+            .unwrap();
+        assert_eq!(resp, [0x7E, 0x00]);
+        // Entity reports the tester address it got in the alive check response
+        let tester = DoIpSettings::default().tester_address.to_be_bytes();
+        assert_eq!(ch.read_bytes(1000).unwrap(), [tester[0], tester[1], 0xAA]);
+    }
+
+    #[test]
+    pub fn test_routing_activation_denied() {
+        let entity = TestEntity::start(0x00, None, false);
+        let mut ch = entity.channel();
+        assert!(matches!(
+            ch.open(),
+            Err(ChannelError::HardwareError(HardwareError::APIError {
+                code: 0x00,
+                ..
+            }))
+        ));
+    }
+}
@@ -0,0 +1,544 @@
+//! Fault injection for transport robustness testing
+//!
+//! [FaultInjectionChannel] wraps any CAN channel and drops, duplicates, reorders, delays or
+//! corrupts the frames passing through it, either at random with [FaultProbabilities] or
+//! exactly where a test needs it with [FaultInjector::schedule]. Errors returned by the channel
+//! (Timeouts, full buffers) and the bus status it reports can be injected as well.
+//!
+//! Faults are controlled through a [FaultInjector], which can be changed while the channel is
+//! in use, EG by an [IsoTpProtocol](super::isotp::IsoTpProtocol) that owns it. Random faults
+//! come from a seeded generator, so a failing test run can be reproduced.
+//!
+//! There is no background thread: delayed frames are delivered by the next read or write
+//! on the channel once they are due.
+
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use crate::core::channel::{
+    CanBusStatus, CanChannel, CanFrame, ChannelError, ChannelResult, FilterPacketChannel, Packet,
+    PacketChannel,
+};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+/// Direction of the frames a fault applies to
+pub enum FaultDirection {
+    /// Frames written to the channel
+    Tx,
+    /// Frames read from the channel
+    Rx,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+/// Fault applied to a single frame
+pub enum Fault {
+    /// The frame is lost
+    Drop,
+    /// The frame is delivered twice
+    Duplicate,
+    /// The frame is held back, and delivered after the next frame in the same direction
+    Reorder,
+    /// The frame is delivered this many milliseconds late
+    Delay(u32),
+    /// Byte `index` of the frame data is XORed with `mask`.
+    /// Frames shorter than `index` are left as they are
+    Corrupt { index: usize, mask: u8 },
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+/// Probability (0.0 - 1.0) of each fault, per frame. At most one fault is applied to a frame
+pub struct FaultProbabilities {
+    pub drop: f64,
+    pub duplicate: f64,
+    pub reorder: f64,
+    pub delay: f64,
+    /// How late delayed frames are delivered
+    pub delay_ms: u32,
+    /// Corrupted frames get one random bit of their data flipped
+    pub corrupt: f64,
+}
+
+#[derive(Debug, Clone)]
+struct ScheduledFault {
+    direction: FaultDirection,
+    id: Option<u32>,
+    /// Matching frames still to let through before the fault applies
+    remaining: usize,
+    fault: Fault,
+}
+
+#[derive(Debug)]
+struct InjectorState {
+    rng: u64,
+    tx: FaultProbabilities,
+    rx: FaultProbabilities,
+    scheduled: Vec<ScheduledFault>,
+    write_errors: VecDeque<ChannelError>,
+    read_errors: VecDeque<ChannelError>,
+    bus_status: Option<CanBusStatus>,
+}
+
+impl InjectorState {
+    /// xorshift64*, good enough to pick faults and reproducible from the seed
+    fn next_u64(&mut self) -> u64 {
+        self.rng ^= self.rng >> 12;
+        self.rng ^= self.rng << 25;
+        self.rng ^= self.rng >> 27;
+        self.rng.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    fn chance(&mut self, p: f64) -> bool {
+        // 53 random bits, uniform in [0, 1)
+        let x = (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64;
+        x < p
+    }
+
+    /// Picks the fault for a frame, scheduled faults first
+    fn fault_for(&mut self, direction: FaultDirection, frame: &CanFrame) -> Option<Fault> {
+        let mut scheduled = None;
+        self.scheduled.retain_mut(|s| {
+            if s.direction != direction || s.id.is_some_and(|id| id != frame.get_address()) {
+                return true;
+            }
+            if s.remaining > 0 {
+                s.remaining -= 1;
+                return true;
+            }
+            if scheduled.is_none() {
+                scheduled = Some(s.fault);
+                return false;
+            }
+            // Another fault applies to this frame, this one applies to the next
+            true
+        });
+        if scheduled.is_some() {
+            return scheduled;
+        }
+        let p = match direction {
+            FaultDirection::Tx => self.tx,
+            FaultDirection::Rx => self.rx,
+        };
+        if self.chance(p.drop) {
+            Some(Fault::Drop)
+        } else if self.chance(p.duplicate) {
+            Some(Fault::Duplicate)
+        } else if self.chance(p.reorder) {
+            Some(Fault::Reorder)
+        } else if self.chance(p.delay) {
+            Some(Fault::Delay(p.delay_ms))
+        } else if self.chance(p.corrupt) && !frame.get_data().is_empty() {
+            let bit = self.next_u64() as usize % (frame.get_data().len() * 8);
+            Some(Fault::Corrupt {
+                index: bit / 8,
+                mask: 1 << (bit % 8),
+            })
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+/// Handle to configure the faults of one or more [FaultInjectionChannel]s
+pub struct FaultInjector {
+    state: Arc<Mutex<InjectorState>>,
+}
+
+impl Default for FaultInjector {
+    fn default() -> Self {
+        Self::new(0x1234_5678)
+    }
+}
+
+impl FaultInjector {
+    /// Creates an injector without any faults. `seed` seeds the random faults
+    pub fn new(seed: u64) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(InjectorState {
+                // xorshift gets stuck at 0
+                rng: seed.max(1),
+                tx: FaultProbabilities::default(),
+                rx: FaultProbabilities::default(),
+                scheduled: vec![],
+                write_errors: VecDeque::new(),
+                read_errors: VecDeque::new(),
+                bus_status: None,
+            })),
+        }
+    }
+
+    /// Sets the probabilities of random faults for frames in `direction`
+    pub fn set_probabilities(&self, direction: FaultDirection, probabilities: FaultProbabilities) {
+        let mut state = self.state.lock().unwrap();
+        match direction {
+            FaultDirection::Tx => state.tx = probabilities,
+            FaultDirection::Rx => state.rx = probabilities,
+        }
+    }
+
+    /// Applies `fault` to the `nth` frame (Counting from 0) in `direction` from now on,
+    /// only counting frames with CAN ID `id` if given. If an earlier scheduled fault
+    /// already applies to that frame, `fault` applies to the next one
+    pub fn schedule(&self, direction: FaultDirection, id: Option<u32>, nth: usize, fault: Fault) {
+        self.state.lock().unwrap().scheduled.push(ScheduledFault {
+            direction,
+            id,
+            remaining: nth,
+            fault,
+        });
+    }
+
+    /// Returns the number of scheduled faults that have not been applied yet
+    pub fn scheduled_count(&self) -> usize {
+        self.state.lock().unwrap().scheduled.len()
+    }
+
+    /// The next write to the channel fails with `error`, without sending anything
+    pub fn fail_next_write(&self, error: ChannelError) {
+        self.state.lock().unwrap().write_errors.push_back(error);
+    }
+
+    /// The next read from the channel fails with `error`, without receiving anything
+    pub fn fail_next_read(&self, error: ChannelError) {
+        self.state.lock().unwrap().read_errors.push_back(error);
+    }
+
+    /// Makes the channel report `status` as its bus status, or its real one if None
+    pub fn set_bus_status(&self, status: Option<CanBusStatus>) {
+        self.state.lock().unwrap().bus_status = status;
+    }
+
+    /// Removes all faults and injected errors
+    pub fn clear(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.tx = FaultProbabilities::default();
+        state.rx = FaultProbabilities::default();
+        state.scheduled.clear();
+        state.write_errors.clear();
+        state.read_errors.clear();
+        state.bus_status = None;
+    }
+}
+
+#[derive(Debug, Default)]
+/// Frames of one direction on their way through the faults
+struct FaultQueue {
+    /// Frames delayed, with when they are due
+    delayed: Vec<(Instant, CanFrame)>,
+    /// Frame held back by [Fault::Reorder]
+    held: Option<CanFrame>,
+    ready: VecDeque<CanFrame>,
+}
+
+impl FaultQueue {
+    fn push(&mut self, frame: CanFrame, fault: Option<Fault>) {
+        let mut frame = frame;
+        match fault {
+            Some(Fault::Drop) => return,
+            Some(Fault::Reorder) if self.held.is_none() => {
+                self.held = Some(frame);
+                return;
+            }
+            Some(Fault::Delay(ms)) => {
+                let due = Instant::now() + Duration::from_millis(ms as u64);
+                self.delayed.push((due, frame));
+                return;
+            }
+            Some(Fault::Duplicate) => self.ready.push_back(frame),
+            Some(Fault::Corrupt { index, mask }) => {
+                let mut data = frame.get_data().to_vec();
+                if let Some(b) = data.get_mut(index) {
+                    *b ^= mask;
+                }
+                frame.set_data(&data);
+            }
+            _ => {}
+        }
+        self.ready.push_back(frame);
+        if let Some(held) = self.held.take() {
+            self.ready.push_back(held);
+        }
+    }
+
+    /// Moves the delayed frames that are due to the ready queue
+    fn release_due(&mut self) {
+        let now = Instant::now();
+        self.delayed.sort_by_key(|(due, _)| *due);
+        while self.delayed.first().is_some_and(|(due, _)| *due <= now) {
+            let (_, frame) = self.delayed.remove(0);
+            self.ready.push_back(frame);
+        }
+    }
+
+    fn next_due(&self) -> Option<Instant> {
+        self.delayed.iter().map(|(due, _)| *due).min()
+    }
+}
+
+/// CAN channel that injects faults into the traffic of the channel it wraps
+pub struct FaultInjectionChannel<C> {
+    inner: C,
+    injector: FaultInjector,
+    tx: FaultQueue,
+    rx: FaultQueue,
+}
+
+impl<C> FaultInjectionChannel<C> {
+    /// Wraps `inner`, with the faults configured in `injector`
+    pub fn new(inner: C, injector: FaultInjector) -> Self {
+        Self {
+            inner,
+            injector,
+            tx: FaultQueue::default(),
+            rx: FaultQueue::default(),
+        }
+    }
+
+    /// Returns the injector of this channel
+    pub fn injector(&self) -> &FaultInjector {
+        &self.injector
+    }
+
+    /// Returns the wrapped channel
+    pub fn into_inner(self) -> C {
+        self.inner
+    }
+}
+
+impl<C: PacketChannel<CanFrame>> FaultInjectionChannel<C> {
+    /// Sends the frames written earlier that are now due
+    fn flush_tx(&mut self) -> ChannelResult<()> {
+        self.tx.release_due();
+        if self.tx.ready.is_empty() {
+            return Ok(());
+        }
+        let frames = self.tx.ready.drain(..).collect();
+        self.inner.write_packets(frames, 0)
+    }
+}
+
+impl<C: PacketChannel<CanFrame>> PacketChannel<CanFrame> for FaultInjectionChannel<C> {
+    fn open(&mut self) -> ChannelResult<()> {
+        self.inner.open()
+    }
+
+    fn close(&mut self) -> ChannelResult<()> {
+        self.inner.close()
+    }
+
+    fn write_packets(&mut self, packets: Vec<CanFrame>, timeout_ms: u32) -> ChannelResult<()> {
+        if let Some(e) = self.injector.state.lock().unwrap().write_errors.pop_front() {
+            return Err(e);
+        }
+        {
+            let mut state = self.injector.state.lock().unwrap();
+            for frame in packets {
+                let fault = state.fault_for(FaultDirection::Tx, &frame);
+                if let Some(f) = fault {
+                    log::debug!("Fault injection: Tx {f:?} {frame:02X?}");
+                }
+                self.tx.push(frame, fault);
+            }
+        }
+        self.tx.release_due();
+        if self.tx.ready.is_empty() {
+            return Ok(());
+        }
+        let frames = self.tx.ready.drain(..).collect();
+        self.inner.write_packets(frames, timeout_ms)
+    }
+
+    /// Reads frames, with the faults applied. Frames that are all dropped or held back
+    /// result in an empty read
+    fn read_packets(&mut self, max: usize, timeout_ms: u32) -> ChannelResult<Vec<CanFrame>> {
+        self.flush_tx()?;
+        if let Some(e) = self.injector.state.lock().unwrap().read_errors.pop_front() {
+            return Err(e);
+        }
+        self.rx.release_due();
+        if self.rx.ready.is_empty() {
+            // Do not wait past the next delayed frame
+            let timeout_ms = match self.rx.next_due() {
+                Some(due) => {
+                    let until_due = due.saturating_duration_since(Instant::now()).as_millis();
+                    (timeout_ms as u128).min(until_due) as u32
+                }
+                None => timeout_ms,
+            };
+            let frames = match self.inner.read_packets(max, timeout_ms) {
+                Ok(frames) => frames,
+                Err(ChannelError::BufferEmpty) if !self.rx.delayed.is_empty() => vec![],
+                Err(e) => return Err(e),
+            };
+            let mut state = self.injector.state.lock().unwrap();
+            for frame in frames {
+                let fault = state.fault_for(FaultDirection::Rx, &frame);
+                if let Some(f) = fault {
+                    log::debug!("Fault injection: Rx {f:?} {frame:02X?}");
+                }
+                self.rx.push(frame, fault);
+            }
+            drop(state);
+            self.rx.release_due();
+        }
+        let count = max.min(self.rx.ready.len());
+        Ok(self.rx.ready.drain(..count).collect())
+    }
+
+    fn clear_rx_buffer(&mut self) -> ChannelResult<()> {
+        self.rx = FaultQueue::default();
+        self.inner.clear_rx_buffer()
+    }
+
+    fn clear_tx_buffer(&mut self) -> ChannelResult<()> {
+        self.tx = FaultQueue::default();
+        self.inner.clear_tx_buffer()
+    }
+}
+
+impl<C: FilterPacketChannel<CanFrame>> FilterPacketChannel<CanFrame> for FaultInjectionChannel<C> {
+    fn add_filter(&mut self, allowed_ids: &[u32]) -> ChannelResult<u32> {
+        self.inner.add_filter(allowed_ids)
+    }
+
+    fn remove_filter(&mut self, filter_id: u32) -> ChannelResult<()> {
+        self.inner.remove_filter(filter_id)
+    }
+}
+
+impl<C: CanChannel> CanChannel for FaultInjectionChannel<C> {
+    fn set_can_cfg(&mut self, baud: u32, use_extended: bool) -> ChannelResult<()> {
+        self.inner.set_can_cfg(baud, use_extended)
+    }
+
+    fn set_can_fd_cfg(
+        &mut self,
+        baud: u32,
+        data_baud: u32,
+        use_extended: bool,
+    ) -> ChannelResult<()> {
+        self.inner.set_can_fd_cfg(baud, data_baud, use_extended)
+    }
+
+    fn get_bus_status(&mut self) -> ChannelResult<CanBusStatus> {
+        match self.injector.state.lock().unwrap().bus_status {
+            Some(status) => Ok(status),
+            None => self.inner.get_bus_status(),
+        }
+    }
+
+    fn detect_can_baud(&mut self, candidates: &[u32], listen_ms: u32) -> ChannelResult<u32> {
+        self.inner.detect_can_baud(candidates, listen_ms)
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use std::time::{Duration, Instant};
+
+    use super::{Fault, FaultDirection, FaultInjectionChannel, FaultInjector, FaultProbabilities};
+    use crate::{
+        core::channel::{CanFrame, ChannelError, Packet, PacketChannel},
+        hardware::virtual_bus::VirtualBus,
+    };
+
+    fn frame(i: u8) -> CanFrame {
+        CanFrame::new(0x7E8, &[i, 0xAA], false)
+    }
+
+    #[test]
+    pub fn test_scheduled_faults() {
+        let bus = VirtualBus::new();
+        let mut ecu = bus.attach();
+        let injector = FaultInjector::default();
+        let mut tester = FaultInjectionChannel::new(bus.attach(), injector.clone());
+        ecu.open().unwrap();
+        tester.open().unwrap();
+
+        injector.schedule(FaultDirection::Rx, None, 1, Fault::Drop);
+        injector.schedule(FaultDirection::Rx, None, 2, Fault::Duplicate);
+        injector.schedule(FaultDirection::Rx, Some(0x7E8), 3, Fault::Reorder);
+        injector.schedule(FaultDirection::Rx, None, 5, Fault::Delay(50));
+        injector.schedule(
+            FaultDirection::Rx,
+            None,
+            5,
+            Fault::Corrupt {
+                index: 1,
+                mask: 0xFF,
+            },
+        );
+        ecu.write_packets((0..8).map(frame).collect(), 0).unwrap();
+
+        let mut rx = vec![];
+        let start = Instant::now();
+        while rx.len() < 8 && start.elapsed() < Duration::from_secs(1) {
+            rx.extend(tester.read_packets(10, 10).unwrap_or_default());
+        }
+        assert!(start.elapsed() >= Duration::from_millis(50));
+        assert_eq!(injector.scheduled_count(), 0);
+        let rx: Vec<&[u8]> = rx.iter().map(|f| f.get_data()).collect();
+        assert_eq!(
+            rx,
+            [
+                &[0, 0xAA][..],
+                // 1 dropped, 2 duplicated
+                &[2, 0xAA],
+                &[2, 0xAA],
+                // 3 and 4 swapped
+                &[4, 0xAA],
+                &[3, 0xAA],
+                // 5 delayed (Its corruption moves on to 6)
+                &[6, 0x55],
+                &[7, 0xAA],
+                &[5, 0xAA],
+            ]
+        );
+
+        injector.fail_next_write(ChannelError::WriteTimeout);
+        injector.fail_next_read(ChannelError::ReadTimeout);
+        assert!(matches!(
+            tester.write_packets(vec![frame(0)], 0),
+            Err(ChannelError::WriteTimeout)
+        ));
+        assert!(matches!(
+            tester.read_packets(1, 0),
+            Err(ChannelError::ReadTimeout)
+        ));
+        assert!(ecu.read_packets(1, 0).is_err());
+    }
+
+    #[test]
+    pub fn test_random_faults() {
+        let run = |seed| {
+            let bus = VirtualBus::new();
+            let mut ecu = bus.attach();
+            let injector = FaultInjector::new(seed);
+            injector.set_probabilities(
+                FaultDirection::Tx,
+                FaultProbabilities {
+                    drop: 0.2,
+                    ..Default::default()
+                },
+            );
+            let mut tester = FaultInjectionChannel::new(bus.attach(), injector);
+            ecu.open().unwrap();
+            tester.open().unwrap();
+            for i in 0..=255 {
+                tester.write_packets(vec![frame(i)], 0).unwrap();
+            }
+            ecu.read_packets(1000, 0)
+                .unwrap()
+                .iter()
+                .map(|f| f.get_data()[0])
+                .collect::<Vec<u8>>()
+        };
+        let received = run(1);
+        assert!((180..235).contains(&received.len()), "{}", received.len());
+        assert_eq!(received, run(1));
+        assert_ne!(received, run(2));
+    }
+}
@@ -1,22 +1,257 @@
 use super::{
     pcan_usb::{pcan_api::PCanDrvNew, pcan_types::PcanUSB},
-    Hardware, HardwareInfo,
+    recording::{RecordingChannel, TraceRecorder},
+    Hardware, HardwareInfo, HardwareResult,
 };
+pub use crate::core::channel::COMMON_CAN_BAUD_RATES;
 use crate::core::channel::{
-    CanChannel, CanFrame, ChannelResult, IsoTPSettings, Packet, PacketChannel,
+    st_min_duration, CanBusState, CanBusStatus, CanChannel, CanFrame, ChannelError, ChannelResult,
+    FilterPacketChannel, IsoTPChannel, IsoTPSettings, IsoTpError, Packet, PacketChannel,
+    PayloadChannel,
 };
-use crate::hardware::pcan_usb::PcanUsbDevice;
+use crate::hardware::pcan_usb::{PcanUsbDevice, PcanUsbScanner};
 use crate::uds::errors::*;
-use std::time::Instant;
+use std::any::Any;
+use std::path::Path;
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
 
-use super::software_isotp::{IsoTpRxAction, IsoTpRxMemory};
+use super::software_isotp::{
+    isotp_frame, single_frame, IsoTpRxAction, IsoTpRxMemory, IsoTpTxMemory,
+};
+
+/// Time in milliseconds the ECU has to start its response to a request
+pub const RESPONSE_TIMEOUT_MS: u32 = 5000;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+/// What [IsoTpProtocol] does when its CAN channel goes bus-off
+pub enum BusRecoveryPolicy {
+    /// Only report the bus-off
+    #[default]
+    Disabled,
+    /// Reset and re-initialize the channel, waiting `delay_ms` before each attempt.
+    /// After `max_attempts` failed attempts in a row, the channel is left as is
+    Automatic { max_attempts: u32, delay_ms: u32 },
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+/// CAN bus health event, see [IsoTpProtocol::subscribe_bus_events]
+pub enum CanBusEvent {
+    /// The error state of the bus changed
+    StateChanged(CanBusStatus),
+    /// Frames were lost to a receive overrun. Contains the total number of overruns
+    Overrun(u32),
+    /// The channel was reset after a bus-off, and is back on the bus.
+    /// Contains the number of attempts it took
+    Recovered(u32),
+    /// The channel is still bus-off after the maximum number of recovery attempts
+    RecoveryFailed,
+}
+
+impl std::fmt::Display for CanBusEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CanBusEvent::StateChanged(status) => write!(f, "CAN bus state: {status}"),
+            CanBusEvent::Overrun(count) => {
+                write!(f, "CAN receive overrun, frames were lost ({count} so far)")
+            }
+            CanBusEvent::Recovered(attempts) => {
+                write!(
+                    f,
+                    "CAN bus recovered from bus-off after {attempts} attempt(s)"
+                )
+            }
+            CanBusEvent::RecoveryFailed => write!(f, "CAN bus recovery failed, still bus-off"),
+        }
+    }
+}
+
+/// Largest 11-bit CAN ID
+const MAX_STANDARD_ID: u32 = 0x7FF;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+/// ISO 15765-2 addressing mode, which determines the CAN IDs of requests and responses,
+/// and whether the first data byte of every frame carries an address
+pub enum IsoTpAddressing {
+    /// Normal addressing with 11-bit CAN IDs
+    Normal { tx_id: u32, rx_id: u32 },
+    /// Normal fixed addressing with 29-bit CAN IDs. Requests are sent on `0x18DA_TT_SS`
+    /// and responses are received on `0x18DA_SS_TT` (TT: target address, SS: source address)
+    NormalFixed { target: u8, source: u8 },
+    /// Extended addressing with 11-bit CAN IDs. The first data byte is the
+    /// target address, `tx_ext` for requests and `rx_ext` for responses.
+    /// 11-bit mixed addressing is the same, with the address extension as both
+    Extended {
+        tx_id: u32,
+        rx_id: u32,
+        tx_ext: u8,
+        rx_ext: u8,
+    },
+    /// Mixed addressing with 29-bit CAN IDs `0x18CE_TT_SS` / `0x18CE_SS_TT`.
+    /// The first data byte is the address extension in both directions
+    Mixed {
+        target: u8,
+        source: u8,
+        extension: u8,
+    },
+}
+
+impl Default for IsoTpAddressing {
+    fn default() -> Self {
+        Self::Normal {
+            tx_id: 0x784,
+            rx_id: 0x7F0,
+        }
+    }
+}
+
+impl IsoTpAddressing {
+    /// CAN ID requests are sent on
+    pub fn tx_id(&self) -> u32 {
+        match *self {
+            Self::Normal { tx_id, .. } | Self::Extended { tx_id, .. } => tx_id,
+            Self::NormalFixed { target, source } => fixed_id(0x18DA, target, source),
+            Self::Mixed { target, source, .. } => fixed_id(0x18CE, target, source),
+        }
+    }
+
+    /// CAN ID responses are received on
+    pub fn rx_id(&self) -> u32 {
+        match *self {
+            Self::Normal { rx_id, .. } | Self::Extended { rx_id, .. } => rx_id,
+            Self::NormalFixed { target, source } => fixed_id(0x18DA, source, target),
+            Self::Mixed { target, source, .. } => fixed_id(0x18CE, source, target),
+        }
+    }
+
+    /// Returns true if the CAN IDs are 29-bit
+    pub fn is_extended_id(&self) -> bool {
+        matches!(self, Self::NormalFixed { .. } | Self::Mixed { .. })
+    }
+
+    /// Returns the address bytes of requests and responses, in that order,
+    /// or None if the addressing mode has none
+    pub fn address_bytes(&self) -> Option<(u8, u8)> {
+        match *self {
+            Self::Normal { .. } | Self::NormalFixed { .. } => None,
+            Self::Extended { tx_ext, rx_ext, .. } => Some((tx_ext, rx_ext)),
+            Self::Mixed { extension, .. } => Some((extension, extension)),
+        }
+    }
+
+    /// Checks that the CAN IDs fit their format and that requests and responses can be told apart
+    pub fn validate(&self) -> ChannelResult<()> {
+        match *self {
+            Self::Normal { tx_id, rx_id } | Self::Extended { tx_id, rx_id, .. } => {
+                if tx_id > MAX_STANDARD_ID || rx_id > MAX_STANDARD_ID {
+                    return Err(ChannelError::ConfigurationError);
+                }
+                if tx_id == rx_id {
+                    return Err(ChannelError::ConfigurationError);
+                }
+            }
+            Self::NormalFixed { target, source } | Self::Mixed { target, source, .. } => {
+                if target == source {
+                    return Err(ChannelError::ConfigurationError);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Builds a 29-bit CAN ID `0xPPPP_TT_SS` of the fixed addressing modes
+fn fixed_id(prefix: u32, target: u8, source: u8) -> u32 {
+    (prefix << 16) | ((target as u32) << 8) | source as u32
+}
+
+impl std::fmt::Display for IsoTpAddressing {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            Self::Normal { tx_id, rx_id } => write!(f, "normal:{tx_id:X}:{rx_id:X}"),
+            Self::NormalFixed { target, source } => {
+                write!(f, "normal-fixed:{target:02X}:{source:02X}")
+            }
+            Self::Extended {
+                tx_id,
+                rx_id,
+                tx_ext,
+                rx_ext,
+            } => write!(f, "extended:{tx_id:X}:{rx_id:X}:{tx_ext:02X}:{rx_ext:02X}"),
+            Self::Mixed {
+                target,
+                source,
+                extension,
+            } => write!(f, "mixed:{target:02X}:{source:02X}:{extension:02X}"),
+        }
+    }
+}
+
+impl std::str::FromStr for IsoTpAddressing {
+    type Err = String;
+
+    /// Parses the addressing mode from the format written by [std::fmt::Display]
+    /// (mode followed by hex values, separated by `:`), and validates it:
+    /// * `normal:TX_ID:RX_ID`
+    /// * `normal-fixed:TARGET:SOURCE`
+    /// * `extended:TX_ID:RX_ID:TX_EXT:RX_EXT`
+    /// * `mixed:TARGET:SOURCE:EXTENSION`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split(':');
+        let mode = parts.next().unwrap_or_default().to_ascii_lowercase();
+        let values = parts
+            .map(|p| u32::from_str_radix(p.trim_start_matches("0x"), 16))
+            .collect::<Result<Vec<u32>, _>>()
+            .map_err(|e| format!("Invalid address in '{s}': {e}"))?;
+        let byte = |v: u32| u8::try_from(v).map_err(|_| format!("Address 0x{v:X} is not a byte"));
+        let addressing = match (mode.as_str(), values.as_slice()) {
+            ("normal", &[tx_id, rx_id]) => Self::Normal { tx_id, rx_id },
+            ("normal-fixed", &[target, source]) => Self::NormalFixed {
+                target: byte(target)?,
+                source: byte(source)?,
+            },
+            ("extended", &[tx_id, rx_id, tx_ext, rx_ext]) => Self::Extended {
+                tx_id,
+                rx_id,
+                tx_ext: byte(tx_ext)?,
+                rx_ext: byte(rx_ext)?,
+            },
+            ("mixed", &[target, source, extension]) => Self::Mixed {
+                target: byte(target)?,
+                source: byte(source)?,
+                extension: byte(extension)?,
+            },
+            _ => return Err(format!("Invalid addressing mode '{s}'")),
+        };
+        addressing
+            .validate()
+            .map_err(|_| format!("Invalid CAN IDs for addressing mode '{s}'"))?;
+        Ok(addressing)
+    }
+}
 
 #[allow(dead_code)]
 pub struct IsoTpProtocol {
     pub connection_status: bool,
-    device: PcanUsbDevice,
     channel: Box<dyn CanChannel>,
     cfg: IsoTPSettings,
+    /// CAN ID the ECU responds on. If set, frames with other IDs are ignored
+    rx_id: Option<u32>,
+    /// CAN ID requests are sent to when used as a [PayloadChannel], see [PayloadChannel::set_ids]
+    tx_id: Option<u32>,
+    /// Receive timestamp of the first frame of the last response
+    last_rx_timestamp_us: Option<u64>,
+    /// Filter installed on `channel` for `rx_id`
+    filter_id: Option<u32>,
+    recovery: BusRecoveryPolicy,
+    /// Last bus status read from `channel`
+    bus_status: Option<CanBusStatus>,
+    bus_event_senders: Vec<mpsc::Sender<CanBusEvent>>,
+    /// Records the traffic of `channel`, see [IsoTpProtocol::start_trace]
+    recorder: TraceRecorder,
+    /// Device that created `channel`. Some devices (PCAN) release their
+    /// handle on drop, so it has to live as long as the channel does
+    device: Option<Box<dyn Any + Send + Sync>>,
 }
 
 impl Drop for IsoTpProtocol {
@@ -25,30 +260,111 @@ impl Drop for IsoTpProtocol {
     }
 }
 
-impl Default for IsoTpProtocol {
-    fn default() -> Self {
-        Self::new()
+impl IsoTpProtocol {
+    /// Creates a new ISOTP protocol handler on top of an existing CAN channel
+    pub fn new(channel: Box<dyn CanChannel>) -> Self {
+        let recorder = TraceRecorder::default();
+        Self {
+            connection_status: false,
+            channel: Box::new(RecordingChannel::new(channel, recorder.clone())),
+            cfg: IsoTPSettings::default(),
+            rx_id: None,
+            tx_id: None,
+            last_rx_timestamp_us: None,
+            filter_id: None,
+            recovery: BusRecoveryPolicy::default(),
+            bus_status: None,
+            bus_event_senders: vec![],
+            recorder,
+            device: None,
+        }
     }
-}
 
-impl IsoTpProtocol {
-    /// Creates a new Native ISOTP channel
-    pub fn new() -> Self {
-        let mut device = PcanUsbDevice::new(
-            PcanUSB::USB1,
+    /// Creates a new ISOTP protocol handler using the CAN channel of a hardware device.
+    /// The device is owned by the protocol handler from then on
+    pub fn new_from_hardware<H: Hardware + Send + Sync + 'static>(
+        mut device: H,
+    ) -> HardwareResult<Self> {
+        let channel = device.create_native_iso_tp_channel()?;
+        let mut protocol = Self::new(channel);
+        protocol.device = Some(Box::new(device));
+        Ok(protocol)
+    }
+
+    /// Creates a new ISOTP protocol handler on a PCAN-USB channel
+    pub fn new_pcan_usb(handle: PcanUSB) -> HardwareResult<Self> {
+        let device = PcanUsbDevice::new(
+            handle,
             HardwareInfo::default(),
             PCanDrvNew {
                 is_connected: false,
             },
-        )
-        .unwrap();
-        let channel = device.create_native_iso_tp_channel().unwrap();
-        Self {
-            connection_status: false,
-            device,
-            channel,
-            cfg: IsoTPSettings::default(),
+        )?;
+        Self::new_from_hardware(device)
+    }
+
+    /// Creates a new ISOTP protocol handler on a PCAN adapter, see [PcanUsbScanner::open_adapter]
+    pub fn new_pcan(adapter: Option<&str>) -> HardwareResult<Self> {
+        let device = PcanUsbScanner::default().open_adapter(adapter)?;
+        Self::new_from_hardware(device)
+    }
+
+    /// Moves the protocol handler over to the CAN channel of another hardware device and opens it.
+    /// The ISOTP settings, RX ID, bus recovery policy and bus event subscribers are kept
+    pub fn switch_hardware<H: Hardware + Send + Sync + 'static>(
+        &mut self,
+        mut device: H,
+    ) -> HardwareResult<()> {
+        if let Err(e) = self.channel.close() {
+            log::error!("Error: close CAN channel {e:?}");
         }
+        self.connection_status = false;
+        // Release the old device first, the new one might be the same adapter
+        self.device = None;
+        self.channel = Box::new(RecordingChannel::new(
+            device.create_native_iso_tp_channel()?,
+            self.recorder.clone(),
+        ));
+        self.device = Some(Box::new(device));
+        self.filter_id = None;
+        self.bus_status = None;
+        self.init();
+        Ok(())
+    }
+
+    /// Detects the baud rate of the bus, see [CanChannel::detect_can_baud], and uses it from then on.
+    /// An open channel is closed for the detection and opened again afterwards
+    pub fn detect_can_speed(&mut self, candidates: &[u32], listen_ms: u32) -> ChannelResult<u32> {
+        let was_open = self.connection_status;
+        if was_open {
+            self.channel.close()?;
+            self.connection_status = false;
+        }
+        let res = self.channel.detect_can_baud(candidates, listen_ms);
+        if let Ok(baud) = res {
+            self.cfg.can_speed = baud;
+        }
+        if was_open {
+            self.init();
+        }
+        res
+    }
+
+    /// Starts recording every CAN frame sent and received to a trace file. The format is
+    /// picked from the file extension: candump (`.log`), PCAN (`.trc`) or Vector (`.asc`).
+    /// A recording already in progress is stopped first
+    pub fn start_trace(&mut self, path: &Path) -> HardwareResult<()> {
+        self.recorder.start(path)
+    }
+
+    /// Stops recording the CAN trace started by [IsoTpProtocol::start_trace]
+    pub fn stop_trace(&mut self) {
+        self.recorder.stop()
+    }
+
+    /// Returns true while a CAN trace is being recorded
+    pub fn is_tracing(&self) -> bool {
+        self.recorder.is_recording()
     }
 
     pub fn set_iso_tp_cfg(&mut self, cfg: IsoTPSettings) -> ChannelResult<()> {
@@ -56,8 +372,158 @@ impl IsoTpProtocol {
         Ok(())
     }
 
+    /// Returns the ISO-TP settings in use
+    pub fn get_iso_tp_cfg(&self) -> IsoTPSettings {
+        self.cfg
+    }
+
+    /// Sets the addressing mode: the CAN ID and format responses are received with, the CAN ID
+    /// format requests are sent with, and the address bytes of both.
+    /// Replaces the RX ID and addressing of the ISO-TP settings.
+    /// The CAN ID format of the channel is applied on [IsoTpProtocol::init]
+    pub fn set_addressing(&mut self, addressing: IsoTpAddressing) -> ChannelResult<()> {
+        addressing.validate()?;
+        self.cfg.can_use_ext_addr = addressing.is_extended_id();
+        self.cfg.extended_addresses = addressing.address_bytes();
+        self.set_rx_id(addressing.rx_id());
+        Ok(())
+    }
+
+    /// Sets the CAN ID the ECU responds on. Frames with any other ID are ignored,
+    /// and if the adapter supports it, filtered out in hardware
+    pub fn set_rx_id(&mut self, rx_id: u32) {
+        self.rx_id = Some(rx_id);
+        if self.connection_status {
+            self.install_rx_filter();
+        }
+    }
+
+    /// Replaces the hardware filter on the channel with one for `rx_id`
+    fn install_rx_filter(&mut self) {
+        if let Some(id) = self.filter_id.take() {
+            if let Err(e) = self.channel.remove_filter(id) {
+                log::error!("Error: remove CAN filter {e:?}");
+            }
+        }
+        let Some(rx_id) = self.rx_id else {
+            return;
+        };
+        match self.channel.add_filter(&[rx_id]) {
+            Ok(id) => {
+                log::debug!("Success: CAN filter for 0x{rx_id:04X}");
+                self.filter_id = Some(id);
+            }
+            // Frames are still filtered by ID when receiving
+            Err(ChannelError::UnsupportedRequest) => {}
+            Err(e) => log::error!("Error: add CAN filter {e:?}"),
+        }
+    }
+
+    /// Sets what happens when the CAN channel goes bus-off
+    pub fn set_bus_recovery(&mut self, policy: BusRecoveryPolicy) {
+        self.recovery = policy;
+    }
+
+    /// Returns a receiver for the bus health events of the channel. Events are
+    /// produced by [IsoTpProtocol::check_bus], which also runs before each request
+    pub fn subscribe_bus_events(&mut self) -> mpsc::Receiver<CanBusEvent> {
+        let (tx, rx) = mpsc::channel();
+        self.bus_event_senders.push(tx);
+        rx
+    }
+
+    fn emit_bus_event(&mut self, evt: CanBusEvent) {
+        log::debug!("CAN bus event: {evt:?}");
+        self.bus_event_senders.retain(|s| s.send(evt).is_ok());
+    }
+
+    /// Reads the bus status of the channel, reports changes to it as [CanBusEvent]s,
+    /// and recovers from bus-off if enabled with [IsoTpProtocol::set_bus_recovery].
+    ///
+    /// Returns None if the channel does not report its bus status
+    pub fn check_bus(&mut self) -> Option<CanBusStatus> {
+        let status = match self.channel.get_bus_status() {
+            Ok(status) => status,
+            Err(ChannelError::UnsupportedRequest | ChannelError::InterfaceNotOpen) => return None,
+            Err(e) => {
+                log::error!("Error: read CAN bus status {e:?}");
+                return None;
+            }
+        };
+        let last = self.bus_status.replace(status).unwrap_or_default();
+        if status.state != last.state {
+            self.emit_bus_event(CanBusEvent::StateChanged(status));
+        }
+        if status.overrun_count > last.overrun_count {
+            self.emit_bus_event(CanBusEvent::Overrun(status.overrun_count));
+        }
+        if status.state == CanBusState::BusOff {
+            if let BusRecoveryPolicy::Automatic {
+                max_attempts,
+                delay_ms,
+            } = self.recovery
+            {
+                return Some(self.recover(max_attempts, delay_ms));
+            }
+        }
+        Some(status)
+    }
+
+    /// Resets and re-initializes the channel until it is no longer bus-off
+    fn recover(&mut self, max_attempts: u32, delay_ms: u32) -> CanBusStatus {
+        let mut status = self.bus_status.unwrap_or_default();
+        for attempt in 1..=max_attempts {
+            std::thread::sleep(Duration::from_millis(delay_ms.into()));
+            log::warn!("CAN bus-off, resetting channel (Attempt {attempt}/{max_attempts})");
+            if let Err(e) = self.channel.close() {
+                log::error!("Error: close CAN channel {e:?}");
+            }
+            self.init();
+            if let Ok(s) = self.channel.get_bus_status() {
+                status = s;
+            }
+            if self.connection_status && status.state != CanBusState::BusOff {
+                self.bus_status = Some(status);
+                self.emit_bus_event(CanBusEvent::Recovered(attempt));
+                self.emit_bus_event(CanBusEvent::StateChanged(status));
+                return status;
+            }
+        }
+        self.connection_status = false;
+        self.emit_bus_event(CanBusEvent::RecoveryFailed);
+        status
+    }
+
+    /// Returns the ISO-TP data of `frame`, without its address byte, or None if the
+    /// frame should not be handled, based on the receive ID and address
+    fn rx_data<'a>(&self, frame: &'a CanFrame) -> Option<&'a [u8]> {
+        if self.rx_id.is_some() && self.rx_id != Some(frame.get_address()) {
+            return None;
+        }
+        let data = frame.get_data();
+        match self.cfg.extended_addresses {
+            Some((_, rx_ext)) => match data.split_first() {
+                Some((&ext, data)) if ext == rx_ext => Some(data),
+                _ => None,
+            },
+            None => Some(data),
+        }
+    }
+
+    /// Maximum ISO-TP data length of a frame, after the address byte
+    fn tx_dl(&self) -> usize {
+        self.cfg.tx_dl() - usize::from(self.cfg.extended_addresses.is_some())
+    }
+
     pub fn init(&mut self) {
-        match self.channel.set_can_cfg(500_000, false) {
+        let use_ext = self.cfg.can_use_ext_addr;
+        let res = match self.cfg.can_fd_speed {
+            Some(data_baud) => self
+                .channel
+                .set_can_fd_cfg(self.cfg.can_speed, data_baud, use_ext),
+            None => self.channel.set_can_cfg(self.cfg.can_speed, use_ext),
+        };
+        match res {
             Ok(()) => {
                 log::debug!("Success: Set Can config")
             }
@@ -71,6 +537,7 @@ impl IsoTpProtocol {
             Ok(()) => {
                 log::debug!("Success: open CAN channel");
                 self.connection_status = true;
+                self.install_rx_filter();
             }
             Err(e) => {
                 log::error!("Error: Open CAN device {e:?}");
@@ -88,191 +555,981 @@ impl IsoTpProtocol {
     }
 
     pub fn send(&mut self, frame: CanFrame) {
-        let timeout_ms = 5000;
-        match self.channel.write_packets(vec![frame], timeout_ms) {
-            Ok(()) => {}
-            Err(e) => {
-                log::error!("Error: write can {e:?}");
-                //println!("Error: write can {e:?}");
-            }
+        self.check_bus();
+        if let Err(e) = self.write_frame_raw(frame) {
+            log::error!("Error: write can {e:?}");
+        }
+    }
+
+    /// Sends a payload to the ECU without waiting for a response.
+    /// Payloads longer than a single frame are segmented into a first frame
+    /// and consecutive frames, following the flow control of the ECU
+    pub fn send_payload(&mut self, addr: u32, payload: &[u8]) {
+        self.check_bus();
+        if let Err(e) = self.write_payload(addr, payload) {
+            log::error!("Error: write payload {e:?}");
+            self.connection_status = false;
+        }
+    }
+
+    /// Sends a payload to the ECU and waits for its response, see [IsoTpProtocol::send_payload].
+    /// Returns an empty response if the request failed, see [IsoTpProtocol::try_send_receive_payload]
+    pub fn send_receive_payload(&mut self, addr: u32, payload: &[u8]) -> Vec<u8> {
+        self.try_send_receive_payload(addr, payload)
+            .unwrap_or_else(|e| {
+                log::error!("Error: ISOTP request {e:?}");
+                vec![]
+            })
+    }
+
+    /// Sends a payload to the ECU and waits for its response.
+    ///
+    /// The ECU has [RESPONSE_TIMEOUT_MS] to start its response, after which
+    /// [ChannelError::ReadTimeout] is returned. Aborted transfers return [ChannelError::IsoTpError]
+    pub fn try_send_receive_payload(
+        &mut self,
+        addr: u32,
+        payload: &[u8],
+    ) -> ChannelResult<Vec<u8>> {
+        self.check_bus();
+        if let Err(e) = self.write_payload(addr, payload) {
+            self.connection_status = false;
+            return Err(e);
+        }
+        let resp = self.receive(addr, RESPONSE_TIMEOUT_MS)?;
+        if !payload.is_empty() && !resp.is_empty() {
+            process_ecu_response(payload, &resp);
         }
+        Ok(resp)
     }
 
-    #[allow(unused_assignments)]
     pub fn send_receive(&mut self, frame: CanFrame) -> Vec<u8> {
-        let timeout_ms = 5000;
-        match self.channel.write_packets(vec![frame], timeout_ms) {
-            Ok(()) => {}
+        self.check_bus();
+        if let Err(e) = self.write_frame_raw(frame) {
+            log::error!("Error: write can {e:?}");
+            self.connection_status = false;
+        }
+        match self.receive(frame.get_address(), RESPONSE_TIMEOUT_MS) {
+            Ok(resp) => {
+                if !resp.is_empty() {
+                    process_ecu_response(&frame.get_data()[1..], &resp);
+                }
+                resp
+            }
             Err(e) => {
-                log::error!("Error: write can {e:?}");
-                //println!("Error: write can {e:?}");
-                self.connection_status = false;
+                log::error!("Error: ISOTP request {e:?}");
+                vec![]
             }
         }
-        std::thread::sleep(std::time::Duration::from_millis(100));
-        let mut rx_memory = IsoTpRxMemory::default();
+    }
+
+    /// Writes a frame, reporting a write timeout as an N_As timeout
+    fn write_frame_raw(&mut self, frame: CanFrame) -> ChannelResult<()> {
+        let res = self
+            .channel
+            .write_packets(vec![frame], self.cfg.timeouts.n_as);
+        match res {
+            Err(ChannelError::WriteTimeout) => Err(IsoTpError::TimeoutAs.into()),
+            res => res,
+        }
+    }
+
+    /// Writes ISO-TP frame data, after the address byte if the addressing mode has one
+    fn write_frame(&mut self, addr: u32, mut data: Vec<u8>) -> ChannelResult<()> {
+        if let Some((tx_ext, _)) = self.cfg.extended_addresses {
+            data.insert(0, tx_ext);
+        }
+        let f = isotp_frame(&self.cfg, addr, data);
+        self.write_frame_raw(f)
+    }
+
+    /// Sends a flow control (Continue to send) with the block size and STmin of the ISO-TP settings
+    fn write_flow_control(&mut self, addr: u32) -> ChannelResult<()> {
+        self.write_frame(addr, vec![0x30, self.cfg.block_size, self.cfg.st_min])
+    }
 
-        let default_tx_addr = frame.get_address();
+    fn write_payload(&mut self, addr: u32, payload: &[u8]) -> ChannelResult<()> {
+        if let Some(data) = single_frame(payload, self.tx_dl()) {
+            return self.write_frame(addr, data);
+        } else if payload.len() > u32::MAX as usize {
+            // Data too large for the first frame escape
+            return Err(ChannelError::UnsupportedRequest);
+        }
 
-        let mut empty_can_frame_count = 0;
+        let mut tx_memory = IsoTpTxMemory {
+            addr,
+            data: payload.to_vec(),
+            tx_dl: self.tx_dl(),
+            timeouts: self.cfg.timeouts,
+            ..Default::default()
+        };
+        let start_frame = tx_memory.get_start_frame();
+        self.write_frame(addr, start_frame)?;
+
+        while !tx_memory.completed {
+            if tx_memory.awaiting_fc {
+                // Block for the flow control instead of polling, until N_Bs runs out
+                let n_bs_left = u128::from(self.cfg.timeouts.n_bs)
+                    .saturating_sub(tx_memory.last_tx_time.elapsed().as_millis());
+                let wait_ms = n_bs_left.max(1) as u32;
+                let frames = match self.channel.read_packets(1, wait_ms) {
+                    Ok(frames) => frames,
+                    Err(ChannelError::BufferEmpty | ChannelError::ReadTimeout) => vec![],
+                    Err(e) => return Err(e),
+                };
+                for data in frames.iter().filter_map(|f| self.rx_data(f)) {
+                    if data.len() >= 3 && data[0] & 0xF0 == 0x30 {
+                        log::debug!("ISOTP Flow control {data:02X?}");
+                        tx_memory.on_flow_control(data)?;
+                    } else {
+                        log::debug!("ISOTP awaiting flow control, ignoring {data:02X?}");
+                    }
+                }
+            }
+            match tx_memory.on_update() {
+                Some(Ok(data)) => self.write_frame(addr, data)?,
+                Some(Err(e)) => return Err(e),
+                None => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Receives a response, sending its flow control frames to `default_tx_addr`.
+    /// The ECU has `timeout_ms` to start the response
+    fn receive(&mut self, default_tx_addr: u32, timeout_ms: u32) -> ChannelResult<Vec<u8>> {
+        let n_cr = Duration::from_millis(self.cfg.timeouts.n_cr.into());
+        let st_min = st_min_duration(self.cfg.st_min);
+        let mut rx_memory = IsoTpRxMemory::default();
+        // Until the first frame arrives this is the response timeout, then N_Cr
+        let mut deadline = Instant::now() + Duration::from_millis(timeout_ms.into());
+        let mut first_rx_timestamp_us = None;
 
         loop {
-            match self.channel.read_packets(1, timeout_ms) {
-                Ok(frames) => {
-                    if frames.is_empty() {
-                        empty_can_frame_count += 1;
-                        if empty_can_frame_count == 800000 {
-                            log::debug!("Count is 800000 exceed timeout");
-                            println!("Count is 800000 exceed timeout");
-                            empty_can_frame_count = 0;
-                            self.connection_status = false;
-
-                            break;
-                        }
-                        continue;
+            let now = Instant::now();
+            if now >= deadline {
+                if rx_memory.receiving {
+                    log::error!(
+                        "ISOTP N_Cr timeout, received {} of {} bytes",
+                        rx_memory.data.len(),
+                        rx_memory.max_size
+                    );
+                    return Err(IsoTpError::TimeoutCr.into());
+                }
+                log::debug!("ISOTP no response within {timeout_ms}ms");
+                self.connection_status = false;
+                return Err(ChannelError::ReadTimeout);
+            }
+            let wait_ms = (deadline - now).as_millis() as u32;
+            let frame = match self.channel.read_packets(1, wait_ms) {
+                Ok(frames) => match frames.first() {
+                    Some(frame) => *frame,
+                    None => continue,
+                },
+                Err(ChannelError::BufferEmpty | ChannelError::ReadTimeout) => continue,
+                Err(e) => {
+                    log::error!("Error: read can {e:?}");
+                    self.connection_status = false;
+                    self.check_bus();
+                    return Err(e);
+                }
+            };
+            let Some(data) = self.rx_data(&frame) else {
+                continue;
+            };
+            let Some(pci) = data.first() else {
+                log::error!("ISOTP CAN frame too short! {frame:?}");
+                continue;
+            };
+            if matches!(pci & 0xF0, 0x00 | 0x10) {
+                first_rx_timestamp_us = frame.get_timestamp_us();
+            }
+            match pci & 0xF0 {
+                0x00 => {
+                    log::debug!("ISOTP One frame {data:02X?}");
+                    rx_memory.add_single_frame(data);
+                    break;
+                }
+                0x10 => {
+                    // Start of multi frame
+                    log::debug!("ISOTP Start frame {data:02X?}");
+                    if rx_memory.receiving {
+                        // ISO 15765-2: A new first frame replaces the reception in progress
+                        log::warn!("ISOTP first frame during reception, restarting");
+                        rx_memory.reset();
                     }
-                    empty_can_frame_count = 0;
-
-                    // Only use 1st frame
-                    let frame = frames[0];
-
-                    //TODO filter by receiving can id
-                    let data = frame.get_data();
-                    let pci_byte_idx = 0; // TODO for EXT ID Rx
-                    match data.get(pci_byte_idx) {
-                        Some(pci) => {
-                            match pci & 0xF0 {
-                                0x00 => {
-                                    log::debug!("ISOTP One frame {data:02X?}");
-                                    //println!("ISOTP One frame {data:02X?}");
-                                    rx_memory.add_single_frame(data);
-                                    break;
-                                }
-                                0x10 => {
-                                    // Start of multi frame
-                                    log::debug!("ISOTP Start frame {data:02X?}");
-                                    //println!("ISOTP First frame {data:02X?}");
-                                    let mut data_tx: Vec<u8> = vec![];
-                                    if rx_memory.receiving {
-                                        data_tx.push(0x32);
-                                    } else {
-                                        data_tx.push(0x30);
-                                        data_tx.push(self.cfg.block_size);
-                                        data_tx.push(self.cfg.st_min);
-                                        rx_memory.bs = self.cfg.block_size;
-                                        rx_memory.add_start_frame(data);
-                                    }
-                                    if self.cfg.pad_frame {
-                                        data_tx.resize(8, 0xCC);
-                                    }
-                                    // Send flow control
-                                    let f = CanFrame::new(
-                                        default_tx_addr,
-                                        &data_tx,
-                                        self.cfg.can_use_ext_addr,
-                                    );
-                                    match self.channel.write_packets(vec![f], timeout_ms) {
-                                        Ok(()) => {}
-                                        Err(e) => {
-                                            log::error!("Error: write can {e:?}");
-                                            //println!("Error: write can {e:?}");
-                                        }
-                                    }
-                                }
-                                0x20 => {
-                                    // Continuation of multi frame
-
-                                    // Check separation time
-                                    let real_time = (Instant::now() - rx_memory.last_rx_time)
-                                        .as_millis()
-                                        as u64;
-                                    let st = u64::from(self.cfg.st_min);
-                                    if real_time < u64::from(self.cfg.st_min) {
-                                        log::error!("Separation time vilation! Clock time {real_time} is not less than min separation time {st}");
-                                        println!("Separation time vilation! This could be a CAN misread error.
-                                                    Try running the command again");
-                                        panic!();
-                                    }
-
-                                    log::debug!("ISOTP continue frame {data:02X?}");
-                                    //println!("ISOTP continue frame {data:02X?}");
-                                    let status = rx_memory.add_continuous_frame(data);
-                                    if status == IsoTpRxAction::SendFC {
-                                        let mut data_tx: Vec<u8> = vec![];
-                                        data_tx.push(0x30);
-                                        data_tx.push(self.cfg.block_size);
-                                        data_tx.push(self.cfg.st_min);
-                                        rx_memory.bs = self.cfg.block_size;
-                                        if self.cfg.pad_frame {
-                                            data_tx.resize(8, 0xCC);
-                                        }
-
-                                        rx_memory.frames_received = 0; // Reset the counter
-
-                                        let f = CanFrame::new(
-                                            default_tx_addr,
-                                            &data_tx,
-                                            self.cfg.can_use_ext_addr,
-                                        );
-                                        match self.channel.write_packets(vec![f], timeout_ms) {
-                                            Ok(()) => {}
-                                            Err(e) => {
-                                                log::error!("Error: write can {e:?}");
-                                                //println!("Error: write can {e:?}");
-                                            }
-                                        }
-                                    } else if status == IsoTpRxAction::Completed {
-                                        break;
-                                    }
-                                }
-                                0x30 => {
-                                    // Flow control
-                                    log::debug!("ISOTP Flow control {data:02X?}. Note: this should not happen");
-                                    //println!("Received FC frame {data:02X?}. Note: this should not happen");
-                                }
-                                _ => {
-                                    log::error!("Invalid ISOTP CAN frame! {frame:?}");
-                                    //println!("Invalid ISOTP CAN frame! {frame:?}");
-                                }
+                    rx_memory.bs = self.cfg.block_size;
+                    rx_memory.add_start_frame(data);
+                    rx_memory.rx_timestamp_us = frame.get_timestamp_us();
+                    self.write_flow_control(default_tx_addr)?;
+                    deadline = Instant::now() + n_cr;
+                }
+                0x20 => {
+                    // Continuation of multi frame
+
+                    // Check separation time, using the receive timestamps
+                    // of the frames where the channel provides them.
+                    // STmin does not apply to the first frame after a flow control
+                    if rx_memory.receiving && rx_memory.frames_received > 0 {
+                        let gap = match (frame.get_timestamp_us(), rx_memory.rx_timestamp_us) {
+                            (Some(now), Some(last)) => {
+                                Duration::from_micros(now.saturating_sub(last))
                             }
+                            _ => rx_memory.last_rx_time.elapsed(),
+                        };
+                        if gap < st_min {
+                            log::warn!(
+                                "ISOTP separation time violation, {}us between frames but STmin is {}us",
+                                gap.as_micros(),
+                                st_min.as_micros()
+                            );
                         }
-                        None => {
-                            log::error!("ISOTP CAN frame too short! {frame:?}");
+                    }
+                    rx_memory.rx_timestamp_us = frame.get_timestamp_us();
+
+                    log::debug!("ISOTP continue frame {data:02X?}");
+                    match rx_memory.add_continuous_frame(data) {
+                        IsoTpRxAction::SendFC => {
+                            rx_memory.bs = self.cfg.block_size;
+                            rx_memory.frames_received = 0; // Reset the counter
+                            self.write_flow_control(default_tx_addr)?;
                         }
+                        IsoTpRxAction::Completed => break,
+                        IsoTpRxAction::WrongSequence => {
+                            return Err(IsoTpError::WrongSequenceNumber.into())
+                        }
+                        IsoTpRxAction::None => {}
+                    }
+                    if rx_memory.receiving {
+                        deadline = Instant::now() + n_cr;
                     }
                 }
-                Err(e) => {
-                    log::error!("Error: read can {e:?}");
-                    //println!("Error: read can {e:?}");
-                    self.connection_status = false;
-                    break;
+                0x30 => {
+                    // Flow control
+                    log::debug!("ISOTP Flow control {data:02X?}. Note: this should not happen");
+                }
+                _ => {
+                    log::error!("Invalid ISOTP CAN frame! {frame:?}");
                 }
             }
         }
 
-        if rx_memory.completed {
-            log::debug!("Received data: {}", rx_memory.format_data());
-            //println!("Received data: {}", rx_memory.format_data());
-            process_ecu_response(frame.clone().get_sid(), &rx_memory.data);
-            return rx_memory.data;
+        log::debug!("Received data: {}", rx_memory.format_data());
+        self.last_rx_timestamp_us = first_rx_timestamp_us;
+        Ok(rx_memory.data)
+    }
+}
+
+/// Lets a diagnostic server run on top of the protocol handler, like on any other ISO-TP channel
+impl PayloadChannel for IsoTpProtocol {
+    fn open(&mut self) -> ChannelResult<()> {
+        if !self.connection_status {
+            self.init();
+        }
+        match self.connection_status {
+            true => Ok(()),
+            false => Err(ChannelError::InterfaceNotOpen),
+        }
+    }
+
+    fn close(&mut self) -> ChannelResult<()> {
+        self.connection_status = false;
+        self.channel.close()
+    }
+
+    fn set_ids(&mut self, send: u32, recv: u32) -> ChannelResult<()> {
+        self.tx_id = Some(send);
+        self.set_rx_id(recv);
+        Ok(())
+    }
+
+    fn read_bytes(&mut self, timeout_ms: u32) -> ChannelResult<Vec<u8>> {
+        let tx_id = self.tx_id.ok_or(ChannelError::ConfigurationError)?;
+        self.receive(tx_id, timeout_ms)
+    }
+
+    /// Writes a payload, the address byte comes from the addressing mode so `ext_id` is not supported.
+    /// Each frame has N_As to be sent, `_timeout_ms` is not used
+    fn write_bytes(
+        &mut self,
+        addr: u32,
+        ext_id: Option<u8>,
+        buffer: &[u8],
+        _timeout_ms: u32,
+    ) -> ChannelResult<()> {
+        if ext_id.is_some() {
+            return Err(ChannelError::UnsupportedRequest);
         }
+        self.check_bus();
+        self.write_payload(addr, buffer).inspect_err(|_| {
+            self.connection_status = false;
+        })
+    }
 
-        vec![]
+    fn clear_rx_buffer(&mut self) -> ChannelResult<()> {
+        self.channel.clear_rx_buffer()
+    }
+
+    fn clear_tx_buffer(&mut self) -> ChannelResult<()> {
+        self.channel.clear_tx_buffer()
+    }
+
+    fn last_rx_timestamp_us(&self) -> Option<u64> {
+        self.last_rx_timestamp_us
+    }
+}
+
+impl IsoTPChannel for IsoTpProtocol {
+    fn set_iso_tp_cfg(&mut self, cfg: IsoTPSettings) -> ChannelResult<()> {
+        self.cfg = cfg;
+        Ok(())
     }
 }
 
 #[cfg(test)]
 pub mod test {
-    use super::IsoTpProtocol;
-    use crate::core::channel::CanFrame;
+    use super::{BusRecoveryPolicy, CanBusEvent, IsoTpAddressing, IsoTpProtocol};
+    use crate::core::channel::{
+        st_min_duration, CanBusState, CanBusStatus, CanFrame, ChannelError, FilterPacketChannel,
+        IsoTPSettings, IsoTpError, IsoTpTimeouts, Packet, PacketChannel, COMMON_CAN_BAUD_RATES,
+    };
+    use crate::hardware::fault_injection::{
+        Fault, FaultDirection, FaultInjectionChannel, FaultInjector,
+    };
+    use crate::hardware::hardware_tests::EmuCanChannel;
+    use crate::hardware::pcan_usb::pcan_types::PcanUSB;
+    use std::sync::{mpsc, Arc, Mutex};
+    use std::time::{Duration, Instant};
 
     #[test]
     pub fn test_can_isotp() {
-        let mut client = IsoTpProtocol::new();
+        let mut client = IsoTpProtocol::new_pcan_usb(PcanUSB::USB1).unwrap();
         client.init();
         let data: [u8; 8] = [0x01, 0x11, 0, 0, 0, 0, 0, 0];
         let msg = CanFrame::new(0x784, &data, false);
 
         client.send_receive(msg);
     }
+
+    #[test]
+    pub fn test_emulated_channel() {
+        let (tester_tx, ecu_rx) = mpsc::channel::<CanFrame>();
+        let (ecu_tx, tester_rx) = mpsc::channel::<CanFrame>();
+        let mut client =
+            IsoTpProtocol::new(Box::new(EmuCanChannel::new(tester_tx, tester_rx, "Tester")));
+        let mut ecu = EmuCanChannel::new(ecu_tx, ecu_rx, "ECU");
+
+        let ecu_thread = std::thread::spawn(move || {
+            let req = ecu.read_packets(1, 5000).unwrap();
+            assert_eq!(&req[0].get_data()[..2], &[0x01, 0x3E]);
+            let resp = CanFrame::new(0x7F0, &[0x02, 0x7E, 0x00, 0, 0, 0, 0, 0], false);
+            ecu.write_packets(vec![resp], 0).unwrap();
+        });
+
+        client.init();
+        assert!(client.connection_status);
+        let data: [u8; 8] = [0x01, 0x3E, 0, 0, 0, 0, 0, 0];
+        let resp = client.send_receive(CanFrame::new(0x784, &data, false));
+        ecu_thread.join().unwrap();
+
+        assert_eq!(resp, vec![0x7E, 0x00]);
+        assert!(client.connection_status);
+    }
+
+    fn recv_frame(ecu: &mut EmuCanChannel, timeout_ms: u128) -> Option<CanFrame> {
+        let start = Instant::now();
+        while start.elapsed().as_millis() < timeout_ms {
+            if let Some(f) = ecu.read_packets(1, 0).unwrap().pop() {
+                return Some(f);
+            }
+        }
+        None
+    }
+
+    #[test]
+    pub fn test_multi_frame_request() {
+        let (tester_tx, ecu_rx) = mpsc::channel::<CanFrame>();
+        let (ecu_tx, tester_rx) = mpsc::channel::<CanFrame>();
+        let mut client =
+            IsoTpProtocol::new(Box::new(EmuCanChannel::new(tester_tx, tester_rx, "Tester")));
+        let mut ecu = EmuCanChannel::new(ecu_tx, ecu_rx, "ECU");
+        let payload = (0..30).collect::<Vec<u8>>();
+        let expected = payload.clone();
+
+        let ecu_thread = std::thread::spawn(move || {
+            let ff = recv_frame(&mut ecu, 5000).unwrap();
+            assert_eq!(&ff.get_data()[..2], &[0x10, 30]);
+            let mut rx = ff.get_data()[2..].to_vec();
+
+            // BS of 2, the tester has to stop after 2 consecutive frames
+            let fc = CanFrame::new(0x7F0, &[0x30, 2, 0, 0, 0, 0, 0, 0], false);
+            ecu.write_packets(vec![fc], 0).unwrap();
+            for pci in [0x21, 0x22] {
+                let cf = recv_frame(&mut ecu, 1000).unwrap();
+                assert_eq!(cf.get_data()[0], pci);
+                rx.extend_from_slice(&cf.get_data()[1..]);
+            }
+            assert!(recv_frame(&mut ecu, 100).is_none());
+
+            // No BS, but 20ms STmin for the rest of the payload
+            let fc = CanFrame::new(0x7F0, &[0x30, 0, 20, 0, 0, 0, 0, 0], false);
+            let fc_time = Instant::now();
+            ecu.write_packets(vec![fc], 0).unwrap();
+            for (pci, min_delay) in [(0x23, 20), (0x24, 40)] {
+                let cf = recv_frame(&mut ecu, 1000).unwrap();
+                assert!(fc_time.elapsed() >= Duration::from_millis(min_delay));
+                assert_eq!(cf.get_data()[0], pci);
+                rx.extend_from_slice(&cf.get_data()[1..]);
+            }
+            rx.truncate(30);
+            assert_eq!(rx, expected);
+
+            let resp = CanFrame::new(0x7F0, &[0x01, 0x40, 0, 0, 0, 0, 0, 0], false);
+            ecu.write_packets(vec![resp], 0).unwrap();
+        });
+
+        client.init();
+        let resp = client.send_receive_payload(0x784, &payload);
+        ecu_thread.join().unwrap();
+
+        assert_eq!(resp, vec![0x40]);
+        assert!(client.connection_status);
+    }
+
+    #[test]
+    pub fn test_lost_consecutive_frame() {
+        let (tester_tx, ecu_rx) = mpsc::channel::<CanFrame>();
+        let (ecu_tx, tester_rx) = mpsc::channel::<CanFrame>();
+        let injector = FaultInjector::default();
+        let tester = EmuCanChannel::new(tester_tx, tester_rx, "Tester");
+        let mut client = IsoTpProtocol::new(Box::new(FaultInjectionChannel::new(
+            tester,
+            injector.clone(),
+        )));
+        let mut ecu = EmuCanChannel::new(ecu_tx, ecu_rx, "ECU");
+
+        let ecu_thread = std::thread::spawn(move || {
+            for _ in 0..2 {
+                recv_frame(&mut ecu, 5000).unwrap();
+                let ff = CanFrame::new(0x7F0, &[0x10, 16, 0x62, 0xF1, 0x90, 1, 2, 3], false);
+                ecu.write_packets(vec![ff], 0).unwrap();
+                assert_eq!(recv_frame(&mut ecu, 1000).unwrap().get_data()[0], 0x30);
+                for cf in [[0x21, 4, 5, 6, 7, 8, 9, 10], [0x22, 11, 12, 13, 0, 0, 0, 0]] {
+                    std::thread::sleep(Duration::from_millis(5));
+                    ecu.write_packets(vec![CanFrame::new(0x7F0, &cf, false)], 0)
+                        .unwrap();
+                }
+            }
+        });
+
+        client.init();
+        let req = CanFrame::new(0x784, &[0x03, 0x22, 0xF1, 0x90, 0, 0, 0, 0], false);
+        // The first consecutive frame is lost, the second one has the wrong sequence number
+        injector.schedule(FaultDirection::Rx, Some(0x7F0), 1, Fault::Drop);
+        assert!(client.send_receive(req).is_empty());
+        assert_eq!(injector.scheduled_count(), 0);
+
+        // The next response is received in full
+        let resp = client.send_receive(req);
+        ecu_thread.join().unwrap();
+        assert_eq!(
+            resp,
+            [0x62, 0xF1, 0x90, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13]
+        );
+    }
+
+    #[test]
+    pub fn test_late_flow_control() {
+        let (tester_tx, ecu_rx) = mpsc::channel::<CanFrame>();
+        let (ecu_tx, tester_rx) = mpsc::channel::<CanFrame>();
+        let injector = FaultInjector::default();
+        let tester = EmuCanChannel::new(tester_tx, tester_rx, "Tester");
+        let mut client = IsoTpProtocol::new(Box::new(FaultInjectionChannel::new(
+            tester,
+            injector.clone(),
+        )));
+        let mut ecu = EmuCanChannel::new(ecu_tx, ecu_rx, "ECU");
+        let payload = (0..20).collect::<Vec<u8>>();
+
+        let ecu_thread = std::thread::spawn(move || {
+            let ff = recv_frame(&mut ecu, 5000).unwrap();
+            assert_eq!(&ff.get_data()[..2], &[0x10, 20]);
+            let fc = CanFrame::new(0x7F0, &[0x30, 0, 0, 0, 0, 0, 0, 0], false);
+            let fc_time = Instant::now();
+            ecu.write_packets(vec![fc], 0).unwrap();
+            for pci in [0x21, 0x22] {
+                let cf = recv_frame(&mut ecu, 1000).unwrap();
+                assert_eq!(cf.get_data()[0], pci);
+            }
+            // The tester waited for the flow control
+            assert!(fc_time.elapsed() >= Duration::from_millis(300));
+            let resp = CanFrame::new(0x7F0, &[0x01, 0x40, 0, 0, 0, 0, 0, 0], false);
+            ecu.write_packets(vec![resp], 0).unwrap();
+        });
+
+        client.init();
+        injector.schedule(FaultDirection::Rx, None, 0, Fault::Delay(300));
+        let resp = client.send_receive_payload(0x784, &payload);
+        ecu_thread.join().unwrap();
+
+        assert_eq!(resp, vec![0x40]);
+        assert!(client.connection_status);
+    }
+
+    #[test]
+    pub fn test_flow_control_wait_and_overflow() {
+        let (tester_tx, ecu_rx) = mpsc::channel::<CanFrame>();
+        let (ecu_tx, tester_rx) = mpsc::channel::<CanFrame>();
+        let mut client =
+            IsoTpProtocol::new(Box::new(EmuCanChannel::new(tester_tx, tester_rx, "Tester")));
+        let mut ecu = EmuCanChannel::new(ecu_tx, ecu_rx, "ECU");
+        let cfg = IsoTPSettings {
+            timeouts: IsoTpTimeouts {
+                n_bs: 200,
+                n_wft_max: 2,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        client.set_iso_tp_cfg(cfg).unwrap();
+        let payload = (0..20).collect::<Vec<u8>>();
+
+        let ecu_thread = std::thread::spawn(move || {
+            let fc = |fs: u8| CanFrame::new(0x7F0, &[0x30 | fs, 0, 0, 0, 0, 0, 0, 0], false);
+            // Each wait frame starts N_Bs again
+            assert_eq!(recv_frame(&mut ecu, 5000).unwrap().get_data()[0], 0x10);
+            for fs in [1, 1, 0] {
+                std::thread::sleep(Duration::from_millis(100));
+                ecu.write_packets(vec![fc(fs)], 0).unwrap();
+            }
+            for pci in [0x21, 0x22] {
+                assert_eq!(recv_frame(&mut ecu, 1000).unwrap().get_data()[0], pci);
+            }
+            let resp = CanFrame::new(0x7F0, &[0x01, 0x40, 0, 0, 0, 0, 0, 0], false);
+            ecu.write_packets(vec![resp], 0).unwrap();
+
+            for fcs in [vec![fc(1), fc(1), fc(1)], vec![fc(2)], vec![]] {
+                assert_eq!(recv_frame(&mut ecu, 5000).unwrap().get_data()[0], 0x10);
+                ecu.write_packets(fcs, 0).unwrap();
+            }
+        });
+
+        client.init();
+        assert_eq!(
+            client.try_send_receive_payload(0x784, &payload).unwrap(),
+            [0x40]
+        );
+        assert!(matches!(
+            client.try_send_receive_payload(0x784, &payload),
+            Err(ChannelError::IsoTpError(IsoTpError::WaitLimitExceeded(2)))
+        ));
+        assert!(matches!(
+            client.try_send_receive_payload(0x784, &payload),
+            Err(ChannelError::IsoTpError(IsoTpError::Overflow))
+        ));
+        let start = Instant::now();
+        assert!(matches!(
+            client.try_send_receive_payload(0x784, &payload),
+            Err(ChannelError::IsoTpError(IsoTpError::TimeoutBs))
+        ));
+        assert!(start.elapsed() >= Duration::from_millis(200));
+        ecu_thread.join().unwrap();
+    }
+
+    #[test]
+    pub fn test_consecutive_frame_timeout() {
+        let (tester_tx, ecu_rx) = mpsc::channel::<CanFrame>();
+        let (ecu_tx, tester_rx) = mpsc::channel::<CanFrame>();
+        let mut client =
+            IsoTpProtocol::new(Box::new(EmuCanChannel::new(tester_tx, tester_rx, "Tester")));
+        let mut ecu = EmuCanChannel::new(ecu_tx, ecu_rx, "ECU");
+        let cfg = IsoTPSettings {
+            st_min: 0xF5,
+            timeouts: IsoTpTimeouts {
+                n_cr: 100,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        client.set_iso_tp_cfg(cfg).unwrap();
+
+        let ecu_thread = std::thread::spawn(move || {
+            recv_frame(&mut ecu, 5000).unwrap();
+            let ff = CanFrame::new(0x7F0, &[0x10, 27, 0x62, 0xF1, 0x90, 1, 2, 3], false);
+            ecu.write_packets(vec![ff], 0).unwrap();
+            // STmin of 500us
+            assert_eq!(
+                &recv_frame(&mut ecu, 1000).unwrap().get_data()[..3],
+                &[0x30, 8, 0xF5]
+            );
+            // Faster than STmin, which is only logged
+            for cf in [
+                [0x21, 4, 5, 6, 7, 8, 9, 10],
+                [0x22, 11, 12, 13, 14, 15, 16, 17],
+            ] {
+                ecu.write_packets(vec![CanFrame::new(0x7F0, &cf, false)], 0)
+                    .unwrap();
+            }
+        });
+
+        client.init();
+        let start = Instant::now();
+        assert!(matches!(
+            client.try_send_receive_payload(0x784, &[0x22, 0xF1, 0x90]),
+            Err(ChannelError::IsoTpError(IsoTpError::TimeoutCr))
+        ));
+        assert!(start.elapsed() >= Duration::from_millis(100));
+        assert!(start.elapsed() < Duration::from_millis(1000));
+        ecu_thread.join().unwrap();
+    }
+
+    #[test]
+    pub fn test_st_min_microseconds() {
+        assert_eq!(st_min_duration(0x7F), Duration::from_millis(127));
+        assert_eq!(st_min_duration(0xF1), Duration::from_micros(100));
+        assert_eq!(st_min_duration(0xF9), Duration::from_micros(900));
+        assert_eq!(st_min_duration(0xFA), Duration::from_millis(127));
+
+        let (tester_tx, ecu_rx) = mpsc::channel::<CanFrame>();
+        let (ecu_tx, tester_rx) = mpsc::channel::<CanFrame>();
+        let mut client =
+            IsoTpProtocol::new(Box::new(EmuCanChannel::new(tester_tx, tester_rx, "Tester")));
+        let mut ecu = EmuCanChannel::new(ecu_tx, ecu_rx, "ECU");
+        let payload = (0..30).collect::<Vec<u8>>();
+
+        let ecu_thread = std::thread::spawn(move || {
+            recv_frame(&mut ecu, 5000).unwrap();
+            // STmin of 900us
+            let fc = CanFrame::new(0x7F0, &[0x30, 0, 0xF9, 0, 0, 0, 0, 0], false);
+            let fc_time = Instant::now();
+            ecu.write_packets(vec![fc], 0).unwrap();
+            for pci in [0x21, 0x22, 0x23, 0x24] {
+                assert_eq!(recv_frame(&mut ecu, 1000).unwrap().get_data()[0], pci);
+            }
+            let elapsed = fc_time.elapsed();
+            assert!(elapsed >= Duration::from_micros(3600));
+            assert!(elapsed < Duration::from_millis(100));
+            let resp = CanFrame::new(0x7F0, &[0x01, 0x40, 0, 0, 0, 0, 0, 0], false);
+            ecu.write_packets(vec![resp], 0).unwrap();
+        });
+
+        client.init();
+        assert_eq!(
+            client.try_send_receive_payload(0x784, &payload).unwrap(),
+            [0x40]
+        );
+        ecu_thread.join().unwrap();
+    }
+
+    #[test]
+    pub fn test_rx_id_filter() {
+        let (tester_tx, ecu_rx) = mpsc::channel::<CanFrame>();
+        let (ecu_tx, tester_rx) = mpsc::channel::<CanFrame>();
+        let tester = Arc::new(Mutex::new(EmuCanChannel::new(
+            tester_tx, tester_rx, "Tester",
+        )));
+        let mut client = IsoTpProtocol::new(Box::new(tester.clone()));
+        let mut ecu = EmuCanChannel::new(ecu_tx, ecu_rx, "ECU");
+
+        client.set_rx_id(0x7F0);
+        client.init();
+        assert_eq!(tester.lock().unwrap().filter_count(), 1);
+        // Drop the adapter filter, so the other ECU's traffic reaches the ISO-TP layer
+        tester.lock().unwrap().remove_filter(0).unwrap();
+
+        let ecu_thread = std::thread::spawn(move || {
+            let req = ecu.read_packets(1, 5000).unwrap();
+            assert_eq!(&req[0].get_data()[..2], &[0x01, 0x3E]);
+            let noise = CanFrame::new(0x7E8, &[0x02, 0x50, 0x01, 0, 0, 0, 0, 0], false);
+            let resp = CanFrame::new(0x7F0, &[0x02, 0x7E, 0x00, 0, 0, 0, 0, 0], false);
+            ecu.write_packets(vec![noise, resp], 0).unwrap();
+        });
+
+        let data: [u8; 8] = [0x01, 0x3E, 0, 0, 0, 0, 0, 0];
+        let resp = client.send_receive(CanFrame::new(0x784, &data, false));
+        ecu_thread.join().unwrap();
+
+        assert_eq!(resp, vec![0x7E, 0x00]);
+    }
+
+    #[test]
+    pub fn test_detect_can_speed() {
+        let (tester_tx, _ecu_rx) = mpsc::channel::<CanFrame>();
+        let (_ecu_tx, tester_rx) = mpsc::channel::<CanFrame>();
+        let tester = Arc::new(Mutex::new(EmuCanChannel::new(
+            tester_tx, tester_rx, "Tester",
+        )));
+        let mut client = IsoTpProtocol::new(Box::new(tester.clone()));
+        tester.lock().unwrap().set_bus_baud(250_000);
+
+        assert!(matches!(
+            client.detect_can_speed(&[500_000, 125_000], 10),
+            Err(ChannelError::BaudNotDetected)
+        ));
+        assert_eq!(
+            client.detect_can_speed(COMMON_CAN_BAUD_RATES, 10).unwrap(),
+            250_000
+        );
+        client.init();
+        assert_eq!(tester.lock().unwrap().baud(), 250_000);
+    }
+
+    fn bus_off_client(
+        policy: BusRecoveryPolicy,
+    ) -> (
+        IsoTpProtocol,
+        Arc<Mutex<EmuCanChannel>>,
+        mpsc::Receiver<CanBusEvent>,
+    ) {
+        let (tester_tx, _ecu_rx) = mpsc::channel::<CanFrame>();
+        let (_ecu_tx, tester_rx) = mpsc::channel::<CanFrame>();
+        let tester = Arc::new(Mutex::new(EmuCanChannel::new(
+            tester_tx, tester_rx, "Tester",
+        )));
+        let mut client = IsoTpProtocol::new(Box::new(tester.clone()));
+        client.set_bus_recovery(policy);
+        let events = client.subscribe_bus_events();
+        client.init();
+        assert_eq!(client.check_bus().unwrap().state, CanBusState::ErrorActive);
+        tester.lock().unwrap().set_bus_state(CanBusState::BusOff);
+        (client, tester, events)
+    }
+
+    #[test]
+    pub fn test_bus_off_recovery() {
+        let policy = BusRecoveryPolicy::Automatic {
+            max_attempts: 3,
+            delay_ms: 0,
+        };
+        let (mut client, tester, events) = bus_off_client(policy);
+
+        assert_eq!(client.check_bus().unwrap().state, CanBusState::ErrorActive);
+        assert!(client.connection_status);
+        assert_eq!(tester.lock().unwrap().open_count(), 2);
+
+        let bus_off = CanBusStatus {
+            state: CanBusState::BusOff,
+            ..Default::default()
+        };
+        assert_eq!(
+            events.try_iter().collect::<Vec<_>>(),
+            vec![
+                CanBusEvent::StateChanged(bus_off),
+                CanBusEvent::Recovered(1),
+                CanBusEvent::StateChanged(CanBusStatus::default()),
+            ]
+        );
+    }
+
+    #[test]
+    pub fn test_bus_off_no_recovery() {
+        let (mut client, tester, events) = bus_off_client(BusRecoveryPolicy::Disabled);
+
+        assert_eq!(client.check_bus().unwrap().state, CanBusState::BusOff);
+        assert_eq!(tester.lock().unwrap().open_count(), 1);
+        assert_eq!(events.try_iter().count(), 1);
+    }
+
+    #[test]
+    pub fn test_addressing_validation() {
+        let fixed = IsoTpAddressing::NormalFixed {
+            target: 0x10,
+            source: 0xF1,
+        };
+        assert_eq!(fixed.tx_id(), 0x18DA_10F1);
+        assert_eq!(fixed.rx_id(), 0x18DA_F110);
+        let mixed = IsoTpAddressing::Mixed {
+            target: 0x10,
+            source: 0xF1,
+            extension: 0x55,
+        };
+        assert_eq!((mixed.tx_id(), mixed.rx_id()), (0x18CE_10F1, 0x18CE_F110));
+        assert_eq!(mixed.address_bytes(), Some((0x55, 0x55)));
+
+        let invalid = [
+            IsoTpAddressing::Normal {
+                tx_id: 0x800,
+                rx_id: 0x7F0,
+            },
+            IsoTpAddressing::Normal {
+                tx_id: 0x7E0,
+                rx_id: 0x7E0,
+            },
+            IsoTpAddressing::Extended {
+                tx_id: 0x784,
+                rx_id: 0x18DA_F110,
+                tx_ext: 0x01,
+                rx_ext: 0x02,
+            },
+            IsoTpAddressing::NormalFixed {
+                target: 0x10,
+                source: 0x10,
+            },
+        ];
+        let mut client = IsoTpProtocol::new(Box::new(EmuCanChannel::new(
+            mpsc::channel().0,
+            mpsc::channel().1,
+            "Tester",
+        )));
+        for addressing in invalid {
+            assert!(matches!(
+                client.set_addressing(addressing),
+                Err(ChannelError::ConfigurationError)
+            ));
+        }
+
+        for addressing in [IsoTpAddressing::default(), fixed, mixed] {
+            assert_eq!(addressing.to_string().parse(), Ok(addressing));
+        }
+        assert_eq!(
+            "extended:7E0:7E8:F1:10".parse(),
+            Ok(IsoTpAddressing::Extended {
+                tx_id: 0x7E0,
+                rx_id: 0x7E8,
+                tx_ext: 0xF1,
+                rx_ext: 0x10,
+            })
+        );
+        for s in [
+            "normal:784",
+            "normal:784:784",
+            "fixed:10:F1",
+            "mixed:10:F1:100",
+        ] {
+            assert!(s.parse::<IsoTpAddressing>().is_err(), "{s}");
+        }
+    }
+
+    #[test]
+    pub fn test_addressing_can_id_format() {
+        let tester = Arc::new(Mutex::new(EmuCanChannel::new(
+            mpsc::channel().0,
+            mpsc::channel().1,
+            "Tester",
+        )));
+        let mut client = IsoTpProtocol::new(Box::new(tester.clone()));
+        client
+            .set_addressing(IsoTpAddressing::NormalFixed {
+                target: 0x10,
+                source: 0xF1,
+            })
+            .unwrap();
+        client.init();
+        assert!(tester.lock().unwrap().use_ext());
+
+        client.set_addressing(IsoTpAddressing::default()).unwrap();
+        client.init();
+        assert!(!tester.lock().unwrap().use_ext());
+    }
+
+    #[test]
+    pub fn test_addressing_modes() {
+        let modes = [
+            IsoTpAddressing::Normal {
+                tx_id: 0x7E0,
+                rx_id: 0x7E8,
+            },
+            IsoTpAddressing::NormalFixed {
+                target: 0x10,
+                source: 0xF1,
+            },
+            IsoTpAddressing::Extended {
+                tx_id: 0x6F1,
+                rx_id: 0x610,
+                tx_ext: 0x10,
+                rx_ext: 0xF1,
+            },
+            IsoTpAddressing::Mixed {
+                target: 0x10,
+                source: 0xF1,
+                extension: 0x55,
+            },
+        ];
+        for addressing in modes {
+            let (tester_tx, ecu_rx) = mpsc::channel::<CanFrame>();
+            let (ecu_tx, tester_rx) = mpsc::channel::<CanFrame>();
+            let mut client =
+                IsoTpProtocol::new(Box::new(EmuCanChannel::new(tester_tx, tester_rx, "Tester")));
+            let mut ecu = EmuCanChannel::new(ecu_tx, ecu_rx, "ECU");
+            client.set_addressing(addressing).unwrap();
+            let request = (0..20).collect::<Vec<u8>>();
+            let response = (0x40..0x4F).collect::<Vec<u8>>();
+            let (ecu_request, ecu_response) = (request.clone(), response.clone());
+
+            let ecu_thread = std::thread::spawn(move || {
+                let ext = addressing.address_bytes();
+                // Checks the CAN ID and address byte of a frame from the tester, and strips it
+                let recv = |ecu: &mut EmuCanChannel| {
+                    let f = recv_frame(ecu, 5000).expect("No frame from tester");
+                    assert_eq!(f.get_address(), addressing.tx_id(), "{addressing}");
+                    assert_eq!(f.is_extended(), addressing.is_extended_id(), "{addressing}");
+                    let data = f.get_data().to_vec();
+                    match ext {
+                        Some((tx_ext, _)) => {
+                            assert_eq!(data[0], tx_ext, "{addressing}");
+                            data[1..].to_vec()
+                        }
+                        None => data,
+                    }
+                };
+                let frame = |data: &[u8]| {
+                    let mut tx = ext.map(|(_, rx_ext)| vec![rx_ext]).unwrap_or_default();
+                    tx.extend_from_slice(data);
+                    tx.resize(8, 0xCC);
+                    CanFrame::new(addressing.rx_id(), &tx, addressing.is_extended_id())
+                };
+
+                // Multi frame request
+                let ff = recv(&mut ecu);
+                assert_eq!(&ff[..2], &[0x10, 20]);
+                let mut received = ff[2..].to_vec();
+                ecu.write_packets(vec![frame(&[0x30, 0, 0])], 0).unwrap();
+                let mut sn = 0x21;
+                while received.len() < 20 {
+                    let cf = recv(&mut ecu);
+                    assert_eq!(cf[0], sn, "{addressing}");
+                    received.extend_from_slice(&cf[1..]);
+                    sn += 1;
+                }
+                assert_eq!(&received[..20], &ecu_request[..]);
+
+                // Frames for other addresses on the same CAN ID are ignored
+                if ext.is_some() {
+                    let mut other = vec![0x00, 0x02, 0x7F, 0x11];
+                    other.resize(8, 0xCC);
+                    let other =
+                        CanFrame::new(addressing.rx_id(), &other, addressing.is_extended_id());
+                    ecu.write_packets(vec![other], 0).unwrap();
+                }
+
+                // Multi frame response
+                let per_frame = if ext.is_some() { 5 } else { 6 };
+                let mut ff = vec![0x10, ecu_response.len() as u8];
+                ff.extend_from_slice(&ecu_response[..per_frame]);
+                ecu.write_packets(vec![frame(&ff)], 0).unwrap();
+                let fc = recv(&mut ecu);
+                assert_eq!(fc[0], 0x30, "{addressing}");
+                let cfs = ecu_response[per_frame..]
+                    .chunks(per_frame + 1)
+                    .zip(0x21..)
+                    .map(|(chunk, sn)| {
+                        let mut cf = vec![sn];
+                        cf.extend_from_slice(chunk);
+                        frame(&cf)
+                    })
+                    .collect();
+                ecu.write_packets(cfs, 0).unwrap();
+            });
+
+            client.init();
+            assert_eq!(
+                client
+                    .try_send_receive_payload(addressing.tx_id(), &request)
+                    .unwrap(),
+                response,
+                "{addressing}"
+            );
+            ecu_thread.join().unwrap();
+        }
+    }
 }
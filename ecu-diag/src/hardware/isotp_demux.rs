@@ -0,0 +1,418 @@
+//! ISO-TP demultiplexer
+//!
+//! An [IsoTpDemux] owns one CAN channel and hands out any number of channels on it, each
+//! keyed by the (TX, RX) CAN ID pair of one ECU. Received frames are routed by CAN ID to
+//! the channel of that ECU, so sessions with several ECUs (VCU, RT, TM, ...) can run in
+//! parallel over a single adapter.
+//!
+//! Frames are routed by whichever channel reads first, there is no background thread.
+//! Frames on CAN IDs without a channel are dropped.
+
+use std::{
+    any::Any,
+    collections::{BTreeMap, VecDeque},
+    sync::{Arc, Mutex, MutexGuard},
+    time::{Duration, Instant},
+};
+
+use crate::core::channel::{
+    CanBusStatus, CanChannel, CanFrame, ChannelError, ChannelResult, FilterPacketChannel,
+    IsoTPChannel, Packet, PacketChannel, PayloadChannel,
+};
+
+use super::{software_isotp::SoftwareIsoTpChannel, Hardware, HardwareResult};
+
+/// Time to wait between polls of the CAN channel while a read blocks
+const POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+/// Maximum number of frames read from the CAN channel per poll
+const POLL_MAX_FRAMES: usize = 100;
+
+#[derive(Debug, Default)]
+struct Route {
+    tx_id: u32,
+    open: bool,
+    rx_queue: VecDeque<CanFrame>,
+}
+
+struct DemuxState {
+    channel: Box<dyn CanChannel>,
+    /// Routes by RX ID
+    routes: BTreeMap<u32, Route>,
+    /// CAN configuration of `channel`, set by the first channel configured
+    can_cfg: Option<(u32, Option<u32>, bool)>,
+    /// Device that created `channel`, see [IsoTpDemux::new_from_hardware]
+    _device: Option<Box<dyn Any + Send + Sync>>,
+}
+
+impl DemuxState {
+    fn is_open(&self) -> bool {
+        self.routes.values().any(|r| r.open)
+    }
+
+    fn route(&mut self, rx_id: u32) -> &mut Route {
+        self.routes
+            .get_mut(&rx_id)
+            .expect("ISO-TP demux route removed while in use")
+    }
+
+    /// Reads all frames available on the CAN channel, and queues them on their routes
+    fn poll(&mut self) -> ChannelResult<()> {
+        let frames = match self.channel.read_packets(POLL_MAX_FRAMES, 0) {
+            Ok(frames) => frames,
+            Err(ChannelError::BufferEmpty | ChannelError::ReadTimeout) => return Ok(()),
+            Err(e) => return Err(e),
+        };
+        for frame in frames {
+            match self.routes.get_mut(&frame.get_address()) {
+                Some(route) if route.open => route.rx_queue.push_back(frame),
+                _ => log::trace!("ISO-TP demux dropping frame {frame:?}"),
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone)]
+/// Hands out ISO-TP channels to several ECUs on one CAN channel. Clones share the CAN channel
+pub struct IsoTpDemux {
+    state: Arc<Mutex<DemuxState>>,
+}
+
+impl IsoTpDemux {
+    /// Creates a demultiplexer on top of an existing CAN channel
+    pub fn new(channel: Box<dyn CanChannel>) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(DemuxState {
+                channel,
+                routes: BTreeMap::new(),
+                can_cfg: None,
+                _device: None,
+            })),
+        }
+    }
+
+    /// Creates a demultiplexer on the CAN channel of a hardware device.
+    /// The device is owned by the demultiplexer from then on
+    pub fn new_from_hardware<H: Hardware + Send + Sync + 'static>(
+        mut device: H,
+    ) -> HardwareResult<Self> {
+        let demux = Self::new(device.create_can_channel()?);
+        demux.lock()._device = Some(Box::new(device));
+        Ok(demux)
+    }
+
+    /// Creates the CAN channel of the ECU that receives on `tx_id` and responds on `rx_id`.
+    /// Only frames with `rx_id` are read from it, which makes it suitable for
+    /// [IsoTpProtocol](super::isotp::IsoTpProtocol).
+    ///
+    /// Returns [ChannelError::ConfigurationError] if another channel already uses either ID
+    pub fn create_can_channel(&self, tx_id: u32, rx_id: u32) -> ChannelResult<DemuxCanChannel> {
+        let mut state = self.lock();
+        let in_use = state
+            .routes
+            .iter()
+            .any(|(rx, r)| [*rx, r.tx_id].contains(&rx_id) || [*rx, r.tx_id].contains(&tx_id));
+        if tx_id == rx_id || in_use {
+            return Err(ChannelError::ConfigurationError);
+        }
+        state.routes.insert(
+            rx_id,
+            Route {
+                tx_id,
+                ..Default::default()
+            },
+        );
+        Ok(DemuxCanChannel {
+            state: self.state.clone(),
+            rx_id,
+        })
+    }
+
+    /// Creates a software ISO-TP channel to the ECU that receives on `tx_id` and responds on
+    /// `rx_id`, see [IsoTpDemux::create_can_channel]. Its IDs are already set
+    pub fn create_iso_tp_channel(
+        &self,
+        tx_id: u32,
+        rx_id: u32,
+    ) -> ChannelResult<Box<dyn IsoTPChannel>> {
+        let can_channel = self.create_can_channel(tx_id, rx_id)?;
+        let mut channel = SoftwareIsoTpChannel::new(Box::new(can_channel));
+        channel.set_ids(tx_id, rx_id)?;
+        Ok(Box::new(channel))
+    }
+
+    /// Returns the (TX, RX) CAN ID pairs of the channels handed out
+    pub fn ids(&self) -> Vec<(u32, u32)> {
+        self.lock()
+            .routes
+            .iter()
+            .map(|(rx_id, r)| (r.tx_id, *rx_id))
+            .collect()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, DemuxState> {
+        self.state.lock().unwrap()
+    }
+}
+
+/// CAN channel of one ECU on an [IsoTpDemux]
+///
+/// The shared CAN channel is opened with the first of these and closed with the last.
+/// Dropping the channel frees its IDs
+pub struct DemuxCanChannel {
+    state: Arc<Mutex<DemuxState>>,
+    rx_id: u32,
+}
+
+impl DemuxCanChannel {
+    fn lock(&self) -> ChannelResult<MutexGuard<'_, DemuxState>> {
+        self.state
+            .lock()
+            .map_err(|e| ChannelError::Other(e.to_string()))
+    }
+
+    fn configure(&mut self, cfg: (u32, Option<u32>, bool)) -> ChannelResult<()> {
+        let mut state = self.lock()?;
+        if state.can_cfg == Some(cfg) {
+            return Ok(());
+        }
+        // The CAN channel is shared, it cannot be reconfigured while another ECU uses it
+        if state.is_open() {
+            return Err(ChannelError::ConfigurationError);
+        }
+        let (baud, fd_baud, ext) = cfg;
+        match fd_baud {
+            Some(data_baud) => state.channel.set_can_fd_cfg(baud, data_baud, ext)?,
+            None => state.channel.set_can_cfg(baud, ext)?,
+        }
+        state.can_cfg = Some(cfg);
+        Ok(())
+    }
+}
+
+impl Drop for DemuxCanChannel {
+    fn drop(&mut self) {
+        let _ = PacketChannel::close(self);
+        if let Ok(mut state) = self.state.lock() {
+            state.routes.remove(&self.rx_id);
+        }
+    }
+}
+
+impl FilterPacketChannel<CanFrame> for DemuxCanChannel {}
+
+impl CanChannel for DemuxCanChannel {
+    fn set_can_cfg(&mut self, baud: u32, use_extended: bool) -> ChannelResult<()> {
+        self.configure((baud, None, use_extended))
+    }
+
+    fn set_can_fd_cfg(
+        &mut self,
+        baud: u32,
+        data_baud: u32,
+        use_extended: bool,
+    ) -> ChannelResult<()> {
+        self.configure((baud, Some(data_baud), use_extended))
+    }
+
+    fn get_bus_status(&mut self) -> ChannelResult<CanBusStatus> {
+        self.lock()?.channel.get_bus_status()
+    }
+}
+
+impl PacketChannel<CanFrame> for DemuxCanChannel {
+    fn open(&mut self) -> ChannelResult<()> {
+        let mut state = self.lock()?;
+        if !state.is_open() {
+            state.channel.open()?;
+        }
+        state.route(self.rx_id).open = true;
+        Ok(())
+    }
+
+    fn close(&mut self) -> ChannelResult<()> {
+        let mut state = self.lock()?;
+        let route = state.route(self.rx_id);
+        if !route.open {
+            return Ok(());
+        }
+        route.open = false;
+        route.rx_queue.clear();
+        if !state.is_open() {
+            state.channel.close()?;
+        }
+        Ok(())
+    }
+
+    fn write_packets(&mut self, packets: Vec<CanFrame>, timeout_ms: u32) -> ChannelResult<()> {
+        let mut state = self.lock()?;
+        if !state.route(self.rx_id).open {
+            return Err(ChannelError::InterfaceNotOpen);
+        }
+        state.channel.write_packets(packets, timeout_ms)
+    }
+
+    fn read_packets(&mut self, max: usize, timeout_ms: u32) -> ChannelResult<Vec<CanFrame>> {
+        let deadline = Instant::now() + Duration::from_millis(timeout_ms.into());
+        loop {
+            let mut state = self.lock()?;
+            if !state.route(self.rx_id).open {
+                return Err(ChannelError::InterfaceNotOpen);
+            }
+            state.poll()?;
+            let queue = &mut state.route(self.rx_id).rx_queue;
+            let frames: Vec<CanFrame> = queue.drain(..max.min(queue.len())).collect();
+            if !frames.is_empty() || timeout_ms == 0 {
+                return Ok(frames);
+            }
+            if Instant::now() >= deadline {
+                return Err(ChannelError::BufferEmpty);
+            }
+            drop(state);
+            std::thread::sleep(POLL_INTERVAL);
+        }
+    }
+
+    fn clear_rx_buffer(&mut self) -> ChannelResult<()> {
+        let mut state = self.lock()?;
+        state.poll()?;
+        state.route(self.rx_id).rx_queue.clear();
+        Ok(())
+    }
+
+    fn clear_tx_buffer(&mut self) -> ChannelResult<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::IsoTpDemux;
+    use crate::core::channel::{
+        CanFrame, ChannelError, FilterPacketChannel, IsoTPSettings, Packet, PacketChannel,
+        PayloadChannel,
+    };
+    use crate::hardware::isotp::IsoTpProtocol;
+    use crate::hardware::virtual_bus::{VirtualBus, VirtualCanChannel};
+
+    /// Attaches an ECU to `bus` that receives requests on `req_id`
+    fn attach_ecu(bus: &VirtualBus, req_id: u32) -> VirtualCanChannel {
+        let mut ecu = bus.attach();
+        ecu.add_filter(&[req_id]).unwrap();
+        ecu.open().unwrap();
+        ecu
+    }
+
+    fn recv(ecu: &mut VirtualCanChannel) -> Vec<u8> {
+        ecu.read_packets(1, 5000).unwrap()[0].get_data().to_vec()
+    }
+
+    #[test]
+    pub fn test_demux_parallel_sessions() {
+        let bus = VirtualBus::new();
+        let ecus = [(0x7E0, 0x7E8), (0x7E1, 0x7E9), (0x18DA10F1, 0x18DAF110)];
+        // Each ECU responds to single frame requests with a 40 byte response,
+        // made of the request SID + 0x40 and the rest of the request repeated
+        let ecu_threads: Vec<_> = ecus
+            .iter()
+            .map(|&(req_id, resp_id)| {
+                let mut ecu = attach_ecu(&bus, req_id);
+                std::thread::spawn(move || {
+                    for _ in 0..3 {
+                        let req = recv(&mut ecu);
+                        let len = req[0] as usize;
+                        let mut resp = vec![req[1] + 0x40];
+                        resp.extend(req[2..=len].iter().cycle().take(39));
+
+                        let mut ff = vec![0x10, resp.len() as u8];
+                        ff.extend_from_slice(&resp[..6]);
+                        ecu.write_packets(vec![CanFrame::new(resp_id, &ff, false)], 0)
+                            .unwrap();
+                        assert_eq!(recv(&mut ecu)[0], 0x30);
+                        let cfs = resp[6..]
+                            .chunks(7)
+                            .zip(0x21..)
+                            .map(|(chunk, sn)| {
+                                let mut cf = vec![sn];
+                                cf.extend_from_slice(chunk);
+                                CanFrame::new(resp_id, &cf, false)
+                            })
+                            .collect();
+                        ecu.write_packets(cfs, 0).unwrap();
+                    }
+                })
+            })
+            .collect();
+
+        let demux = IsoTpDemux::new(Box::new(bus.attach()));
+        let testers: Vec<_> = ecus
+            .iter()
+            .enumerate()
+            .map(|(i, &(tx_id, rx_id))| {
+                let channel = demux.create_can_channel(tx_id, rx_id).unwrap();
+                let mut client = IsoTpProtocol::new(Box::new(channel));
+                client.set_rx_id(rx_id);
+                client.init();
+                assert!(client.connection_status);
+                std::thread::spawn(move || {
+                    for n in 0..3u8 {
+                        let resp = client.try_send_receive_payload(tx_id, &[0x22, i as u8, n]);
+                        let expected: Vec<u8> = std::iter::once(0x62)
+                            .chain([i as u8, n].into_iter().cycle().take(39))
+                            .collect();
+                        assert_eq!(resp.unwrap(), expected);
+                    }
+                })
+            })
+            .collect();
+        assert_eq!(
+            demux.ids(),
+            [(0x7E0, 0x7E8), (0x7E1, 0x7E9), (0x18DA10F1, 0x18DAF110)]
+        );
+
+        for t in testers.into_iter().chain(ecu_threads) {
+            t.join().unwrap();
+        }
+        // The channels were dropped with their sessions
+        assert!(demux.ids().is_empty());
+    }
+
+    #[test]
+    pub fn test_demux_iso_tp_channel() {
+        let bus = VirtualBus::new();
+        let mut ecu = attach_ecu(&bus, 0x7E0);
+        let ecu_thread = std::thread::spawn(move || {
+            let req = ecu.read_packets(1, 5000).unwrap();
+            assert_eq!(&req[0].get_data()[..3], &[0x02, 0x3E, 0x00]);
+            let other = CanFrame::new(0x7E9, &[0x02, 0x7E, 0x01], false);
+            let resp = CanFrame::new(0x7E8, &[0x02, 0x7E, 0x00], false);
+            ecu.write_packets(vec![other, resp], 0).unwrap();
+        });
+
+        let demux = IsoTpDemux::new(Box::new(bus.attach()));
+        let mut tester = demux.create_iso_tp_channel(0x7E0, 0x7E8).unwrap();
+        tester.set_iso_tp_cfg(IsoTPSettings::default()).unwrap();
+        tester.open().unwrap();
+        let mut other = demux.create_can_channel(0x7E1, 0x7E9).unwrap();
+        other.open().unwrap();
+        // Each ID belongs to one channel
+        for (tx_id, rx_id) in [(0x7E2, 0x7E8), (0x7E8, 0x7EA), (0x7E3, 0x7E3)] {
+            assert!(matches!(
+                demux.create_can_channel(tx_id, rx_id),
+                Err(ChannelError::ConfigurationError)
+            ));
+        }
+
+        let resp = tester.read_write_bytes(0x7E0, None, &[0x3E, 0x00], 100, 5000);
+        assert_eq!(resp.unwrap(), [0x7E, 0x00]);
+        ecu_thread.join().unwrap();
+        let frames = other.read_packets(10, 1000).unwrap();
+        assert_eq!(frames.len(), 1);
+        assert_eq!(&frames[0].get_data()[..3], &[0x02, 0x7E, 0x01]);
+
+        drop(other);
+        assert_eq!(demux.ids(), [(0x7E0, 0x7E8)]);
+        assert!(demux.create_can_channel(0x7E1, 0x7E9).is_ok());
+    }
+}
@@ -2,12 +2,28 @@
 //! for interacting with common hardware that can be used for either Bench setups or OBD2 adapters
 //! in order to communicate with vehicle ECUs
 
+pub mod async_channel;
+pub mod doip;
+pub mod fault_injection;
 pub mod isotp;
+pub mod isotp_demux;
+#[cfg(feature = "passthru")]
+pub mod passthru;
 pub mod pcan_usb;
+pub mod recording;
+pub mod replay;
+pub mod slcan;
+#[cfg(target_os = "linux")]
+pub mod socketcan;
 pub mod software_isotp;
 pub mod tcp;
+pub mod trace;
+pub mod udp_can;
+pub mod virtual_bus;
 
 use crate::core::channel::{CanChannel, IsoTPChannel};
+#[cfg(feature = "passthru")]
+use std::sync::Arc;
 
 /// Hardware API result
 pub type HardwareResult<T> = Result<T, HardwareError>;
@@ -28,7 +44,7 @@ pub trait Hardware: Clone {
         force_native: bool,
     ) -> HardwareResult<Box<dyn IsoTPChannel>>;
 
-    fn create_native_iso_tp_channel(&mut self) -> HardwareResult<dyn CanChannel>>;
+    fn create_native_iso_tp_channel(&mut self) -> HardwareResult<Box<dyn CanChannel>>;
 
     /// Creates a CAN Channel on the devices.
     /// This channel will live for as long as the hardware trait. Upon being dropped,
@@ -69,6 +85,8 @@ pub struct HardwareInfo {
     pub vendor: Option<String>,
     /// Optional version of the firmware running on the adapter / device
     pub device_fw_version: Option<String>,
+    /// Optional ID the adapter reports, used to tell identical adapters apart
+    pub device_id: Option<u32>,
     /// Optional API standard the device conforms to
     pub api_version: Option<String>,
     /// Optional library (Dll/So/Dynlib) version used
@@ -116,6 +134,9 @@ pub enum HardwareError {
     /// Function called on device that has not yet been opened
     #[error("Device was not opened")]
     DeviceNotOpen,
+    /// A CAN trace file could not be read or parsed
+    #[error("Trace file error: {0}")]
+    TraceFileError(String),
 
     /// Lib loading error
     #[cfg(feature = "passthru")]
@@ -237,22 +258,33 @@ pub mod hardware_tests {
     }
 
     use std::{
+        collections::BTreeMap,
         sync::{mpsc, Arc},
         time::Instant,
     };
 
     use crate::{
         core::channel::{
-            CanChannel, CanFrame, ChannelError, IsoTPChannel, IsoTPSettings, PacketChannel,
+            host_timestamp_us, CanBusState, CanBusStatus, CanChannel, CanFrame, ChannelError,
+            FilterPacketChannel, IsoTPChannel, IsoTPSettings, IsoTpError, Packet, PacketChannel,
             PayloadChannel,
         },
-        hardware::software_isotp::SoftwareIsoTpChannel,
+        hardware::{
+            fault_injection::{Fault, FaultDirection, FaultInjectionChannel, FaultInjector},
+            software_isotp::SoftwareIsoTpChannel,
+        },
     };
 
     pub struct EmuCanChannel {
         name: &'static str,
         in_queue: Arc<mpsc::Receiver<CanFrame>>,
         out_queue: mpsc::Sender<CanFrame>,
+        filters: BTreeMap<u32, Vec<u32>>,
+        bus_status: CanBusStatus,
+        open_count: usize,
+        baud: u32,
+        bus_baud: Option<u32>,
+        use_ext: bool,
     }
 
     unsafe impl Send for EmuCanChannel {}
@@ -268,22 +300,108 @@ pub mod hardware_tests {
                 name,
                 in_queue: Arc::new(receiver),
                 out_queue: sender,
+                filters: BTreeMap::new(),
+                bus_status: CanBusStatus::default(),
+                open_count: 0,
+                baud: 0,
+                bus_baud: None,
+                use_ext: false,
             }
         }
+
+        /// Returns true if a frame with `id` passes the active filters
+        fn is_allowed(&self, id: u32) -> bool {
+            self.filters.is_empty() || self.filters.values().any(|ids| ids.contains(&id))
+        }
+
+        /// Returns the number of active filters
+        pub fn filter_count(&self) -> usize {
+            self.filters.len()
+        }
+
+        /// Simulates a change of the bus state. Opening the channel resets it
+        pub fn set_bus_state(&mut self, state: CanBusState) {
+            self.bus_status.state = state;
+        }
+
+        /// Returns how many times the channel was opened
+        pub fn open_count(&self) -> usize {
+            self.open_count
+        }
+
+        /// Simulates traffic on the bus at a baud rate, for [CanChannel::detect_can_baud]
+        pub fn set_bus_baud(&mut self, baud: u32) {
+            self.bus_baud = Some(baud);
+        }
+
+        /// Returns the baud rate the channel was last configured with
+        pub fn baud(&self) -> u32 {
+            self.baud
+        }
+
+        /// Returns true if the channel was last configured for 29-bit CAN IDs
+        pub fn use_ext(&self) -> bool {
+            self.use_ext
+        }
+    }
+
+    impl FilterPacketChannel<CanFrame> for EmuCanChannel {
+        fn add_filter(&mut self, allowed_ids: &[u32]) -> crate::core::channel::ChannelResult<u32> {
+            let id = self.filters.keys().last().map_or(0, |x| x + 1);
+            self.filters.insert(id, allowed_ids.to_vec());
+            Ok(id)
+        }
+
+        fn remove_filter(&mut self, filter_id: u32) -> crate::core::channel::ChannelResult<()> {
+            self.filters
+                .remove(&filter_id)
+                .map(|_| ())
+                .ok_or(ChannelError::ConfigurationError)
+        }
     }
 
     impl CanChannel for EmuCanChannel {
         fn set_can_cfg(
+            &mut self,
+            baud: u32,
+            use_extended: bool,
+        ) -> crate::core::channel::ChannelResult<()> {
+            self.baud = baud;
+            self.use_ext = use_extended;
+            Ok(())
+        }
+
+        fn set_can_fd_cfg(
             &mut self,
             _baud: u32,
-            _use_extended: bool,
+            _data_baud: u32,
+            use_extended: bool,
         ) -> crate::core::channel::ChannelResult<()> {
+            self.use_ext = use_extended;
             Ok(())
         }
+
+        fn get_bus_status(&mut self) -> crate::core::channel::ChannelResult<CanBusStatus> {
+            Ok(self.bus_status)
+        }
+
+        fn detect_can_baud(
+            &mut self,
+            candidates: &[u32],
+            _listen_ms: u32,
+        ) -> crate::core::channel::ChannelResult<u32> {
+            candidates
+                .iter()
+                .copied()
+                .find(|baud| Some(*baud) == self.bus_baud)
+                .ok_or(ChannelError::BaudNotDetected)
+        }
     }
 
     impl PacketChannel<CanFrame> for EmuCanChannel {
         fn open(&mut self) -> crate::core::channel::ChannelResult<()> {
+            self.open_count += 1;
+            self.bus_status.state = CanBusState::ErrorActive;
             Ok(())
         }
 
@@ -316,8 +434,13 @@ pub mod hardware_tests {
                     .try_recv()
                     .map_err(|_| ChannelError::BufferEmpty);
                 match res {
-                    Ok(f) => {
+                    Ok(f) if !self.is_allowed(f.get_address()) => {
+                        log::debug!("{} Filtered -> {f:02X?}", self.name);
+                        continue;
+                    }
+                    Ok(mut f) => {
                         log::debug!("{} In  -> {f:02X?}", self.name);
+                        f.set_timestamp_us(host_timestamp_us());
                         read_packets.push(f);
                     }
                     Err(ChannelError::BufferEmpty) => return Ok(read_packets),
@@ -353,6 +476,7 @@ pub mod hardware_tests {
         ext_address: Option<(u8, u8)>,
         ecu1_addr: u32,
         ecu2_addr: u32,
+        can_fd_speed: Option<u32>,
     ) -> (SoftwareIsoTpChannel, SoftwareIsoTpChannel) {
         let (ecu1tx, ecu1rx) = mpsc::channel::<CanFrame>();
         let (ecu2tx, ecu2rx) = mpsc::channel::<CanFrame>();
@@ -369,6 +493,8 @@ pub mod hardware_tests {
             pad_frame: padding,
             can_speed: 500_000,
             can_use_ext_addr: false,
+            can_fd_speed,
+            ..Default::default()
         });
 
         iso_tp2.set_iso_tp_cfg(IsoTPSettings {
@@ -378,6 +504,8 @@ pub mod hardware_tests {
             pad_frame: padding,
             can_speed: 500_000,
             can_use_ext_addr: false,
+            can_fd_speed,
+            ..Default::default()
         });
 
         iso_tp1.set_ids(ecu1_addr, ecu2_addr);
@@ -394,7 +522,7 @@ pub mod hardware_tests {
         env_logger::try_init();
         let TX_BYTES = &[0x01, 0x02, 0x03, 0x04, 0x05];
 
-        let (mut ch1, mut ch2) = setup(8, 20, true, None, 0x07E1, 0x07E9);
+        let (mut ch1, mut ch2) = setup(8, 20, true, None, 0x07E1, 0x07E9, None);
 
         ch1.write_bytes(0x07E1, None, TX_BYTES, 0)
             .expect("Write failed!");
@@ -409,7 +537,7 @@ pub mod hardware_tests {
         env_logger::try_init();
         let TX_BYTES = (0..0xFF).collect::<Vec<u8>>();
 
-        let (mut ch1, mut ch2) = setup(8, 20, true, None, 0x07E1, 0x07E9);
+        let (mut ch1, mut ch2) = setup(8, 20, true, None, 0x07E1, 0x07E9, None);
 
         ch1.write_bytes(0x07E1, None, &TX_BYTES, 5000)
             .expect("Write failed!");
@@ -418,6 +546,7 @@ pub mod hardware_tests {
 
         assert!(r.is_ok());
         assert_eq!(TX_BYTES.to_vec(), r.unwrap());
+        let first_rx_time = ch2.last_rx_timestamp_us().expect("No Rx timestamp");
 
         let TX2_BYTES = (0x00..0xFF).rev().collect::<Vec<u8>>();
 
@@ -428,5 +557,137 @@ pub mod hardware_tests {
 
         assert!(r.is_ok());
         assert_eq!(TX2_BYTES.to_vec(), r.unwrap());
+        assert!(ch2.last_rx_timestamp_us().unwrap() > first_rx_time);
+    }
+
+    #[test]
+    fn test_multi_frame_wrong_sequence_number() {
+        let _ = env_logger::try_init();
+        let tx_bytes = (0..30).collect::<Vec<u8>>();
+        let (ecu1tx, ecu1rx) = mpsc::channel::<CanFrame>();
+        let (ecu2tx, ecu2rx) = mpsc::channel::<CanFrame>();
+        let injector = FaultInjector::default();
+        let ecu1 = Box::new(EmuCanChannel::new(ecu2tx, ecu1rx, "Tester"));
+        let ecu2 = Box::new(FaultInjectionChannel::new(
+            EmuCanChannel::new(ecu1tx, ecu2rx, "ECU"),
+            injector.clone(),
+        ));
+        let mut ch1 = SoftwareIsoTpChannel::new(ecu1);
+        let mut ch2 = SoftwareIsoTpChannel::new(ecu2);
+        for (ch, ids) in [(&mut ch1, (0x07E1, 0x07E9)), (&mut ch2, (0x07E9, 0x07E1))] {
+            ch.set_iso_tp_cfg(IsoTPSettings {
+                block_size: 0,
+                st_min: 0,
+                extended_addresses: None,
+                pad_frame: true,
+                can_speed: 500_000,
+                can_use_ext_addr: false,
+                can_fd_speed: None,
+                ..Default::default()
+            })
+            .unwrap();
+            ch.set_ids(ids.0, ids.1).unwrap();
+            PayloadChannel::open(ch).unwrap();
+        }
+
+        // The second consecutive frame arrives with sequence number 3
+        injector.schedule(
+            FaultDirection::Rx,
+            None,
+            2,
+            Fault::Corrupt {
+                index: 0,
+                mask: 0x01,
+            },
+        );
+        ch1.write_bytes(0x07E1, None, &tx_bytes, 5000)
+            .expect("Write failed!");
+        // The reception is aborted instead of returning corrupted data
+        assert!(matches!(
+            ch2.read_bytes(500),
+            Err(ChannelError::IsoTpError(IsoTpError::WrongSequenceNumber))
+        ));
+
+        // The sender might still be busy with the rest of the aborted transfer
+        let start = Instant::now();
+        while let Err(ChannelError::BufferFull) = ch1.write_bytes(0x07E1, None, &tx_bytes, 5000) {
+            assert!(start.elapsed().as_millis() < 1000);
+            std::thread::sleep(std::time::Duration::from_millis(1));
+        }
+        assert_eq!(ch2.read_bytes(5000).unwrap(), tx_bytes);
+    }
+
+    #[test]
+    fn test_fd_single_frame() {
+        let _ = env_logger::try_init();
+        // Larger than a classic SF, uses the FD SF escape
+        let tx_bytes = (0..20).collect::<Vec<u8>>();
+
+        let (mut ch1, mut ch2) = setup(8, 0, true, None, 0x07E1, 0x07E9, Some(2_000_000));
+
+        ch1.write_bytes(0x07E1, None, &tx_bytes, 0)
+            .expect("Write failed!");
+
+        let r = ch2.read_bytes(1000);
+        assert!(r.is_ok());
+        assert_eq!(tx_bytes, r.unwrap());
+    }
+
+    #[test]
+    fn test_fd_multi_frame() {
+        let _ = env_logger::try_init();
+        let tx_bytes = (0..200).collect::<Vec<u8>>();
+
+        let (mut ch1, mut ch2) = setup(2, 0, true, None, 0x07E1, 0x07E9, Some(2_000_000));
+
+        ch1.write_bytes(0x07E1, None, &tx_bytes, 5000)
+            .expect("Write failed!");
+
+        let r = ch2.read_bytes(5000);
+        assert!(r.is_ok());
+        assert_eq!(tx_bytes, r.unwrap());
+
+        // Over 4095 bytes, uses the 32bit FF length escape
+        let tx2_bytes = (0..5000).map(|x| x as u8).collect::<Vec<u8>>();
+
+        ch1.write_bytes(0x07E1, None, &tx2_bytes, 5000)
+            .expect("Write failed!");
+
+        let r = ch2.read_bytes(5000);
+        assert!(r.is_ok());
+        assert_eq!(tx2_bytes, r.unwrap());
+    }
+
+    #[test]
+    fn test_first_frame_escape_boundary() {
+        let _ = env_logger::try_init();
+        // The 32bit FF length escape is used from 4096 bytes, on classic CAN too
+        for len in [4095, 4096] {
+            let tx_bytes = (0..len).map(|x| x as u8).collect::<Vec<u8>>();
+            let (mut ch1, mut ch2) = setup(0, 0, true, None, 0x07E1, 0x07E9, None);
+            ch1.write_bytes(0x07E1, None, &tx_bytes, 5000)
+                .expect("Write failed!");
+            assert_eq!(ch2.read_bytes(5000).unwrap(), tx_bytes);
+        }
+
+        let (tester_tx, ecu_rx) = mpsc::channel::<CanFrame>();
+        let (_ecu_tx, tester_rx) = mpsc::channel::<CanFrame>();
+        let mut tester =
+            SoftwareIsoTpChannel::new(Box::new(EmuCanChannel::new(tester_tx, tester_rx, "Tester")));
+        tester.set_iso_tp_cfg(IsoTPSettings::default()).unwrap();
+        tester.set_ids(0x07E1, 0x07E9).unwrap();
+        PayloadChannel::open(&mut tester).unwrap();
+        for (len, first_frame) in [
+            (4095, [0x1F, 0xFF, 0x00, 0x01, 0x02, 0x03, 0x04, 0x05]),
+            (4096, [0x10, 0x00, 0x00, 0x00, 0x10, 0x00, 0x00, 0x01]),
+        ] {
+            let tx_bytes = (0..len).map(|x| x as u8).collect::<Vec<u8>>();
+            tester.write_bytes(0x07E1, None, &tx_bytes, 0).unwrap();
+            let f = ecu_rx
+                .recv_timeout(std::time::Duration::from_secs(1))
+                .unwrap();
+            assert_eq!(f.get_data(), first_frame);
+            PayloadChannel::clear_tx_buffer(&mut tester).unwrap();
+        }
     }
 }
@@ -0,0 +1,365 @@
+use libloading::Library;
+use std::ffi::{c_char, c_void, CStr};
+use std::fmt;
+use std::sync::Arc;
+
+use j2534_rust::{
+    FilterType, IoctlID, IoctlParam, PassthruError, Protocol, SConfig, SConfigList, PASSTHRU_MSG,
+};
+
+use crate::core::channel::{ChannelError, ChannelResult};
+use crate::hardware::{HardwareError, HardwareResult};
+
+type PassThruOpenFn = unsafe extern "system" fn(name: *const c_void, device_id: *mut u32) -> i32;
+
+type PassThruCloseFn = unsafe extern "system" fn(device_id: u32) -> i32;
+
+type PassThruConnectFn = unsafe extern "system" fn(
+    device_id: u32,
+    protocol_id: u32,
+    flags: u32,
+    baudrate: u32,
+    channel_id: *mut u32,
+) -> i32;
+
+type PassThruDisconnectFn = unsafe extern "system" fn(channel_id: u32) -> i32;
+
+type PassThruReadMsgsFn = unsafe extern "system" fn(
+    channel_id: u32,
+    msgs: *mut PASSTHRU_MSG,
+    num_msgs: *mut u32,
+    timeout: u32,
+) -> i32;
+
+type PassThruWriteMsgsFn = unsafe extern "system" fn(
+    channel_id: u32,
+    msgs: *mut PASSTHRU_MSG,
+    num_msgs: *mut u32,
+    timeout: u32,
+) -> i32;
+
+type PassThruStartMsgFilterFn = unsafe extern "system" fn(
+    channel_id: u32,
+    filter_type: u32,
+    mask: *const PASSTHRU_MSG,
+    pattern: *const PASSTHRU_MSG,
+    flow_control: *const PASSTHRU_MSG,
+    filter_id: *mut u32,
+) -> i32;
+
+type PassThruStopMsgFilterFn = unsafe extern "system" fn(channel_id: u32, filter_id: u32) -> i32;
+
+type PassThruIoctlFn = unsafe extern "system" fn(
+    handle_id: u32,
+    ioctl_id: u32,
+    input: *mut c_void,
+    output: *mut c_void,
+) -> i32;
+
+type PassThruReadVersionFn = unsafe extern "system" fn(
+    device_id: u32,
+    firmware_version: *mut c_char,
+    dll_version: *mut c_char,
+    api_version: *mut c_char,
+) -> i32;
+
+type PassThruGetLastErrorFn = unsafe extern "system" fn(error_description: *mut c_char) -> i32;
+
+/// Device version info returned by `PassThruReadVersion`
+#[derive(Debug, Clone, Default)]
+pub struct PassthruVersion {
+    /// Adapter firmware version
+    pub fw_version: String,
+    /// Library version
+    pub dll_version: String,
+    /// J2534 API version
+    pub api_version: String,
+}
+
+#[derive(Clone)]
+/// Loaded J2534 PassThru library
+pub struct PassthruDrv {
+    /// Loaded library to interface with the device
+    lib: Arc<Library>,
+    /// Path of the loaded library
+    path: String,
+    open_fn: PassThruOpenFn,
+    close_fn: PassThruCloseFn,
+    connect_fn: PassThruConnectFn,
+    disconnect_fn: PassThruDisconnectFn,
+    read_msg_fn: PassThruReadMsgsFn,
+    write_msg_fn: PassThruWriteMsgsFn,
+    start_msg_filter_fn: PassThruStartMsgFilterFn,
+    stop_msg_filter_fn: PassThruStopMsgFilterFn,
+    ioctl_fn: PassThruIoctlFn,
+    read_version_fn: PassThruReadVersionFn,
+    get_last_error_fn: PassThruGetLastErrorFn,
+}
+
+impl fmt::Debug for PassthruDrv {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PassthruDrv")
+            .field("path", &self.path)
+            .field("library", &self.lib)
+            .finish()
+    }
+}
+
+impl PassthruDrv {
+    /// Loads the J2534 library at `path`
+    pub fn load_lib(path: &str) -> HardwareResult<PassthruDrv> {
+        let lib = unsafe { Library::new(path)? };
+        unsafe {
+            Ok(Self {
+                open_fn: *lib.get::<PassThruOpenFn>(b"PassThruOpen\0")?.into_raw(),
+                close_fn: *lib.get::<PassThruCloseFn>(b"PassThruClose\0")?.into_raw(),
+                connect_fn: *lib
+                    .get::<PassThruConnectFn>(b"PassThruConnect\0")?
+                    .into_raw(),
+                disconnect_fn: *lib
+                    .get::<PassThruDisconnectFn>(b"PassThruDisconnect\0")?
+                    .into_raw(),
+                read_msg_fn: *lib
+                    .get::<PassThruReadMsgsFn>(b"PassThruReadMsgs\0")?
+                    .into_raw(),
+                write_msg_fn: *lib
+                    .get::<PassThruWriteMsgsFn>(b"PassThruWriteMsgs\0")?
+                    .into_raw(),
+                start_msg_filter_fn: *lib
+                    .get::<PassThruStartMsgFilterFn>(b"PassThruStartMsgFilter\0")?
+                    .into_raw(),
+                stop_msg_filter_fn: *lib
+                    .get::<PassThruStopMsgFilterFn>(b"PassThruStopMsgFilter\0")?
+                    .into_raw(),
+                ioctl_fn: *lib.get::<PassThruIoctlFn>(b"PassThruIoctl\0")?.into_raw(),
+                read_version_fn: *lib
+                    .get::<PassThruReadVersionFn>(b"PassThruReadVersion\0")?
+                    .into_raw(),
+                get_last_error_fn: *lib
+                    .get::<PassThruGetLastErrorFn>(b"PassThruGetLastError\0")?
+                    .into_raw(),
+                lib: Arc::new(lib),
+                path: path.to_string(),
+            })
+        }
+    }
+
+    /// Returns the path of the loaded library
+    pub fn get_path(&self) -> &str {
+        &self.path
+    }
+
+    fn check_result<T>(&self, ret: T, status: i32) -> HardwareResult<T> {
+        if status == PassthruError::STATUS_NOERROR as i32 {
+            return Ok(ret);
+        }
+        let mut desc = match PassthruError::try_from(status as u32) {
+            Ok(e) => e.to_string(),
+            Err(_) => "Unknown error".to_string(),
+        };
+        if let Ok(detail) = self.get_last_error() {
+            if !detail.is_empty() {
+                desc = format!("{desc} ({detail})");
+            }
+        }
+        Err(HardwareError::APIError {
+            code: status as u32,
+            desc,
+        })
+    }
+
+    /// Converts a channel API error, mapping Tx/Rx buffer status to their [ChannelError]
+    fn check_channel_result<T>(&self, ret: T, status: i32) -> ChannelResult<T> {
+        match PassthruError::try_from(status as u32) {
+            Ok(PassthruError::ERR_BUFFER_EMPTY) => Err(ChannelError::BufferEmpty),
+            Ok(PassthruError::ERR_BUFFER_FULL) => Err(ChannelError::BufferFull),
+            _ => self.check_result(ret, status).map_err(ChannelError::from),
+        }
+    }
+
+    pub fn get_last_error(&self) -> HardwareResult<String> {
+        let mut err: [c_char; 80] = [0; 80];
+        let res = unsafe { (self.get_last_error_fn)(err.as_mut_ptr()) };
+        if res != PassthruError::STATUS_NOERROR as i32 {
+            return Err(HardwareError::APIError {
+                code: res as u32,
+                desc: "PassThruGetLastError failed".into(),
+            });
+        }
+        Ok(unsafe { CStr::from_ptr(err.as_ptr()) }
+            .to_string_lossy()
+            .to_string())
+    }
+
+    pub fn open(&self) -> HardwareResult<u32> {
+        let mut id: u32 = 0;
+        let res = unsafe { (self.open_fn)(std::ptr::null(), &mut id) };
+        self.check_result(id, res)
+    }
+
+    pub fn close(&self, device_id: u32) -> HardwareResult<()> {
+        let res = unsafe { (self.close_fn)(device_id) };
+        self.check_result((), res)
+    }
+
+    pub fn read_version(&self, device_id: u32) -> HardwareResult<PassthruVersion> {
+        let mut fw: [c_char; 80] = [0; 80];
+        let mut dll: [c_char; 80] = [0; 80];
+        let mut api: [c_char; 80] = [0; 80];
+        let res = unsafe {
+            (self.read_version_fn)(
+                device_id,
+                fw.as_mut_ptr(),
+                dll.as_mut_ptr(),
+                api.as_mut_ptr(),
+            )
+        };
+        let to_string = |s: &[c_char]| {
+            unsafe { CStr::from_ptr(s.as_ptr()) }
+                .to_string_lossy()
+                .to_string()
+        };
+        self.check_result(
+            PassthruVersion {
+                fw_version: to_string(&fw),
+                dll_version: to_string(&dll),
+                api_version: to_string(&api),
+            },
+            res,
+        )
+    }
+
+    pub fn connect(
+        &self,
+        device_id: u32,
+        protocol: Protocol,
+        flags: u32,
+        baud: u32,
+    ) -> HardwareResult<u32> {
+        log::debug!("PassThruConnect: protocol {protocol}, flags 0x{flags:08X}, baud {baud}");
+        let mut id: u32 = 0;
+        let res = unsafe { (self.connect_fn)(device_id, protocol as u32, flags, baud, &mut id) };
+        self.check_result(id, res)
+    }
+
+    pub fn disconnect(&self, channel_id: u32) -> HardwareResult<()> {
+        let res = unsafe { (self.disconnect_fn)(channel_id) };
+        self.check_result((), res)
+    }
+
+    /// Reads up to `max` messages. Returns an empty list if no messages were received
+    pub fn read_messages(
+        &self,
+        channel_id: u32,
+        max: u32,
+        timeout_ms: u32,
+    ) -> ChannelResult<Vec<PASSTHRU_MSG>> {
+        let mut msgs = vec![PASSTHRU_MSG::default(); max as usize];
+        let mut num = max;
+        let res =
+            unsafe { (self.read_msg_fn)(channel_id, msgs.as_mut_ptr(), &mut num, timeout_ms) };
+        match PassthruError::try_from(res as u32) {
+            // Timeout still reports the messages read so far
+            Ok(PassthruError::ERR_BUFFER_EMPTY) | Ok(PassthruError::ERR_TIMEOUT) => {}
+            _ => self.check_channel_result((), res)?,
+        }
+        msgs.truncate(num as usize);
+        Ok(msgs)
+    }
+
+    pub fn write_messages(
+        &self,
+        channel_id: u32,
+        msgs: &mut [PASSTHRU_MSG],
+        timeout_ms: u32,
+    ) -> ChannelResult<()> {
+        if msgs.is_empty() {
+            return Ok(());
+        }
+        let mut num = msgs.len() as u32;
+        let res =
+            unsafe { (self.write_msg_fn)(channel_id, msgs.as_mut_ptr(), &mut num, timeout_ms) };
+        if res == PassthruError::ERR_TIMEOUT as i32 {
+            return Err(ChannelError::WriteTimeout);
+        }
+        self.check_channel_result((), res)
+    }
+
+    pub fn start_msg_filter(
+        &self,
+        channel_id: u32,
+        filter_type: FilterType,
+        mask: &PASSTHRU_MSG,
+        pattern: &PASSTHRU_MSG,
+        flow_control: Option<&PASSTHRU_MSG>,
+    ) -> ChannelResult<u32> {
+        let mut id: u32 = 0;
+        let res = unsafe {
+            (self.start_msg_filter_fn)(
+                channel_id,
+                filter_type as u32,
+                mask,
+                pattern,
+                flow_control.map_or(std::ptr::null(), |fc| fc as *const PASSTHRU_MSG),
+                &mut id,
+            )
+        };
+        self.check_channel_result(id, res)
+    }
+
+    pub fn stop_msg_filter(&self, channel_id: u32, filter_id: u32) -> ChannelResult<()> {
+        let res = unsafe { (self.stop_msg_filter_fn)(channel_id, filter_id) };
+        self.check_channel_result((), res)
+    }
+
+    /// Sets configuration parameters of a channel
+    pub fn set_config(&self, channel_id: u32, params: &[(IoctlParam, u32)]) -> ChannelResult<()> {
+        let mut cfg: Vec<SConfig> = params
+            .iter()
+            .map(|(p, v)| SConfig {
+                parameter: *p as u32,
+                value: *v,
+            })
+            .collect();
+        let mut list = SConfigList {
+            num_of_params: cfg.len() as u32,
+            config_ptr: cfg.as_mut_ptr(),
+        };
+        let res = unsafe {
+            (self.ioctl_fn)(
+                channel_id,
+                IoctlID::SET_CONFIG as u32,
+                &mut list as *mut SConfigList as *mut c_void,
+                std::ptr::null_mut(),
+            )
+        };
+        self.check_channel_result((), res)
+    }
+
+    /// Runs an IOCTL without input or output (EG: [IoctlID::CLEAR_RX_BUFFER])
+    pub fn ioctl(&self, handle_id: u32, id: IoctlID) -> ChannelResult<()> {
+        let res = unsafe {
+            (self.ioctl_fn)(
+                handle_id,
+                id as u32,
+                std::ptr::null_mut(),
+                std::ptr::null_mut(),
+            )
+        };
+        self.check_channel_result((), res)
+    }
+
+    /// Reads the voltage of the OBD battery pin in millivolts
+    pub fn read_battery_voltage(&self, device_id: u32) -> HardwareResult<u32> {
+        let mut mv: u32 = 0;
+        let res = unsafe {
+            (self.ioctl_fn)(
+                device_id,
+                IoctlID::READ_VBATT as u32,
+                std::ptr::null_mut(),
+                &mut mv as *mut u32 as *mut c_void,
+            )
+        };
+        self.check_result(mv, res)
+    }
+}
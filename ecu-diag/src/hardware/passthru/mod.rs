@@ -0,0 +1,782 @@
+//! Diagnostic implementation for SAE J2534 PassThru adapters
+//!
+//! PassThru adapters are listed from JSON manifests in `~/.passthru/` (one file per adapter),
+//! following the convention used by J2534 drivers on Linux and macOS:
+//!
+//! ```json
+//! {
+//!     "NAME": "Macchina A0",
+//!     "VENDOR": "Macchina",
+//!     "FUNCTION_LIB": "/usr/share/passthru/macchina.so",
+//!     "CAN": true,
+//!     "ISO15765": true
+//! }
+//! ```
+//!
+//! If the adapter supports ISO15765, ISO-TP is handled natively by the adapter.
+
+mod lib_funcs;
+
+use std::{
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Instant,
+};
+
+use j2534_rust::{
+    ConnectFlags, FilterType, IoctlID, IoctlParam, Protocol, RxFlag, TxFlag, PASSTHRU_MSG,
+};
+use serde::Deserialize;
+
+use crate::core::channel::{
+    CanChannel, CanFrame, ChannelError, ChannelResult, FilterPacketChannel, IsoTPChannel,
+    IsoTPSettings, Packet, PacketChannel, PayloadChannel,
+};
+
+use super::{
+    software_isotp::SoftwareIsoTpChannel, Hardware, HardwareCapabilities, HardwareError,
+    HardwareInfo, HardwareResult, HardwareScanner, IsoTpChannelType,
+};
+
+pub use lib_funcs::{PassthruDrv, PassthruVersion};
+
+/// Adapter manifest, as found in `~/.passthru/*.json`
+#[derive(Debug, Clone, Deserialize)]
+struct PassthruManifest {
+    #[serde(rename = "NAME")]
+    name: String,
+    #[serde(rename = "VENDOR", default)]
+    vendor: Option<String>,
+    #[serde(rename = "FUNCTION_LIB")]
+    function_lib: String,
+    #[serde(rename = "CAN", default)]
+    can: bool,
+    #[serde(rename = "ISO15765", default)]
+    iso15765: bool,
+    #[serde(rename = "ISO9141", default)]
+    iso9141: bool,
+    #[serde(rename = "ISO14230", default)]
+    iso14230: bool,
+    #[serde(rename = "J1850VPW", default)]
+    j1850vpw: bool,
+    #[serde(rename = "J1850PWM", default)]
+    j1850pwm: bool,
+    #[serde(rename = "SCI_A_ENGINE", default)]
+    sci_a_engine: bool,
+}
+
+impl From<PassthruManifest> for HardwareInfo {
+    fn from(m: PassthruManifest) -> Self {
+        HardwareInfo {
+            name: m.name,
+            vendor: m.vendor,
+            device_fw_version: None,
+            device_id: None,
+            api_version: None,
+            library_version: None,
+            library_location: Some(m.function_lib),
+            capabilities: HardwareCapabilities {
+                iso_tp: if m.iso15765 {
+                    IsoTpChannelType::Protocol
+                } else if m.can {
+                    IsoTpChannelType::Emulated
+                } else {
+                    IsoTpChannelType::None
+                },
+                can: m.can,
+                kline: m.iso9141,
+                kline_kwp: m.iso14230,
+                sae_j1850: m.j1850vpw || m.j1850pwm,
+                sci: m.sci_a_engine,
+                ip: false,
+            },
+        }
+    }
+}
+
+/// J2534 device handle. The device is closed once the last clone of
+/// the device and all its channels are dropped
+#[derive(Debug)]
+struct PassthruHandle {
+    drv: PassthruDrv,
+    device_id: u32,
+}
+
+impl Drop for PassthruHandle {
+    fn drop(&mut self) {
+        let _ = self.drv.close(self.device_id);
+    }
+}
+
+#[derive(Clone, Debug)]
+/// J2534 PassThru device
+pub struct PassthruDevice {
+    info: HardwareInfo,
+    handle: Arc<PassthruHandle>,
+    can_channel: Arc<AtomicBool>,
+    has_isotp_channel: Arc<AtomicBool>,
+}
+
+impl PassthruDevice {
+    /// Loads the adapter library listed in `info` and opens the device
+    pub fn open_device(info: &HardwareInfo) -> HardwareResult<Self> {
+        let path = info
+            .library_location
+            .as_ref()
+            .ok_or(HardwareError::DeviceNotFound)?;
+        let drv = PassthruDrv::load_lib(path)?;
+        let device_id = drv.open()?;
+        let handle = Arc::new(PassthruHandle { drv, device_id });
+        let mut info = info.clone();
+        if let Ok(v) = handle.drv.read_version(device_id) {
+            info.device_fw_version = Some(v.fw_version);
+            info.library_version = Some(v.dll_version);
+            info.api_version = Some(v.api_version);
+        }
+        Ok(Self {
+            info,
+            handle,
+            can_channel: Arc::new(AtomicBool::new(false)),
+            has_isotp_channel: Arc::new(AtomicBool::new(false)),
+        })
+    }
+}
+
+impl Hardware for PassthruDevice {
+    fn create_iso_tp_channel(
+        &mut self,
+        force_native: bool,
+    ) -> HardwareResult<Box<dyn IsoTPChannel>> {
+        if self.info.capabilities.iso_tp == IsoTpChannelType::Protocol {
+            if self.has_isotp_channel.load(Ordering::Relaxed) {
+                return Err(HardwareError::ConflictingChannel);
+            }
+            self.has_isotp_channel.store(true, Ordering::Relaxed);
+            Ok(Box::new(PassthruIsoTpChannel::new(
+                self.handle.clone(),
+                self.has_isotp_channel.clone(),
+            )))
+        } else if force_native {
+            Err(HardwareError::ChannelNotSupported)
+        } else {
+            // Use software
+            let can_channel = self.create_can_channel()?;
+            Ok(Box::new(SoftwareIsoTpChannel::new(can_channel)))
+        }
+    }
+
+    fn create_native_iso_tp_channel(&mut self) -> HardwareResult<Box<dyn CanChannel>> {
+        self.has_isotp_channel.store(true, Ordering::Relaxed);
+        self.create_can_channel()
+    }
+
+    fn create_can_channel(&mut self) -> HardwareResult<Box<dyn CanChannel>> {
+        if !self.info.capabilities.can {
+            Err(HardwareError::ChannelNotSupported)
+        } else if self.can_channel.load(Ordering::Relaxed) {
+            // Already open
+            Err(HardwareError::ConflictingChannel)
+        } else {
+            self.can_channel.store(true, Ordering::Relaxed);
+            Ok(Box::new(PassthruCanChannel::new(
+                self.handle.clone(),
+                self.can_channel.clone(),
+            )))
+        }
+    }
+
+    fn is_iso_tp_channel_open(&self) -> bool {
+        self.has_isotp_channel.load(Ordering::Relaxed)
+    }
+
+    fn is_can_channel_open(&self) -> bool {
+        self.can_channel.load(Ordering::Relaxed)
+    }
+
+    fn read_battery_voltage(&mut self) -> Option<f32> {
+        self.handle
+            .drv
+            .read_battery_voltage(self.handle.device_id)
+            .ok()
+            .map(|mv| mv as f32 / 1000.0)
+    }
+
+    fn read_ignition_voltage(&mut self) -> Option<f32> {
+        None
+    }
+
+    fn get_info(&self) -> &HardwareInfo {
+        &self.info
+    }
+
+    fn is_connected(&self) -> bool {
+        true
+    }
+}
+
+/// Builds a message for `protocol`. The first 4 bytes are the big endian CAN ID
+fn new_msg(protocol: Protocol, id: u32, data: &[u8], tx_flags: u32) -> PASSTHRU_MSG {
+    let mut msg = PASSTHRU_MSG {
+        protocol_id: protocol as u32,
+        tx_flags,
+        data_size: 4 + data.len() as u32,
+        ..Default::default()
+    };
+    msg.data[0..4].copy_from_slice(&id.to_be_bytes());
+    msg.data[4..4 + data.len()].copy_from_slice(data);
+    msg
+}
+
+fn msg_id(msg: &PASSTHRU_MSG) -> u32 {
+    u32::from_be_bytes([msg.data[0], msg.data[1], msg.data[2], msg.data[3]])
+}
+
+#[derive(Debug)]
+/// Raw CAN channel on a PassThru adapter
+pub struct PassthruCanChannel {
+    handle: Arc<PassthruHandle>,
+    channel_id: Option<u32>,
+    baud: Option<u32>,
+    use_ext: bool,
+    device_state: Arc<AtomicBool>,
+}
+
+impl PassthruCanChannel {
+    fn new(handle: Arc<PassthruHandle>, device_state: Arc<AtomicBool>) -> Self {
+        Self {
+            handle,
+            channel_id: None,
+            baud: None,
+            use_ext: false,
+            device_state,
+        }
+    }
+
+    fn get_channel_id(&self) -> ChannelResult<u32> {
+        self.channel_id.ok_or(ChannelError::InterfaceNotOpen)
+    }
+}
+
+impl Drop for PassthruCanChannel {
+    fn drop(&mut self) {
+        let _ = PacketChannel::close(self);
+        self.device_state.store(false, Ordering::Relaxed);
+    }
+}
+
+impl FilterPacketChannel<CanFrame> for PassthruCanChannel {}
+
+impl CanChannel for PassthruCanChannel {
+    fn set_can_cfg(&mut self, baud: u32, use_extended: bool) -> ChannelResult<()> {
+        if self.channel_id.is_some() {
+            return Err(ChannelError::InterfaceOpen);
+        }
+        self.baud = Some(baud);
+        self.use_ext = use_extended;
+        Ok(())
+    }
+}
+
+impl PacketChannel<CanFrame> for PassthruCanChannel {
+    fn open(&mut self) -> ChannelResult<()> {
+        if self.channel_id.is_some() {
+            return Ok(());
+        }
+        let baud = self.baud.ok_or(ChannelError::ConfigurationError)?;
+        let drv = &self.handle.drv;
+        let id = drv.connect(
+            self.handle.device_id,
+            Protocol::CAN,
+            ConnectFlags::CAN_ID_BOTH.bits(),
+            baud,
+        )?;
+        // Open filter, all traffic is received
+        let mask = new_msg(Protocol::CAN, 0, &[], 0);
+        let pattern = new_msg(Protocol::CAN, 0, &[], 0);
+        if let Err(e) = drv.start_msg_filter(id, FilterType::PASS_FILTER, &mask, &pattern, None) {
+            let _ = drv.disconnect(id);
+            return Err(e);
+        }
+        self.channel_id = Some(id);
+        Ok(())
+    }
+
+    fn close(&mut self) -> ChannelResult<()> {
+        if let Some(id) = self.channel_id.take() {
+            self.handle.drv.disconnect(id)?;
+        }
+        Ok(())
+    }
+
+    fn write_packets(&mut self, packets: Vec<CanFrame>, timeout_ms: u32) -> ChannelResult<()> {
+        let id = self.get_channel_id()?;
+        let mut msgs: Vec<PASSTHRU_MSG> = packets
+            .iter()
+            .map(|p| {
+                let flags = if p.is_extended() || self.use_ext {
+                    TxFlag::CAN_29BIT_ID.bits()
+                } else {
+                    0
+                };
+                new_msg(Protocol::CAN, p.get_address(), p.get_data(), flags)
+            })
+            .collect();
+        self.handle.drv.write_messages(id, &mut msgs, timeout_ms)
+    }
+
+    fn read_packets(&mut self, max: usize, timeout_ms: u32) -> ChannelResult<Vec<CanFrame>> {
+        let id = self.get_channel_id()?;
+        let msgs =
+            self.handle
+                .drv
+                .read_messages(id, max.min(u32::MAX as usize) as u32, timeout_ms)?;
+        Ok(msgs
+            .iter()
+            .filter(|m| m.rx_status & RxFlag::TX_MSG_TYPE.bits() == 0 && m.data_size >= 4)
+            .map(|m| {
+                let mut frame = CanFrame::new(
+                    msg_id(m),
+                    &m.data[4..m.data_size as usize],
+                    m.rx_status & RxFlag::CAN_29BIT_ID.bits() != 0,
+                );
+                frame.set_timestamp_us(u64::from(m.timestamp));
+                frame
+            })
+            .collect())
+    }
+
+    fn clear_rx_buffer(&mut self) -> ChannelResult<()> {
+        self.handle
+            .drv
+            .ioctl(self.get_channel_id()?, IoctlID::CLEAR_RX_BUFFER)
+    }
+
+    fn clear_tx_buffer(&mut self) -> ChannelResult<()> {
+        self.handle
+            .drv
+            .ioctl(self.get_channel_id()?, IoctlID::CLEAR_TX_BUFFER)
+    }
+}
+
+#[derive(Debug)]
+/// Native ISO15765 channel on a PassThru adapter. Segmentation and flow control are
+/// handled by the adapter
+pub struct PassthruIsoTpChannel {
+    handle: Arc<PassthruHandle>,
+    channel_id: Option<u32>,
+    filter_id: Option<u32>,
+    ids: Option<(u32, u32)>,
+    cfg: Option<IsoTPSettings>,
+    device_state: Arc<AtomicBool>,
+    /// Adapter timestamp of the last payload read
+    last_rx_timestamp: Option<u64>,
+}
+
+impl PassthruIsoTpChannel {
+    fn new(handle: Arc<PassthruHandle>, device_state: Arc<AtomicBool>) -> Self {
+        Self {
+            handle,
+            channel_id: None,
+            filter_id: None,
+            ids: None,
+            cfg: None,
+            device_state,
+            last_rx_timestamp: None,
+        }
+    }
+
+    fn get_channel_id(&self) -> ChannelResult<u32> {
+        self.channel_id.ok_or(ChannelError::InterfaceNotOpen)
+    }
+
+    fn tx_flags(cfg: &IsoTPSettings) -> u32 {
+        let mut flags = TxFlag::TX_NORMAL_TRANSMIT;
+        if cfg.pad_frame {
+            flags |= TxFlag::ISO15765_FRAME_PAD;
+        }
+        if cfg.can_use_ext_addr {
+            flags |= TxFlag::CAN_29BIT_ID;
+        }
+        if cfg.extended_addresses.is_some() {
+            flags |= TxFlag::ISO15765_EXT_ADDR;
+        }
+        flags.bits()
+    }
+
+    /// Sets the flow control filter, which tells the adapter which IDs to send and receive with
+    fn set_flow_control_filter(&mut self) -> ChannelResult<()> {
+        let id = self.get_channel_id()?;
+        let cfg = self.cfg.ok_or(ChannelError::ConfigurationError)?;
+        let (send, recv) = self.ids.ok_or(ChannelError::ConfigurationError)?;
+        if let Some(filter) = self.filter_id.take() {
+            self.handle.drv.stop_msg_filter(id, filter)?;
+        }
+        let flags = Self::tx_flags(&cfg);
+        let id_mask = if cfg.can_use_ext_addr {
+            0x1FFFFFFF
+        } else {
+            0x7FF
+        };
+        let (mask, pattern, fc) = match cfg.extended_addresses {
+            Some((tx_ext, rx_ext)) => (
+                new_msg(Protocol::ISO15765, id_mask, &[0xFF], flags),
+                new_msg(Protocol::ISO15765, recv, &[rx_ext], flags),
+                new_msg(Protocol::ISO15765, send, &[tx_ext], flags),
+            ),
+            None => (
+                new_msg(Protocol::ISO15765, id_mask, &[], flags),
+                new_msg(Protocol::ISO15765, recv, &[], flags),
+                new_msg(Protocol::ISO15765, send, &[], flags),
+            ),
+        };
+        self.filter_id = Some(self.handle.drv.start_msg_filter(
+            id,
+            FilterType::FLOW_CONTROL_FILTER,
+            &mask,
+            &pattern,
+            Some(&fc),
+        )?);
+        Ok(())
+    }
+}
+
+impl Drop for PassthruIsoTpChannel {
+    fn drop(&mut self) {
+        let _ = PayloadChannel::close(self);
+        self.device_state.store(false, Ordering::Relaxed);
+    }
+}
+
+impl PayloadChannel for PassthruIsoTpChannel {
+    fn open(&mut self) -> ChannelResult<()> {
+        if self.channel_id.is_some() {
+            return Ok(());
+        }
+        let cfg = self.cfg.ok_or(ChannelError::ConfigurationError)?;
+        let mut flags = ConnectFlags::empty();
+        if cfg.can_use_ext_addr {
+            flags |= ConnectFlags::CAN_29BIT_ID;
+        }
+        if cfg.extended_addresses.is_some() {
+            flags |= ConnectFlags::ISO15765_ADDR_TYPE;
+        }
+        let drv = self.handle.drv.clone();
+        let id = drv.connect(
+            self.handle.device_id,
+            Protocol::ISO15765,
+            flags.bits(),
+            cfg.can_speed,
+        )?;
+        self.channel_id = Some(id);
+        let res = drv
+            .set_config(
+                id,
+                &[
+                    (IoctlParam::ISO15765_BS, cfg.block_size as u32),
+                    (IoctlParam::ISO15765_STMIN, cfg.st_min as u32),
+                    (IoctlParam::ISO15765_WFT_MAX, cfg.timeouts.n_wft_max as u32),
+                ],
+            )
+            .and_then(|_| self.set_flow_control_filter());
+        if res.is_err() {
+            let _ = PayloadChannel::close(self);
+        }
+        res
+    }
+
+    fn close(&mut self) -> ChannelResult<()> {
+        self.filter_id = None;
+        if let Some(id) = self.channel_id.take() {
+            self.handle.drv.disconnect(id)?;
+        }
+        Ok(())
+    }
+
+    fn set_ids(&mut self, send: u32, recv: u32) -> ChannelResult<()> {
+        self.ids = Some((send, recv));
+        if self.channel_id.is_some() {
+            self.set_flow_control_filter()?;
+        }
+        Ok(())
+    }
+
+    fn read_bytes(&mut self, timeout_ms: u32) -> ChannelResult<Vec<u8>> {
+        let id = self.get_channel_id()?;
+        let header_len = match self.cfg.and_then(|c| c.extended_addresses) {
+            Some(_) => 5,
+            None => 4,
+        };
+        let start = Instant::now();
+        loop {
+            let remaining = (timeout_ms as u128).saturating_sub(start.elapsed().as_millis());
+            let msgs = self.handle.drv.read_messages(id, 1, remaining as u32)?;
+            for m in msgs {
+                // Skip first frame and Tx complete indications
+                let indication = RxFlag::START_OF_MESSAGE | RxFlag::TX_DONE | RxFlag::TX_MSG_TYPE;
+                if m.rx_status & indication.bits() == 0 && m.data_size as usize >= header_len {
+                    self.last_rx_timestamp = Some(u64::from(m.timestamp));
+                    return Ok(m.data[header_len..m.data_size as usize].to_vec());
+                }
+            }
+            if timeout_ms == 0 {
+                return Err(ChannelError::BufferEmpty);
+            } else if remaining == 0 {
+                return Err(ChannelError::ReadTimeout);
+            }
+        }
+    }
+
+    fn write_bytes(
+        &mut self,
+        addr: u32,
+        ext_id: Option<u8>,
+        buffer: &[u8],
+        timeout_ms: u32,
+    ) -> ChannelResult<()> {
+        let id = self.get_channel_id()?;
+        let cfg = self.cfg.ok_or(ChannelError::ConfigurationError)?;
+        let flags = Self::tx_flags(&cfg);
+        let mut msg = match ext_id.or(cfg.extended_addresses.map(|(tx, _)| tx)) {
+            Some(ext) => {
+                let mut data = vec![ext];
+                data.extend_from_slice(buffer);
+                new_msg(Protocol::ISO15765, addr, &data, flags)
+            }
+            None => new_msg(Protocol::ISO15765, addr, buffer, flags),
+        };
+        self.handle
+            .drv
+            .write_messages(id, std::slice::from_mut(&mut msg), timeout_ms)
+    }
+
+    fn clear_rx_buffer(&mut self) -> ChannelResult<()> {
+        self.handle
+            .drv
+            .ioctl(self.get_channel_id()?, IoctlID::CLEAR_RX_BUFFER)
+    }
+
+    fn clear_tx_buffer(&mut self) -> ChannelResult<()> {
+        self.handle
+            .drv
+            .ioctl(self.get_channel_id()?, IoctlID::CLEAR_TX_BUFFER)
+    }
+
+    fn last_rx_timestamp_us(&self) -> Option<u64> {
+        self.last_rx_timestamp
+    }
+}
+
+impl IsoTPChannel for PassthruIsoTpChannel {
+    fn set_iso_tp_cfg(&mut self, cfg: IsoTPSettings) -> ChannelResult<()> {
+        if self.channel_id.is_some() {
+            return Err(ChannelError::InterfaceOpen);
+        }
+        self.cfg = Some(cfg);
+        Ok(())
+    }
+}
+
+/// PassThru device scanner. Lists the adapters registered in `~/.passthru/`
+#[derive(Debug, Clone)]
+pub struct PassthruScanner {
+    cache: Vec<HardwareInfo>,
+}
+
+impl Default for PassthruScanner {
+    fn default() -> Self {
+        let home = std::env::var_os("HOME").or_else(|| std::env::var_os("USERPROFILE"));
+        match home {
+            Some(h) => Self::new_from_dir(&PathBuf::from(h).join(".passthru")),
+            None => Self { cache: vec![] },
+        }
+    }
+}
+
+impl PassthruScanner {
+    /// Creates a scanner listing the adapter manifests found in `dir`
+    pub fn new_from_dir(dir: &Path) -> Self {
+        let mut res: Vec<HardwareInfo> = vec![];
+        if let Ok(entries) = std::fs::read_dir(dir) {
+            for entry in entries.flatten() {
+                let path = entry.path();
+                if path.extension().and_then(|e| e.to_str()) != Some("json") {
+                    continue;
+                }
+                let manifest = std::fs::read_to_string(&path)
+                    .ok()
+                    .and_then(|s| serde_json::from_str::<PassthruManifest>(&s).ok());
+                match manifest {
+                    Some(m) => res.push(m.into()),
+                    None => log::warn!("Invalid PassThru manifest {}", path.display()),
+                }
+            }
+        }
+        res.sort();
+        Self { cache: res }
+    }
+}
+
+impl HardwareScanner<PassthruDevice> for PassthruScanner {
+    fn list_devices(&self) -> Vec<HardwareInfo> {
+        self.cache.clone()
+    }
+
+    fn open_device_by_index(&self, idx: usize) -> HardwareResult<PassthruDevice> {
+        match self.cache.get(idx) {
+            Some(info) => PassthruDevice::open_device(info),
+            None => Err(HardwareError::DeviceNotFound),
+        }
+    }
+
+    fn open_device_by_name(&self, name: &str) -> HardwareResult<PassthruDevice> {
+        match self.cache.iter().find(|x| x.name == name) {
+            Some(info) => PassthruDevice::open_device(info),
+            None => Err(HardwareError::DeviceNotFound),
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use std::path::PathBuf;
+
+    use super::{PassthruDevice, PassthruScanner};
+    use crate::core::channel::{CanFrame, IsoTPSettings, PacketChannel, PayloadChannel};
+    use crate::hardware::{Hardware, HardwareScanner, IsoTpChannelType};
+
+    /// Minimal J2534 library. Every message written is looped back to the reader
+    const STUB_SRC: &str = r#"
+        #include <string.h>
+        typedef struct {
+            unsigned int protocol_id, rx_status, tx_flags, timestamp, data_size, extra_data_size;
+            unsigned char data[4128];
+        } PASSTHRU_MSG;
+        static PASSTHRU_MSG queue[64];
+        static unsigned int queue_len = 0;
+        int PassThruOpen(void *name, unsigned int *id) { *id = 1; return 0; }
+        int PassThruClose(unsigned int id) { return 0; }
+        int PassThruConnect(unsigned int dev, unsigned int proto, unsigned int flags, unsigned int baud, unsigned int *ch) {
+            *ch = proto; queue_len = 0; return 0;
+        }
+        int PassThruDisconnect(unsigned int ch) { return 0; }
+        int PassThruReadMsgs(unsigned int ch, PASSTHRU_MSG *msgs, unsigned int *num, unsigned int timeout) {
+            unsigned int n = *num < queue_len ? *num : queue_len;
+            memcpy(msgs, queue, n * sizeof(PASSTHRU_MSG));
+            memmove(queue, queue + n, (queue_len - n) * sizeof(PASSTHRU_MSG));
+            queue_len -= n;
+            *num = n;
+            return n == 0 ? 0x10 : 0;
+        }
+        int PassThruWriteMsgs(unsigned int ch, PASSTHRU_MSG *msgs, unsigned int *num, unsigned int timeout) {
+            for (unsigned int i = 0; i < *num && queue_len < 64; i++) {
+                queue[queue_len] = msgs[i];
+                queue[queue_len].rx_status = msgs[i].tx_flags & 0x100;
+                queue_len++;
+            }
+            return 0;
+        }
+        int PassThruStartMsgFilter(unsigned int ch, unsigned int ty, const PASSTHRU_MSG *mask,
+                                   const PASSTHRU_MSG *pattern, const PASSTHRU_MSG *fc, unsigned int *id) {
+            if (ty == 3 && fc == 0) { return 0x04; }
+            *id = 1; return 0;
+        }
+        int PassThruStopMsgFilter(unsigned int ch, unsigned int id) { return 0; }
+        int PassThruIoctl(unsigned int handle, unsigned int ioctl, void *in, void *out) {
+            if (ioctl == 0x03) { *(unsigned int *)out = 12600; }
+            if (ioctl == 0x08) { queue_len = 0; }
+            return 0;
+        }
+        int PassThruReadVersion(unsigned int dev, char *fw, char *dll, char *api) {
+            strcpy(fw, "1.0"); strcpy(dll, "2.0"); strcpy(api, "04.04"); return 0;
+        }
+        int PassThruGetLastError(char *err) { err[0] = 0; return 0; }
+    "#;
+
+    /// Builds the stub library and its manifest. Returns the manifest directory,
+    /// or None if no C compiler is available
+    fn build_stub(name: &str) -> Option<PathBuf> {
+        let dir = std::env::temp_dir().join(format!("passthru_{name}_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).ok()?;
+        let src = dir.join("stub.c");
+        let lib = dir.join("libstub.so");
+        std::fs::write(&src, STUB_SRC).ok()?;
+        let status = std::process::Command::new("cc")
+            .args(["-shared", "-fPIC", "-o"])
+            .arg(&lib)
+            .arg(&src)
+            .status()
+            .ok()?;
+        if !status.success() {
+            return None;
+        }
+        let manifest = serde_json::json!({
+            "NAME": "Stub VCI",
+            "VENDOR": "Test",
+            "FUNCTION_LIB": lib.to_string_lossy(),
+            "CAN": true,
+            "ISO15765": true,
+        });
+        std::fs::write(dir.join("stub.json"), manifest.to_string()).ok()?;
+        Some(dir)
+    }
+
+    fn open_stub(name: &str) -> Option<PassthruDevice> {
+        let Some(dir) = build_stub(name) else {
+            println!("C compiler not found, skipping");
+            return None;
+        };
+        let scanner = PassthruScanner::new_from_dir(&dir);
+        let devices = scanner.list_devices();
+        assert_eq!(devices.len(), 1);
+        assert_eq!(devices[0].name, "Stub VCI");
+        assert_eq!(devices[0].capabilities.iso_tp, IsoTpChannelType::Protocol);
+        Some(scanner.open_device_by_name("Stub VCI").unwrap())
+    }
+
+    #[test]
+    pub fn test_passthru_can() {
+        let Some(mut dev) = open_stub("can") else {
+            return;
+        };
+        assert_eq!(dev.get_info().api_version.as_deref(), Some("04.04"));
+        assert_eq!(dev.read_battery_voltage(), Some(12.6));
+
+        let mut can = dev.create_can_channel().unwrap();
+        assert!(dev.create_can_channel().is_err());
+        can.set_can_cfg(500_000, false).unwrap();
+        PacketChannel::open(&mut can).unwrap();
+        let tx = vec![
+            CanFrame::new(0x7E0, &[0x02, 0x3E, 0x00], false),
+            CanFrame::new(0x18DA10F1, &[0x01, 0x3E], true),
+        ];
+        can.write_packets(tx.clone(), 100).unwrap();
+        assert_eq!(can.read_packets(10, 100).unwrap(), tx);
+        assert!(can.read_packets(10, 0).unwrap().is_empty());
+        drop(can);
+        assert!(dev.create_can_channel().is_ok());
+    }
+
+    #[test]
+    pub fn test_passthru_iso15765() {
+        let Some(mut dev) = open_stub("isotp") else {
+            return;
+        };
+        let mut isotp = dev.create_iso_tp_channel(true).unwrap();
+        isotp
+            .set_iso_tp_cfg(IsoTPSettings {
+                can_use_ext_addr: false,
+                ..Default::default()
+            })
+            .unwrap();
+        isotp.set_ids(0x7E0, 0x7E8).unwrap();
+        PayloadChannel::open(&mut isotp).unwrap();
+
+        let payload = (0..100).collect::<Vec<u8>>();
+        isotp.write_bytes(0x7E0, None, &payload, 100).unwrap();
+        assert_eq!(isotp.read_bytes(100).unwrap(), payload);
+        assert!(isotp.read_bytes(0).is_err());
+    }
+}
@@ -15,11 +15,11 @@ use super::pcan_types::{
 };
 
 type GetErrorTextFn =
-    unsafe extern "stdcall" fn(error: TPCANStatus, languge: WORD, buffer: LPSTR) -> TPCANStatus;
+    unsafe extern "system" fn(error: TPCANStatus, languge: WORD, buffer: LPSTR) -> TPCANStatus;
 
-type GetStatusFn = unsafe extern "stdcall" fn(channel: TPCANHandle) -> TPCANStatus;
+type GetStatusFn = unsafe extern "system" fn(channel: TPCANHandle) -> TPCANStatus;
 
-type InitializeFn = unsafe extern "stdcall" fn(
+type InitializeFn = unsafe extern "system" fn(
     channel: TPCANHandle,
     btr0btr1: TPCANBaudrate,
     hwtype: TPCANType,
@@ -28,53 +28,53 @@ type InitializeFn = unsafe extern "stdcall" fn(
 ) -> TPCANStatus;
 
 type InitializeFdFn =
-    unsafe extern "stdcall" fn(channel: TPCANHandle, bitrate: TPCANBitrateFD) -> TPCANStatus;
+    unsafe extern "system" fn(channel: TPCANHandle, bitrate: TPCANBitrateFD) -> TPCANStatus;
 
 type LookUpChannelFn =
-    unsafe extern "stdcall" fn(paramters: LPSTR, found_channel: *mut TPCANHandle) -> TPCANStatus;
+    unsafe extern "system" fn(paramters: LPSTR, found_channel: *mut TPCANHandle) -> TPCANStatus;
 
-type ReadFn = unsafe extern "stdcall" fn(
+type ReadFn = unsafe extern "system" fn(
     channel: TPCANHandle,
     buffer: *mut TpCanMsg,
     timestamp: *mut TpCanTimestamp,
 ) -> TPCANStatus;
 
-type ReadFdFn = unsafe extern "stdcall" fn(
+type ReadFdFn = unsafe extern "system" fn(
     channel: TPCANHandle,
     buffer: *mut TpCanMsgFD,
     timestamp: *mut TpCanTimestamp,
 ) -> TPCANStatus;
 
-type ResetFn = unsafe extern "stdcall" fn(channel: TPCANHandle) -> TPCANStatus;
+type ResetFn = unsafe extern "system" fn(channel: TPCANHandle) -> TPCANStatus;
 
-type FilterMessagesFn = unsafe extern "stdcall" fn(
+type FilterMessagesFn = unsafe extern "system" fn(
     channel: TPCANHandle,
     from_id: DWORD,
     to_id: DWORD,
     mode: TPCANMode,
 ) -> TPCANStatus;
 
-type GetValueFn = unsafe extern "stdcall" fn(
+type GetValueFn = unsafe extern "system" fn(
     channel: TPCANHandle,
     parameter: TPCANParameter,
     buffer: *mut c_void,
     buffer_len: DWORD,
 ) -> TPCANStatus;
 
-type SetValueFn = unsafe extern "stdcall" fn(
+type SetValueFn = unsafe extern "system" fn(
     channel: TPCANHandle,
     parameter: TPCANParameter,
     buffer: *mut c_void,
     buffer_len: DWORD,
 ) -> TPCANStatus;
 
-type UninitalizeFn = unsafe extern "stdcall" fn(channel: TPCANHandle) -> TPCANStatus;
+type UninitalizeFn = unsafe extern "system" fn(channel: TPCANHandle) -> TPCANStatus;
 
 type WriteFn =
-    unsafe extern "stdcall" fn(channel: TPCANHandle, buffer: *mut TpCanMsg) -> TPCANStatus;
+    unsafe extern "system" fn(channel: TPCANHandle, buffer: *mut TpCanMsg) -> TPCANStatus;
 
 type WriteFdFn =
-    unsafe extern "stdcall" fn(channel: TPCANHandle, buffer: *mut TpCanMsgFD) -> TPCANStatus;
+    unsafe extern "system" fn(channel: TPCANHandle, buffer: *mut TpCanMsgFD) -> TPCANStatus;
 
 #[allow(dead_code)]
 fn check_pcan_func_result<T>(ret: T, status: TPCANStatus) -> PCanResult<T> {
@@ -6,26 +6,28 @@ pub mod pcan_api;
 pub mod pcan_types;
 
 use std::{
+    collections::BTreeMap,
     sync::{
         atomic::{AtomicBool, Ordering},
         Arc,
     },
-    time::Instant,
+    time::{Duration, Instant},
 };
 
 use crate::{
     core::channel::{
-        CanChannel, CanFrame, ChannelError, ChannelResult, IsoTPChannel, PacketChannel,
+        CanBusState, CanBusStatus, CanChannel, CanFrame, ChannelError, ChannelResult,
+        FilterPacketChannel, IsoTPChannel, PacketChannel,
     },
     hardware::{
-        pcan_usb::pcan_types::{PCANError, ALL_USB_DEVICES},
+        pcan_usb::pcan_types::{PCANError, ALL_LAN_DEVICES, ALL_PCI_DEVICES, ALL_USB_DEVICES},
         HardwareCapabilities,
     },
 };
 
 use self::{
     pcan_api::PCanDrvNew,
-    pcan_types::{PCANBaud, PcanUSB},
+    pcan_types::{PCANBaud, PCanErrorTy, PcanUSB, TPCANStatus},
 };
 
 use super::{
@@ -102,7 +104,10 @@ impl Hardware for PcanUsbDevice {
             let s = PcanUsbpacketChannel {
                 baud: None,
                 use_ext: None,
+                fd_bitrate: None,
                 filter_active: false,
+                filters: BTreeMap::new(),
+                overrun_count: 0,
                 dev_handle: self.dev_handle,
                 open: false,
                 driver: self.driver.clone(),
@@ -143,7 +148,13 @@ impl Hardware for PcanUsbDevice {
 pub struct PcanUsbpacketChannel {
     pub(crate) baud: Option<PCANBaud>,
     pub(crate) use_ext: Option<bool>,
+    /// PCAN FD bit rate string, set when the channel runs in CAN FD mode
+    pub(crate) fd_bitrate: Option<String>,
     pub(crate) filter_active: bool,
+    /// Filters added with [FilterPacketChannel::add_filter], by filter ID
+    filters: BTreeMap<u32, Vec<u32>>,
+    /// Receive overruns seen by [CanChannel::get_bus_status]
+    overrun_count: u32,
     dev_handle: PcanUSB,
     driver: PCanDrvNew,
     device_state: Arc<AtomicBool>,
@@ -156,41 +167,239 @@ impl Drop for PcanUsbpacketChannel {
     }
 }
 
+impl PcanUsbpacketChannel {
+    /// Applies the active filters to the adapter. Without filters, all traffic is received
+    fn apply_filters(&mut self) -> ChannelResult<()> {
+        if !self.open {
+            // Applied on open
+            return Ok(());
+        }
+        if self.filters.is_empty() {
+            self.driver.set_message_filter(self.dev_handle, true)?;
+            self.filter_active = false;
+            return Ok(());
+        }
+        // The driver widens its filter to cover each range, so upper layers should still
+        // check the IDs of frames they receive
+        self.driver.set_message_filter(self.dev_handle, false)?;
+        let use_ext = self.use_ext.unwrap_or(false);
+        for (from, to) in id_ranges(self.filters.values().flatten().copied()) {
+            self.driver
+                .filter_messages(self.dev_handle, from, to, use_ext || to > 0x7FF)?;
+        }
+        self.filter_active = true;
+        Ok(())
+    }
+
+    /// Listens in listen-only mode at a baud rate. Returns true if frames were
+    /// received without the CAN controller seeing any bus errors
+    fn listen_for_traffic(&mut self, baud: PCANBaud, listen_ms: u32) -> ChannelResult<bool> {
+        self.driver.set_listen_only(self.dev_handle, true)?;
+        self.driver.initialize_can(self.dev_handle, baud)?;
+        let start = Instant::now();
+        let mut frames = 0;
+        while start.elapsed().as_millis() < listen_ms as u128 {
+            match self.driver.read(self.dev_handle) {
+                Ok(_) => frames += 1,
+                Err(ChannelError::BufferEmpty) => std::thread::sleep(Duration::from_millis(1)),
+                // Bus errors are reported by the read, the baud rate is wrong
+                Err(_) => return Ok(false),
+            }
+        }
+        let (state, _) = bus_state_from_status(self.driver.get_status(self.dev_handle))?;
+        Ok(frames > 0 && state == CanBusState::ErrorActive)
+    }
+}
+
+/// Converts the status flags of `CAN_GetStatus` into a bus state, and if frames were lost
+/// to an overrun. Statuses that are not about the bus are returned as errors
+fn bus_state_from_status(status: TPCANStatus) -> ChannelResult<(CanBusState, bool)> {
+    const BUS_FLAGS: TPCANStatus = PCANError::XMTFull as TPCANStatus
+        | PCANError::Overrun as TPCANStatus
+        | PCANError::BusLight as TPCANStatus
+        | PCANError::BusHeavy as TPCANStatus
+        | PCANError::BusOff as TPCANStatus
+        | PCANError::QrcvEmpty as TPCANStatus
+        | PCANError::Qoverrun as TPCANStatus
+        | PCANError::QxmtFull as TPCANStatus;
+    if status & !BUS_FLAGS != 0 {
+        return Err(match PCANError::from_repr(status) {
+            Some(e) => PCanErrorTy::StandardError(e),
+            None => PCanErrorTy::Unknown(status),
+        }
+        .into());
+    }
+    let state = if status & PCANError::BusOff as TPCANStatus != 0 {
+        CanBusState::BusOff
+    } else if status & PCANError::BusHeavy as TPCANStatus != 0 {
+        CanBusState::ErrorPassive
+    } else if status & PCANError::BusLight as TPCANStatus != 0 {
+        CanBusState::Warning
+    } else {
+        CanBusState::ErrorActive
+    };
+    let overrun =
+        status & (PCANError::Overrun as TPCANStatus | PCANError::Qoverrun as TPCANStatus) != 0;
+    Ok((state, overrun))
+}
+
+/// Merges IDs into the smallest list of inclusive ID ranges
+fn id_ranges(ids: impl Iterator<Item = u32>) -> Vec<(u32, u32)> {
+    let mut ids: Vec<u32> = ids.collect();
+    ids.sort_unstable();
+    ids.dedup();
+    let mut ranges: Vec<(u32, u32)> = vec![];
+    for id in ids {
+        match ranges.last_mut() {
+            Some((_, to)) if *to + 1 == id => *to = id,
+            _ => ranges.push((id, id)),
+        }
+    }
+    ranges
+}
+
+impl FilterPacketChannel<CanFrame> for PcanUsbpacketChannel {
+    fn add_filter(&mut self, allowed_ids: &[u32]) -> ChannelResult<u32> {
+        if allowed_ids.is_empty() {
+            return Err(ChannelError::ConfigurationError);
+        }
+        let id = self.filters.keys().last().map_or(0, |x| x + 1);
+        self.filters.insert(id, allowed_ids.to_vec());
+        if let Err(e) = self.apply_filters() {
+            self.filters.remove(&id);
+            return Err(e);
+        }
+        Ok(id)
+    }
+
+    fn remove_filter(&mut self, filter_id: u32) -> ChannelResult<()> {
+        if self.filters.remove(&filter_id).is_none() {
+            return Err(ChannelError::ConfigurationError);
+        }
+        self.apply_filters()
+    }
+}
+
+/// Maps a baud rate to one of the fixed PCAN baud rates
+fn pcan_baud(baud: u32) -> ChannelResult<PCANBaud> {
+    Ok(match baud {
+        1_000_000 => PCANBaud::Can1Mbps,
+        800_000 => PCANBaud::Can800Kbps,
+        500_000 => PCANBaud::Can500Kbps,
+        250_000 => PCANBaud::Can250Kbps,
+        125_000 => PCANBaud::Can125Kbps,
+        100_000 => PCANBaud::Can100Kbps,
+        95_238 => PCANBaud::Can95Kbps,
+        83_333 => PCANBaud::Can83Kbps,
+        50_000 => PCANBaud::Can50Kbps,
+        47_619 => PCANBaud::Can47Kbps,
+        33_333 => PCANBaud::Can33Kbps,
+        20_000 => PCANBaud::Can20Kbps,
+        10_000 => PCANBaud::Can10Kbps,
+        5_000 => PCANBaud::Can5Kbps,
+        _ => return Err(ChannelError::ConfigurationError),
+    })
+}
+
 impl CanChannel for PcanUsbpacketChannel {
     fn set_can_cfg(&mut self, baud: u32, use_extended: bool) -> ChannelResult<()> {
-        let baud_ty = match baud {
-            1_000_000 => PCANBaud::Can1Mbps,
-            800_000 => PCANBaud::Can800Kbps,
-            500_000 => PCANBaud::Can500Kbps,
-            250_000 => PCANBaud::Can250Kbps,
-            125_000 => PCANBaud::Can125Kbps,
-            100_000 => PCANBaud::Can100Kbps,
-            95_238 => PCANBaud::Can95Kbps,
-            83_333 => PCANBaud::Can83Kbps,
-            50_000 => PCANBaud::Can50Kbps,
-            47_619 => PCANBaud::Can47Kbps,
-            33_333 => PCANBaud::Can33Kbps,
-            20_000 => PCANBaud::Can20Kbps,
-            10_000 => PCANBaud::Can10Kbps,
-            5_000 => PCANBaud::Can5Kbps,
-            _ => return Err(ChannelError::ConfigurationError),
-        };
-
+        let baud_ty = pcan_baud(baud)?;
         self.baud = Some(baud_ty);
         self.use_ext = Some(use_extended);
+        self.fd_bitrate = None;
+        Ok(())
+    }
+
+    fn set_can_fd_cfg(
+        &mut self,
+        baud: u32,
+        data_baud: u32,
+        use_extended: bool,
+    ) -> ChannelResult<()> {
+        let (nom_brp, nom_tseg1, nom_tseg2) =
+            fd_bit_timing(baud, 256, 128).ok_or(ChannelError::ConfigurationError)?;
+        let (data_brp, data_tseg1, data_tseg2) =
+            fd_bit_timing(data_baud, 32, 16).ok_or(ChannelError::ConfigurationError)?;
+        self.fd_bitrate = Some(format!(
+            "f_clock_mhz={}, nom_brp={nom_brp}, nom_tseg1={nom_tseg1}, nom_tseg2={nom_tseg2}, nom_sjw={nom_tseg2}, data_brp={data_brp}, data_tseg1={data_tseg1}, data_tseg2={data_tseg2}, data_sjw={data_tseg2}",
+            PCAN_FD_CLOCK / 1_000_000
+        ));
+        self.use_ext = Some(use_extended);
         Ok(())
     }
+
+    fn get_bus_status(&mut self) -> ChannelResult<CanBusStatus> {
+        if !self.open {
+            return Err(ChannelError::InterfaceNotOpen);
+        }
+        let (state, overrun) = bus_state_from_status(self.driver.get_status(self.dev_handle))?;
+        if overrun {
+            self.overrun_count += 1;
+        }
+        Ok(CanBusStatus {
+            state,
+            // Not reported by the PCAN-Basic API
+            tx_error_count: None,
+            rx_error_count: None,
+            overrun_count: self.overrun_count,
+        })
+    }
+
+    fn detect_can_baud(&mut self, candidates: &[u32], listen_ms: u32) -> ChannelResult<u32> {
+        if self.open {
+            return Err(ChannelError::InterfaceOpen);
+        }
+        for &baud in candidates {
+            let baud_ty = pcan_baud(baud)?;
+            let res = self.listen_for_traffic(baud_ty, listen_ms);
+            let _ = self.driver.reset_handle(self.dev_handle);
+            let _ = self.driver.set_listen_only(self.dev_handle, false);
+            if res? {
+                log::debug!("Detected CAN baud rate {baud}");
+                return Ok(baud);
+            }
+        }
+        Err(ChannelError::BaudNotDetected)
+    }
+}
+
+/// Clock used for CAN FD bit timing calculation
+const PCAN_FD_CLOCK: u32 = 80_000_000;
+
+/// Calculates (brp, tseg1, tseg2) for a bitrate at an ~80% sample point.
+/// Returns None if the bitrate cannot be reached exactly with the FD clock
+fn fd_bit_timing(baud: u32, max_tseg1: u32, max_tseg2: u32) -> Option<(u32, u32, u32)> {
+    if baud == 0 {
+        return None;
+    }
+    (1..=1024u32).find_map(|brp| {
+        let prescaled = brp.checked_mul(baud)?;
+        // Time quanta per bit, including the sync segment
+        let tq = PCAN_FD_CLOCK / prescaled;
+        if tq * prescaled != PCAN_FD_CLOCK {
+            return None;
+        }
+        let tseg2 = (tq / 5).max(1);
+        let tseg1 = tq.checked_sub(1 + tseg2)?;
+        if tseg1 == 0 || tseg1 > max_tseg1 || tseg2 > max_tseg2 {
+            return None;
+        }
+        Some((brp, tseg1, tseg2))
+    })
 }
 
 impl PacketChannel<CanFrame> for PcanUsbpacketChannel {
     fn open(&mut self) -> ChannelResult<()> {
         if self.open {
             Ok(())
+        } else if let Some(fd) = &self.fd_bitrate {
+            self.driver.initialize_can_fd(self.dev_handle, fd)?;
+            self.open = true;
+            self.apply_filters()
         } else if let Some(b) = self.baud {
             self.driver.initialize_can(self.dev_handle, b)?;
-            // Open filter set
             self.open = true;
-            Ok(())
+            self.apply_filters()
         } else {
             Err(ChannelError::ConfigurationError)
         }
@@ -212,7 +421,11 @@ impl PacketChannel<CanFrame> for PcanUsbpacketChannel {
     fn write_packets(&mut self, packets: Vec<CanFrame>, timeout_ms: u32) -> ChannelResult<()> {
         let start = Instant::now();
         for frame in packets {
-            self.driver.write(self.dev_handle, frame)?;
+            if self.fd_bitrate.is_some() {
+                self.driver.write_fd(self.dev_handle, frame)?;
+            } else {
+                self.driver.write(self.dev_handle, frame)?;
+            }
             // Write timeout
             if timeout_ms != 0 && start.elapsed().as_millis() > timeout_ms as u128 {
                 return Err(ChannelError::WriteTimeout);
@@ -225,7 +438,11 @@ impl PacketChannel<CanFrame> for PcanUsbpacketChannel {
         let mut read_packets = vec![];
         let start = Instant::now();
         loop {
-            let res = self.driver.read(self.dev_handle);
+            let res = if self.fd_bitrate.is_some() {
+                self.driver.read_fd(self.dev_handle)
+            } else {
+                self.driver.read(self.dev_handle)
+            };
             match res {
                 Ok(f) => {
                     read_packets.push(f);
@@ -274,20 +491,28 @@ impl Default for PcanUsbScanner {
 }
 
 impl PcanUsbScanner {
-    fn scan_devices(&mut self) {
+    /// Enumerates all PCAN channels (USB, PCI and LAN) which have hardware attached.
+    /// Called on construction, call again to pick up adapters plugged in since
+    pub fn scan_devices(&mut self) {
         match &self.driver {
             Ok(drv) => {
                 let mut res = vec![];
-                for dev in ALL_USB_DEVICES {
-                    if let Ok((name, version)) = drv.get_device_info(dev) {
+                let handles = ALL_USB_DEVICES
+                    .iter()
+                    .chain(ALL_PCI_DEVICES)
+                    .chain(ALL_LAN_DEVICES);
+                for dev in handles {
+                    if let Ok(dev_info) = drv.get_device_info(dev) {
+                        log::debug!("Found {} on 0x{:04X}", dev_info.hardware_name, dev.repr());
                         res.push((
                             *dev,
                             HardwareInfo {
-                                name: format!("PCAN-USB_0x{:04X}", *dev as u32),
+                                name: channel_name(dev),
                                 vendor: Some("PEAK-System Technik GmbH".to_string()),
-                                device_fw_version: Some(name),
-                                api_version: Some(version.clone()),
-                                library_version: Some(version),
+                                device_fw_version: dev_info.firmware_version,
+                                device_id: dev_info.device_id,
+                                api_version: Some(dev_info.api_version.clone()),
+                                library_version: Some(dev_info.api_version),
                                 library_location: Some(drv.get_path().to_string()),
                                 capabilities: HardwareCapabilities {
                                     iso_tp: IsoTpChannelType::Protocol,
@@ -308,6 +533,37 @@ impl PcanUsbScanner {
         }
     }
 
+    /// Returns the PCAN channel handle of a device listed by [HardwareScanner::list_devices]
+    pub fn get_handle(&self, name: &str) -> Option<PcanUSB> {
+        self.cache
+            .iter()
+            .find(|(_, info)| info.name == name)
+            .map(|(handle, _)| *handle)
+    }
+
+    /// Opens an adapter given either its name or its index in [HardwareScanner::list_devices].
+    /// Without one, the first adapter found is opened. If none were found (Or the driver
+    /// cannot enumerate them), [PcanUSB::USB1] is used
+    pub fn open_adapter(&self, adapter: Option<&str>) -> HardwareResult<PcanUsbDevice> {
+        match adapter {
+            Some(adapter) => match adapter.parse::<usize>() {
+                Ok(idx) => self.open_device_by_index(idx),
+                Err(_) => self.open_device_by_name(adapter),
+            },
+            None if !self.cache.is_empty() => self.open_device_by_index(0),
+            None => PcanUsbDevice::new(
+                PcanUSB::USB1,
+                HardwareInfo {
+                    name: channel_name(&PcanUSB::USB1),
+                    ..Default::default()
+                },
+                PCanDrvNew {
+                    is_connected: false,
+                },
+            ),
+        }
+    }
+
     fn open_handle(&self, handle: &PcanUSB, info: HardwareInfo) -> HardwareResult<PcanUsbDevice> {
         PcanUsbDevice::new(*handle, info, self.driver.as_ref().unwrap().clone())
     }
@@ -334,3 +590,52 @@ impl HardwareScanner<PcanUsbDevice> for PcanUsbScanner {
         Err(HardwareError::DeviceNotFound)
     }
 }
+
+/// Name of a PCAN channel as listed by [PcanUsbScanner], for example `PCAN-USB_0x0051`
+fn channel_name(handle: &PcanUSB) -> String {
+    format!("{}_0x{:04X}", handle.interface(), handle.repr())
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::pcan_types::PcanUSB;
+    use super::{bus_state_from_status, channel_name, id_ranges};
+    use crate::core::channel::CanBusState;
+
+    #[test]
+    pub fn test_filter_id_ranges() {
+        let ids = [0x7E9, 0x7E8, 0x7F0, 0x7E8, 0x100, 0x7EA];
+        assert_eq!(
+            id_ranges(ids.into_iter()),
+            vec![(0x100, 0x100), (0x7E8, 0x7EA), (0x7F0, 0x7F0)]
+        );
+        assert!(id_ranges(std::iter::empty()).is_empty());
+    }
+
+    #[test]
+    pub fn test_bus_state_from_status() {
+        assert_eq!(
+            bus_state_from_status(0).unwrap(),
+            (CanBusState::ErrorActive, false)
+        );
+        assert_eq!(
+            bus_state_from_status(0x08).unwrap(),
+            (CanBusState::ErrorPassive, false)
+        );
+        // Bus-off with a lost frame in the receive queue
+        assert_eq!(
+            bus_state_from_status(0x10 | 0x40).unwrap(),
+            (CanBusState::BusOff, true)
+        );
+        // Channel is not initialized
+        assert!(bus_state_from_status(0x4000000).is_err());
+    }
+
+    #[test]
+    pub fn test_channel_name() {
+        assert_eq!(channel_name(&PcanUSB::USB1), "PCAN-USB_0x0051");
+        assert_eq!(channel_name(&PcanUSB::USB16), "PCAN-USB_0x0510");
+        assert_eq!(channel_name(&PcanUSB::PCI9), "PCAN-PCI_0x0409");
+        assert_eq!(channel_name(&PcanUSB::LAN2), "PCAN-LAN_0x0802");
+    }
+}
@@ -1,12 +1,17 @@
 use super::pcan_types::{
-    MsgType, PCANBaud, PCANError, PCanErrorTy, PCanResult, PcanUSB, TPCANBaudrate, TPCANHandle,
-    TPCANMode, TPCANParameter, TPCANStatus, TPCANType, TpCanMsg, TpCanTimestamp,
+    MsgType, PCANBaud, PCANError, PCanErrorTy, PCanResult, PcanUSB, TPCANBaudrate, TPCANBitrateFD,
+    TPCANHandle, TPCANMode, TPCANParameter, TPCANStatus, TPCANTimestampFD, TPCANType, TpCanMsg,
+    TpCanMsgFD, TpCanTimestamp, MAX_LENGTH_HARDWARE_NAME, MAX_LENGTH_VERSION_STRING,
+    PCAN_CHANNEL_AVAILABLE, PCAN_CHANNEL_OCCUPIED, PCAN_FILTER_CLOSE, PCAN_FILTER_OPEN,
+    PCAN_MODE_EXTENDED, PCAN_MODE_STANDARD,
 };
 use super::pcan_types::{DWORD, LPSTR, WORD};
-use crate::core::channel::{CanFrame, ChannelError, ChannelResult, Packet};
+use crate::core::channel::{
+    can_fd_dlc_to_len, can_fd_len_to_dlc, CanFrame, ChannelError, ChannelResult, Packet,
+};
 use crate::hardware::pcan_usb::pcan_types::PCANParameter;
 use crate::hardware::{HardwareError, HardwareResult};
-use std::ffi::{c_void, CStr};
+use std::ffi::{c_void, CStr, CString};
 use std::fmt;
 
 #[cfg(windows)]
@@ -31,6 +36,14 @@ extern "C" {
     ) -> TPCANStatus;
     fn CAN_Write(channel: TPCANHandle, msgBuffer: *mut TpCanMsg) -> TPCANStatus;
 
+    fn CAN_InitializeFD(channel: TPCANHandle, bitrateFD: TPCANBitrateFD) -> TPCANStatus;
+    fn CAN_ReadFD(
+        channel: TPCANHandle,
+        msgBuffer: *mut TpCanMsgFD,
+        timeStmpBuffer: *mut TPCANTimestampFD,
+    ) -> TPCANStatus;
+    fn CAN_WriteFD(channel: TPCANHandle, msgBuffer: *mut TpCanMsgFD) -> TPCANStatus;
+
     fn CAN_FilterMessages(
         channel: TPCANHandle,
         from_id: DWORD,
@@ -74,6 +87,14 @@ extern "C" {
     ) -> TPCANStatus;
     fn CAN_Write(channel: TPCANHandle, msgBuffer: *mut TpCanMsg) -> TPCANStatus;
 
+    fn CAN_InitializeFD(channel: TPCANHandle, bitrateFD: TPCANBitrateFD) -> TPCANStatus;
+    fn CAN_ReadFD(
+        channel: TPCANHandle,
+        msgBuffer: *mut TpCanMsgFD,
+        timeStmpBuffer: *mut TPCANTimestampFD,
+    ) -> TPCANStatus;
+    fn CAN_WriteFD(channel: TPCANHandle, msgBuffer: *mut TpCanMsgFD) -> TPCANStatus;
+
     fn CAN_FilterMessages(
         channel: TPCANHandle,
         from_id: DWORD,
@@ -122,6 +143,19 @@ impl fmt::Debug for PCanDrvNew {
     }
 }
 
+/// Identification of an attached PCAN channel, see [PCanDrvNew::get_device_info]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PcanChannelInfo {
+    /// Name of the hardware, for example `PCAN-USB FD`
+    pub hardware_name: String,
+    /// User configurable device ID, used to tell adapters of the same type apart
+    pub device_id: Option<u32>,
+    /// Firmware version of the adapter
+    pub firmware_version: Option<String>,
+    /// Version of the PCAN-Basic API
+    pub api_version: String,
+}
+
 impl PCanDrvNew {
     pub fn reset_driver(&self) -> HardwareResult<()> {
         // log::debug!("reset_driver called");
@@ -136,40 +170,92 @@ impl PCanDrvNew {
         check_pcan_func_result((), res).map_err(|e| e.into())
     }
 
-    pub fn get_device_info(&self, handle: &PcanUSB) -> HardwareResult<(String, String)> {
-        log::debug!("get_device_info called: handle: 0x{:04X}", *handle as i16);
-        let mut n: [u8; 33] = [0; 33];
-        let mut v: [u8; 256] = [0; 256];
+    /// Returns the raw status of the channel. Besides errors, these are the bus state
+    /// flags of [PCANError] (BusLight, BusHeavy, BusOff, Overrun, ...)
+    pub fn get_status(&self, handle: PcanUSB) -> TPCANStatus {
+        unsafe { CAN_GetStatus(handle.repr()) }
+    }
+
+    /// Reads the identification of a channel. Fails if no hardware is attached to the channel
+    pub fn get_device_info(&self, handle: &PcanUSB) -> HardwareResult<PcanChannelInfo> {
+        log::debug!("get_device_info called: handle: 0x{:04X}", handle.repr());
+        let mut condition: DWORD = 0;
+        self.get_value(*handle, PCANParameter::ChannelCondition, &mut condition)?;
+        if condition & (PCAN_CHANNEL_AVAILABLE | PCAN_CHANNEL_OCCUPIED) == 0 {
+            return Err(HardwareError::DeviceNotFound);
+        }
+
+        let hardware_name = self.get_string(
+            *handle,
+            PCANParameter::HardwareName,
+            MAX_LENGTH_HARDWARE_NAME,
+        )?;
+        let api_version = self.get_string(
+            *handle,
+            PCANParameter::APIVersion,
+            MAX_LENGTH_VERSION_STRING,
+        )?;
+        // Not every adapter supports these two
+        let mut device_id: DWORD = 0;
+        let device_id = self
+            .get_value(*handle, PCANParameter::DeviceID, &mut device_id)
+            .ok()
+            .map(|_| device_id);
+        let firmware_version = self
+            .get_string(
+                *handle,
+                PCANParameter::FirmwareVersion,
+                MAX_LENGTH_VERSION_STRING,
+            )
+            .ok();
+
+        Ok(PcanChannelInfo {
+            hardware_name,
+            device_id,
+            firmware_version,
+            api_version,
+        })
+    }
 
+    fn get_value<T>(
+        &self,
+        handle: PcanUSB,
+        param: PCANParameter,
+        value: &mut T,
+    ) -> HardwareResult<()> {
         check_pcan_func_result((), unsafe {
             CAN_GetValue(
                 handle.repr(),
-                PCANParameter::HardwareName.repr(),
-                &mut n as *mut _ as *mut c_void,
-                256,
+                param.repr(),
+                value as *mut T as *mut c_void,
+                std::mem::size_of::<T>() as DWORD,
             )
         })
-        .map_err(HardwareError::from)?;
+        .map_err(HardwareError::from)
+    }
 
+    fn get_string(
+        &self,
+        handle: PcanUSB,
+        param: PCANParameter,
+        max_len: usize,
+    ) -> HardwareResult<String> {
+        let mut buf = vec![0u8; max_len];
         check_pcan_func_result((), unsafe {
             CAN_GetValue(
                 handle.repr(),
-                PCANParameter::APIVersion.repr(),
-                &mut v as *mut _ as *mut c_void,
-                33,
+                param.repr(),
+                buf.as_mut_ptr() as *mut c_void,
+                max_len as DWORD,
             )
         })
         .map_err(HardwareError::from)?;
-
-        let name = CStr::from_bytes_until_nul(&n)
-            .unwrap()
-            .to_string_lossy()
-            .to_string();
-        let version = CStr::from_bytes_until_nul(&v)
+        // Guard against a missing terminator
+        buf[max_len - 1] = 0;
+        Ok(CStr::from_bytes_until_nul(&buf)
             .unwrap()
             .to_string_lossy()
-            .to_string();
-        Ok((name, version))
+            .to_string())
     }
 
     pub fn initialize_can(&mut self, handle: PcanUSB, baud: PCANBaud) -> HardwareResult<()> {
@@ -185,7 +271,93 @@ impl PCanDrvNew {
             CAN_Initialize(handle.repr(), baud.repr(), 0, 0, 0)
         })
         .map_err(HardwareError::from)?;
+        self.configure_channel(handle)
+    }
+
+    /// Initializes a CAN FD channel. `bitrate` is a PCAN FD bit rate string
+    /// (EG: `f_clock_mhz=80, nom_brp=1, nom_tseg1=127, ...`)
+    pub fn initialize_can_fd(&mut self, handle: PcanUSB, bitrate: &str) -> HardwareResult<()> {
+        log::debug!(
+            "initialize_can_fd called: handle: 0x{:04X}, bitrate: {bitrate}",
+            handle as i16
+        );
+        let bitrate = CString::new(bitrate).map_err(|_| HardwareError::APIError {
+            code: PCANError::IllParamVal as u32,
+            desc: "Invalid FD bitrate string".into(),
+        })?;
+        check_pcan_func_result((), unsafe {
+            CAN_InitializeFD(handle.repr(), bitrate.as_ptr())
+        })
+        .map_err(HardwareError::from)?;
+        self.configure_channel(handle)
+    }
+
+    /// Opens (all messages are received) or closes (no messages are received) the message filter.
+    /// [PCanDrvNew::filter_messages] then widens a closed filter
+    pub fn set_message_filter(&mut self, handle: PcanUSB, open: bool) -> ChannelResult<()> {
+        log::debug!(
+            "set_message_filter called: handle: 0x{:04X}, open: {open}",
+            handle as i16
+        );
+        let mut param: [TPCANParameter; 1] = [if open {
+            PCAN_FILTER_OPEN
+        } else {
+            PCAN_FILTER_CLOSE
+        }];
+        check_pcan_func_result((), unsafe {
+            CAN_SetValue(
+                handle.repr(),
+                PCANParameter::MessageFilter.repr(),
+                param.as_mut_ptr() as *mut c_void,
+                std::mem::size_of_val(&param) as DWORD,
+            )
+        })
+        .map_err(ChannelError::from)
+    }
+
+    /// Switches listen-only mode on or off. In listen-only mode, the CAN controller neither sends
+    /// nor acknowledges frames. Takes effect on the next [PCanDrvNew::initialize_can]
+    pub fn set_listen_only(&mut self, handle: PcanUSB, on: bool) -> HardwareResult<()> {
+        log::debug!(
+            "set_listen_only called: handle: 0x{:04X}, on: {on}",
+            handle as i16
+        );
+        let mut param: DWORD = on.into();
+        check_pcan_func_result((), unsafe {
+            CAN_SetValue(
+                handle.repr(),
+                PCANParameter::ListenOnly.repr(),
+                &mut param as *mut DWORD as *mut c_void,
+                std::mem::size_of_val(&param) as DWORD,
+            )
+        })
+        .map_err(HardwareError::from)
+    }
+
+    /// Adds the ID range `from_id..=to_id` to the message filter
+    pub fn filter_messages(
+        &mut self,
+        handle: PcanUSB,
+        from_id: u32,
+        to_id: u32,
+        extended: bool,
+    ) -> ChannelResult<()> {
+        log::debug!(
+            "filter_messages called: handle: 0x{:04X}, from: 0x{from_id:08X}, to: 0x{to_id:08X}",
+            handle as i16
+        );
+        let mode = if extended {
+            PCAN_MODE_EXTENDED
+        } else {
+            PCAN_MODE_STANDARD
+        };
+        check_pcan_func_result((), unsafe {
+            CAN_FilterMessages(handle.repr(), from_id, to_id, mode)
+        })
+        .map_err(ChannelError::from)
+    }
 
+    fn configure_channel(&mut self, handle: PcanUSB) -> HardwareResult<()> {
         // Configure Open filter
         let mut param: [TPCANParameter; 1] = [0x01];
         let mut p_type = PCANParameter::MessageFilter.repr();
@@ -222,14 +394,17 @@ impl PCanDrvNew {
             len: 0,
             data: [0; 8],
         };
-        let res = unsafe { CAN_Read(handle.repr(), &mut can_msg, std::ptr::null_mut()) };
+        let mut timestamp = TpCanTimestamp::default();
+        let res = unsafe { CAN_Read(handle.repr(), &mut can_msg, &mut timestamp) };
         check_pcan_func_result((), res).map_err(ChannelError::from)?;
         // Read OK!
-        Ok(CanFrame::new(
+        let mut frame = CanFrame::new(
             can_msg.id,
             &can_msg.data[0..can_msg.len as usize],
             can_msg.msgtype == MsgType::Extended,
-        ))
+        );
+        frame.set_timestamp_us(timestamp.as_micros());
+        Ok(frame)
     }
 
     pub fn write(&mut self, handle: PcanUSB, packet: CanFrame) -> ChannelResult<()> {
@@ -255,6 +430,62 @@ impl PCanDrvNew {
         check_pcan_func_result((), status).map_err(ChannelError::from)
     }
 
+    pub fn read_fd(&mut self, handle: PcanUSB) -> ChannelResult<CanFrame> {
+        let mut can_msg = TpCanMsgFD {
+            id: 0,
+            msg_type: MsgType::Standard as u8,
+            dlc: 0,
+            data: [0; 64],
+        };
+        let mut timestamp: TPCANTimestampFD = 0;
+        let res = unsafe { CAN_ReadFD(handle.repr(), &mut can_msg, &mut timestamp) };
+        check_pcan_func_result((), res).map_err(ChannelError::from)?;
+        let ext = can_msg.msg_type & MsgType::Extended as u8 != 0;
+        let data = &can_msg.data[0..can_fd_dlc_to_len(can_msg.dlc)];
+        let mut frame = if can_msg.msg_type & MsgType::Fd as u8 != 0 {
+            CanFrame::new_fd(
+                can_msg.id,
+                data,
+                ext,
+                can_msg.msg_type & MsgType::Brs as u8 != 0,
+            )
+        } else {
+            CanFrame::new(can_msg.id, data, ext)
+        };
+        // FD timestamps are already in microseconds
+        frame.set_timestamp_us(timestamp);
+        Ok(frame)
+    }
+
+    pub fn write_fd(&mut self, handle: PcanUSB, packet: CanFrame) -> ChannelResult<()> {
+        log::debug!(
+            "write_fd called: handle: 0x{:04X}, addr: 0x{:08X}, content: {:02X?}",
+            handle as i16,
+            packet.get_address(),
+            packet.get_data()
+        );
+        let mut msg_type = MsgType::Standard as u8;
+        if packet.is_extended() {
+            msg_type |= MsgType::Extended as u8;
+        }
+        if packet.is_fd() {
+            msg_type |= MsgType::Fd as u8;
+        }
+        if packet.is_brs() {
+            msg_type |= MsgType::Brs as u8;
+        }
+        let l = packet.get_data().len();
+        let mut can_msg = TpCanMsgFD {
+            id: packet.get_address(),
+            msg_type,
+            dlc: can_fd_len_to_dlc(l),
+            data: [0; 64],
+        };
+        can_msg.data[0..l].copy_from_slice(packet.get_data());
+        let status = unsafe { CAN_WriteFD(handle.repr(), &mut can_msg) };
+        check_pcan_func_result((), status).map_err(ChannelError::from)
+    }
+
     pub fn get_path(&self) -> &'static str {
         "PCANBasic.dll"
     }
@@ -270,10 +501,27 @@ impl PCanDrvNew {
 
 #[cfg(test)]
 pub mod test {
-    use super::super::pcan_types::{PCANBaud, PcanUSB};
+    use super::super::pcan_types::{PCANBaud, PcanUSB, TpCanTimestamp};
     use super::PCanDrvNew;
     use crate::core::channel::CanFrame;
 
+    #[test]
+    pub fn test_timestamp_micros() {
+        let ts = TpCanTimestamp {
+            millis: 1500,
+            millis_overflow: 0,
+            micros: 250,
+        };
+        assert_eq!(ts.as_micros(), 1_500_250);
+        // millis wrapped around once
+        let ts = TpCanTimestamp {
+            millis: 2,
+            millis_overflow: 1,
+            micros: 0,
+        };
+        assert_eq!(ts.as_micros(), (0x1_0000_0000 + 2) * 1000);
+    }
+
     #[test]
     pub fn test_pcan_api() {
         let mut canApi = PCanDrvNew { is_connected: true };
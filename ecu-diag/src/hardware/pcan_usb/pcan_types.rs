@@ -1,10 +1,10 @@
+use std::ffi::c_char;
+
 use enum_repr::EnumRepr;
 use thiserror::Error;
 
-#[allow(dead_code)]
-const MAX_LENGTH_HARDWARE_NAME: usize = 33;
-#[allow(dead_code)]
-const MAX_LENGTH_VERSION_STRING: usize = 256;
+pub(crate) const MAX_LENGTH_HARDWARE_NAME: usize = 33;
+pub(crate) const MAX_LENGTH_VERSION_STRING: usize = 256;
 
 // PEAK Header redefinition types (PCANBasic.h)
 
@@ -15,7 +15,7 @@ pub(crate) type BYTE = u8;
 #[allow(clippy::upper_case_acronyms)]
 pub(crate) type DWORD = u32;
 #[allow(clippy::upper_case_acronyms)]
-pub(crate) type LPSTR = i8;
+pub(crate) type LPSTR = *mut c_char;
 #[allow(clippy::upper_case_acronyms)]
 pub(crate) type UINT64 = u64;
 
@@ -28,10 +28,24 @@ pub(crate) type TPCANMessageType = BYTE;
 pub(crate) type TPCANType = BYTE;
 pub(crate) type TPCANMode = BYTE;
 pub(crate) type TPCANBaudrate = WORD;
-pub(crate) type TPCANBitrateFD = LPSTR;
+/// FD bit rate string. Only read by the driver
+pub(crate) type TPCANBitrateFD = *const c_char;
 #[allow(dead_code)]
 pub(crate) type TPCANTimestampFD = UINT64;
 
+/// [PCANParameter::MessageFilter] value. Closes the filter, no messages are received
+pub(crate) const PCAN_FILTER_CLOSE: TPCANParameter = 0x00;
+/// [PCANParameter::MessageFilter] value. Opens the filter, all messages are received
+pub(crate) const PCAN_FILTER_OPEN: TPCANParameter = 0x01;
+/// `CAN_FilterMessages` mode for 11bit IDs
+pub(crate) const PCAN_MODE_STANDARD: TPCANMode = 0x00;
+/// `CAN_FilterMessages` mode for 29bit IDs
+pub(crate) const PCAN_MODE_EXTENDED: TPCANMode = 0x02;
+/// [PCANParameter::ChannelCondition] flag. The channel is attached and can be used
+pub(crate) const PCAN_CHANNEL_AVAILABLE: DWORD = 0x01;
+/// [PCANParameter::ChannelCondition] flag. The channel is attached but used by another application
+pub(crate) const PCAN_CHANNEL_OCCUPIED: DWORD = 0x02;
+
 #[allow(dead_code)]
 pub enum PcanEnumWrapper<T, E> {
     Std(T),
@@ -57,6 +71,46 @@ pub(crate) const ALL_USB_DEVICES: &[PcanUSB] = &[
     PcanUSB::USB16,
 ];
 
+pub(crate) const ALL_PCI_DEVICES: &[PcanUSB] = &[
+    PcanUSB::PCI1,
+    PcanUSB::PCI2,
+    PcanUSB::PCI3,
+    PcanUSB::PCI4,
+    PcanUSB::PCI5,
+    PcanUSB::PCI6,
+    PcanUSB::PCI7,
+    PcanUSB::PCI8,
+    PcanUSB::PCI9,
+    PcanUSB::PCI10,
+    PcanUSB::PCI11,
+    PcanUSB::PCI12,
+    PcanUSB::PCI13,
+    PcanUSB::PCI14,
+    PcanUSB::PCI15,
+    PcanUSB::PCI16,
+];
+
+pub(crate) const ALL_LAN_DEVICES: &[PcanUSB] = &[
+    PcanUSB::LAN1,
+    PcanUSB::LAN2,
+    PcanUSB::LAN3,
+    PcanUSB::LAN4,
+    PcanUSB::LAN5,
+    PcanUSB::LAN6,
+    PcanUSB::LAN7,
+    PcanUSB::LAN8,
+    PcanUSB::LAN9,
+    PcanUSB::LAN10,
+    PcanUSB::LAN11,
+    PcanUSB::LAN12,
+    PcanUSB::LAN13,
+    PcanUSB::LAN14,
+    PcanUSB::LAN15,
+    PcanUSB::LAN16,
+];
+
+/// PCAN channel handle. Despite the name, this covers the PCI and LAN
+/// channels as well as the USB ones
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Ord, PartialOrd)]
 #[EnumRepr(type = "TPCANHandle")]
 pub enum PcanUSB {
@@ -76,6 +130,51 @@ pub enum PcanUSB {
     USB14 = 0x50E,
     USB15 = 0x50F,
     USB16 = 0x510,
+    PCI1 = 0x41,
+    PCI2 = 0x42,
+    PCI3 = 0x43,
+    PCI4 = 0x44,
+    PCI5 = 0x45,
+    PCI6 = 0x46,
+    PCI7 = 0x47,
+    PCI8 = 0x48,
+    PCI9 = 0x409,
+    PCI10 = 0x40A,
+    PCI11 = 0x40B,
+    PCI12 = 0x40C,
+    PCI13 = 0x40D,
+    PCI14 = 0x40E,
+    PCI15 = 0x40F,
+    PCI16 = 0x410,
+    LAN1 = 0x801,
+    LAN2 = 0x802,
+    LAN3 = 0x803,
+    LAN4 = 0x804,
+    LAN5 = 0x805,
+    LAN6 = 0x806,
+    LAN7 = 0x807,
+    LAN8 = 0x808,
+    LAN9 = 0x809,
+    LAN10 = 0x80A,
+    LAN11 = 0x80B,
+    LAN12 = 0x80C,
+    LAN13 = 0x80D,
+    LAN14 = 0x80E,
+    LAN15 = 0x80F,
+    LAN16 = 0x810,
+}
+
+impl PcanUSB {
+    /// Returns the PCAN interface family of the channel (`"PCAN-USB"`, `"PCAN-PCI"` or `"PCAN-LAN"`)
+    pub fn interface(&self) -> &'static str {
+        if ALL_PCI_DEVICES.contains(self) {
+            "PCAN-PCI"
+        } else if ALL_LAN_DEVICES.contains(self) {
+            "PCAN-LAN"
+        } else {
+            "PCAN-USB"
+        }
+    }
 }
 
 #[allow(dead_code)]
@@ -264,16 +363,25 @@ pub struct TpCanMsg {
 }
 
 #[repr(C)]
+#[derive(Debug, Default)]
 pub struct TpCanTimestamp {
     pub(crate) millis: DWORD,
     pub(crate) millis_overflow: WORD,
     pub(crate) micros: WORD,
 }
 
+impl TpCanTimestamp {
+    /// Total microseconds since the driver was started
+    pub(crate) fn as_micros(&self) -> u64 {
+        ((u64::from(self.millis_overflow) << 32) | u64::from(self.millis)) * 1000
+            + u64::from(self.micros)
+    }
+}
+
 #[repr(C)]
 pub struct TpCanMsgFD {
     pub(crate) id: DWORD,
-    pub(crate) msg_type: MsgType,
+    pub(crate) msg_type: TPCANMessageType,
     pub(crate) dlc: BYTE,
     pub(crate) data: [BYTE; 64],
 }
@@ -0,0 +1,203 @@
+//! Recording of CAN traffic
+//!
+//! [RecordingChannel] wraps any CAN channel and writes every frame sent and received
+//! through it to a trace file (candump, PCAN `.trc` or Vector `.asc`, see [TraceWriter]).
+//! Recording is controlled through a [TraceRecorder], which can be started and stopped
+//! at any time while the channel is in use.
+
+use std::{
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use crate::core::channel::{
+    CanBusStatus, CanChannel, CanFrame, ChannelResult, FilterPacketChannel, PacketChannel,
+};
+
+use super::{
+    trace::{TraceDirection, TraceWriter},
+    HardwareResult,
+};
+
+#[derive(Clone, Default)]
+/// Handle to start and stop recording on one or more [RecordingChannel]s
+pub struct TraceRecorder {
+    writer: Arc<Mutex<Option<TraceWriter>>>,
+}
+
+impl TraceRecorder {
+    /// Starts recording to a trace file, the format is picked from the file extension.
+    /// A recording already in progress is stopped first
+    pub fn start(&self, path: &Path) -> HardwareResult<()> {
+        let writer = TraceWriter::create(path)?;
+        self.start_with_writer(writer);
+        Ok(())
+    }
+
+    /// Starts recording with an existing trace writer
+    pub fn start_with_writer(&self, writer: TraceWriter) {
+        self.stop();
+        *self.writer.lock().unwrap() = Some(writer);
+    }
+
+    /// Stops recording and closes the trace. Does nothing if not recording
+    pub fn stop(&self) {
+        if let Some(mut writer) = self.writer.lock().unwrap().take() {
+            if let Err(e) = writer.finish() {
+                log::error!("Error: finish CAN trace {e:?}");
+            }
+        }
+    }
+
+    /// Returns true while recording
+    pub fn is_recording(&self) -> bool {
+        self.writer.lock().unwrap().is_some()
+    }
+
+    /// Writes frames to the trace. If writing fails, recording is stopped
+    fn record(&self, direction: TraceDirection, frames: &[CanFrame]) {
+        let mut writer = self.writer.lock().unwrap();
+        if let Some(w) = writer.as_mut() {
+            if let Err(e) = frames.iter().try_for_each(|f| w.write_frame(direction, f)) {
+                log::error!("Error: write CAN trace, recording stopped {e:?}");
+                *writer = None;
+            }
+        }
+    }
+}
+
+/// CAN channel that records all traffic of the channel it wraps
+pub struct RecordingChannel<C> {
+    inner: C,
+    recorder: TraceRecorder,
+}
+
+impl<C> RecordingChannel<C> {
+    /// Wraps `inner`, frames are recorded while `recorder` is recording
+    pub fn new(inner: C, recorder: TraceRecorder) -> Self {
+        Self { inner, recorder }
+    }
+
+    /// Returns the recorder of this channel
+    pub fn recorder(&self) -> &TraceRecorder {
+        &self.recorder
+    }
+
+    /// Returns the wrapped channel
+    pub fn into_inner(self) -> C {
+        self.inner
+    }
+}
+
+impl<C: PacketChannel<CanFrame>> PacketChannel<CanFrame> for RecordingChannel<C> {
+    fn open(&mut self) -> ChannelResult<()> {
+        self.inner.open()
+    }
+
+    fn close(&mut self) -> ChannelResult<()> {
+        self.inner.close()
+    }
+
+    fn write_packets(&mut self, packets: Vec<CanFrame>, timeout_ms: u32) -> ChannelResult<()> {
+        // Frames are recorded once they are handed to the adapter
+        let res = self.inner.write_packets(packets.clone(), timeout_ms);
+        if res.is_ok() {
+            self.recorder.record(TraceDirection::Tx, &packets);
+        }
+        res
+    }
+
+    fn read_packets(&mut self, max: usize, timeout_ms: u32) -> ChannelResult<Vec<CanFrame>> {
+        let frames = self.inner.read_packets(max, timeout_ms)?;
+        self.recorder.record(TraceDirection::Rx, &frames);
+        Ok(frames)
+    }
+
+    fn clear_rx_buffer(&mut self) -> ChannelResult<()> {
+        self.inner.clear_rx_buffer()
+    }
+
+    fn clear_tx_buffer(&mut self) -> ChannelResult<()> {
+        self.inner.clear_tx_buffer()
+    }
+}
+
+impl<C: FilterPacketChannel<CanFrame>> FilterPacketChannel<CanFrame> for RecordingChannel<C> {
+    fn add_filter(&mut self, allowed_ids: &[u32]) -> ChannelResult<u32> {
+        self.inner.add_filter(allowed_ids)
+    }
+
+    fn remove_filter(&mut self, filter_id: u32) -> ChannelResult<()> {
+        self.inner.remove_filter(filter_id)
+    }
+}
+
+impl<C: CanChannel> CanChannel for RecordingChannel<C> {
+    fn set_can_cfg(&mut self, baud: u32, use_extended: bool) -> ChannelResult<()> {
+        self.inner.set_can_cfg(baud, use_extended)
+    }
+
+    fn set_can_fd_cfg(
+        &mut self,
+        baud: u32,
+        data_baud: u32,
+        use_extended: bool,
+    ) -> ChannelResult<()> {
+        self.inner.set_can_fd_cfg(baud, data_baud, use_extended)
+    }
+
+    fn get_bus_status(&mut self) -> ChannelResult<CanBusStatus> {
+        self.inner.get_bus_status()
+    }
+
+    fn detect_can_baud(&mut self, candidates: &[u32], listen_ms: u32) -> ChannelResult<u32> {
+        self.inner.detect_can_baud(candidates, listen_ms)
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::{RecordingChannel, TraceRecorder};
+    use crate::{
+        core::channel::{CanFrame, PacketChannel},
+        hardware::{
+            replay::{ReplayDevice, ReplayTiming},
+            trace::{parse_trace, read_trace_file, TraceDirection, TraceFormat},
+            Hardware,
+        },
+    };
+
+    #[test]
+    pub fn test_recording_channel() {
+        let records = parse_trace("(1.000000) can0 7E8#027E00\n", TraceFormat::Candump).unwrap();
+        let mut device = ReplayDevice::new("rx.log", records, ReplayTiming::AsFastAsPossible);
+        let recorder = TraceRecorder::default();
+        let mut channel =
+            RecordingChannel::new(device.create_can_channel().unwrap(), recorder.clone());
+        channel.open().unwrap();
+
+        let path =
+            std::env::temp_dir().join(format!("ecu-diag-recording-{}.asc", std::process::id()));
+        recorder.start(&path).unwrap();
+        assert!(recorder.is_recording());
+        let tx = CanFrame::new(0x7E0, &[0x02, 0x3E, 0x00], false);
+        channel.write_packets(vec![tx], 0).unwrap();
+        let rx = channel.read_packets(1, 0).unwrap();
+        recorder.stop();
+        assert!(!recorder.is_recording());
+        // Not recorded once stopped
+        channel.write_packets(vec![tx], 0).unwrap();
+
+        let trace = read_trace_file(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(trace.len(), 2);
+        assert_eq!(
+            (trace[0].direction, trace[0].frame),
+            (TraceDirection::Tx, tx)
+        );
+        assert_eq!(
+            (trace[1].direction, trace[1].frame),
+            (TraceDirection::Rx, rx[0])
+        );
+    }
+}
@@ -0,0 +1,383 @@
+//! Replay of recorded CAN traces
+//!
+//! [ReplayDevice] plays back a trace recorded with candump, PCAN-View or Vector tools
+//! (see [trace](super::trace)) through [PacketChannel::read_packets], either with the
+//! original timing between frames or as fast as possible. This allows a capture taken in
+//! the field to be re-run offline through [SoftwareIsoTpChannel],
+//! [IsoTpProtocol](super::isotp::IsoTpProtocol) and the DID decoders.
+//!
+//! Only frames the recording adapter received are played back, frames it sent (The
+//! tester's requests) are skipped. Frames written to a replay channel are discarded.
+
+use std::{
+    collections::BTreeMap,
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use crate::core::channel::{
+    CanChannel, CanFrame, ChannelError, ChannelResult, FilterPacketChannel, IsoTPChannel, Packet,
+    PacketChannel,
+};
+
+use super::{
+    software_isotp::SoftwareIsoTpChannel,
+    trace::{read_trace_file, TraceDirection, TraceRecord},
+    Hardware, HardwareCapabilities, HardwareError, HardwareInfo, HardwareResult, IsoTpChannelType,
+};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+/// Playback speed of a [ReplayDevice]
+pub enum ReplayTiming {
+    /// Frames are delivered with the same spacing as they were recorded,
+    /// starting from when the channel is opened
+    #[default]
+    Original,
+    /// Frames are delivered as fast as they are read
+    AsFastAsPossible,
+}
+
+#[derive(Clone, Debug)]
+/// Replay device, playing back a recorded CAN trace
+pub struct ReplayDevice {
+    info: HardwareInfo,
+    records: Arc<Vec<TraceRecord>>,
+    timing: ReplayTiming,
+    can_channel: Arc<AtomicBool>,
+    has_isotp_channel: Arc<AtomicBool>,
+}
+
+impl ReplayDevice {
+    /// Creates a replay device from a candump (`.log`), PCAN (`.trc`) or Vector (`.asc`)
+    /// trace file. The format is picked from the file extension
+    pub fn open_file(path: &Path, timing: ReplayTiming) -> HardwareResult<Self> {
+        let records = read_trace_file(path)?;
+        let name = path
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default();
+        Ok(Self::new(&name, records, timing))
+    }
+
+    /// Creates a replay device from already parsed trace records
+    pub fn new(name: &str, records: Vec<TraceRecord>, timing: ReplayTiming) -> Self {
+        let records = records
+            .into_iter()
+            .filter(|r| r.direction == TraceDirection::Rx)
+            .collect();
+        Self {
+            info: replay_info(name),
+            records: Arc::new(records),
+            timing,
+            can_channel: Arc::new(AtomicBool::new(false)),
+            has_isotp_channel: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Returns the number of frames that will be played back
+    pub fn frame_count(&self) -> usize {
+        self.records.len()
+    }
+}
+
+impl Hardware for ReplayDevice {
+    fn create_iso_tp_channel(
+        &mut self,
+        force_native: bool,
+    ) -> HardwareResult<Box<dyn IsoTPChannel>> {
+        if force_native {
+            return Err(HardwareError::ChannelNotSupported);
+        }
+        let can_channel = self.create_can_channel()?;
+        self.has_isotp_channel.store(true, Ordering::Relaxed);
+        Ok(Box::new(SoftwareIsoTpChannel::new(can_channel)))
+    }
+
+    fn create_native_iso_tp_channel(&mut self) -> HardwareResult<Box<dyn CanChannel>> {
+        self.has_isotp_channel.store(true, Ordering::Relaxed);
+        self.create_can_channel()
+    }
+
+    fn create_can_channel(&mut self) -> HardwareResult<Box<dyn CanChannel>> {
+        if self.can_channel.load(Ordering::Relaxed) {
+            // Already open
+            Err(HardwareError::ConflictingChannel)
+        } else {
+            self.can_channel.store(true, Ordering::Relaxed);
+            Ok(Box::new(ReplayChannel::new(
+                self.records.clone(),
+                self.timing,
+                self.can_channel.clone(),
+            )))
+        }
+    }
+
+    fn is_iso_tp_channel_open(&self) -> bool {
+        self.has_isotp_channel.load(Ordering::Relaxed)
+    }
+
+    fn is_can_channel_open(&self) -> bool {
+        self.can_channel.load(Ordering::Relaxed)
+    }
+
+    fn read_battery_voltage(&mut self) -> Option<f32> {
+        None
+    }
+
+    fn read_ignition_voltage(&mut self) -> Option<f32> {
+        None
+    }
+
+    fn get_info(&self) -> &HardwareInfo {
+        &self.info
+    }
+
+    fn is_connected(&self) -> bool {
+        true
+    }
+}
+
+#[derive(Debug)]
+/// CAN channel of a [ReplayDevice]
+pub struct ReplayChannel {
+    records: Arc<Vec<TraceRecord>>,
+    timing: ReplayTiming,
+    /// Index of the next record to play back
+    position: usize,
+    /// When the channel was opened. None if closed
+    start: Option<Instant>,
+    filters: BTreeMap<u32, Vec<u32>>,
+    device_state: Arc<AtomicBool>,
+}
+
+impl ReplayChannel {
+    fn new(
+        records: Arc<Vec<TraceRecord>>,
+        timing: ReplayTiming,
+        device_state: Arc<AtomicBool>,
+    ) -> Self {
+        Self {
+            records,
+            timing,
+            position: 0,
+            start: None,
+            filters: BTreeMap::new(),
+            device_state,
+        }
+    }
+
+    /// Returns true once every frame of the trace has been played back
+    pub fn is_finished(&self) -> bool {
+        self.position >= self.records.len()
+    }
+
+    /// Returns when the record at `idx` is due to be played back
+    fn due_at(&self, start: Instant, idx: usize) -> Instant {
+        match self.timing {
+            ReplayTiming::Original => {
+                let first = self.records[0].timestamp_us;
+                let offset = self.records[idx].timestamp_us.saturating_sub(first);
+                start + Duration::from_micros(offset)
+            }
+            ReplayTiming::AsFastAsPossible => start,
+        }
+    }
+
+    fn is_allowed(&self, id: u32) -> bool {
+        self.filters.is_empty() || self.filters.values().any(|ids| ids.contains(&id))
+    }
+}
+
+impl Drop for ReplayChannel {
+    fn drop(&mut self) {
+        self.device_state.store(false, Ordering::Relaxed);
+    }
+}
+
+impl FilterPacketChannel<CanFrame> for ReplayChannel {
+    fn add_filter(&mut self, allowed_ids: &[u32]) -> ChannelResult<u32> {
+        let id = self.filters.keys().last().map_or(0, |x| x + 1);
+        self.filters.insert(id, allowed_ids.to_vec());
+        Ok(id)
+    }
+
+    fn remove_filter(&mut self, filter_id: u32) -> ChannelResult<()> {
+        self.filters
+            .remove(&filter_id)
+            .map(|_| ())
+            .ok_or(ChannelError::ConfigurationError)
+    }
+}
+
+impl CanChannel for ReplayChannel {
+    fn set_can_cfg(&mut self, _baud: u32, _use_extended: bool) -> ChannelResult<()> {
+        Ok(())
+    }
+
+    fn set_can_fd_cfg(
+        &mut self,
+        _baud: u32,
+        _data_baud: u32,
+        _use_extended: bool,
+    ) -> ChannelResult<()> {
+        Ok(())
+    }
+}
+
+impl PacketChannel<CanFrame> for ReplayChannel {
+    /// Opening the channel (Re)starts playback from the beginning of the trace
+    fn open(&mut self) -> ChannelResult<()> {
+        self.position = 0;
+        self.start = Some(Instant::now());
+        Ok(())
+    }
+
+    fn close(&mut self) -> ChannelResult<()> {
+        self.start = None;
+        Ok(())
+    }
+
+    fn write_packets(&mut self, packets: Vec<CanFrame>, _timeout_ms: u32) -> ChannelResult<()> {
+        self.start.ok_or(ChannelError::InterfaceNotOpen)?;
+        for frame in packets {
+            log::debug!(
+                "Replay: discarding written frame {:04X} {:02X?}",
+                frame.get_address(),
+                frame.get_data()
+            );
+        }
+        Ok(())
+    }
+
+    fn read_packets(&mut self, max: usize, timeout_ms: u32) -> ChannelResult<Vec<CanFrame>> {
+        let start = self.start.ok_or(ChannelError::InterfaceNotOpen)?;
+        let deadline = Instant::now() + Duration::from_millis(timeout_ms as u64);
+        let mut read_packets = vec![];
+        loop {
+            while read_packets.len() < max && !self.is_finished() {
+                let due = self.due_at(start, self.position);
+                if Instant::now() < due {
+                    break;
+                }
+                let record = self.records[self.position];
+                self.position += 1;
+                if self.is_allowed(record.frame.get_address()) {
+                    let mut frame = record.frame;
+                    frame.set_timestamp_us(record.timestamp_us);
+                    read_packets.push(frame);
+                }
+            }
+            if !read_packets.is_empty() {
+                return Ok(read_packets);
+            }
+            let now = Instant::now();
+            if self.is_finished() || now >= deadline {
+                return Err(ChannelError::BufferEmpty);
+            }
+            // Wait for the next frame to become due, or the timeout
+            let due = self.due_at(start, self.position);
+            std::thread::sleep(due.min(deadline).saturating_duration_since(now));
+        }
+    }
+
+    fn clear_rx_buffer(&mut self) -> ChannelResult<()> {
+        Ok(())
+    }
+
+    fn clear_tx_buffer(&mut self) -> ChannelResult<()> {
+        Ok(())
+    }
+}
+
+fn replay_info(name: &str) -> HardwareInfo {
+    HardwareInfo {
+        name: name.to_string(),
+        vendor: Some("CAN trace replay".to_string()),
+        device_fw_version: None,
+        device_id: None,
+        api_version: None,
+        library_version: None,
+        library_location: None,
+        capabilities: HardwareCapabilities {
+            iso_tp: IsoTpChannelType::Emulated,
+            can: true,
+            kline: false,
+            kline_kwp: false,
+            sae_j1850: false,
+            sci: false,
+            ip: false,
+        },
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use std::time::{Duration, Instant};
+
+    use super::{ReplayDevice, ReplayTiming};
+    use crate::{
+        core::channel::{ChannelError, PacketChannel},
+        hardware::{
+            isotp::IsoTpProtocol,
+            trace::{parse_trace, TraceFormat},
+            Hardware,
+        },
+    };
+
+    const VIN_TRACE: &str = "\
+(1700000000.000000) can0 7E0#0322F19000000000 T
+(1700000000.010000) can0 7E8#101462F190574442
+(1700000000.011000) can0 7E0#3000000000000000 T
+(1700000000.012000) can0 7E8#2130303030303030
+(1700000000.013000) can0 7E8#2230303030303030
+";
+
+    #[test]
+    pub fn test_replay_isotp() {
+        let records = parse_trace(VIN_TRACE, TraceFormat::Candump).unwrap();
+        let device = ReplayDevice::new("vin.log", records, ReplayTiming::AsFastAsPossible);
+        assert_eq!(device.frame_count(), 3);
+
+        let mut client = IsoTpProtocol::new_from_hardware(device).unwrap();
+        client.set_rx_id(0x7E8);
+        client.init();
+        let resp = client.send_receive_payload(0x7E0, &[0x22, 0xF1, 0x90]);
+        assert_eq!(
+            resp,
+            [&[0x62, 0xF1, 0x90][..], b"WDB00000000000000"].concat()
+        );
+    }
+
+    #[test]
+    pub fn test_replay_original_timing() {
+        let records = parse_trace(
+            "(10.000000) can0 123#01\n(10.100000) can0 123#02\n",
+            TraceFormat::Candump,
+        )
+        .unwrap();
+        let mut device = ReplayDevice::new("timing.log", records, ReplayTiming::Original);
+        let mut channel = device.create_can_channel().unwrap();
+        channel.open().unwrap();
+
+        let first = channel.read_packets(10, 0).unwrap();
+        assert_eq!(first.len(), 1);
+        assert_eq!(first[0].get_timestamp_us(), Some(10_000_000));
+        assert!(matches!(
+            channel.read_packets(10, 0),
+            Err(ChannelError::BufferEmpty)
+        ));
+
+        let start = Instant::now();
+        let second = channel.read_packets(10, 1000).unwrap();
+        assert_eq!(second.len(), 1);
+        assert!(start.elapsed() >= Duration::from_millis(50));
+        assert!(matches!(
+            channel.read_packets(10, 1000),
+            Err(ChannelError::BufferEmpty)
+        ));
+    }
+}
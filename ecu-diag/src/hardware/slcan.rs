@@ -0,0 +1,690 @@
+//! Diagnostic implementation for SLCAN (Lawicel) serial adapters
+//!
+//! Many low cost USB-CAN adapters (CANable, USBtin, CANtact, ...) speak the ASCII SLCAN
+//! protocol over a (virtual) serial port. Commands are terminated by `\r`, the adapter
+//! answers `\r` on success or BELL (`0x07`) on error.
+//!
+//! Supported are the bit rates of `S0`-`S8` (10 kbps to 1 Mbps), standard and extended
+//! data frames, adapter timestamps (`Z1`, if the adapter supports them) and the status flags
+//! of the `F` command, see [CanChannel::get_bus_status]. CAN FD and remote frames are not supported.
+
+use std::{
+    collections::{BTreeMap, VecDeque},
+    io::{Read, Write},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use serialport::{SerialPort, SerialPortType};
+
+use crate::core::channel::{
+    host_timestamp_us, CanBusState, CanBusStatus, CanChannel, CanFrame, ChannelError,
+    ChannelResult, FilterPacketChannel, IsoTPChannel, Packet, PacketChannel,
+};
+
+use super::{
+    software_isotp::SoftwareIsoTpChannel, Hardware, HardwareCapabilities, HardwareError,
+    HardwareInfo, HardwareResult, HardwareScanner, IsoTpChannelType,
+};
+
+/// Default serial port speed. USB adapters ignore it
+pub const DEFAULT_SERIAL_BAUD: u32 = 115_200;
+
+/// How long to wait for the adapter to answer a command
+const RESPONSE_TIMEOUT_MS: u32 = 500;
+
+/// BELL, the adapter's error response
+const SLCAN_ERROR: u8 = 0x07;
+
+/// Adapter timestamps (`Z1`) count milliseconds and wrap at 60 seconds
+const SLCAN_TIMESTAMP_WRAP_MS: u64 = 60_000;
+
+/// Status flags returned by the `F` command
+const FLAG_ERROR_WARNING: u8 = 1 << 2;
+const FLAG_DATA_OVERRUN: u8 = 1 << 3;
+const FLAG_ERROR_PASSIVE: u8 = 1 << 5;
+const FLAG_BUS_ERROR: u8 = 1 << 7;
+
+/// Returns the `Sn` command for a CAN bit rate
+fn bitrate_command(baud: u32) -> Option<&'static str> {
+    Some(match baud {
+        10_000 => "S0",
+        20_000 => "S1",
+        50_000 => "S2",
+        100_000 => "S3",
+        125_000 => "S4",
+        250_000 => "S5",
+        500_000 => "S6",
+        800_000 => "S7",
+        1_000_000 => "S8",
+        _ => return None,
+    })
+}
+
+/// Encodes a frame as a transmit command, EG `t7E0802100100000000`
+fn encode_frame(frame: &CanFrame) -> String {
+    let data = frame.get_data();
+    let mut cmd = if frame.is_extended() {
+        format!("T{:08X}", frame.get_address())
+    } else {
+        format!("t{:03X}", frame.get_address())
+    };
+    cmd.push_str(&format!("{}", data.len()));
+    for b in data {
+        cmd.push_str(&format!("{b:02X}"));
+    }
+    cmd
+}
+
+/// Decodes a received frame, with the adapter timestamp if there is one
+fn decode_frame(line: &str) -> Option<(CanFrame, Option<u16>)> {
+    let (is_ext, id_len) = match line.get(0..1)? {
+        "t" => (false, 3),
+        "T" => (true, 8),
+        _ => return None,
+    };
+    let id = u32::from_str_radix(line.get(1..1 + id_len)?, 16).ok()?;
+    let dlc_idx = 1 + id_len;
+    let dlc = line.get(dlc_idx..dlc_idx + 1)?.parse::<usize>().ok()?;
+    if dlc > 8 {
+        return None;
+    }
+    let data_idx = dlc_idx + 1;
+    let data = (0..dlc)
+        .map(|i| u8::from_str_radix(line.get(data_idx + i * 2..data_idx + i * 2 + 2)?, 16).ok())
+        .collect::<Option<Vec<u8>>>()?;
+    let timestamp = match &line[data_idx + dlc * 2..] {
+        "" => None,
+        ts if ts.len() == 4 => Some(u16::from_str_radix(ts, 16).ok()?),
+        _ => return None,
+    };
+    Some((CanFrame::new(id, &data, is_ext), timestamp))
+}
+
+fn io_error<E: Into<std::io::Error>>(e: E) -> ChannelError {
+    ChannelError::IOError(Arc::new(e.into()))
+}
+
+#[derive(Debug, Default, Clone)]
+/// Lists the serial ports of the system, which may have SLCAN adapters attached
+pub struct SlcanScanner;
+
+impl SlcanScanner {
+    fn scan(&self) -> Vec<HardwareInfo> {
+        match serialport::available_ports() {
+            Ok(ports) => ports
+                .into_iter()
+                .map(|p| {
+                    let mut info = slcan_info(&p.port_name);
+                    if let SerialPortType::UsbPort(usb) = p.port_type {
+                        info.vendor = usb.manufacturer.or(info.vendor);
+                    }
+                    info
+                })
+                .collect(),
+            Err(e) => {
+                log::error!("Error: list serial ports {e:?}");
+                vec![]
+            }
+        }
+    }
+}
+
+impl HardwareScanner<SlcanDevice> for SlcanScanner {
+    fn list_devices(&self) -> Vec<HardwareInfo> {
+        self.scan()
+    }
+
+    fn open_device_by_index(&self, idx: usize) -> HardwareResult<SlcanDevice> {
+        self.scan()
+            .get(idx)
+            .map(|info| SlcanDevice::new(&info.name))
+            .ok_or(HardwareError::DeviceNotFound)
+    }
+
+    fn open_device_by_name(&self, name: &str) -> HardwareResult<SlcanDevice> {
+        self.scan()
+            .iter()
+            .find(|info| info.name == name)
+            .map(|info| SlcanDevice::new(&info.name))
+            .ok_or(HardwareError::DeviceNotFound)
+    }
+}
+
+#[derive(Clone, Debug)]
+/// SLCAN adapter on a serial port
+pub struct SlcanDevice {
+    info: HardwareInfo,
+    port_name: String,
+    serial_baud: u32,
+    can_channel: Arc<AtomicBool>,
+    has_isotp_channel: Arc<AtomicBool>,
+}
+
+impl SlcanDevice {
+    /// Creates a new SLCAN device on a serial port (EG: `/dev/ttyACM0` or `COM3`).
+    /// The port is opened when the CAN channel is opened
+    pub fn new(port_name: &str) -> Self {
+        Self {
+            info: slcan_info(port_name),
+            port_name: port_name.to_string(),
+            serial_baud: DEFAULT_SERIAL_BAUD,
+            can_channel: Arc::new(AtomicBool::new(false)),
+            has_isotp_channel: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Sets the serial port speed, for adapters on a real UART.
+    /// Defaults to [DEFAULT_SERIAL_BAUD]
+    pub fn set_serial_baud(&mut self, baud: u32) {
+        self.serial_baud = baud;
+    }
+
+    /// Returns the name of the serial port
+    pub fn get_port_name(&self) -> &str {
+        &self.port_name
+    }
+}
+
+impl Hardware for SlcanDevice {
+    fn create_iso_tp_channel(
+        &mut self,
+        force_native: bool,
+    ) -> HardwareResult<Box<dyn IsoTPChannel>> {
+        if force_native {
+            return Err(HardwareError::ChannelNotSupported);
+        }
+        let can_channel = self.create_can_channel()?;
+        self.has_isotp_channel.store(true, Ordering::Relaxed);
+        Ok(Box::new(SoftwareIsoTpChannel::new(can_channel)))
+    }
+
+    fn create_native_iso_tp_channel(&mut self) -> HardwareResult<Box<dyn CanChannel>> {
+        self.has_isotp_channel.store(true, Ordering::Relaxed);
+        self.create_can_channel()
+    }
+
+    fn create_can_channel(&mut self) -> HardwareResult<Box<dyn CanChannel>> {
+        if self.can_channel.load(Ordering::Relaxed) {
+            // Already open
+            Err(HardwareError::ConflictingChannel)
+        } else {
+            self.can_channel.store(true, Ordering::Relaxed);
+            Ok(Box::new(SlcanChannel::new(
+                &self.port_name,
+                self.serial_baud,
+                self.can_channel.clone(),
+            )))
+        }
+    }
+
+    fn is_iso_tp_channel_open(&self) -> bool {
+        self.has_isotp_channel.load(Ordering::Relaxed)
+    }
+
+    fn is_can_channel_open(&self) -> bool {
+        self.can_channel.load(Ordering::Relaxed)
+    }
+
+    fn read_battery_voltage(&mut self) -> Option<f32> {
+        None
+    }
+
+    fn read_ignition_voltage(&mut self) -> Option<f32> {
+        None
+    }
+
+    fn get_info(&self) -> &HardwareInfo {
+        &self.info
+    }
+
+    fn is_connected(&self) -> bool {
+        true
+    }
+}
+
+/// CAN channel of an SLCAN adapter
+pub struct SlcanChannel {
+    port_name: String,
+    serial_baud: u32,
+    /// Serial ports are Send but not Sync, the mutex makes the channel Sync.
+    /// It is only accessed through `&mut self`, so it is never locked
+    port: Option<Mutex<Box<dyn SerialPort>>>,
+    baud: Option<u32>,
+    /// Bytes received that do not form a complete line yet
+    rx_buf: Vec<u8>,
+    rx_queue: VecDeque<CanFrame>,
+    /// Command responses, None is an error (BELL)
+    responses: VecDeque<Option<String>>,
+    /// Last adapter timestamp (ms), and the frame timestamp (µs) it was converted to
+    last_adapter_ts: Option<(u16, u64)>,
+    filters: BTreeMap<u32, Vec<u32>>,
+    overrun_count: u32,
+    device_state: Arc<AtomicBool>,
+}
+
+impl std::fmt::Debug for SlcanChannel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SlcanChannel")
+            .field("port_name", &self.port_name)
+            .field("open", &self.port.is_some())
+            .field("baud", &self.baud)
+            .finish()
+    }
+}
+
+impl SlcanChannel {
+    fn new(port_name: &str, serial_baud: u32, device_state: Arc<AtomicBool>) -> Self {
+        Self {
+            port_name: port_name.to_string(),
+            serial_baud,
+            port: None,
+            baud: None,
+            rx_buf: vec![],
+            rx_queue: VecDeque::new(),
+            responses: VecDeque::new(),
+            last_adapter_ts: None,
+            filters: BTreeMap::new(),
+            overrun_count: 0,
+            device_state,
+        }
+    }
+
+    /// Reads what the adapter sent within `wait`, and splits it into frames and responses
+    fn fill(&mut self, wait: Duration) -> ChannelResult<()> {
+        let port = self
+            .port
+            .as_mut()
+            .ok_or(ChannelError::InterfaceNotOpen)?
+            .get_mut()
+            .unwrap();
+        port.set_timeout(wait).map_err(io_error)?;
+        let mut buf = [0u8; 512];
+        match port.read(&mut buf) {
+            Ok(n) => self.rx_buf.extend_from_slice(&buf[..n]),
+            Err(e)
+                if e.kind() == std::io::ErrorKind::TimedOut
+                    || e.kind() == std::io::ErrorKind::WouldBlock => {}
+            Err(e) => return Err(io_error(e)),
+        }
+        while let Some(pos) = self
+            .rx_buf
+            .iter()
+            .position(|b| *b == b'\r' || *b == SLCAN_ERROR)
+        {
+            let line: Vec<u8> = self.rx_buf.drain(..=pos).collect();
+            if line[pos] == SLCAN_ERROR {
+                self.responses.push_back(None);
+                continue;
+            }
+            let line = String::from_utf8_lossy(&line[..pos]).to_string();
+            match line.get(0..1) {
+                Some("t" | "T") => match decode_frame(&line) {
+                    Some((mut frame, ts)) => {
+                        frame.set_timestamp_us(self.frame_timestamp_us(ts));
+                        self.rx_queue.push_back(frame);
+                    }
+                    None => log::warn!("SLCAN {}: invalid frame '{line}'", self.port_name),
+                },
+                // Remote frames are not supported
+                Some("r" | "R") => {}
+                _ => self.responses.push_back(Some(line)),
+            }
+        }
+        Ok(())
+    }
+
+    /// Converts an adapter timestamp to a frame timestamp. Adapter timestamps wrap every
+    /// minute, so they are accumulated onto the host timestamp of the first frame
+    fn frame_timestamp_us(&mut self, adapter_ts: Option<u16>) -> u64 {
+        let now = host_timestamp_us();
+        let Some(raw) = adapter_ts else {
+            return now;
+        };
+        let ts = match self.last_adapter_ts {
+            // Wraps can only be counted if frames are less than a minute apart
+            Some((last_raw, last_us)) if now.saturating_sub(last_us) < 30_000_000 => {
+                let elapsed_ms = (raw as u64 + SLCAN_TIMESTAMP_WRAP_MS - last_raw as u64)
+                    % SLCAN_TIMESTAMP_WRAP_MS;
+                last_us + elapsed_ms * 1000
+            }
+            _ => now,
+        };
+        self.last_adapter_ts = Some((raw, ts));
+        ts
+    }
+
+    /// Sends a command and waits for the adapter's response.
+    /// Returns the response text, which is empty for a plain acknowledgement
+    fn command(&mut self, cmd: &str) -> ChannelResult<String> {
+        self.responses.clear();
+        let port = self
+            .port
+            .as_mut()
+            .ok_or(ChannelError::InterfaceNotOpen)?
+            .get_mut()
+            .unwrap();
+        port.write_all(format!("{cmd}\r").as_bytes())
+            .and_then(|_| port.flush())
+            .map_err(io_error)?;
+        let deadline = Instant::now() + Duration::from_millis(RESPONSE_TIMEOUT_MS as u64);
+        loop {
+            match self.responses.pop_front() {
+                Some(Some(resp)) => return Ok(resp),
+                Some(None) => {
+                    return Err(ChannelError::HardwareError(HardwareError::APIError {
+                        code: SLCAN_ERROR as u32,
+                        desc: format!("SLCAN adapter rejected '{cmd}'"),
+                    }))
+                }
+                None => {}
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(ChannelError::ReadTimeout);
+            }
+            self.fill(deadline - now)?;
+        }
+    }
+
+    /// Configures the bit rate and timestamps, and opens the CAN channel of the adapter
+    fn start(&mut self, baud: u32) -> ChannelResult<()> {
+        // Close the channel, in case the adapter was left open
+        let _ = self.command("C");
+        self.command(bitrate_command(baud).ok_or(ChannelError::ConfigurationError)?)?;
+        if let Err(e) = self.command("Z1") {
+            log::debug!("SLCAN {}: no adapter timestamps {e}", self.port_name);
+        }
+        self.command("O").map(|_| ())
+    }
+
+    fn is_allowed(&self, id: u32) -> bool {
+        self.filters.is_empty() || self.filters.values().any(|ids| ids.contains(&id))
+    }
+}
+
+impl Drop for SlcanChannel {
+    fn drop(&mut self) {
+        let _ = PacketChannel::close(self);
+        self.device_state.store(false, Ordering::Relaxed);
+    }
+}
+
+impl FilterPacketChannel<CanFrame> for SlcanChannel {
+    fn add_filter(&mut self, allowed_ids: &[u32]) -> ChannelResult<u32> {
+        let id = self.filters.keys().last().map_or(0, |x| x + 1);
+        self.filters.insert(id, allowed_ids.to_vec());
+        Ok(id)
+    }
+
+    fn remove_filter(&mut self, filter_id: u32) -> ChannelResult<()> {
+        self.filters
+            .remove(&filter_id)
+            .map(|_| ())
+            .ok_or(ChannelError::ConfigurationError)
+    }
+}
+
+impl CanChannel for SlcanChannel {
+    fn set_can_cfg(&mut self, baud: u32, _use_extended: bool) -> ChannelResult<()> {
+        if bitrate_command(baud).is_none() {
+            return Err(ChannelError::ConfigurationError);
+        }
+        if self.port.is_some() {
+            // The bit rate can only be changed while the CAN channel is closed
+            return Err(ChannelError::InterfaceOpen);
+        }
+        self.baud = Some(baud);
+        Ok(())
+    }
+
+    /// Polls the status flags of the adapter (`F`). Adapters clear the flags when they are read
+    fn get_bus_status(&mut self) -> ChannelResult<CanBusStatus> {
+        let resp = self.command("F")?;
+        let flags = resp
+            .strip_prefix('F')
+            .and_then(|f| u8::from_str_radix(f, 16).ok())
+            .ok_or_else(|| ChannelError::Other(format!("Invalid SLCAN status '{resp}'")))?;
+        if flags & FLAG_DATA_OVERRUN != 0 {
+            self.overrun_count += 1;
+        }
+        if flags & FLAG_BUS_ERROR != 0 {
+            log::debug!("SLCAN {}: bus error", self.port_name);
+        }
+        let state = if flags & FLAG_ERROR_PASSIVE != 0 {
+            CanBusState::ErrorPassive
+        } else if flags & FLAG_ERROR_WARNING != 0 {
+            CanBusState::Warning
+        } else {
+            CanBusState::ErrorActive
+        };
+        Ok(CanBusStatus {
+            state,
+            tx_error_count: None,
+            rx_error_count: None,
+            overrun_count: self.overrun_count,
+        })
+    }
+}
+
+impl PacketChannel<CanFrame> for SlcanChannel {
+    fn open(&mut self) -> ChannelResult<()> {
+        if self.port.is_some() {
+            return Ok(());
+        }
+        let baud = self.baud.ok_or(ChannelError::ConfigurationError)?;
+        let mut port = serialport::new(&self.port_name, self.serial_baud)
+            .timeout(Duration::from_millis(RESPONSE_TIMEOUT_MS as u64))
+            .open()
+            .map_err(io_error)?;
+        // Flush any partial command the adapter has buffered
+        port.write_all(b"\r\r\r").map_err(io_error)?;
+        self.port = Some(Mutex::new(port));
+        self.rx_buf.clear();
+        self.rx_queue.clear();
+        while self.fill(Duration::from_millis(20)).is_ok() && !self.rx_buf.is_empty() {
+            self.rx_buf.clear();
+        }
+        self.responses.clear();
+
+        match self.start(baud) {
+            Ok(()) => {
+                log::debug!("SLCAN {} opened at {baud} bps", self.port_name);
+                self.last_adapter_ts = None;
+                Ok(())
+            }
+            Err(e) => {
+                self.port = None;
+                Err(e)
+            }
+        }
+    }
+
+    fn close(&mut self) -> ChannelResult<()> {
+        if self.port.is_none() {
+            return Ok(());
+        }
+        let res = self.command("C").map(|_| ());
+        self.port = None;
+        res
+    }
+
+    fn write_packets(&mut self, packets: Vec<CanFrame>, _timeout_ms: u32) -> ChannelResult<()> {
+        for frame in packets {
+            if frame.is_fd() {
+                return Err(ChannelError::UnsupportedRequest);
+            }
+            // The adapter answers `z` / `Z` once the frame is queued, or BELL if its buffer is full
+            match self.command(&encode_frame(&frame)) {
+                Ok(_) => {}
+                Err(ChannelError::HardwareError(_)) => return Err(ChannelError::BufferFull),
+                Err(ChannelError::ReadTimeout) => return Err(ChannelError::WriteTimeout),
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+
+    fn read_packets(&mut self, max: usize, timeout_ms: u32) -> ChannelResult<Vec<CanFrame>> {
+        if self.port.is_none() {
+            return Err(ChannelError::InterfaceNotOpen);
+        }
+        let deadline = Instant::now() + Duration::from_millis(timeout_ms as u64);
+        let mut read_packets = vec![];
+        loop {
+            while read_packets.len() < max {
+                match self.rx_queue.pop_front() {
+                    Some(f) if self.is_allowed(f.get_address()) => read_packets.push(f),
+                    Some(_) => {}
+                    None => break,
+                }
+            }
+            if !read_packets.is_empty() {
+                return Ok(read_packets);
+            }
+            let now = Instant::now();
+            if now >= deadline && timeout_ms != 0 {
+                return Err(ChannelError::BufferEmpty);
+            }
+            self.fill(deadline.saturating_duration_since(now))?;
+            if timeout_ms == 0 && self.rx_queue.is_empty() {
+                return Err(ChannelError::BufferEmpty);
+            }
+        }
+    }
+
+    fn clear_rx_buffer(&mut self) -> ChannelResult<()> {
+        if self.port.is_some() {
+            self.fill(Duration::ZERO)?;
+        }
+        self.rx_queue.clear();
+        Ok(())
+    }
+
+    fn clear_tx_buffer(&mut self) -> ChannelResult<()> {
+        Ok(())
+    }
+}
+
+fn slcan_info(port_name: &str) -> HardwareInfo {
+    HardwareInfo {
+        name: port_name.to_string(),
+        vendor: Some("SLCAN".to_string()),
+        device_fw_version: None,
+        device_id: None,
+        api_version: None,
+        library_version: None,
+        library_location: None,
+        capabilities: HardwareCapabilities {
+            iso_tp: IsoTpChannelType::Emulated,
+            can: true,
+            kline: false,
+            kline_kwp: false,
+            sae_j1850: false,
+            sci: false,
+            ip: false,
+        },
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::{decode_frame, encode_frame, SlcanDevice};
+    use crate::core::channel::{CanBusState, CanFrame, ChannelError, PacketChannel};
+    use crate::hardware::Hardware;
+
+    #[test]
+    pub fn test_slcan_frame_encoding() {
+        let std = CanFrame::new(0x7E0, &[0x02, 0x3E, 0x00], false);
+        assert_eq!(encode_frame(&std), "t7E03023E00");
+        let ext = CanFrame::new(0x18DAF110, &[], true);
+        assert_eq!(encode_frame(&ext), "T18DAF1100");
+
+        assert_eq!(decode_frame("t7E03023E00"), Some((std, None)));
+        assert_eq!(decode_frame("T18DAF1100EA5F"), Some((ext, Some(0xEA5F))));
+        assert_eq!(decode_frame("t7E0302"), None);
+        assert_eq!(decode_frame("t7E09"), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    pub fn test_slcan_pty() {
+        use std::io::{Read, Write};
+
+        use serialport::{SerialPort, TTYPort};
+
+        let (mut adapter, port) = TTYPort::pair().unwrap();
+        let port_name = port.name().unwrap();
+
+        // Stands in for the adapter
+        let adapter_thread = std::thread::spawn(move || {
+            adapter
+                .set_timeout(std::time::Duration::from_secs(5))
+                .unwrap();
+            let mut commands = vec![];
+            let mut buf = vec![];
+            loop {
+                let mut byte = [0u8];
+                // Runs until the tester closes the port
+                if adapter.read_exact(&mut byte).is_err() {
+                    return commands;
+                }
+                if byte[0] != b'\r' {
+                    buf.push(byte[0]);
+                    continue;
+                }
+                let cmd = String::from_utf8(std::mem::take(&mut buf)).unwrap();
+                let resp: &[u8] = match cmd.as_str() {
+                    "" => continue,
+                    "S6" | "O" | "Z1" => b"\r",
+                    // Not open yet
+                    "C" if !commands.contains(&"O".to_string()) => b"\x07",
+                    "C" => b"\r",
+                    "F" => b"F24\r",
+                    "t7E03023E00" => b"z\rt7E83027E00EA5F\rt7E83027E00EA64\r",
+                    _ => b"\x07",
+                };
+                adapter.write_all(resp).unwrap();
+                commands.push(cmd);
+            }
+        });
+
+        let mut device = SlcanDevice::new(&port_name);
+        let mut channel = device.create_can_channel().unwrap();
+        assert!(matches!(
+            channel.set_can_cfg(33_333, false),
+            Err(ChannelError::ConfigurationError)
+        ));
+        channel.set_can_cfg(500_000, false).unwrap();
+        channel.open().unwrap();
+
+        let req = CanFrame::new(0x7E0, &[0x02, 0x3E, 0x00], false);
+        channel.write_packets(vec![req], 100).unwrap();
+        let resp = channel.read_packets(2, 1000).unwrap();
+        assert_eq!(resp.len(), 2);
+        assert_eq!(resp[0], CanFrame::new(0x7E8, &[0x02, 0x7E, 0x00], false));
+        // Adapter timestamps are 5ms apart
+        let ts: Vec<u64> = resp.iter().filter_map(|f| f.get_timestamp_us()).collect();
+        assert_eq!(ts[1] - ts[0], 5000);
+
+        // Error warning and error passive flags
+        let status = channel.get_bus_status().unwrap();
+        assert_eq!(status.state, CanBusState::ErrorPassive);
+
+        assert!(matches!(
+            channel.write_packets(vec![CanFrame::new(0x123, &[], false)], 100),
+            Err(ChannelError::BufferFull)
+        ));
+        channel.close().unwrap();
+        drop(port);
+
+        let commands = adapter_thread.join().unwrap();
+        assert_eq!(
+            commands,
+            ["C", "S6", "Z1", "O", "t7E03023E00", "F", "t1230", "C"]
+        );
+    }
+}
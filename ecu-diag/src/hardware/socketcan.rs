@@ -0,0 +1,911 @@
+//! Diagnostic implementation for Linux SocketCAN
+//!
+//! This works with any CAN adapter that is supported by the mainline kernel
+//! (`can0`, `slcan0`, ...), as well as the virtual `vcan` driver, which
+//! allows the whole stack to run on a developer machine without hardware.
+//!
+//! NOTE: The bit rate of a SocketCAN interface is owned by the kernel and is
+//! configured with `ip link set can0 type can bitrate 500000`. [CanChannel::set_can_cfg]
+//! only records the requested configuration. For CAN FD, the interface must be configured
+//! with `dbitrate ... fd on`, [CanChannel::set_can_fd_cfg] then enables FD frames on the socket.
+//!
+//! If the kernel has ISO-TP support (`can-isotp`, mainline since 5.10), [SocketCanIsoTpChannel]
+//! provides a native ISO-TP channel, where flow control and STmin timing are handled by the kernel.
+
+use std::{
+    ffi::CString,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Instant,
+};
+
+use crate::core::channel::{
+    host_timestamp_us, CanChannel, CanFrame, ChannelError, ChannelResult, FilterPacketChannel,
+    IsoTPChannel, IsoTPSettings, Packet, PacketChannel, PayloadChannel,
+};
+
+use super::{
+    software_isotp::SoftwareIsoTpChannel, Hardware, HardwareCapabilities, HardwareError,
+    HardwareInfo, HardwareResult, HardwareScanner, IsoTpChannelType,
+};
+
+/// `ARPHRD_CAN` from `linux/if_arp.h`. Network interfaces of this type are CAN interfaces
+const ARPHRD_CAN: u32 = 280;
+
+#[derive(Clone, Debug)]
+/// SocketCAN device, bound to a single kernel network interface
+pub struct SocketCanDevice {
+    info: HardwareInfo,
+    iface: String,
+    can_channel: Arc<AtomicBool>,
+    has_isotp_channel: Arc<AtomicBool>,
+}
+
+impl SocketCanDevice {
+    /// Creates a new SocketCAN device for the network interface `iface` (EG: `can0` or `vcan0`)
+    pub fn new(iface: &str) -> HardwareResult<Self> {
+        if if_index(iface).is_none() {
+            return Err(HardwareError::DeviceNotFound);
+        }
+        Ok(Self {
+            info: socketcan_info(iface),
+            iface: iface.to_string(),
+            can_channel: Arc::new(AtomicBool::new(false)),
+            has_isotp_channel: Arc::new(AtomicBool::new(false)),
+        })
+    }
+
+    /// Returns the name of the network interface this device is bound to
+    pub fn get_interface(&self) -> &str {
+        &self.iface
+    }
+}
+
+impl Hardware for SocketCanDevice {
+    fn create_iso_tp_channel(
+        &mut self,
+        force_native: bool,
+    ) -> HardwareResult<Box<dyn IsoTPChannel>> {
+        if force_native {
+            if !kernel_isotp_supported() {
+                return Err(HardwareError::ChannelNotSupported);
+            }
+            if self.has_isotp_channel.load(Ordering::Relaxed) {
+                return Err(HardwareError::ConflictingChannel);
+            }
+            self.has_isotp_channel.store(true, Ordering::Relaxed);
+            Ok(Box::new(SocketCanIsoTpChannel::new(
+                &self.iface,
+                self.has_isotp_channel.clone(),
+            )))
+        } else {
+            // Use software
+            let can_channel = self.create_can_channel()?;
+            Ok(Box::new(SoftwareIsoTpChannel::new(can_channel)))
+        }
+    }
+
+    fn create_native_iso_tp_channel(&mut self) -> HardwareResult<Box<dyn CanChannel>> {
+        // ISO-TP is done in software on the CAN channel, which tracks its own state
+        self.create_can_channel()
+    }
+
+    fn create_can_channel(&mut self) -> HardwareResult<Box<dyn CanChannel>> {
+        if self.can_channel.load(Ordering::Relaxed) {
+            // Already open
+            Err(HardwareError::ConflictingChannel)
+        } else {
+            self.can_channel.store(true, Ordering::Relaxed);
+            Ok(Box::new(SocketCanChannel::new(
+                &self.iface,
+                self.can_channel.clone(),
+            )))
+        }
+    }
+
+    fn is_iso_tp_channel_open(&self) -> bool {
+        self.has_isotp_channel.load(Ordering::Relaxed)
+    }
+
+    fn is_can_channel_open(&self) -> bool {
+        self.can_channel.load(Ordering::Relaxed)
+    }
+
+    fn read_battery_voltage(&mut self) -> Option<f32> {
+        None
+    }
+
+    fn read_ignition_voltage(&mut self) -> Option<f32> {
+        None
+    }
+
+    fn get_info(&self) -> &HardwareInfo {
+        &self.info
+    }
+
+    fn is_connected(&self) -> bool {
+        if_index(&self.iface).is_some()
+    }
+}
+
+#[derive(Debug)]
+/// Raw `AF_CAN` socket channel
+pub struct SocketCanChannel {
+    iface: String,
+    fd: Option<libc::c_int>,
+    baud: Option<u32>,
+    use_ext: Option<bool>,
+    can_fd: bool,
+    device_state: Arc<AtomicBool>,
+}
+
+impl SocketCanChannel {
+    fn new(iface: &str, device_state: Arc<AtomicBool>) -> Self {
+        Self {
+            iface: iface.to_string(),
+            fd: None,
+            baud: None,
+            use_ext: None,
+            can_fd: false,
+            device_state,
+        }
+    }
+
+    fn get_fd(&self) -> ChannelResult<libc::c_int> {
+        self.fd.ok_or(ChannelError::InterfaceNotOpen)
+    }
+
+    /// Waits up to `timeout_ms` for the socket to become readable.
+    /// Returns false if the wait timed out
+    fn poll_readable(fd: libc::c_int, timeout_ms: u32) -> ChannelResult<bool> {
+        let mut pfd = libc::pollfd {
+            fd,
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        let res = unsafe { libc::poll(&mut pfd, 1, timeout_ms as libc::c_int) };
+        if res < 0 {
+            Err(last_os_error())
+        } else {
+            Ok(res > 0)
+        }
+    }
+}
+
+impl Drop for SocketCanChannel {
+    fn drop(&mut self) {
+        let _ = PacketChannel::close(self);
+        self.device_state.store(false, Ordering::Relaxed);
+    }
+}
+
+impl FilterPacketChannel<CanFrame> for SocketCanChannel {}
+
+impl CanChannel for SocketCanChannel {
+    fn set_can_cfg(&mut self, baud: u32, use_extended: bool) -> ChannelResult<()> {
+        if baud == 0 {
+            return Err(ChannelError::ConfigurationError);
+        }
+        // Bit rate is set by the kernel (ip link), nothing to configure on the socket itself
+        log::debug!(
+            "SocketCAN {}: requested {baud} bps. Bit rate is configured by the OS",
+            self.iface
+        );
+        self.baud = Some(baud);
+        self.use_ext = Some(use_extended);
+        self.can_fd = false;
+        Ok(())
+    }
+
+    fn set_can_fd_cfg(
+        &mut self,
+        baud: u32,
+        data_baud: u32,
+        use_extended: bool,
+    ) -> ChannelResult<()> {
+        if baud == 0 || data_baud == 0 {
+            return Err(ChannelError::ConfigurationError);
+        }
+        if self.fd.is_some() {
+            // FD frames can only be enabled before the socket is opened
+            return Err(ChannelError::ConfigurationError);
+        }
+        log::debug!(
+            "SocketCAN {}: requested {baud}/{data_baud} bps CAN FD. Bit rate is configured by the OS",
+            self.iface
+        );
+        self.baud = Some(baud);
+        self.use_ext = Some(use_extended);
+        self.can_fd = true;
+        Ok(())
+    }
+}
+
+impl PacketChannel<CanFrame> for SocketCanChannel {
+    fn open(&mut self) -> ChannelResult<()> {
+        if self.fd.is_some() {
+            return Ok(());
+        }
+        if self.baud.is_none() {
+            return Err(ChannelError::ConfigurationError);
+        }
+        let idx = if_index(&self.iface)
+            .ok_or(ChannelError::HardwareError(HardwareError::DeviceNotFound))?;
+
+        let fd = unsafe { libc::socket(libc::PF_CAN, libc::SOCK_RAW, libc::CAN_RAW) };
+        if fd < 0 {
+            return Err(last_os_error());
+        }
+
+        let mut addr: libc::sockaddr_can = unsafe { std::mem::zeroed() };
+        addr.can_family = libc::AF_CAN as libc::sa_family_t;
+        addr.can_ifindex = idx as libc::c_int;
+        let res = unsafe {
+            libc::bind(
+                fd,
+                &addr as *const libc::sockaddr_can as *const libc::sockaddr,
+                std::mem::size_of::<libc::sockaddr_can>() as libc::socklen_t,
+            )
+        };
+        if res < 0 {
+            let e = last_os_error();
+            unsafe { libc::close(fd) };
+            return Err(e);
+        }
+        if self.can_fd {
+            let enable: libc::c_int = 1;
+            if let Err(e) = set_sock_opt(fd, libc::SOL_CAN_RAW, libc::CAN_RAW_FD_FRAMES, &enable) {
+                unsafe { libc::close(fd) };
+                return Err(e);
+            }
+        }
+        log::debug!("SocketCAN {} opened", self.iface);
+        self.fd = Some(fd);
+        Ok(())
+    }
+
+    fn close(&mut self) -> ChannelResult<()> {
+        if let Some(fd) = self.fd.take() {
+            if unsafe { libc::close(fd) } < 0 {
+                return Err(last_os_error());
+            }
+        }
+        Ok(())
+    }
+
+    fn write_packets(&mut self, packets: Vec<CanFrame>, timeout_ms: u32) -> ChannelResult<()> {
+        let fd = self.get_fd()?;
+        let start = Instant::now();
+        for frame in packets {
+            let raw = to_raw_frame(&frame);
+            let mtu = if frame.is_fd() {
+                libc::CANFD_MTU
+            } else {
+                libc::CAN_MTU
+            };
+            loop {
+                let res = unsafe {
+                    libc::write(
+                        fd,
+                        &raw as *const libc::canfd_frame as *const libc::c_void,
+                        mtu,
+                    )
+                };
+                if res >= 0 {
+                    break;
+                }
+                let e = std::io::Error::last_os_error();
+                // Kernel TX queue is full. Retry until the timeout
+                if e.raw_os_error() != Some(libc::ENOBUFS) {
+                    return Err(ChannelError::IOError(Arc::new(e)));
+                }
+                if timeout_ms == 0 {
+                    return Err(ChannelError::BufferFull);
+                }
+                if start.elapsed().as_millis() > timeout_ms as u128 {
+                    return Err(ChannelError::WriteTimeout);
+                }
+                std::thread::yield_now();
+            }
+        }
+        Ok(())
+    }
+
+    fn read_packets(&mut self, max: usize, timeout_ms: u32) -> ChannelResult<Vec<CanFrame>> {
+        let fd = self.get_fd()?;
+        let mut read_packets = vec![];
+        let start = Instant::now();
+        loop {
+            let mut raw: libc::canfd_frame = unsafe { std::mem::zeroed() };
+            let res = unsafe {
+                libc::recv(
+                    fd,
+                    &mut raw as *mut libc::canfd_frame as *mut libc::c_void,
+                    libc::CANFD_MTU,
+                    libc::MSG_DONTWAIT,
+                )
+            };
+            if res < 0 {
+                let e = std::io::Error::last_os_error();
+                if e.kind() != std::io::ErrorKind::WouldBlock {
+                    return Err(ChannelError::IOError(Arc::new(e)));
+                }
+                // Nothing in the socket buffer
+                let elapsed = start.elapsed().as_millis() as u32;
+                if timeout_ms == 0 || !read_packets.is_empty() {
+                    return Ok(read_packets);
+                } else if elapsed >= timeout_ms || !Self::poll_readable(fd, timeout_ms - elapsed)? {
+                    return Err(ChannelError::BufferEmpty);
+                }
+                continue;
+            }
+            if let Some(mut f) = from_raw_frame(&raw, res as usize == libc::CANFD_MTU) {
+                f.set_timestamp_us(host_timestamp_us());
+                read_packets.push(f);
+            }
+            if read_packets.len() == max {
+                return Ok(read_packets);
+            }
+        }
+    }
+
+    fn clear_rx_buffer(&mut self) -> ChannelResult<()> {
+        while !self.read_packets(usize::MAX, 0)?.is_empty() {}
+        Ok(())
+    }
+
+    fn clear_tx_buffer(&mut self) -> ChannelResult<()> {
+        Ok(())
+    }
+}
+
+/// `SOL_CAN_ISOTP` socket option level, from `linux/can/isotp.h`
+const SOL_CAN_ISOTP: libc::c_int = libc::SOL_CAN_BASE + libc::CAN_ISOTP;
+const CAN_ISOTP_OPTS: libc::c_int = 1;
+const CAN_ISOTP_RECV_FC: libc::c_int = 2;
+const CAN_ISOTP_LL_OPTS: libc::c_int = 5;
+
+const CAN_ISOTP_EXTEND_ADDR: u32 = 0x002;
+const CAN_ISOTP_TX_PADDING: u32 = 0x004;
+const CAN_ISOTP_RX_EXT_ADDR: u32 = 0x200;
+
+/// Padding byte, same as the software ISO-TP implementation
+const ISOTP_PAD_BYTE: u8 = 0xCC;
+
+/// `struct can_isotp_options`
+#[repr(C)]
+#[derive(Debug, Default)]
+struct CanIsoTpOptions {
+    flags: u32,
+    frame_txtime: u32,
+    ext_address: u8,
+    txpad_content: u8,
+    rxpad_content: u8,
+    rx_ext_address: u8,
+}
+
+/// `struct can_isotp_fc_options`. Flow control parameters sent by the kernel when receiving
+#[repr(C)]
+#[derive(Debug, Default)]
+struct CanIsoTpFcOptions {
+    bs: u8,
+    stmin: u8,
+    wftmax: u8,
+}
+
+/// `struct can_isotp_ll_options`. Link layer (CAN / CAN FD) options
+#[repr(C)]
+#[derive(Debug, Default)]
+struct CanIsoTpLlOptions {
+    mtu: u8,
+    tx_dl: u8,
+    tx_flags: u8,
+}
+
+/// Returns true if the running kernel supports `CAN_ISOTP` sockets
+pub fn kernel_isotp_supported() -> bool {
+    let fd = unsafe { libc::socket(libc::PF_CAN, libc::SOCK_DGRAM, libc::CAN_ISOTP) };
+    if fd < 0 {
+        false
+    } else {
+        unsafe { libc::close(fd) };
+        true
+    }
+}
+
+#[derive(Debug)]
+/// Native ISO-TP channel, using the Linux kernel `CAN_ISOTP` socket.
+///
+/// Segmentation, flow control and STmin are handled by the kernel. [IsoTPSettings]
+/// are mapped to the socket options when the channel is opened.
+pub struct SocketCanIsoTpChannel {
+    iface: String,
+    fd: Option<libc::c_int>,
+    ids: Option<(u32, u32)>,
+    cfg: Option<IsoTPSettings>,
+    /// Tx ID and Tx extended address the socket is currently bound with
+    bound_tx: Option<(u32, Option<u8>)>,
+    device_state: Arc<AtomicBool>,
+}
+
+impl SocketCanIsoTpChannel {
+    fn new(iface: &str, device_state: Arc<AtomicBool>) -> Self {
+        Self {
+            iface: iface.to_string(),
+            fd: None,
+            ids: None,
+            cfg: None,
+            bound_tx: None,
+            device_state,
+        }
+    }
+
+    fn get_fd(&self) -> ChannelResult<libc::c_int> {
+        self.fd.ok_or(ChannelError::InterfaceNotOpen)
+    }
+
+    /// Opens and binds the socket, sending with `tx_id` and extended address `tx_ext`
+    fn bind(&mut self, tx_id: u32, tx_ext: Option<u8>) -> ChannelResult<()> {
+        let cfg = self.cfg.ok_or(ChannelError::ConfigurationError)?;
+        let (_, rx_id) = self.ids.ok_or(ChannelError::ConfigurationError)?;
+        let idx = if_index(&self.iface)
+            .ok_or(ChannelError::HardwareError(HardwareError::DeviceNotFound))?;
+        PayloadChannel::close(self)?;
+
+        let fd = unsafe { libc::socket(libc::PF_CAN, libc::SOCK_DGRAM, libc::CAN_ISOTP) };
+        if fd < 0 {
+            return Err(last_os_error());
+        }
+        if let Err(e) = Self::set_options(fd, &cfg, tx_ext) {
+            unsafe { libc::close(fd) };
+            return Err(e);
+        }
+
+        let mut addr: libc::sockaddr_can = unsafe { std::mem::zeroed() };
+        addr.can_family = libc::AF_CAN as libc::sa_family_t;
+        addr.can_ifindex = idx as libc::c_int;
+        addr.can_addr.tp = libc::__c_anonymous_sockaddr_can_tp {
+            rx_id: to_raw_id(rx_id, cfg.can_use_ext_addr),
+            tx_id: to_raw_id(tx_id, cfg.can_use_ext_addr),
+        };
+        let res = unsafe {
+            libc::bind(
+                fd,
+                &addr as *const libc::sockaddr_can as *const libc::sockaddr,
+                std::mem::size_of::<libc::sockaddr_can>() as libc::socklen_t,
+            )
+        };
+        if res < 0 {
+            let e = last_os_error();
+            unsafe { libc::close(fd) };
+            return Err(e);
+        }
+        log::debug!(
+            "SocketCAN ISO-TP {} opened. Tx 0x{tx_id:04X}, Rx 0x{rx_id:04X}",
+            self.iface
+        );
+        self.fd = Some(fd);
+        self.bound_tx = Some((tx_id, tx_ext));
+        Ok(())
+    }
+
+    fn set_options(fd: libc::c_int, cfg: &IsoTPSettings, tx_ext: Option<u8>) -> ChannelResult<()> {
+        let mut opts = CanIsoTpOptions::default();
+        if cfg.pad_frame {
+            opts.flags |= CAN_ISOTP_TX_PADDING;
+            opts.txpad_content = ISOTP_PAD_BYTE;
+        }
+        if let Some(ext) = tx_ext {
+            opts.flags |= CAN_ISOTP_EXTEND_ADDR;
+            opts.ext_address = ext;
+        }
+        if let Some((_, rx_ext)) = cfg.extended_addresses {
+            opts.flags |= CAN_ISOTP_RX_EXT_ADDR;
+            opts.rx_ext_address = rx_ext;
+        }
+        set_sock_opt(fd, SOL_CAN_ISOTP, CAN_ISOTP_OPTS, &opts)?;
+
+        let fc_opts = CanIsoTpFcOptions {
+            bs: cfg.block_size,
+            stmin: cfg.st_min,
+            wftmax: 0,
+        };
+        set_sock_opt(fd, SOL_CAN_ISOTP, CAN_ISOTP_RECV_FC, &fc_opts)?;
+
+        if let Some(fd_speed) = cfg.can_fd_speed {
+            let ll_opts = CanIsoTpLlOptions {
+                mtu: libc::CANFD_MTU as u8,
+                tx_dl: cfg.tx_dl() as u8,
+                tx_flags: if fd_speed != cfg.can_speed {
+                    libc::CANFD_BRS as u8
+                } else {
+                    0
+                },
+            };
+            set_sock_opt(fd, SOL_CAN_ISOTP, CAN_ISOTP_LL_OPTS, &ll_opts)?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for SocketCanIsoTpChannel {
+    fn drop(&mut self) {
+        let _ = PayloadChannel::close(self);
+        self.device_state.store(false, Ordering::Relaxed);
+    }
+}
+
+impl PayloadChannel for SocketCanIsoTpChannel {
+    fn open(&mut self) -> ChannelResult<()> {
+        if self.fd.is_some() {
+            return Ok(());
+        }
+        let (tx_id, _) = self.ids.ok_or(ChannelError::ConfigurationError)?;
+        let tx_ext = self
+            .cfg
+            .ok_or(ChannelError::ConfigurationError)?
+            .extended_addresses
+            .map(|(tx, _)| tx);
+        self.bind(tx_id, tx_ext)
+    }
+
+    fn close(&mut self) -> ChannelResult<()> {
+        self.bound_tx = None;
+        if let Some(fd) = self.fd.take() {
+            if unsafe { libc::close(fd) } < 0 {
+                return Err(last_os_error());
+            }
+        }
+        Ok(())
+    }
+
+    fn set_ids(&mut self, send: u32, recv: u32) -> ChannelResult<()> {
+        self.ids = Some((send, recv));
+        if self.fd.is_some() {
+            // Rebind with the new IDs
+            PayloadChannel::close(self)?;
+            PayloadChannel::open(self)?;
+        }
+        Ok(())
+    }
+
+    fn read_bytes(&mut self, timeout_ms: u32) -> ChannelResult<Vec<u8>> {
+        let fd = self.get_fd()?;
+        if timeout_ms != 0 && !SocketCanChannel::poll_readable(fd, timeout_ms)? {
+            return Err(ChannelError::ReadTimeout);
+        }
+        // Largest ISO-TP payload is 2^32 bytes with the first frame escape. Kernel limits to its own buffer size
+        let mut buf = vec![0u8; 0x10000];
+        let res = unsafe {
+            libc::recv(
+                fd,
+                buf.as_mut_ptr() as *mut libc::c_void,
+                buf.len(),
+                libc::MSG_DONTWAIT,
+            )
+        };
+        if res < 0 {
+            let e = std::io::Error::last_os_error();
+            return if e.kind() == std::io::ErrorKind::WouldBlock {
+                Err(ChannelError::BufferEmpty)
+            } else {
+                Err(ChannelError::IOError(Arc::new(e)))
+            };
+        }
+        buf.truncate(res as usize);
+        Ok(buf)
+    }
+
+    fn write_bytes(
+        &mut self,
+        addr: u32,
+        ext_id: Option<u8>,
+        buffer: &[u8],
+        timeout_ms: u32,
+    ) -> ChannelResult<()> {
+        let tx_ext = ext_id.or(self
+            .cfg
+            .ok_or(ChannelError::ConfigurationError)?
+            .extended_addresses
+            .map(|(tx, _)| tx));
+        if self.bound_tx != Some((addr, tx_ext)) {
+            // Kernel socket sends with the ID it is bound to
+            self.bind(addr, tx_ext)?;
+        }
+        let fd = self.get_fd()?;
+        let tv = libc::timeval {
+            tv_sec: (timeout_ms / 1000) as libc::time_t,
+            tv_usec: ((timeout_ms % 1000) * 1000) as libc::suseconds_t,
+        };
+        set_sock_opt(fd, libc::SOL_SOCKET, libc::SO_SNDTIMEO, &tv)?;
+        let res = unsafe { libc::write(fd, buffer.as_ptr() as *const libc::c_void, buffer.len()) };
+        if res < 0 {
+            let e = std::io::Error::last_os_error();
+            return if e.kind() == std::io::ErrorKind::WouldBlock {
+                Err(ChannelError::WriteTimeout)
+            } else {
+                Err(ChannelError::IOError(Arc::new(e)))
+            };
+        }
+        Ok(())
+    }
+
+    fn clear_rx_buffer(&mut self) -> ChannelResult<()> {
+        loop {
+            match self.read_bytes(0) {
+                Ok(_) => {}
+                Err(ChannelError::BufferEmpty) => return Ok(()),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    fn clear_tx_buffer(&mut self) -> ChannelResult<()> {
+        Ok(())
+    }
+}
+
+impl IsoTPChannel for SocketCanIsoTpChannel {
+    fn set_iso_tp_cfg(&mut self, cfg: IsoTPSettings) -> ChannelResult<()> {
+        self.cfg = Some(cfg);
+        if self.fd.is_some() {
+            // Socket options can only be set before binding
+            PayloadChannel::close(self)?;
+            PayloadChannel::open(self)?;
+        }
+        Ok(())
+    }
+}
+
+/// SocketCAN device scanner. Lists all CAN network interfaces on the system
+#[derive(Debug, Clone)]
+pub struct SocketCanScanner {
+    cache: Vec<HardwareInfo>,
+}
+
+impl Default for SocketCanScanner {
+    fn default() -> Self {
+        let mut s = Self { cache: vec![] };
+        s.scan_devices();
+        s
+    }
+}
+
+impl SocketCanScanner {
+    fn scan_devices(&mut self) {
+        let mut res = vec![];
+        if let Ok(dir) = std::fs::read_dir("/sys/class/net") {
+            for entry in dir.flatten() {
+                let name = entry.file_name().to_string_lossy().to_string();
+                let is_can = std::fs::read_to_string(entry.path().join("type"))
+                    .ok()
+                    .and_then(|t| t.trim().parse::<u32>().ok())
+                    == Some(ARPHRD_CAN);
+                if is_can {
+                    res.push(socketcan_info(&name));
+                }
+            }
+        }
+        res.sort();
+        self.cache = res;
+    }
+}
+
+impl HardwareScanner<SocketCanDevice> for SocketCanScanner {
+    fn list_devices(&self) -> Vec<HardwareInfo> {
+        self.cache.clone()
+    }
+
+    fn open_device_by_index(&self, idx: usize) -> HardwareResult<SocketCanDevice> {
+        match self.cache.get(idx) {
+            Some(info) => SocketCanDevice::new(&info.name),
+            None => Err(HardwareError::DeviceNotFound),
+        }
+    }
+
+    fn open_device_by_name(&self, name: &str) -> HardwareResult<SocketCanDevice> {
+        match self.cache.iter().find(|x| x.name == name) {
+            Some(info) => SocketCanDevice::new(&info.name),
+            None => Err(HardwareError::DeviceNotFound),
+        }
+    }
+}
+
+fn socketcan_info(iface: &str) -> HardwareInfo {
+    HardwareInfo {
+        name: iface.to_string(),
+        vendor: Some("Linux SocketCAN".to_string()),
+        device_fw_version: None,
+        device_id: None,
+        api_version: None,
+        library_version: None,
+        library_location: None,
+        capabilities: HardwareCapabilities {
+            iso_tp: if kernel_isotp_supported() {
+                IsoTpChannelType::Protocol
+            } else {
+                IsoTpChannelType::Emulated
+            },
+            can: true,
+            kline: false,
+            kline_kwp: false,
+            sae_j1850: false,
+            sci: false,
+            ip: false,
+        },
+    }
+}
+
+fn if_index(iface: &str) -> Option<u32> {
+    let name = CString::new(iface).ok()?;
+    match unsafe { libc::if_nametoindex(name.as_ptr()) } {
+        0 => None,
+        idx => Some(idx),
+    }
+}
+
+fn to_raw_id(id: u32, use_ext: bool) -> libc::canid_t {
+    if use_ext || id > 0x7FF {
+        (id & libc::CAN_EFF_MASK) | libc::CAN_EFF_FLAG
+    } else {
+        id & libc::CAN_SFF_MASK
+    }
+}
+
+fn set_sock_opt<T>(
+    fd: libc::c_int,
+    level: libc::c_int,
+    name: libc::c_int,
+    val: &T,
+) -> ChannelResult<()> {
+    let res = unsafe {
+        libc::setsockopt(
+            fd,
+            level,
+            name,
+            val as *const T as *const libc::c_void,
+            std::mem::size_of::<T>() as libc::socklen_t,
+        )
+    };
+    if res < 0 {
+        Err(last_os_error())
+    } else {
+        Ok(())
+    }
+}
+
+fn last_os_error() -> ChannelError {
+    ChannelError::IOError(Arc::new(std::io::Error::last_os_error()))
+}
+
+/// Converts to a kernel frame. Classic frames share the `canfd_frame` layout,
+/// and are written with [libc::CAN_MTU]
+fn to_raw_frame(frame: &CanFrame) -> libc::canfd_frame {
+    let mut raw: libc::canfd_frame = unsafe { std::mem::zeroed() };
+    raw.can_id = to_raw_id(frame.get_address(), frame.is_extended());
+    let data = frame.get_data();
+    raw.len = data.len() as u8;
+    raw.data[0..data.len()].copy_from_slice(data);
+    if frame.is_brs() {
+        raw.flags = libc::CANFD_BRS as u8;
+    }
+    raw
+}
+
+/// Converts a kernel frame. Error and remote frames are discarded
+fn from_raw_frame(raw: &libc::canfd_frame, is_fd: bool) -> Option<CanFrame> {
+    if raw.can_id & (libc::CAN_ERR_FLAG | libc::CAN_RTR_FLAG) != 0 {
+        return None;
+    }
+    let ext = raw.can_id & libc::CAN_EFF_FLAG != 0;
+    let id = if ext {
+        raw.can_id & libc::CAN_EFF_MASK
+    } else {
+        raw.can_id & libc::CAN_SFF_MASK
+    };
+    if is_fd {
+        let len = std::cmp::min(raw.len as usize, 64);
+        let brs = raw.flags & libc::CANFD_BRS as u8 != 0;
+        Some(CanFrame::new_fd(id, &raw.data[0..len], ext, brs))
+    } else {
+        let len = std::cmp::min(raw.len as usize, 8);
+        Some(CanFrame::new(id, &raw.data[0..len], ext))
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::{from_raw_frame, kernel_isotp_supported, to_raw_frame, SocketCanDevice};
+    use crate::core::channel::{
+        CanChannel, CanFrame, IsoTPSettings, Packet, PacketChannel, PayloadChannel,
+    };
+    use crate::hardware::Hardware;
+
+    #[test]
+    pub fn test_raw_frame_conversion() {
+        let std_frame = CanFrame::new(0x7E0, &[0x02, 0x10, 0x03], false);
+        let raw = to_raw_frame(&std_frame);
+        assert_eq!(raw.can_id, 0x7E0);
+        assert_eq!(raw.len, 3);
+        assert_eq!(from_raw_frame(&raw, false), Some(std_frame));
+
+        let ext_frame = CanFrame::new(0x18DA00F1, &[0x01, 0x3E], true);
+        let raw = to_raw_frame(&ext_frame);
+        assert_eq!(raw.can_id, 0x18DA00F1 | libc::CAN_EFF_FLAG);
+        assert_eq!(from_raw_frame(&raw, false), Some(ext_frame));
+
+        let fd_frame = CanFrame::new_fd(0x7E0, &[0x55; 18], false, true);
+        let raw = to_raw_frame(&fd_frame);
+        assert_eq!(raw.len, 20);
+        assert_eq!(raw.flags, libc::CANFD_BRS as u8);
+        assert_eq!(from_raw_frame(&raw, true), Some(fd_frame));
+    }
+
+    #[test]
+    pub fn test_software_isotp_channel_state() {
+        // Channels are only created here, so any interface will do
+        let mut dev = SocketCanDevice::new("lo").unwrap();
+        let channel = dev.create_native_iso_tp_channel().unwrap();
+        assert!(!dev.is_iso_tp_channel_open());
+        drop(channel);
+        assert!(!dev.is_can_channel_open());
+
+        let _channel = dev.create_iso_tp_channel(false).unwrap();
+        assert!(dev.is_can_channel_open());
+        assert!(!dev.is_iso_tp_channel_open());
+    }
+
+    /// Requires a virtual CAN interface:
+    /// `ip link add dev vcan0 type vcan && ip link set up vcan0`
+    #[test]
+    #[ignore = "requires the vcan0 interface"]
+    pub fn test_vcan_loopback() {
+        let mut dev1 = SocketCanDevice::new("vcan0").unwrap();
+        let mut dev2 = SocketCanDevice::new("vcan0").unwrap();
+        let mut tester = dev1.create_can_channel().unwrap();
+        let mut ecu = dev2.create_can_channel().unwrap();
+        tester.set_can_cfg(500_000, false).unwrap();
+        ecu.set_can_cfg(500_000, false).unwrap();
+        PacketChannel::open(&mut tester).unwrap();
+        PacketChannel::open(&mut ecu).unwrap();
+
+        let tx = CanFrame::new(0x784, &[0x02, 0x3E, 0x00], false);
+        tester.write_packets(vec![tx], 100).unwrap();
+        let rx = ecu.read_packets(1, 1000).unwrap();
+        assert_eq!(rx.len(), 1);
+        assert_eq!(rx[0].get_address(), 0x784);
+        assert_eq!(rx[0].get_data(), tx.get_data());
+    }
+
+    /// Requires `vcan0` and a kernel with `CAN_ISOTP` support
+    #[test]
+    #[ignore = "requires the vcan0 interface and kernel ISO-TP"]
+    pub fn test_vcan_kernel_isotp() {
+        assert!(kernel_isotp_supported());
+        let mut dev1 = SocketCanDevice::new("vcan0").unwrap();
+        let mut dev2 = SocketCanDevice::new("vcan0").unwrap();
+        let cfg = IsoTPSettings {
+            block_size: 4,
+            st_min: 1,
+            can_use_ext_addr: false,
+            ..Default::default()
+        };
+        let mut tester = dev1.create_iso_tp_channel(true).unwrap();
+        let mut ecu = dev2.create_iso_tp_channel(true).unwrap();
+        tester.set_iso_tp_cfg(cfg).unwrap();
+        ecu.set_iso_tp_cfg(cfg).unwrap();
+        tester.set_ids(0x7E0, 0x7E8).unwrap();
+        ecu.set_ids(0x7E8, 0x7E0).unwrap();
+        PayloadChannel::open(&mut tester).unwrap();
+        PayloadChannel::open(&mut ecu).unwrap();
+
+        let payload = (0..200).map(|x| x as u8).collect::<Vec<u8>>();
+        tester.write_bytes(0x7E0, None, &payload, 1000).unwrap();
+        assert_eq!(ecu.read_bytes(1000).unwrap(), payload);
+
+        ecu.write_bytes(0x7E8, None, &[0x7E, 0x00], 1000).unwrap();
+        assert_eq!(tester.read_bytes(1000).unwrap(), vec![0x7E, 0x00]);
+    }
+}
@@ -4,7 +4,7 @@ use std::{
     collections::VecDeque,
     sync::{
         atomic::{AtomicBool, AtomicU32, Ordering},
-        mpsc, Arc,
+        mpsc, Arc, Mutex,
     },
     time::{Duration, Instant},
 };
@@ -12,10 +12,47 @@ use std::{
 use log::debug;
 
 use crate::core::channel::{
-    CanChannel, CanFrame, ChannelError, ChannelResult, IsoTPChannel, IsoTPSettings, Packet,
-    PacketChannel, PayloadChannel,
+    can_fd_dlc_to_len, can_fd_len_to_dlc, host_timestamp_us, st_min_duration, CanBusStatus,
+    CanChannel, CanFrame, ChannelError, ChannelResult, FilterPacketChannel, IsoTPChannel,
+    IsoTPSettings, IsoTpError, IsoTpTimeouts, Packet, PacketChannel, PayloadChannel,
 };
 
+/// Pads ISO-TP frame data with 0xCC. Classic frames are only padded (to 8 bytes) if `pad_frame`
+/// is set, but CAN FD frames longer than 8 bytes always have to be padded to a valid CAN FD length
+pub(crate) fn pad_frame_data(data: &mut Vec<u8>, pad_frame: bool) {
+    if pad_frame && data.len() < 8 {
+        data.resize(8, 0xCC);
+    }
+    if data.len() > 8 {
+        data.resize(can_fd_dlc_to_len(can_fd_len_to_dlc(data.len())), 0xCC);
+    }
+}
+
+/// Creates the CAN Frame for ISO-TP frame data, padding it according to `cfg`
+pub(crate) fn isotp_frame(cfg: &IsoTPSettings, id: u32, mut data: Vec<u8>) -> CanFrame {
+    pad_frame_data(&mut data, cfg.pad_frame);
+    match cfg.can_fd_speed {
+        Some(fd_speed) => {
+            CanFrame::new_fd(id, &data, cfg.can_use_ext_addr, fd_speed != cfg.can_speed)
+        }
+        None => CanFrame::new(id, &data, cfg.can_use_ext_addr),
+    }
+}
+
+/// Returns the single frame for `data`, or None if it needs a multi frame transfer.
+/// Payloads over 7 bytes (or `tx_dl` - 1 if that is less) use the CAN FD single frame escape (ISO 15765-2:2016) if `tx_dl` allows it
+pub(crate) fn single_frame(data: &[u8], tx_dl: usize) -> Option<Vec<u8>> {
+    let mut tx = if data.len() <= min(7, tx_dl - 1) {
+        vec![data.len() as u8]
+    } else if data.len() <= tx_dl - 2 {
+        vec![0x00, data.len() as u8]
+    } else {
+        return None;
+    };
+    tx.extend_from_slice(data);
+    Some(tx)
+}
+
 #[derive(Debug, Clone)]
 /// Software ISOTP layer.
 /// This is useful for certain hardware layers that might not
@@ -24,6 +61,8 @@ pub struct SoftwareIsoTpChannel {
     running: Arc<AtomicBool>,
     can_msg_sender: mpsc::Sender<CanMessage>,
     isotp_msg_sender: mpsc::Sender<IsoTpMessage>,
+    /// Timestamp of the last frame of the last payload read
+    last_rx_timestamp: Arc<Mutex<Option<u64>>>,
 }
 
 impl Drop for SoftwareIsoTpChannel {
@@ -40,6 +79,10 @@ pub struct IsoTpRxMemory {
     pub frames_received: usize,
     pub data: Vec<u8>,
     pub max_size: usize,
+    /// Receive timestamp of the last frame, in microseconds
+    pub rx_timestamp_us: Option<u64>,
+    /// Sequence number expected in the next consecutive frame
+    pub sn: u8,
 }
 
 impl Default for IsoTpRxMemory {
@@ -52,6 +95,8 @@ impl Default for IsoTpRxMemory {
             frames_received: 0,
             data: vec![],
             max_size: 0,
+            rx_timestamp_us: None,
+            sn: 0,
         }
     }
 }
@@ -61,6 +106,9 @@ pub enum IsoTpRxAction {
     None,
     Completed,
     SendFC,
+    /// The consecutive frame had the wrong sequence number (A frame was lost),
+    /// the reception was aborted
+    WrongSequence,
 }
 
 impl IsoTpRxMemory {
@@ -69,26 +117,54 @@ impl IsoTpRxMemory {
         self.completed = false;
         self.receiving = false;
         self.frames_received = 0;
+        self.rx_timestamp_us = None;
     }
 
     pub fn add_single_frame(&mut self, s: &[u8]) {
         self.completed = true;
         self.receiving = false;
-        let len = s[0] as usize;
-        self.data = s[1..1 + len].to_vec();
+        // CAN FD single frames over 8 bytes use an escape sequence (0x00, len)
+        let (start, len) = if s[0] == 0 && s.len() > 8 {
+            (2, s[1] as usize)
+        } else {
+            (1, s[0] as usize)
+        };
+        self.data = s[start..min(start + len, s.len())].to_vec();
     }
 
     pub fn add_start_frame(&mut self, s: &[u8]) {
         self.max_size = ((((s[0] & 0x0F) as u16) << 8) | (s[1] as u16)) as usize;
+        let mut start = 2;
+        if self.max_size == 0 && s.len() >= 6 {
+            // First frame escape sequence, for payloads over 4095 bytes
+            self.max_size = u32::from_be_bytes([s[2], s[3], s[4], s[5]]) as usize;
+            start = 6;
+        }
         self.receiving = true;
         self.frames_received = 0;
-        self.data.extend_from_slice(&s[2..]);
+        self.sn = 1;
+        self.data
+            .extend_from_slice(&s[start..min(s.len(), start + self.max_size)]);
         self.last_rx_time = Instant::now();
     }
 
     // Returns true if Rx is done!
     pub fn add_continuous_frame(&mut self, s: &[u8]) -> IsoTpRxAction {
-        let max_copy = min(self.max_size - self.data.len(), 7);
+        if !self.receiving || self.completed {
+            // No first frame, or it was lost
+            return IsoTpRxAction::None;
+        }
+        if s[0] & 0x0F != self.sn {
+            log::error!(
+                "ISOTP wrong sequence number {:X}, expected {:X}",
+                s[0] & 0x0F,
+                self.sn
+            );
+            self.reset();
+            return IsoTpRxAction::WrongSequence;
+        }
+        self.sn = (self.sn + 1) & 0x0F;
+        let max_copy = min(self.max_size - self.data.len(), s.len() - 1);
         self.data.extend_from_slice(&s[1..1 + max_copy]);
         self.frames_received += 1;
         self.last_rx_time = Instant::now();
@@ -111,8 +187,10 @@ impl IsoTpRxMemory {
     }
 }
 
-struct IsoTpTxMemory {
+pub struct IsoTpTxMemory {
     pub addr: u32,
+    /// Maximum data length of a single frame (8 for CAN, up to 64 for CAN FD)
+    pub tx_dl: usize,
     pub completed: bool,
     pub transmitting: bool,
     pub awaiting_fc: bool,
@@ -125,6 +203,9 @@ struct IsoTpTxMemory {
     pub fc_bs: u8,
     // Set by receiving ECU
     pub fc_stmin: u8,
+    /// Flow control WAIT frames received in a row
+    pub fc_wait_count: u8,
+    pub timeouts: IsoTpTimeouts,
 }
 
 impl Default for IsoTpTxMemory {
@@ -132,6 +213,7 @@ impl Default for IsoTpTxMemory {
         Self {
             frames_txed: 0,
             addr: 0,
+            tx_dl: 8,
             completed: false,
             transmitting: false,
             awaiting_fc: false,
@@ -141,29 +223,62 @@ impl Default for IsoTpTxMemory {
             current_pci: 0x21,
             fc_bs: 0,
             fc_stmin: 0,
+            fc_wait_count: 0,
+            timeouts: IsoTpTimeouts::default(),
         }
     }
 }
 
 impl IsoTpTxMemory {
     pub fn reset(&mut self) {
-        *self = Self::default();
+        *self = Self {
+            timeouts: self.timeouts,
+            ..Self::default()
+        };
     }
 
-    pub fn on_flow_control(&mut self, data: &[u8]) {
-        self.fc_bs = data[1];
-        self.fc_stmin = data[2];
-        self.awaiting_fc = false;
+    /// Handles a flow control frame from the receiver. An error means the transfer has to be aborted
+    pub fn on_flow_control(&mut self, data: &[u8]) -> ChannelResult<()> {
+        if data.len() < 3 {
+            log::debug!("ISOTP flow control too short, ignoring {data:02X?}");
+            return Ok(());
+        }
+        match data[0] & 0x0F {
+            // Continue to send
+            0x00 => {
+                self.fc_bs = data[1];
+                self.fc_stmin = data[2];
+                self.awaiting_fc = false;
+                self.frames_txed = 0;
+                self.fc_wait_count = 0;
+            }
+            // Wait, N_Bs starts again
+            0x01 => {
+                if self.fc_wait_count >= self.timeouts.n_wft_max {
+                    return Err(IsoTpError::WaitLimitExceeded(self.timeouts.n_wft_max).into());
+                }
+                self.fc_wait_count += 1;
+            }
+            0x02 => return Err(IsoTpError::Overflow.into()),
+            fs => return Err(IsoTpError::InvalidFlowStatus(fs).into()),
+        }
         self.last_tx_time = Instant::now();
-        self.frames_txed = 0;
+        Ok(())
     }
 
-    pub fn get_start_frame(&mut self) -> [u8; 8] {
-        let mut tx = [0; 8];
-        tx[0] = 0x10 | ((self.data.len() >> 8) & 0x0F) as u8;
-        tx[1] = (self.data.len() & 0xFF) as u8;
-        tx[2..8].copy_from_slice(&self.data[0..6]);
-        self.current_pos = 6;
+    pub fn get_start_frame(&mut self) -> Vec<u8> {
+        let len = self.data.len();
+        let mut tx = if len > 4095 {
+            // First frame escape sequence (ISO 15765-2:2016), also valid on classic CAN
+            let mut tx = vec![0x10, 0x00];
+            tx.extend_from_slice(&(len as u32).to_be_bytes());
+            tx
+        } else {
+            vec![0x10 | ((len >> 8) & 0x0F) as u8, (len & 0xFF) as u8]
+        };
+        let max_data = min(self.tx_dl - tx.len(), len);
+        tx.extend_from_slice(&self.data[0..max_data]);
+        self.current_pos = max_data;
         self.awaiting_fc = true;
         self.completed = false;
         self.current_pci = 0x21;
@@ -173,54 +288,50 @@ impl IsoTpTxMemory {
         tx
     }
 
-    pub fn on_update(&mut self, timeout: u32, pad_frame: bool) -> Option<ChannelResult<Vec<u8>>> {
+    /// Returns the next consecutive frame data once flow control and STmin allow it.
+    /// The data is not padded, see [isotp_frame]
+    pub fn on_update(&mut self) -> Option<ChannelResult<Vec<u8>>> {
         if self.completed {
             return None;
         }
 
-        let mut t_out = timeout;
-        if timeout == 0 {
-            t_out = 1000
-        }
         if self.transmitting {
-            // Timeout for awaiting FC
-            if self.awaiting_fc && self.last_tx_time.elapsed().as_millis() > (t_out * 2) as u128 {
-                log::error!(
-                    "Awaiting FC timed out. {timeout} - {}",
-                    self.last_tx_time.elapsed().as_millis()
-                );
-                Some(Err(ChannelError::WriteTimeout))
-            } else if self.last_tx_time.elapsed().as_millis() >= self.fc_stmin as u128
-                || self.fc_stmin == 0
+            if self.awaiting_fc {
+                // N_Bs, timeout for awaiting FC
+                if self.last_tx_time.elapsed().as_millis() > self.timeouts.n_bs as u128 {
+                    log::error!(
+                        "Awaiting FC timed out after {}ms",
+                        self.last_tx_time.elapsed().as_millis()
+                    );
+                    Some(Err(IsoTpError::TimeoutBs.into()))
+                } else {
+                    None
+                }
+            } else if self.fc_stmin == 0
+                || self.last_tx_time.elapsed() >= st_min_duration(self.fc_stmin)
             {
                 // We can transmit
 
                 let mut tx_data = vec![];
                 tx_data.push(self.current_pci);
 
-                let max_data = min(7, self.data.len() - self.current_pos);
+                let max_data = min(self.tx_dl - 1, self.data.len() - self.current_pos);
                 tx_data
                     .extend_from_slice(&self.data[self.current_pos..self.current_pos + max_data]);
-                if pad_frame {
-                    tx_data.resize(8, 0xCC);
-                }
                 self.current_pos += max_data;
+                self.frames_txed += 1;
+                self.last_tx_time = Instant::now();
 
-                if self.fc_bs != 0 && self.frames_txed > self.fc_bs as usize {
+                if self.current_pos >= self.data.len() {
+                    log::debug!("Tx done!");
+                    self.completed = true;
+                } else if self.fc_bs != 0 && self.frames_txed >= self.fc_bs as usize {
                     // Await flow control after this update!
                     log::debug!("Awaiting FC");
-                    println!("Awaiting FC");
                     self.awaiting_fc = true;
                     self.frames_txed = 0;
                 }
 
-                if self.current_pos >= self.data.len() {
-                    log::debug!("Tx done!");
-                    println!("Tx done!");
-                    self.completed = true;
-                }
-
-                self.frames_txed += 1;
                 self.current_pci += 1;
                 if self.current_pci == 0x30 {
                     self.current_pci = 0x20;
@@ -257,10 +368,14 @@ enum IsoTpMessage {
 enum CanMessage {
     Open(mpsc::Sender<ChannelResult<()>>),
     Close(mpsc::Sender<ChannelResult<()>>),
-    Configure(u32, bool, mpsc::Sender<ChannelResult<()>>),
+    Configure(u32, Option<u32>, bool, mpsc::Sender<ChannelResult<()>>),
     ReadFrames(usize, u32, mpsc::Sender<ChannelResult<Vec<CanFrame>>>),
     WriteFrames(u32, Vec<CanFrame>, mpsc::Sender<ChannelResult<()>>),
     ClearRxBuffer(mpsc::Sender<ChannelResult<()>>),
+    AddFilter(Vec<u32>, mpsc::Sender<ChannelResult<u32>>),
+    RemoveFilter(u32, mpsc::Sender<ChannelResult<()>>),
+    GetBusStatus(mpsc::Sender<ChannelResult<CanBusStatus>>),
+    DetectBaud(Vec<u32>, u32, mpsc::Sender<ChannelResult<u32>>),
 }
 
 unsafe impl Sync for SoftwareIsoTpChannel {}
@@ -295,6 +410,9 @@ impl SoftwareIsoTpChannel {
         let can_open = Arc::new(AtomicBool::new(false));
         let can_open_c = can_open.clone();
 
+        let last_rx_timestamp = Arc::new(Mutex::new(None));
+        let last_rx_timestamp_c = last_rx_timestamp.clone();
+
         let (can_to_isotp_rx_frame_tx, can_to_isotp_rx_frame_rx) = mpsc::channel::<CanFrame>();
 
         let (isotp_msg_sender, isotp_msg_receiver) = mpsc::channel::<IsoTpMessage>();
@@ -304,14 +422,14 @@ impl SoftwareIsoTpChannel {
         std::thread::spawn(move || {
             let mut rx_memory = IsoTpRxMemory::default();
             let mut bg_rx_receiver: Option<mpsc::Sender<ChannelResult<Vec<u8>>>> = None;
-            let mut rx_timeout = 0;
+            let mut rx_deadline = Instant::now();
 
             let mut tx_memory = IsoTpTxMemory::default();
             let mut bg_tx_receiver: Option<mpsc::Sender<ChannelResult<()>>> = None;
-            let mut tx_timeout = 0;
 
             let mut isotp_settings: Option<IsoTPSettings> = None;
             let mut default_tx_addr = 0;
+            let mut rx_filter_id: Option<u32> = None;
             let mut _isotp_running = false;
             while running_c.load(Ordering::Relaxed) {
                 if let Ok(msg) = isotp_msg_receiver.try_recv() {
@@ -341,12 +459,30 @@ impl SoftwareIsoTpChannel {
                         IsoTpMessage::SetIds(send, recv, sender_resp) => {
                             default_tx_addr = send;
                             isotp_listen_id.store(recv, Ordering::Relaxed);
-                            let _ = sender_resp.send(Ok(()));
+                            // Let the adapter drop traffic not meant for us, if it can
+                            if let Some(id) = rx_filter_id.take() {
+                                let (tx, rx) = mpsc::channel::<ChannelResult<()>>();
+                                let _ = can_msg_sender_isotp.send(CanMessage::RemoveFilter(id, tx));
+                                let _ = rx.recv();
+                            }
+                            let (tx, rx) = mpsc::channel::<ChannelResult<u32>>();
+                            let _ =
+                                can_msg_sender_isotp.send(CanMessage::AddFilter(vec![recv], tx));
+                            let res = match rx.recv().unwrap() {
+                                Ok(id) => {
+                                    rx_filter_id = Some(id);
+                                    Ok(())
+                                }
+                                Err(ChannelError::UnsupportedRequest) => Ok(()),
+                                Err(e) => Err(e),
+                            };
+                            let _ = sender_resp.send(res);
                         }
                         IsoTpMessage::ReadBytes(timeout_ms, sender_resp) => {
                             if rx_memory.completed {
                                 debug!("RX done!: {:02X?}", rx_memory.data);
                                 println!("RX done!: {:02X?}", rx_memory.data);
+                                *last_rx_timestamp_c.lock().unwrap() = rx_memory.rx_timestamp_us;
                                 let _ = sender_resp.send(Ok(rx_memory.data.clone()));
                                 rx_memory.reset();
                             } else if timeout_ms == 0 {
@@ -354,8 +490,8 @@ impl SoftwareIsoTpChannel {
                                 let _ = sender_resp.send(Err(ChannelError::BufferEmpty));
                             } else {
                                 // Blocking - No data in buffer
-                                rx_memory.last_rx_time = Instant::now();
-                                rx_timeout = timeout_ms;
+                                rx_deadline =
+                                    Instant::now() + Duration::from_millis(timeout_ms as u64);
                                 bg_rx_receiver = Some(sender_resp);
                             }
                         }
@@ -363,7 +499,7 @@ impl SoftwareIsoTpChannel {
                             send_id,
                             ext_id,
                             data,
-                            timeout_ms,
+                            _timeout_ms,
                             sender_resp,
                         ) => {
                             if ext_id.is_some() {
@@ -374,44 +510,30 @@ impl SoftwareIsoTpChannel {
                                     .ok_or(ChannelError::ConfigurationError)
                                 {
                                     Ok(cfg) => {
-                                        if data.len() <= 7 {
-                                            let mut data_len = data.len() + 1;
-                                            if cfg.pad_frame {
-                                                data_len = 8;
-                                            }
+                                        if let Some(tx_data) = single_frame(&data, cfg.tx_dl()) {
                                             // 1 time send
                                             let (tx, rx) = mpsc::channel::<ChannelResult<()>>();
-                                            let mut tx_data: Vec<u8> = vec![0xCC; data_len]; // Fill with padding byte
-                                            tx_data[0] = data.len() as u8;
-                                            tx_data[1..1 + data.len()].copy_from_slice(&data);
-                                            let f = CanFrame::new(
-                                                send_id,
-                                                &tx_data,
-                                                cfg.can_use_ext_addr,
-                                            );
+                                            let f = isotp_frame(&cfg, send_id, tx_data);
                                             let _ = can_msg_sender_isotp
                                                 .send(CanMessage::WriteFrames(0, vec![f], tx));
                                             rx.recv().unwrap()
                                         } else {
                                             // Multi frame Tx
-                                            if data.len() > 4095 {
-                                                // Data too large for ISO-TP
+                                            if data.len() > u32::MAX as usize {
+                                                // Data too large for the first frame escape
                                                 Err(ChannelError::UnsupportedRequest)
                                             } else if tx_memory.transmitting {
                                                 Err(ChannelError::BufferFull)
                                             } else {
                                                 tx_memory.reset();
                                                 tx_memory.data = data.clone();
-                                                tx_timeout = timeout_ms;
+                                                tx_memory.timeouts = cfg.timeouts;
                                                 tx_memory.addr = send_id;
+                                                tx_memory.tx_dl = cfg.tx_dl();
                                                 let tx_data = tx_memory.get_start_frame();
                                                 // Send the ISO-TP start frame
                                                 let (tx, rx) = mpsc::channel::<ChannelResult<()>>();
-                                                let f = CanFrame::new(
-                                                    send_id,
-                                                    &tx_data,
-                                                    cfg.can_use_ext_addr,
-                                                );
+                                                let f = isotp_frame(&cfg, send_id, tx_data);
                                                 let _ = can_msg_sender_isotp
                                                     .send(CanMessage::WriteFrames(0, vec![f], tx));
                                                 rx.recv().unwrap()
@@ -436,6 +558,7 @@ impl SoftwareIsoTpChannel {
                             let (tx, rx) = mpsc::channel::<ChannelResult<()>>();
                             let _ = can_msg_sender_isotp.send(CanMessage::Configure(
                                 cfg.can_speed,
+                                cfg.can_fd_speed,
                                 cfg.can_use_ext_addr,
                                 tx,
                             ));
@@ -451,6 +574,8 @@ impl SoftwareIsoTpChannel {
                 if let Ok(frame) = can_to_isotp_rx_frame_rx.try_recv() {
                     if let Some(cfg) = isotp_settings {
                         let data = frame.get_data();
+                        let rx_timestamp =
+                            frame.get_timestamp_us().unwrap_or_else(host_timestamp_us);
                         let pci_byte_idx = 0; // TODO for EXT ID Rx
                         match data.get(pci_byte_idx) {
                             Some(pci) => {
@@ -459,31 +584,25 @@ impl SoftwareIsoTpChannel {
                                         log::debug!("ISOTP One frame {data:02X?}");
                                         println!("ISOTP One frame {data:02X?}");
                                         rx_memory.add_single_frame(data);
+                                        rx_memory.rx_timestamp_us = Some(rx_timestamp);
                                     }
                                     0x10 => {
                                         // Start of multi frame
                                         log::debug!("ISOTP Start frame {data:02X?}");
                                         println!("ISOTP Start frame {data:02X?}");
-                                        let mut data_tx: Vec<u8> = vec![];
                                         if rx_memory.receiving {
-                                            data_tx.push(0x32);
-                                        } else {
-                                            data_tx.push(0x30);
-                                            data_tx.push(cfg.block_size);
-                                            data_tx.push(cfg.st_min);
-                                            rx_memory.bs = cfg.block_size;
-                                            rx_memory.add_start_frame(data);
-                                        }
-                                        if cfg.pad_frame {
-                                            data_tx.resize(8, 0xCC);
+                                            // ISO 15765-2: A new first frame replaces the reception in progress
+                                            log::warn!(
+                                                "ISOTP first frame during reception, restarting"
+                                            );
+                                            rx_memory.reset();
                                         }
+                                        let data_tx = vec![0x30, cfg.block_size, cfg.st_min];
+                                        rx_memory.bs = cfg.block_size;
+                                        rx_memory.add_start_frame(data);
                                         // Send flow control
                                         let (tx, rx) = mpsc::channel::<ChannelResult<()>>();
-                                        let f = CanFrame::new(
-                                            default_tx_addr,
-                                            &data_tx,
-                                            cfg.can_use_ext_addr,
-                                        );
+                                        let f = isotp_frame(&cfg, default_tx_addr, data_tx);
                                         let _ = can_msg_sender_isotp.send(CanMessage::WriteFrames(
                                             0,
                                             vec![f],
@@ -495,26 +614,22 @@ impl SoftwareIsoTpChannel {
                                         // Continuation of multi frame
                                         log::debug!("ISOTP continue frame {data:02X?}");
                                         println!("ISOTP continue frame {data:02X?}");
-                                        if IsoTpRxAction::SendFC
-                                            == rx_memory.add_continuous_frame(data)
-                                        {
-                                            let mut data_tx: Vec<u8> = vec![];
-                                            data_tx.push(0x30);
-                                            data_tx.push(cfg.block_size);
-                                            data_tx.push(cfg.st_min);
-                                            rx_memory.bs = cfg.block_size;
-                                            if cfg.pad_frame {
-                                                data_tx.resize(8, 0xCC);
+                                        rx_memory.rx_timestamp_us = Some(rx_timestamp);
+                                        let action = rx_memory.add_continuous_frame(data);
+                                        if action == IsoTpRxAction::WrongSequence {
+                                            if let Some(x) = bg_rx_receiver.take() {
+                                                let _ = x.send(Err(
+                                                    IsoTpError::WrongSequenceNumber.into(),
+                                                ));
                                             }
+                                        } else if action == IsoTpRxAction::SendFC {
+                                            let data_tx = vec![0x30, cfg.block_size, cfg.st_min];
+                                            rx_memory.bs = cfg.block_size;
 
                                             rx_memory.frames_received = 0; // Reset the counter
 
                                             let (tx, rx) = mpsc::channel::<ChannelResult<()>>();
-                                            let f = CanFrame::new(
-                                                default_tx_addr,
-                                                &data_tx,
-                                                cfg.can_use_ext_addr,
-                                            );
+                                            let f = isotp_frame(&cfg, default_tx_addr, data_tx);
                                             let _ = can_msg_sender_isotp
                                                 .send(CanMessage::WriteFrames(0, vec![f], tx));
                                             let _ = rx.recv().unwrap();
@@ -524,7 +639,12 @@ impl SoftwareIsoTpChannel {
                                         // Flow control
                                         log::debug!("ISOTP Flow control {data:02X?}");
                                         println!("ISOTP Flow control {data:02X?}");
-                                        tx_memory.on_flow_control(data);
+                                        if !tx_memory.awaiting_fc {
+                                            log::debug!("ISOTP unexpected flow control, ignoring");
+                                        } else if let Err(e) = tx_memory.on_flow_control(data) {
+                                            log::error!("ISOTP transfer aborted: {e}");
+                                            tx_memory.reset();
+                                        }
                                     }
                                     _ => {
                                         log::error!("Invalid ISOTP CAN frame! {frame:?}");
@@ -542,31 +662,32 @@ impl SoftwareIsoTpChannel {
                 if bg_rx_receiver.is_some() {
                     if rx_memory.completed {
                         // Done!
+                        *last_rx_timestamp_c.lock().unwrap() = rx_memory.rx_timestamp_us;
                         let _ = bg_rx_receiver
                             .take()
                             .unwrap()
                             .send(Ok(rx_memory.data.clone()));
                         rx_memory.reset();
-                    } else if rx_memory.last_rx_time.elapsed().as_millis() >= rx_timeout as u128 {
+                    } else if Instant::now() >= rx_deadline {
                         let _ = bg_rx_receiver
                             .take()
                             .unwrap()
                             .send(Err(ChannelError::ReadTimeout));
-                        rx_memory.reset();
+                        // The reception is only abandoned after N_Cr,
+                        // the next read picks it up until then
+                        let n_cr = isotp_settings.unwrap_or_default().timeouts.n_cr;
+                        if rx_memory.last_rx_time.elapsed().as_millis() >= n_cr as u128 {
+                            rx_memory.reset();
+                        }
                     }
                 }
 
                 if tx_memory.transmitting {
-                    if let Some(action_res) =
-                        tx_memory.on_update(tx_timeout, isotp_settings.unwrap().pad_frame)
-                    {
+                    if let Some(action_res) = tx_memory.on_update() {
                         match action_res {
                             Ok(to_tx) => {
-                                let cf = CanFrame::new(
-                                    tx_memory.addr,
-                                    &to_tx,
-                                    isotp_settings.unwrap().can_use_ext_addr,
-                                );
+                                let cf =
+                                    isotp_frame(&isotp_settings.unwrap(), tx_memory.addr, to_tx);
                                 let (tx, rx) = mpsc::channel::<ChannelResult<()>>();
                                 let _ = can_msg_sender_isotp.send(CanMessage::WriteFrames(
                                     0,
@@ -586,6 +707,7 @@ impl SoftwareIsoTpChannel {
                                 }
                             }
                             Err(e) => {
+                                log::error!("ISOTP transfer aborted: {e}");
                                 if let Some(x) = bg_tx_receiver.take() {
                                     let _ = x.send(Err(e));
                                 }
@@ -599,7 +721,7 @@ impl SoftwareIsoTpChannel {
 
         // CAN channel dispatcher - Sends and receives CAN frames from raw interface
         std::thread::spawn(move || {
-            let mut can_cfg: Option<(u32, bool)> = None;
+            let mut can_cfg: Option<(u32, Option<u32>, bool)> = None;
             let mut can_queue: VecDeque<CanFrame> = VecDeque::new();
             let mut is_reading = false;
             let mut reading_length: usize = 0;
@@ -624,10 +746,10 @@ impl SoftwareIsoTpChannel {
                             }
                             let _ = resp_sender.send(res);
                         }
-                        CanMessage::Configure(baud, ext, resp_sender) => {
+                        CanMessage::Configure(baud, fd_baud, ext, resp_sender) => {
                             // If configurations are the same, then we can allow this
                             if let Some(current_cfg) = can_cfg {
-                                if current_cfg == (baud, ext) {
+                                if current_cfg == (baud, fd_baud, ext) {
                                     let _ = resp_sender.send(Ok(()));
                                     continue;
                                 }
@@ -635,9 +757,12 @@ impl SoftwareIsoTpChannel {
                             let _ = if can_open.load(Ordering::Relaxed) {
                                 resp_sender.send(Err(ChannelError::ConfigurationError))
                             } else {
-                                let res = channel.set_can_cfg(baud, ext);
+                                let res = match fd_baud {
+                                    Some(data_baud) => channel.set_can_fd_cfg(baud, data_baud, ext),
+                                    None => channel.set_can_cfg(baud, ext),
+                                };
                                 if res.is_ok() {
-                                    can_cfg = Some((baud, ext));
+                                    can_cfg = Some((baud, fd_baud, ext));
                                 }
                                 resp_sender.send(res)
                             };
@@ -672,6 +797,19 @@ impl SoftwareIsoTpChannel {
                             can_queue.clear();
                             let _ = resp_sender.send(Ok(())); // Don't clear Hardware buffer, since this is also in use for ISOTP
                         }
+                        CanMessage::AddFilter(ids, resp_sender) => {
+                            let _ = resp_sender.send(channel.add_filter(&ids));
+                        }
+                        CanMessage::RemoveFilter(id, resp_sender) => {
+                            let _ = resp_sender.send(channel.remove_filter(id));
+                        }
+                        CanMessage::GetBusStatus(resp_sender) => {
+                            let _ = resp_sender.send(channel.get_bus_status());
+                        }
+                        CanMessage::DetectBaud(candidates, listen_ms, resp_sender) => {
+                            let _ =
+                                resp_sender.send(channel.detect_can_baud(&candidates, listen_ms));
+                        }
                     }
                 }
                 if can_open.load(Ordering::Relaxed) {
@@ -714,6 +852,7 @@ impl SoftwareIsoTpChannel {
             running,
             can_msg_sender,
             isotp_msg_sender,
+            last_rx_timestamp,
         }
     }
 }
@@ -786,6 +925,9 @@ impl PayloadChannel for SoftwareIsoTpChannel {
             .map_err(|e| ChannelError::Other(e.to_string()))?;
         rx.recv().unwrap()
     }
+    fn last_rx_timestamp_us(&self) -> Option<u64> {
+        *self.last_rx_timestamp.lock().unwrap()
+    }
 }
 
 impl IsoTPChannel for SoftwareIsoTpChannel {
@@ -844,11 +986,63 @@ impl PacketChannel<CanFrame> for SoftwareIsoTpChannel {
     }
 }
 
+impl FilterPacketChannel<CanFrame> for SoftwareIsoTpChannel {
+    fn add_filter(&mut self, allowed_ids: &[u32]) -> ChannelResult<u32> {
+        let (tx, rx) = mpsc::channel::<ChannelResult<u32>>();
+        self.can_msg_sender
+            .send(CanMessage::AddFilter(allowed_ids.to_vec(), tx))
+            .map_err(|e| ChannelError::Other(e.to_string()))?;
+        rx.recv().unwrap()
+    }
+
+    fn remove_filter(&mut self, filter_id: u32) -> ChannelResult<()> {
+        let (tx, rx) = mpsc::channel::<ChannelResult<()>>();
+        self.can_msg_sender
+            .send(CanMessage::RemoveFilter(filter_id, tx))
+            .map_err(|e| ChannelError::Other(e.to_string()))?;
+        rx.recv().unwrap()
+    }
+}
+
 impl CanChannel for SoftwareIsoTpChannel {
     fn set_can_cfg(&mut self, baud: u32, use_extended: bool) -> ChannelResult<()> {
         let (tx, rx) = mpsc::channel::<ChannelResult<()>>();
         self.can_msg_sender
-            .send(CanMessage::Configure(baud, use_extended, tx))
+            .send(CanMessage::Configure(baud, None, use_extended, tx))
+            .map_err(|e| ChannelError::Other(e.to_string()))?;
+        rx.recv().unwrap()
+    }
+
+    fn set_can_fd_cfg(
+        &mut self,
+        baud: u32,
+        data_baud: u32,
+        use_extended: bool,
+    ) -> ChannelResult<()> {
+        let (tx, rx) = mpsc::channel::<ChannelResult<()>>();
+        self.can_msg_sender
+            .send(CanMessage::Configure(
+                baud,
+                Some(data_baud),
+                use_extended,
+                tx,
+            ))
+            .map_err(|e| ChannelError::Other(e.to_string()))?;
+        rx.recv().unwrap()
+    }
+
+    fn get_bus_status(&mut self) -> ChannelResult<CanBusStatus> {
+        let (tx, rx) = mpsc::channel::<ChannelResult<CanBusStatus>>();
+        self.can_msg_sender
+            .send(CanMessage::GetBusStatus(tx))
+            .map_err(|e| ChannelError::Other(e.to_string()))?;
+        rx.recv().unwrap()
+    }
+
+    fn detect_can_baud(&mut self, candidates: &[u32], listen_ms: u32) -> ChannelResult<u32> {
+        let (tx, rx) = mpsc::channel::<ChannelResult<u32>>();
+        self.can_msg_sender
+            .send(CanMessage::DetectBaud(candidates.to_vec(), listen_ms, tx))
             .map_err(|e| ChannelError::Other(e.to_string()))?;
         rx.recv().unwrap()
     }
@@ -0,0 +1,732 @@
+//! CAN trace log formats
+//!
+//! Reads and writes the common text formats CAN bus traffic is logged in:
+//! * candump log files (`candump -l`), EG `(1436509052.249713) can0 7E8#0662F190`
+//! * PCAN-View `.trc` trace files, file versions 1.x and 2.x
+//! * Vector `.asc` ASCII logs
+//!
+//! Remote frames, error frames and events are skipped when reading.
+//! [TraceWriter] writes traces that can be opened by the same tools.
+
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::core::channel::{can_fd_len_to_dlc, host_timestamp_us, CanFrame, Packet};
+
+use super::{HardwareError, HardwareResult};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+/// Text format of a CAN trace
+pub enum TraceFormat {
+    /// candump log file (`candump -l`), usually `.log`
+    Candump,
+    /// PCAN-View trace file, `.trc`
+    PcanTrc,
+    /// Vector ASCII log, `.asc`
+    VectorAsc,
+}
+
+impl TraceFormat {
+    /// Picks the format from the extension of a trace file (`.log`, `.trc` or `.asc`)
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let ext = path.extension()?.to_str()?.to_ascii_lowercase();
+        match ext.as_str() {
+            "log" | "candump" => Some(Self::Candump),
+            "trc" => Some(Self::PcanTrc),
+            "asc" => Some(Self::VectorAsc),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+/// Direction of a frame, as seen by the adapter that recorded the trace
+pub enum TraceDirection {
+    /// Received from the bus
+    Rx,
+    /// Sent by the adapter
+    Tx,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+/// A CAN frame in a trace
+pub struct TraceRecord {
+    /// Timestamp in microseconds. Where it starts from depends on the format
+    pub timestamp_us: u64,
+    /// Direction of the frame
+    pub direction: TraceDirection,
+    /// The frame
+    pub frame: CanFrame,
+}
+
+/// Reads a trace file, the format is picked from the file extension
+pub fn read_trace_file(path: &Path) -> HardwareResult<Vec<TraceRecord>> {
+    let format = TraceFormat::from_path(path).ok_or_else(|| {
+        HardwareError::TraceFileError(format!("Unknown trace format: {}", path.display()))
+    })?;
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| HardwareError::TraceFileError(format!("{}: {e}", path.display())))?;
+    parse_trace(&text, format)
+}
+
+/// Parses the text of a trace
+pub fn parse_trace(text: &str, format: TraceFormat) -> HardwareResult<Vec<TraceRecord>> {
+    let mut parser = TraceParser::default();
+    let mut records = vec![];
+    for (idx, line) in text.lines().enumerate() {
+        let res = match format {
+            TraceFormat::Candump => parser.parse_candump(line),
+            TraceFormat::PcanTrc => parser.parse_trc(line),
+            TraceFormat::VectorAsc => parser.parse_asc(line),
+        };
+        match res {
+            Ok(Some(record)) => records.push(record),
+            Ok(None) => {}
+            Err(e) => {
+                return Err(HardwareError::TraceFileError(format!(
+                    "Line {}: {e} ('{}')",
+                    idx + 1,
+                    line.trim()
+                )))
+            }
+        }
+    }
+    Ok(records)
+}
+
+/// Writes CAN frames to a trace file
+///
+/// Timestamps are taken from [host_timestamp_us], offsets in the trace are relative to when
+/// the writer was created. Every frame is flushed as it is written, so the trace is usable
+/// even if the program does not exit cleanly.
+pub struct TraceWriter {
+    out: Box<dyn Write + Send>,
+    format: TraceFormat,
+    /// [host_timestamp_us] when the writer was created
+    start_us: u64,
+    /// Unix time when the writer was created, in microseconds
+    start_unix_us: u64,
+    count: usize,
+    finished: bool,
+}
+
+impl TraceWriter {
+    /// Creates a trace file, the format is picked from the file extension
+    pub fn create(path: &Path) -> HardwareResult<Self> {
+        let format = TraceFormat::from_path(path).ok_or_else(|| {
+            HardwareError::TraceFileError(format!("Unknown trace format: {}", path.display()))
+        })?;
+        File::create(path)
+            .and_then(|f| Self::new(Box::new(BufWriter::new(f)), format))
+            .map_err(|e| HardwareError::TraceFileError(format!("{}: {e}", path.display())))
+    }
+
+    /// Creates a trace writer on any output, and writes the header of the trace
+    pub fn new(out: Box<dyn Write + Send>, format: TraceFormat) -> std::io::Result<Self> {
+        let mut writer = Self {
+            out,
+            format,
+            start_us: host_timestamp_us(),
+            start_unix_us: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_micros() as u64)
+                .unwrap_or_default(),
+            count: 0,
+            finished: false,
+        };
+        writer.write_header()?;
+        Ok(writer)
+    }
+
+    /// Returns the format of the trace
+    pub fn format(&self) -> TraceFormat {
+        self.format
+    }
+
+    /// Returns the number of frames written so far
+    pub fn frame_count(&self) -> usize {
+        self.count
+    }
+
+    fn write_header(&mut self) -> std::io::Result<()> {
+        let date = chrono::Local::now();
+        match self.format {
+            TraceFormat::Candump => {}
+            TraceFormat::PcanTrc => {
+                // Start time is an OLE automation date, days since 30/12/1899
+                let days = self.start_unix_us as f64 / 86_400_000_000.0 + 25_569.0;
+                writeln!(self.out, ";$FILEVERSION=2.1")?;
+                writeln!(self.out, ";$STARTTIME={days:.10}")?;
+                writeln!(self.out, ";$COLUMNS=N,O,T,B,I,d,R,L,D")?;
+                writeln!(self.out, ";")?;
+                writeln!(
+                    self.out,
+                    ";   Start time: {}",
+                    date.format("%d/%m/%Y %H:%M:%S%.3f")
+                )?;
+                writeln!(self.out, ";   Generated by ecu-diag")?;
+                writeln!(self.out, ";")?;
+            }
+            TraceFormat::VectorAsc => {
+                let date = date.format("%a %b %d %I:%M:%S%.3f %P %Y");
+                writeln!(self.out, "date {date}")?;
+                writeln!(self.out, "base hex  timestamps absolute")?;
+                writeln!(self.out, "internal events logged")?;
+                writeln!(self.out, "Begin Triggerblock {date}")?;
+                writeln!(self.out, "   0.000000 Start of measurement")?;
+            }
+        }
+        self.out.flush()
+    }
+
+    /// Writes a frame, timestamped now
+    pub fn write_frame(
+        &mut self,
+        direction: TraceDirection,
+        frame: &CanFrame,
+    ) -> std::io::Result<()> {
+        self.write_record(&TraceRecord {
+            timestamp_us: host_timestamp_us(),
+            direction,
+            frame: *frame,
+        })
+    }
+
+    /// Writes a frame. The timestamp of the record must be from [host_timestamp_us]
+    pub fn write_record(&mut self, record: &TraceRecord) -> std::io::Result<()> {
+        let offset_us = record.timestamp_us.saturating_sub(self.start_us);
+        let frame = &record.frame;
+        let data = frame.get_data();
+        let hex: Vec<String> = data.iter().map(|b| format!("{b:02X}")).collect();
+        let (dir, dir_flag) = match record.direction {
+            TraceDirection::Rx => ("Rx", "R"),
+            TraceDirection::Tx => ("Tx", "T"),
+        };
+        self.count += 1;
+        match self.format {
+            TraceFormat::Candump => {
+                let ts = self.start_unix_us + offset_us;
+                let id = if frame.is_extended() {
+                    format!("{:08X}", frame.get_address())
+                } else {
+                    format!("{:03X}", frame.get_address())
+                };
+                let sep = if frame.is_fd() {
+                    format!("##{}", u8::from(frame.is_brs()))
+                } else {
+                    "#".to_string()
+                };
+                writeln!(
+                    self.out,
+                    "({}.{:06}) can0 {id}{sep}{} {dir_flag}",
+                    ts / 1_000_000,
+                    ts % 1_000_000,
+                    hex.concat()
+                )?;
+            }
+            TraceFormat::PcanTrc => {
+                let ty = match (frame.is_fd(), frame.is_brs()) {
+                    (false, _) => "DT",
+                    (true, false) => "FD",
+                    (true, true) => "FB",
+                };
+                let id = if frame.is_extended() {
+                    format!("{:08X}", frame.get_address())
+                } else {
+                    format!("{:04X}", frame.get_address())
+                };
+                let dlc = if frame.is_fd() {
+                    can_fd_len_to_dlc(data.len())
+                } else {
+                    data.len() as u8
+                };
+                writeln!(
+                    self.out,
+                    "{:>7} {:>9}.{:03} {ty} 1 {id:>8} {dir} - {dlc:>2}    {}",
+                    self.count,
+                    offset_us / 1000,
+                    offset_us % 1000,
+                    hex.join(" ")
+                )?;
+            }
+            TraceFormat::VectorAsc => {
+                let ext = if frame.is_extended() { "x" } else { "" };
+                let id = format!("{:X}{ext}", frame.get_address());
+                let ts = format!("{:>4}.{:06}", offset_us / 1_000_000, offset_us % 1_000_000);
+                if frame.is_fd() {
+                    writeln!(
+                        self.out,
+                        "{ts} CANFD   1 {dir} {id:>9} {} 0 {:x} {:>2} {}",
+                        u8::from(frame.is_brs()),
+                        can_fd_len_to_dlc(data.len()),
+                        data.len(),
+                        hex.join(" ")
+                    )?;
+                } else {
+                    writeln!(
+                        self.out,
+                        "{ts} 1  {id:<15} {dir}   d {:x} {}",
+                        data.len(),
+                        hex.join(" ")
+                    )?;
+                }
+            }
+        }
+        self.out.flush()
+    }
+
+    /// Writes the end of the trace. Called when the writer is dropped
+    pub fn finish(&mut self) -> std::io::Result<()> {
+        if self.finished {
+            return Ok(());
+        }
+        self.finished = true;
+        if self.format == TraceFormat::VectorAsc {
+            writeln!(self.out, "End TriggerBlock")?;
+        }
+        self.out.flush()
+    }
+}
+
+impl Drop for TraceWriter {
+    fn drop(&mut self) {
+        let _ = self.finish();
+    }
+}
+
+/// State carried between the lines of a trace (From headers, or relative timestamps)
+#[derive(Debug, Default)]
+struct TraceParser {
+    /// `.trc` file version, 1.1 if not given
+    trc_version: Option<(u32, u32)>,
+    /// `.trc` 2.x column layout
+    trc_columns: Vec<char>,
+    /// `.asc` IDs are decimal
+    asc_decimal: bool,
+    /// `.asc` timestamps are relative to the previous event
+    asc_relative: bool,
+    last_timestamp_us: u64,
+}
+
+type LineResult = Result<Option<TraceRecord>, String>;
+
+impl TraceParser {
+    fn parse_candump(&mut self, line: &str) -> LineResult {
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        let Some(ts) = tokens.first() else {
+            return Ok(None);
+        };
+        let ts = ts
+            .strip_prefix('(')
+            .and_then(|t| t.strip_suffix(')'))
+            .ok_or("Missing timestamp")?;
+        let timestamp_us = parse_seconds(ts)?;
+        let frame = tokens.get(2).ok_or("Missing frame")?;
+        // Optional direction flag of `candump -x`
+        let direction = match tokens.get(3) {
+            Some(&"T") => TraceDirection::Tx,
+            _ => TraceDirection::Rx,
+        };
+        let (id, data) = frame.split_once('#').ok_or("Missing '#'")?;
+        let is_ext = id.len() > 3;
+        let id = parse_hex_id(id)?;
+        let frame = if let Some(fd) = data.strip_prefix('#') {
+            // `##<flags><data>`, flags bit 0 is BRS
+            let flags = fd.get(0..1).ok_or("Missing CAN FD flags")?;
+            let flags = u8::from_str_radix(flags, 16).map_err(|e| e.to_string())?;
+            CanFrame::new_fd(id, &parse_hex_string(&fd[1..])?, is_ext, flags & 0x01 != 0)
+        } else if data.starts_with('R') {
+            return Ok(None);
+        } else {
+            CanFrame::new(id, &parse_hex_string(data)?, is_ext)
+        };
+        Ok(Some(TraceRecord {
+            timestamp_us,
+            direction,
+            frame,
+        }))
+    }
+
+    fn parse_trc(&mut self, line: &str) -> LineResult {
+        let line = line.trim();
+        if let Some(header) = line.strip_prefix(';') {
+            if let Some(version) = header.strip_prefix("$FILEVERSION=") {
+                let (major, minor) = version.trim().split_once('.').unwrap_or((version, "0"));
+                self.trc_version = Some((
+                    major.parse().map_err(|_| "Invalid file version")?,
+                    minor.parse().map_err(|_| "Invalid file version")?,
+                ));
+            } else if let Some(columns) = header.strip_prefix("$COLUMNS=") {
+                self.trc_columns = columns
+                    .split(',')
+                    .filter_map(|c| c.trim().chars().next())
+                    .collect();
+            }
+            return Ok(None);
+        }
+        if line.is_empty() {
+            return Ok(None);
+        }
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        match self.trc_version.unwrap_or((1, 1)) {
+            (1, minor) => Self::parse_trc_v1(&tokens, minor),
+            _ => self.parse_trc_v2(&tokens),
+        }
+    }
+
+    /// `1)  1841.0  [Bus]  Rx  0300  [-]  8  00 00 00 00 04 00 00 00`
+    fn parse_trc_v1(tokens: &[&str], minor: u32) -> LineResult {
+        if !tokens.first().is_some_and(|t| t.ends_with(')')) {
+            return Ok(None);
+        }
+        let timestamp_us = parse_millis(tokens.get(1).ok_or("Missing time offset")?)?;
+        let mut rest = &tokens[2..];
+        let mut direction = TraceDirection::Rx;
+        // 1.0 has no type column, 1.2 and later have a bus column before it
+        if minor > 0 {
+            let type_idx = if minor >= 2 { 1 } else { 0 };
+            direction = match rest.get(type_idx) {
+                Some(&"Rx") => TraceDirection::Rx,
+                Some(&"Tx") => TraceDirection::Tx,
+                // Error frames, warnings and so on
+                _ => return Ok(None),
+            };
+            rest = &rest[type_idx + 1..];
+        }
+        let id = rest.first().ok_or("Missing ID")?;
+        rest = &rest[1..];
+        if rest.first() == Some(&"-") {
+            rest = &rest[1..];
+        }
+        let dlc = rest.first().ok_or("Missing DLC")?;
+        if dlc.starts_with("RTR") || rest.get(1) == Some(&"RTR") {
+            return Ok(None);
+        }
+        let dlc: usize = dlc.parse().map_err(|_| "Invalid DLC")?;
+        let data = parse_hex_bytes(rest.get(1..dlc + 1).ok_or("Missing data")?)?;
+        Ok(Some(TraceRecord {
+            timestamp_us,
+            direction,
+            frame: CanFrame::new(parse_hex_id(id)?, &data, id.len() > 4),
+        }))
+    }
+
+    /// Columns are given by the `$COLUMNS` header, EG `N,O,T,B,I,d,R,L,D`:
+    /// `1  1059.900 DT 1 0300 Rx - 7  00 00 00 00 04 00 00`
+    fn parse_trc_v2(&self, tokens: &[&str]) -> LineResult {
+        let columns: &[char] = if self.trc_columns.is_empty() {
+            &['N', 'O', 'T', 'I', 'd', 'l', 'D']
+        } else {
+            &self.trc_columns
+        };
+        let mut timestamp_us = 0;
+        let mut direction = TraceDirection::Rx;
+        let mut id = None;
+        let mut len = None;
+        let mut fd = false;
+        let mut brs = false;
+        for (idx, column) in columns.iter().enumerate() {
+            let token = tokens.get(idx);
+            match (column, token) {
+                ('O', Some(t)) => timestamp_us = parse_millis(t)?,
+                ('T', Some(t)) => match *t {
+                    "DT" => {}
+                    "FD" | "FE" => fd = true,
+                    "FB" | "BI" => {
+                        fd = true;
+                        brs = true;
+                    }
+                    // Remote frames, errors, events and status changes
+                    _ => return Ok(None),
+                },
+                ('I', Some(t)) => id = Some(*t),
+                ('d', Some(&"Tx")) => direction = TraceDirection::Tx,
+                ('l', Some(t)) => len = Some(t.parse::<usize>().map_err(|_| "Invalid length")?),
+                ('L', Some(t)) => {
+                    let dlc: u8 = t.parse().map_err(|_| "Invalid DLC")?;
+                    len = Some(crate::core::channel::can_fd_dlc_to_len(dlc));
+                }
+                ('D', _) => {
+                    let id = id.ok_or("Missing ID")?;
+                    let len = len.ok_or("Missing length")?;
+                    let data = parse_hex_bytes(tokens.get(idx..idx + len).ok_or("Missing data")?)?;
+                    let is_ext = id.len() > 4;
+                    let id = parse_hex_id(id)?;
+                    let frame = if fd {
+                        CanFrame::new_fd(id, &data, is_ext, brs)
+                    } else {
+                        CanFrame::new(id, &data, is_ext)
+                    };
+                    return Ok(Some(TraceRecord {
+                        timestamp_us,
+                        direction,
+                        frame,
+                    }));
+                }
+                (_, Some(_)) => {}
+                (_, None) => return Err("Missing column".into()),
+            }
+        }
+        Err("Missing data column".into())
+    }
+
+    fn parse_asc(&mut self, line: &str) -> LineResult {
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        match tokens.first() {
+            Some(&"base") => {
+                self.asc_decimal = tokens.get(1) == Some(&"dec");
+                self.asc_relative = tokens.get(3) == Some(&"relative");
+                return Ok(None);
+            }
+            Some(t) if t.parse::<f64>().is_ok() => {}
+            _ => return Ok(None),
+        }
+        let mut timestamp_us = parse_seconds(tokens[0])?;
+        if self.asc_relative {
+            timestamp_us += self.last_timestamp_us;
+        }
+        self.last_timestamp_us = timestamp_us;
+
+        if tokens.get(1) == Some(&"CANFD") {
+            // time CANFD ch dir id [name] brs esi dlc len data...
+            let direction = parse_asc_direction(tokens.get(3))?;
+            let Some(direction) = direction else {
+                return Ok(None);
+            };
+            let (id, is_ext) = self.parse_asc_id(tokens.get(4).ok_or("Missing ID")?)?;
+            let mut rest = &tokens[5..];
+            if !rest.first().is_some_and(|t| *t == "0" || *t == "1") {
+                // Symbolic name of the frame
+                rest = rest.get(1..).unwrap_or_default();
+            }
+            let brs = rest.first() == Some(&"1");
+            let len: usize = rest
+                .get(3)
+                .ok_or("Missing length")?
+                .parse()
+                .map_err(|_| "Invalid length")?;
+            let data = parse_hex_bytes(rest.get(4..4 + len).ok_or("Missing data")?)?;
+            return Ok(Some(TraceRecord {
+                timestamp_us,
+                direction,
+                frame: CanFrame::new_fd(id, &data, is_ext, brs),
+            }));
+        }
+
+        // time ch id dir d dlc data...
+        if tokens.get(1).and_then(|t| t.parse::<u8>().ok()).is_none() {
+            return Ok(None);
+        }
+        let Some(direction) = parse_asc_direction(tokens.get(3))? else {
+            // Error frames, statistics and so on
+            return Ok(None);
+        };
+        if tokens.get(4) != Some(&"d") {
+            // Remote frame
+            return Ok(None);
+        }
+        let (id, is_ext) = self.parse_asc_id(tokens[2])?;
+        let dlc = usize::from_str_radix(tokens.get(5).ok_or("Missing DLC")?, 16)
+            .map_err(|_| "Invalid DLC")?;
+        let data = parse_hex_bytes(tokens.get(6..6 + dlc).ok_or("Missing data")?)?;
+        Ok(Some(TraceRecord {
+            timestamp_us,
+            direction,
+            frame: CanFrame::new(id, &data, is_ext),
+        }))
+    }
+
+    /// IDs with an `x` suffix are extended
+    fn parse_asc_id(&self, id: &str) -> Result<(u32, bool), String> {
+        let (id, is_ext) = match id.strip_suffix('x') {
+            Some(id) => (id, true),
+            None => (id, false),
+        };
+        let id = if self.asc_decimal {
+            id.parse().map_err(|_| format!("Invalid ID {id}"))?
+        } else {
+            parse_hex_id(id)?
+        };
+        Ok((id, is_ext))
+    }
+}
+
+fn parse_asc_direction(token: Option<&&str>) -> Result<Option<TraceDirection>, String> {
+    Ok(match token {
+        Some(&"Rx") => Some(TraceDirection::Rx),
+        Some(&"Tx") => Some(TraceDirection::Tx),
+        _ => None,
+    })
+}
+
+/// Parses `seconds.fraction` into microseconds
+fn parse_seconds(ts: &str) -> Result<u64, String> {
+    let (secs, frac) = ts.split_once('.').unwrap_or((ts, "0"));
+    let secs: u64 = secs
+        .parse()
+        .map_err(|_| format!("Invalid timestamp {ts}"))?;
+    // Pad or cut the fraction to 6 digits
+    let frac: String = frac.chars().chain(std::iter::repeat('0')).take(6).collect();
+    let micros: u64 = frac
+        .parse()
+        .map_err(|_| format!("Invalid timestamp {ts}"))?;
+    Ok(secs * 1_000_000 + micros)
+}
+
+/// Parses `milliseconds.fraction` into microseconds
+fn parse_millis(ts: &str) -> Result<u64, String> {
+    let (millis, frac) = ts.split_once('.').unwrap_or((ts, "0"));
+    let millis: u64 = millis
+        .parse()
+        .map_err(|_| format!("Invalid time offset {ts}"))?;
+    let frac: String = frac.chars().chain(std::iter::repeat('0')).take(3).collect();
+    let micros: u64 = frac
+        .parse()
+        .map_err(|_| format!("Invalid time offset {ts}"))?;
+    Ok(millis * 1000 + micros)
+}
+
+fn parse_hex_id(id: &str) -> Result<u32, String> {
+    u32::from_str_radix(id, 16).map_err(|_| format!("Invalid ID {id}"))
+}
+
+/// Parses bytes written without separators, EG `DEADBEEF`
+fn parse_hex_string(data: &str) -> Result<Vec<u8>, String> {
+    if !data.is_ascii() || data.len() % 2 == 1 {
+        return Err(format!("Invalid data {data}"));
+    }
+    (0..data.len())
+        .step_by(2)
+        .map(|i| {
+            u8::from_str_radix(&data[i..i + 2], 16).map_err(|_| format!("Invalid data {data}"))
+        })
+        .collect()
+}
+
+/// Parses bytes written as separate tokens, EG `DE AD BE EF`
+fn parse_hex_bytes(tokens: &[&str]) -> Result<Vec<u8>, String> {
+    tokens
+        .iter()
+        .map(|b| u8::from_str_radix(b, 16).map_err(|_| format!("Invalid data byte {b}")))
+        .collect()
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::{parse_trace, read_trace_file, TraceDirection, TraceFormat, TraceWriter};
+    use crate::core::channel::{CanFrame, Packet};
+
+    #[test]
+    pub fn test_parse_candump() {
+        let log = "(1436509052.249713) can0 7E0#0322F19000000000\n\
+                   (1436509052.250001) can0 18DAF110#1014621234 T\n\
+                   (1436509052.251) can0 7E8##10211223334\n\
+                   (1436509052.252000) can0 123#R\n";
+        let records = parse_trace(log, TraceFormat::Candump).unwrap();
+        assert_eq!(records.len(), 3);
+        assert_eq!(records[0].timestamp_us, 1436509052249713);
+        assert_eq!(
+            records[0].frame,
+            CanFrame::new(0x7E0, &[0x03, 0x22, 0xF1, 0x90, 0, 0, 0, 0], false)
+        );
+        assert_eq!(records[1].direction, TraceDirection::Tx);
+        assert!(records[1].frame.is_extended());
+        assert_eq!(records[1].frame.get_address(), 0x18DAF110);
+        assert_eq!(records[2].timestamp_us, 1436509052251000);
+        assert!(records[2].frame.is_fd() && records[2].frame.is_brs());
+        assert_eq!(records[2].frame.get_data(), &[0x02, 0x11, 0x22, 0x33, 0x34]);
+        assert!(parse_trace("(1.0) can0 7E0#ABC", TraceFormat::Candump).is_err());
+    }
+
+    #[test]
+    pub fn test_parse_trc() {
+        let v1 = ";$FILEVERSION=1.1\n\
+                  ;   Message Number\n\
+                  \x20    1)      1841.0  Rx         0300  8  00 00 00 00 04 00 00 00\n\
+                  \x20    2)      1842.5  Tx     18DAF110  2  3E 00\n\
+                  \x20    3)      1843.0  Error      0000  4  00 00 00 00\n";
+        let records = parse_trace(v1, TraceFormat::PcanTrc).unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].timestamp_us, 1_841_000);
+        assert_eq!(records[0].frame.get_address(), 0x300);
+        assert_eq!(records[1].direction, TraceDirection::Tx);
+        assert!(records[1].frame.is_extended());
+        assert_eq!(records[1].frame.get_data(), &[0x3E, 0x00]);
+
+        let v2 = ";$FILEVERSION=2.1\n\
+                  ;$COLUMNS=N,O,T,B,I,d,R,L,D\n\
+                  \x20     1      1059.900 DT 1      0300 Rx -  7    00 00 00 00 04 00 00\n\
+                  \x20     2      1060.125 FB 1      07E8 Rx -  9    10 11 22 33 44 55 66 77 88 99 AA BB\n\
+                  \x20     3      1061.000 ST 1           Rx -  4    00 00 00 08\n";
+        let records = parse_trace(v2, TraceFormat::PcanTrc).unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].timestamp_us, 1_059_900);
+        assert_eq!(records[0].frame.get_data().len(), 7);
+        assert!(records[1].frame.is_fd() && records[1].frame.is_brs());
+        assert_eq!(records[1].frame.get_data().len(), 12);
+    }
+
+    #[test]
+    pub fn test_parse_asc() {
+        let asc = "date Wed Jun 6 10:20:30 am 2018\n\
+                   base hex  timestamps absolute\n\
+                   Begin Triggerblock Wed Jun 6 10:20:30 am 2018\n\
+                   \x20  0.000000 Start of measurement\n\
+                   \x20  0.015991 1  7E0             Tx   d 8 02 3E 00 00 00 00 00 00\n\
+                   \x20  0.020000 1  18DAF110x       Rx   d 2 7E 00\n\
+                   \x20  0.030000 1  123             Rx   r\n\
+                   \x20  0.040000 1  ErrorFrame\n\
+                   \x20  0.050000 CANFD   1 Rx        7E8  Resp  1 0 9 12 10 11 22 33 44 55 66 77 88 99 AA BB\n\
+                   End TriggerBlock\n";
+        let records = parse_trace(asc, TraceFormat::VectorAsc).unwrap();
+        assert_eq!(records.len(), 3);
+        assert_eq!(records[0].direction, TraceDirection::Tx);
+        assert_eq!(records[0].timestamp_us, 15_991);
+        assert_eq!(records[1].frame.get_address(), 0x18DAF110);
+        assert!(records[1].frame.is_extended());
+        assert_eq!(records[2].timestamp_us, 50_000);
+        assert!(records[2].frame.is_fd() && records[2].frame.is_brs());
+        assert_eq!(records[2].frame.get_data().len(), 12);
+    }
+
+    #[test]
+    pub fn test_write_read_back() {
+        let frames = [
+            (
+                TraceDirection::Tx,
+                CanFrame::new(0x7E0, &[0x02, 0x3E, 0x00], false),
+            ),
+            (
+                TraceDirection::Rx,
+                CanFrame::new(0x18DAF110, &[0x02, 0x7E, 0x00, 0xAA], true),
+            ),
+            (
+                TraceDirection::Rx,
+                CanFrame::new_fd(0x7E8, &[0x11; 12], false, true),
+            ),
+            (TraceDirection::Tx, CanFrame::new(0x123, &[], false)),
+        ];
+        for ext in ["log", "trc", "asc"] {
+            let path =
+                std::env::temp_dir().join(format!("ecu-diag-trace-{}.{ext}", std::process::id()));
+            let mut writer = TraceWriter::create(&path).unwrap();
+            for (direction, frame) in &frames {
+                writer.write_frame(*direction, frame).unwrap();
+            }
+            assert_eq!(writer.frame_count(), frames.len());
+            drop(writer);
+
+            let records = read_trace_file(&path).unwrap();
+            std::fs::remove_file(&path).unwrap();
+            let read: Vec<_> = records.iter().map(|r| (r.direction, r.frame)).collect();
+            assert_eq!(read, frames, "{ext}");
+            assert!(records
+                .windows(2)
+                .all(|w| w[0].timestamp_us <= w[1].timestamp_us));
+        }
+    }
+}
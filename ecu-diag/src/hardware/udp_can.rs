@@ -0,0 +1,649 @@
+//! CAN over UDP
+//!
+//! [UdpCanChannel] carries CAN frames in UDP datagrams, using the framing of
+//! [cannelloni](https://github.com/mguentner/cannelloni). [UdpCanGateway] bridges a local
+//! CAN channel (PCAN, SocketCAN, ...) to UDP, so a tester on one machine can run a diagnostic
+//! session against a vehicle attached to another. Either end also works with cannelloni itself.
+//!
+//! Each datagram has a 5 byte header, followed by the frames (all values big endian):
+//!
+//! | Field | Size | |
+//! |---|---|---|
+//! | Version | 1 | Always 2 |
+//! | Op code | 1 | 0 (Data) |
+//! | Sequence number | 1 | Incremented for every datagram |
+//! | Frame count | 2 | |
+//!
+//! | Frame field | Size | |
+//! |---|---|---|
+//! | CAN ID | 4 | Linux `can_id`, bit 31 is set for extended IDs |
+//! | Length | 1 | Bit 7 is set for CAN FD frames |
+//! | FD flags | 1 | CAN FD frames only, bit 0 is bit rate switch |
+//! | Data | Length | |
+//!
+//! Remote and error frames received are dropped.
+
+use std::{
+    collections::{BTreeMap, VecDeque},
+    io::ErrorKind,
+    net::{SocketAddr, UdpSocket},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use crate::core::channel::{
+    host_timestamp_us, CanChannel, CanFrame, ChannelError, ChannelResult, FilterPacketChannel,
+    IsoTPChannel, Packet, PacketChannel,
+};
+
+use super::{
+    software_isotp::SoftwareIsoTpChannel, Hardware, HardwareCapabilities, HardwareError,
+    HardwareInfo, HardwareResult, IsoTpChannelType,
+};
+
+/// UDP port cannelloni uses by default
+pub const DEFAULT_UDP_PORT: u16 = 20000;
+
+const CANNELLONI_VERSION: u8 = 2;
+const OP_DATA: u8 = 0;
+const HEADER_LEN: usize = 5;
+
+/// Largest datagram sent, so it fits an Ethernet frame without IP fragmentation
+const MAX_DATAGRAM_LEN: usize = 1472;
+
+/// Flags of the Linux `can_id`
+const CAN_EFF_FLAG: u32 = 0x8000_0000;
+const CAN_RTR_FLAG: u32 = 0x4000_0000;
+const CAN_ERR_FLAG: u32 = 0x2000_0000;
+const CAN_EFF_MASK: u32 = 0x1FFF_FFFF;
+const CAN_SFF_MASK: u32 = 0x7FF;
+
+/// Set in the length byte of CAN FD frames
+const CANFD_FRAME: u8 = 0x80;
+/// Bit rate switch, in the flags byte of CAN FD frames
+const CANFD_BRS: u8 = 0x01;
+
+/// How long [UdpCanGateway::run] waits for datagrams before polling the CAN channel again
+const GATEWAY_POLL_MS: u32 = 1;
+
+/// Frames forwarded at most per direction and poll
+const GATEWAY_BATCH: usize = 64;
+
+fn encode_frame(frame: &CanFrame, buf: &mut Vec<u8>) {
+    let mut id = frame.get_address();
+    if frame.is_extended() {
+        id |= CAN_EFF_FLAG;
+    }
+    let data = frame.get_data();
+    buf.extend_from_slice(&id.to_be_bytes());
+    if frame.is_fd() {
+        buf.push(data.len() as u8 | CANFD_FRAME);
+        buf.push(if frame.is_brs() { CANFD_BRS } else { 0 });
+    } else {
+        buf.push(data.len() as u8);
+    }
+    buf.extend_from_slice(data);
+}
+
+/// Encodes frames into as many datagrams as needed, numbering them from `seq`
+fn encode_datagrams(seq: &mut u8, frames: &[CanFrame]) -> Vec<Vec<u8>> {
+    let mut datagrams = vec![];
+    let mut frames = frames.iter().peekable();
+    while frames.peek().is_some() {
+        let mut buf = vec![CANNELLONI_VERSION, OP_DATA, *seq, 0, 0];
+        let mut count: u16 = 0;
+        let mut frame_buf = vec![];
+        while let Some(frame) = frames.peek() {
+            frame_buf.clear();
+            encode_frame(frame, &mut frame_buf);
+            if count != 0 && buf.len() + frame_buf.len() > MAX_DATAGRAM_LEN {
+                break;
+            }
+            buf.extend_from_slice(&frame_buf);
+            count += 1;
+            frames.next();
+        }
+        buf[3..HEADER_LEN].copy_from_slice(&count.to_be_bytes());
+        datagrams.push(buf);
+        *seq = seq.wrapping_add(1);
+    }
+    datagrams
+}
+
+/// Decodes a datagram into its sequence number and frames.
+/// Returns None if it is not a valid data datagram
+fn decode_datagram(buf: &[u8]) -> Option<(u8, Vec<CanFrame>)> {
+    let header = buf.get(..HEADER_LEN)?;
+    if header[0] != CANNELLONI_VERSION || header[1] != OP_DATA {
+        return None;
+    }
+    let count = u16::from_be_bytes([header[3], header[4]]);
+    let mut pos = HEADER_LEN;
+    let mut frames = vec![];
+    for _ in 0..count {
+        let id = u32::from_be_bytes(buf.get(pos..pos + 4)?.try_into().ok()?);
+        let len_byte = *buf.get(pos + 4)?;
+        pos += 5;
+        let is_fd = len_byte & CANFD_FRAME != 0;
+        let len = (len_byte & !CANFD_FRAME) as usize;
+        let brs = if is_fd {
+            pos += 1;
+            buf.get(pos - 1)? & CANFD_BRS != 0
+        } else {
+            false
+        };
+        let max_len = if is_fd { 64 } else { 8 };
+        if len > max_len {
+            return None;
+        }
+        // Remote frames carry no data
+        if id & CAN_RTR_FLAG != 0 {
+            continue;
+        }
+        let data = buf.get(pos..pos + len)?;
+        pos += len;
+        if id & CAN_ERR_FLAG != 0 {
+            continue;
+        }
+        let is_ext = id & CAN_EFF_FLAG != 0;
+        let id = id & if is_ext { CAN_EFF_MASK } else { CAN_SFF_MASK };
+        frames.push(if is_fd {
+            CanFrame::new_fd(id, data, is_ext, brs)
+        } else {
+            CanFrame::new(id, data, is_ext)
+        });
+    }
+    Some((header[2], frames))
+}
+
+fn io_error(e: std::io::Error) -> ChannelError {
+    ChannelError::IOError(Arc::new(e))
+}
+
+#[derive(Clone, Debug)]
+/// CAN bus reached over UDP, through a [UdpCanGateway] or cannelloni
+pub struct UdpCanDevice {
+    info: HardwareInfo,
+    local: SocketAddr,
+    remote: Option<SocketAddr>,
+    can_channel: Arc<AtomicBool>,
+    has_isotp_channel: Arc<AtomicBool>,
+}
+
+impl UdpCanDevice {
+    /// Creates a device that receives datagrams on `local` and sends them to `remote`.
+    ///
+    /// If `remote` is None, datagrams are sent to wherever the last one came from,
+    /// and frames written before anything was received are dropped.
+    /// The socket is bound when the CAN channel is opened
+    pub fn new(local: SocketAddr, remote: Option<SocketAddr>) -> Self {
+        let name = match remote {
+            Some(remote) => format!("udp://{remote}"),
+            None => format!("udp://{local}"),
+        };
+        Self {
+            info: udp_can_info(&name),
+            local,
+            remote,
+            can_channel: Arc::new(AtomicBool::new(false)),
+            has_isotp_channel: Arc::new(AtomicBool::new(false)),
+        }
+    }
+}
+
+impl Hardware for UdpCanDevice {
+    fn create_iso_tp_channel(
+        &mut self,
+        force_native: bool,
+    ) -> HardwareResult<Box<dyn IsoTPChannel>> {
+        if force_native {
+            return Err(HardwareError::ChannelNotSupported);
+        }
+        let can_channel = self.create_can_channel()?;
+        self.has_isotp_channel.store(true, Ordering::Relaxed);
+        Ok(Box::new(SoftwareIsoTpChannel::new(can_channel)))
+    }
+
+    fn create_native_iso_tp_channel(&mut self) -> HardwareResult<Box<dyn CanChannel>> {
+        self.has_isotp_channel.store(true, Ordering::Relaxed);
+        self.create_can_channel()
+    }
+
+    fn create_can_channel(&mut self) -> HardwareResult<Box<dyn CanChannel>> {
+        if self.can_channel.load(Ordering::Relaxed) {
+            // Already open
+            Err(HardwareError::ConflictingChannel)
+        } else {
+            self.can_channel.store(true, Ordering::Relaxed);
+            let mut channel = UdpCanChannel::new(self.local, self.remote);
+            channel.device_state = Some(self.can_channel.clone());
+            Ok(Box::new(channel))
+        }
+    }
+
+    fn is_iso_tp_channel_open(&self) -> bool {
+        self.has_isotp_channel.load(Ordering::Relaxed)
+    }
+
+    fn is_can_channel_open(&self) -> bool {
+        self.can_channel.load(Ordering::Relaxed)
+    }
+
+    fn read_battery_voltage(&mut self) -> Option<f32> {
+        None
+    }
+
+    fn read_ignition_voltage(&mut self) -> Option<f32> {
+        None
+    }
+
+    fn get_info(&self) -> &HardwareInfo {
+        &self.info
+    }
+
+    fn is_connected(&self) -> bool {
+        true
+    }
+}
+
+#[derive(Debug)]
+/// CAN channel carrying frames over UDP
+pub struct UdpCanChannel {
+    local: SocketAddr,
+    /// Fixed peer, None to answer the last sender
+    remote: Option<SocketAddr>,
+    peer: Option<SocketAddr>,
+    socket: Option<UdpSocket>,
+    rx_buf: Vec<u8>,
+    rx_queue: VecDeque<CanFrame>,
+    tx_seq: u8,
+    /// Sequence number expected next
+    rx_seq: Option<u8>,
+    filters: BTreeMap<u32, Vec<u32>>,
+    device_state: Option<Arc<AtomicBool>>,
+}
+
+impl UdpCanChannel {
+    /// Creates a channel that receives datagrams on `local` and sends them to `remote`.
+    /// See [UdpCanDevice::new]
+    pub fn new(local: SocketAddr, remote: Option<SocketAddr>) -> Self {
+        Self {
+            local,
+            remote,
+            peer: remote,
+            socket: None,
+            rx_buf: vec![],
+            rx_queue: VecDeque::new(),
+            tx_seq: 0,
+            rx_seq: None,
+            filters: BTreeMap::new(),
+            device_state: None,
+        }
+    }
+
+    /// Returns the address the socket is bound to, once the channel is open
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.socket.as_ref().and_then(|s| s.local_addr().ok())
+    }
+
+    /// Receives a datagram if one arrives within `wait`. Returns false if none did
+    fn fill(&mut self, wait: Duration) -> ChannelResult<bool> {
+        let socket = self.socket.as_ref().ok_or(ChannelError::InterfaceNotOpen)?;
+        if wait.is_zero() {
+            socket.set_nonblocking(true).map_err(io_error)?;
+        } else {
+            socket.set_nonblocking(false).map_err(io_error)?;
+            socket.set_read_timeout(Some(wait)).map_err(io_error)?;
+        }
+        let (len, from) = match socket.recv_from(&mut self.rx_buf) {
+            Ok(r) => r,
+            // ICMP port unreachable from an earlier send shows up as a receive error
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    ErrorKind::WouldBlock
+                        | ErrorKind::TimedOut
+                        | ErrorKind::ConnectionReset
+                        | ErrorKind::ConnectionRefused
+                ) =>
+            {
+                return Ok(false)
+            }
+            Err(e) => return Err(io_error(e)),
+        };
+        if self.remote.is_some_and(|r| r != from) {
+            log::debug!("UDP CAN: ignoring datagram from {from}");
+            return Ok(true);
+        }
+        let Some((seq, frames)) = decode_datagram(&self.rx_buf[..len]) else {
+            log::warn!("UDP CAN: invalid datagram from {from}");
+            return Ok(true);
+        };
+        if let Some(expected) = self.rx_seq.filter(|e| *e != seq) {
+            log::debug!(
+                "UDP CAN: {} datagram(s) lost from {from}",
+                seq.wrapping_sub(expected)
+            );
+        }
+        self.rx_seq = Some(seq.wrapping_add(1));
+        self.peer = Some(from);
+        let timestamp = host_timestamp_us();
+        for mut frame in frames {
+            frame.set_timestamp_us(timestamp);
+            self.rx_queue.push_back(frame);
+        }
+        Ok(true)
+    }
+
+    fn is_allowed(&self, id: u32) -> bool {
+        self.filters.is_empty() || self.filters.values().any(|ids| ids.contains(&id))
+    }
+}
+
+impl Drop for UdpCanChannel {
+    fn drop(&mut self) {
+        if let Some(state) = &self.device_state {
+            state.store(false, Ordering::Relaxed);
+        }
+    }
+}
+
+impl FilterPacketChannel<CanFrame> for UdpCanChannel {
+    fn add_filter(&mut self, allowed_ids: &[u32]) -> ChannelResult<u32> {
+        let id = self.filters.keys().last().map_or(0, |x| x + 1);
+        self.filters.insert(id, allowed_ids.to_vec());
+        Ok(id)
+    }
+
+    fn remove_filter(&mut self, filter_id: u32) -> ChannelResult<()> {
+        self.filters
+            .remove(&filter_id)
+            .map(|_| ())
+            .ok_or(ChannelError::ConfigurationError)
+    }
+}
+
+impl CanChannel for UdpCanChannel {
+    /// The bit rate is set where the bus is attached, so this does nothing
+    fn set_can_cfg(&mut self, _baud: u32, _use_extended: bool) -> ChannelResult<()> {
+        Ok(())
+    }
+
+    fn set_can_fd_cfg(
+        &mut self,
+        _baud: u32,
+        _data_baud: u32,
+        _use_extended: bool,
+    ) -> ChannelResult<()> {
+        Ok(())
+    }
+}
+
+impl PacketChannel<CanFrame> for UdpCanChannel {
+    fn open(&mut self) -> ChannelResult<()> {
+        if self.socket.is_some() {
+            return Ok(());
+        }
+        self.socket = Some(UdpSocket::bind(self.local).map_err(io_error)?);
+        self.rx_buf = vec![0; u16::MAX as usize];
+        self.rx_queue.clear();
+        self.rx_seq = None;
+        self.peer = self.remote;
+        log::debug!("UDP CAN: listening on {:?}", self.local_addr());
+        Ok(())
+    }
+
+    fn close(&mut self) -> ChannelResult<()> {
+        self.socket = None;
+        Ok(())
+    }
+
+    fn write_packets(&mut self, packets: Vec<CanFrame>, _timeout_ms: u32) -> ChannelResult<()> {
+        let socket = self.socket.as_ref().ok_or(ChannelError::InterfaceNotOpen)?;
+        let Some(peer) = self.peer else {
+            log::debug!("UDP CAN: no peer yet, dropping {} frame(s)", packets.len());
+            return Ok(());
+        };
+        for datagram in encode_datagrams(&mut self.tx_seq, &packets) {
+            socket.send_to(&datagram, peer).map_err(io_error)?;
+        }
+        Ok(())
+    }
+
+    fn read_packets(&mut self, max: usize, timeout_ms: u32) -> ChannelResult<Vec<CanFrame>> {
+        if self.socket.is_none() {
+            return Err(ChannelError::InterfaceNotOpen);
+        }
+        let deadline = Instant::now() + Duration::from_millis(timeout_ms as u64);
+        let mut read_packets = vec![];
+        loop {
+            while read_packets.len() < max {
+                match self.rx_queue.pop_front() {
+                    Some(f) if self.is_allowed(f.get_address()) => read_packets.push(f),
+                    Some(_) => {}
+                    None => break,
+                }
+            }
+            if !read_packets.is_empty() {
+                return Ok(read_packets);
+            }
+            let now = Instant::now();
+            if now >= deadline && timeout_ms != 0 {
+                return Err(ChannelError::BufferEmpty);
+            }
+            self.fill(deadline.saturating_duration_since(now))?;
+            if timeout_ms == 0 && self.rx_queue.is_empty() {
+                return Err(ChannelError::BufferEmpty);
+            }
+        }
+    }
+
+    fn clear_rx_buffer(&mut self) -> ChannelResult<()> {
+        if self.socket.is_some() {
+            // Drain what the socket has buffered
+            while self.fill(Duration::ZERO)? {}
+        }
+        self.rx_queue.clear();
+        Ok(())
+    }
+
+    fn clear_tx_buffer(&mut self) -> ChannelResult<()> {
+        Ok(())
+    }
+}
+
+/// Bridges a CAN channel to UDP.
+///
+/// Every frame received on the CAN channel is sent over UDP, and every frame received
+/// over UDP is written to the CAN channel
+pub struct UdpCanGateway {
+    can: Box<dyn CanChannel>,
+    udp: UdpCanChannel,
+}
+
+impl UdpCanGateway {
+    /// Opens `can` at `baud` and a UDP socket on `local`, exchanging frames with `remote`.
+    /// If `remote` is None, frames are sent to the tester that sent the last datagram
+    pub fn new(
+        mut can: Box<dyn CanChannel>,
+        baud: u32,
+        local: SocketAddr,
+        remote: Option<SocketAddr>,
+    ) -> ChannelResult<Self> {
+        let mut udp = UdpCanChannel::new(local, remote);
+        udp.open()?;
+        can.set_can_cfg(baud, false)?;
+        can.open()?;
+        Ok(Self { can, udp })
+    }
+
+    /// Returns the address the UDP socket is bound to
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.udp.local_addr()
+    }
+
+    /// Forwards frames until `stop` is set, or the UDP socket fails
+    pub fn run(&mut self, stop: &AtomicBool) -> ChannelResult<()> {
+        while !stop.load(Ordering::Relaxed) {
+            self.poll(GATEWAY_POLL_MS)?;
+        }
+        Ok(())
+    }
+
+    /// Forwards the frames pending in both directions, waiting up to `timeout_ms` for
+    /// datagrams. Returns the number of frames forwarded.
+    ///
+    /// Errors writing to the CAN channel are logged and the frames dropped,
+    /// like a frame lost on a busy bus
+    pub fn poll(&mut self, timeout_ms: u32) -> ChannelResult<usize> {
+        let mut forwarded = 0;
+        match self.can.read_packets(GATEWAY_BATCH, 0) {
+            Ok(frames) => {
+                forwarded += frames.len();
+                self.udp.write_packets(frames, 0)?;
+            }
+            Err(ChannelError::BufferEmpty | ChannelError::ReadTimeout) => {}
+            Err(e) => log::warn!("UDP CAN gateway: CAN read failed {e}"),
+        }
+        match self.udp.read_packets(GATEWAY_BATCH, timeout_ms) {
+            Ok(frames) => {
+                forwarded += frames.len();
+                if let Err(e) = self.can.write_packets(frames, 100) {
+                    log::warn!("UDP CAN gateway: CAN write failed {e}");
+                }
+            }
+            Err(ChannelError::BufferEmpty) => {}
+            Err(e) => return Err(e),
+        }
+        Ok(forwarded)
+    }
+}
+
+impl Drop for UdpCanGateway {
+    fn drop(&mut self) {
+        let _ = self.can.close();
+    }
+}
+
+fn udp_can_info(name: &str) -> HardwareInfo {
+    HardwareInfo {
+        name: name.to_string(),
+        vendor: Some("CAN over UDP".to_string()),
+        device_fw_version: None,
+        device_id: None,
+        api_version: None,
+        library_version: None,
+        library_location: None,
+        capabilities: HardwareCapabilities {
+            iso_tp: IsoTpChannelType::Emulated,
+            can: true,
+            kline: false,
+            kline_kwp: false,
+            sae_j1850: false,
+            sci: false,
+            ip: false,
+        },
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use std::{
+        net::SocketAddr,
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc,
+        },
+    };
+
+    use super::{decode_datagram, encode_datagrams, UdpCanDevice, UdpCanGateway};
+    use crate::{
+        core::channel::{CanFrame, Packet, PacketChannel},
+        hardware::{
+            isotp::IsoTpProtocol,
+            virtual_bus::{VirtualBus, VirtualBusDevice},
+            Hardware,
+        },
+    };
+
+    #[test]
+    pub fn test_udp_can_encoding() {
+        let std = CanFrame::new(0x7E0, &[0x02, 0x3E, 0x00], false);
+        let ext = CanFrame::new(0x18DAF110, &[], true);
+        let fd = CanFrame::new_fd(0x123, &[0xAA; 12], false, true);
+        let mut seq = 0xFF;
+        let datagrams = encode_datagrams(&mut seq, &[std, ext, fd]);
+        assert_eq!(seq, 0);
+        assert_eq!(datagrams.len(), 1);
+        assert_eq!(
+            &datagrams[0][..18],
+            &[
+                2, 0, 0xFF, 0, 3, // Header
+                0x00, 0x00, 0x07, 0xE0, 3, 0x02, 0x3E, 0x00, // Standard
+                0x98, 0xDA, 0xF1, 0x10, 0, // Extended
+            ]
+        );
+        assert_eq!(&datagrams[0][18..24], &[0x00, 0x00, 0x01, 0x23, 0x8C, 0x01]);
+        assert_eq!(
+            decode_datagram(&datagrams[0]),
+            Some((0xFF, vec![std, ext, fd]))
+        );
+        // Truncated
+        assert_eq!(decode_datagram(&datagrams[0][..20]), None);
+
+        // Split to fit the MTU
+        let frames = vec![CanFrame::new_fd(0x123, &[0x55; 64], false, false); 40];
+        let datagrams = encode_datagrams(&mut seq, &frames);
+        assert_eq!(datagrams.len(), 2);
+        assert!(datagrams.iter().all(|d| d.len() <= super::MAX_DATAGRAM_LEN));
+        let decoded: Vec<CanFrame> = datagrams
+            .iter()
+            .flat_map(|d| decode_datagram(d).unwrap().1)
+            .collect();
+        assert_eq!(decoded, frames);
+    }
+
+    #[test]
+    pub fn test_udp_can_gateway() {
+        let localhost = |port| SocketAddr::from(([127, 0, 0, 1], port));
+        let bus = VirtualBus::new();
+        let mut ecu = bus.attach();
+        ecu.open().unwrap();
+        let ecu_thread = std::thread::spawn(move || {
+            let req = ecu.read_packets(1, 5000).unwrap();
+            assert_eq!(&req[0].get_data()[..3], &[0x02, 0x3E, 0x00]);
+            ecu.write_packets(vec![CanFrame::new(0x7E8, &[0x02, 0x7E, 0x00], false)], 0)
+                .unwrap();
+        });
+
+        let mut bench = VirtualBusDevice::new(&bus, "Bench");
+        let mut gateway = UdpCanGateway::new(
+            bench.create_can_channel().unwrap(),
+            500_000,
+            localhost(0),
+            None,
+        )
+        .unwrap();
+        let gateway_addr = gateway.local_addr().unwrap();
+        let stop = Arc::new(AtomicBool::new(false));
+        let gateway_thread = {
+            let stop = stop.clone();
+            std::thread::spawn(move || gateway.run(&stop))
+        };
+
+        let device = UdpCanDevice::new(localhost(0), Some(gateway_addr));
+        let mut client = IsoTpProtocol::new_from_hardware(device).unwrap();
+        client.set_rx_id(0x7E8);
+        client.init();
+        assert_eq!(
+            client.send_receive_payload(0x7E0, &[0x3E, 0x00]),
+            [0x7E, 0x00]
+        );
+        ecu_thread.join().unwrap();
+        stop.store(true, Ordering::Relaxed);
+        gateway_thread.join().unwrap().unwrap();
+    }
+}
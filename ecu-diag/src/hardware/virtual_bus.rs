@@ -0,0 +1,520 @@
+//! In-process virtual CAN bus
+//!
+//! A [VirtualBus] is a broadcast bus that any number of [VirtualCanChannel] endpoints
+//! attach to. Every frame written by an endpoint is received by all other open endpoints,
+//! so ECU simulators, sniffers and several testers can share one bus without hardware.
+//!
+//! Frames are delivered after the configured latency. With arbitration enabled, frames
+//! pending on the bus at the same time are delivered in CAN arbitration order (Lowest ID
+//! first), rather than in the order they were written.
+//!
+//! [VirtualBusDevice] implements [Hardware] on top of the bus, for code that expects a device.
+
+use std::{
+    collections::{BTreeMap, VecDeque},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Condvar, Mutex, MutexGuard,
+    },
+    time::{Duration, Instant},
+};
+
+use crate::core::channel::{
+    host_timestamp_us, CanChannel, CanFrame, ChannelError, ChannelResult, FilterPacketChannel,
+    IsoTPChannel, Packet, PacketChannel,
+};
+
+use super::{
+    software_isotp::SoftwareIsoTpChannel, Hardware, HardwareCapabilities, HardwareError,
+    HardwareInfo, HardwareResult, IsoTpChannelType,
+};
+
+/// A frame written to the bus, not yet delivered
+#[derive(Debug)]
+struct PendingFrame {
+    frame: CanFrame,
+    /// Endpoint that sent the frame
+    sender: usize,
+    /// Order the frame was written in
+    seq: u64,
+    due: Instant,
+}
+
+#[derive(Debug, Default)]
+struct Endpoint {
+    open: bool,
+    rx_queue: VecDeque<CanFrame>,
+}
+
+#[derive(Debug, Default)]
+struct BusState {
+    endpoints: BTreeMap<usize, Endpoint>,
+    pending: Vec<PendingFrame>,
+    next_endpoint: usize,
+    next_seq: u64,
+    latency: Duration,
+    arbitration: bool,
+}
+
+impl BusState {
+    /// Delivers all frames that are due to the open endpoints
+    fn deliver_due(&mut self, now: Instant) {
+        let (mut due, pending): (Vec<_>, Vec<_>) =
+            self.pending.drain(..).partition(|p| p.due <= now);
+        self.pending = pending;
+        if self.arbitration {
+            due.sort_by_key(|p| (arbitration_key(&p.frame), p.seq));
+        } else {
+            due.sort_by_key(|p| p.seq);
+        }
+        for p in due {
+            // Frames are delivered lazily, timestamp them with when they were due
+            let late_us = now.saturating_duration_since(p.due).as_micros() as u64;
+            let mut frame = p.frame;
+            frame.set_timestamp_us(host_timestamp_us().saturating_sub(late_us));
+            for (_, endpoint) in self
+                .endpoints
+                .iter_mut()
+                .filter(|(id, e)| **id != p.sender && e.open)
+            {
+                endpoint.rx_queue.push_back(frame);
+            }
+        }
+    }
+
+    /// Returns when the next pending frame is due
+    fn next_due(&self) -> Option<Instant> {
+        self.pending.iter().map(|p| p.due).min()
+    }
+}
+
+/// Priority of a frame on the bus, lower wins arbitration.
+///
+/// The 11bit base ID is compared first. For the same base ID, a standard frame wins over an
+/// extended one, then the extended ID bits decide
+fn arbitration_key(frame: &CanFrame) -> (u32, bool, u32) {
+    let id = frame.get_address();
+    if frame.is_extended() {
+        (id >> 18, true, id)
+    } else {
+        (id, false, id)
+    }
+}
+
+#[derive(Debug, Default)]
+struct BusInner {
+    state: Mutex<BusState>,
+    /// Signalled when frames are written
+    written: Condvar,
+}
+
+#[derive(Clone, Debug, Default)]
+/// Virtual CAN bus. Clones refer to the same bus
+pub struct VirtualBus {
+    inner: Arc<BusInner>,
+}
+
+impl VirtualBus {
+    /// Creates a new bus, without latency or arbitration
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the time between a frame being written and it being received
+    pub fn set_latency(&self, latency: Duration) {
+        self.lock().latency = latency;
+    }
+
+    /// Enables delivering frames pending at the same time in CAN arbitration order
+    pub fn set_arbitration(&self, enabled: bool) {
+        self.lock().arbitration = enabled;
+    }
+
+    /// Attaches a new endpoint to the bus
+    pub fn attach(&self) -> VirtualCanChannel {
+        let mut state = self.lock();
+        let id = state.next_endpoint;
+        state.next_endpoint += 1;
+        state.endpoints.insert(id, Endpoint::default());
+        VirtualCanChannel {
+            bus: self.clone(),
+            id,
+            filters: BTreeMap::new(),
+            device_state: None,
+        }
+    }
+
+    /// Returns the number of endpoints attached to the bus
+    pub fn endpoint_count(&self) -> usize {
+        self.lock().endpoints.len()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, BusState> {
+        self.inner.state.lock().unwrap()
+    }
+}
+
+/// Endpoint on a [VirtualBus]
+///
+/// Frames are only received while the channel is open. Dropping the channel detaches it from the bus
+#[derive(Debug)]
+pub struct VirtualCanChannel {
+    bus: VirtualBus,
+    id: usize,
+    filters: BTreeMap<u32, Vec<u32>>,
+    /// Set when the channel belongs to a [VirtualBusDevice]
+    device_state: Option<Arc<AtomicBool>>,
+}
+
+impl VirtualCanChannel {
+    fn is_allowed(&self, id: u32) -> bool {
+        self.filters.is_empty() || self.filters.values().any(|ids| ids.contains(&id))
+    }
+
+    fn endpoint<'a>(&self, state: &'a mut BusState) -> &'a mut Endpoint {
+        state
+            .endpoints
+            .get_mut(&self.id)
+            .expect("Virtual CAN endpoint detached while in use")
+    }
+}
+
+impl Drop for VirtualCanChannel {
+    fn drop(&mut self) {
+        self.bus.lock().endpoints.remove(&self.id);
+        if let Some(state) = &self.device_state {
+            state.store(false, Ordering::Relaxed);
+        }
+    }
+}
+
+impl FilterPacketChannel<CanFrame> for VirtualCanChannel {
+    fn add_filter(&mut self, allowed_ids: &[u32]) -> ChannelResult<u32> {
+        let id = self.filters.keys().last().map_or(0, |x| x + 1);
+        self.filters.insert(id, allowed_ids.to_vec());
+        Ok(id)
+    }
+
+    fn remove_filter(&mut self, filter_id: u32) -> ChannelResult<()> {
+        self.filters
+            .remove(&filter_id)
+            .map(|_| ())
+            .ok_or(ChannelError::ConfigurationError)
+    }
+}
+
+impl CanChannel for VirtualCanChannel {
+    fn set_can_cfg(&mut self, _baud: u32, _use_extended: bool) -> ChannelResult<()> {
+        Ok(())
+    }
+
+    fn set_can_fd_cfg(
+        &mut self,
+        _baud: u32,
+        _data_baud: u32,
+        _use_extended: bool,
+    ) -> ChannelResult<()> {
+        Ok(())
+    }
+}
+
+impl PacketChannel<CanFrame> for VirtualCanChannel {
+    fn open(&mut self) -> ChannelResult<()> {
+        let mut state = self.bus.lock();
+        self.endpoint(&mut state).open = true;
+        Ok(())
+    }
+
+    fn close(&mut self) -> ChannelResult<()> {
+        let mut state = self.bus.lock();
+        let endpoint = self.endpoint(&mut state);
+        endpoint.open = false;
+        endpoint.rx_queue.clear();
+        Ok(())
+    }
+
+    fn write_packets(&mut self, packets: Vec<CanFrame>, _timeout_ms: u32) -> ChannelResult<()> {
+        let mut state = self.bus.lock();
+        if !self.endpoint(&mut state).open {
+            return Err(ChannelError::InterfaceNotOpen);
+        }
+        let due = Instant::now() + state.latency;
+        for frame in packets {
+            let seq = state.next_seq;
+            state.next_seq += 1;
+            state.pending.push(PendingFrame {
+                frame,
+                sender: self.id,
+                seq,
+                due,
+            });
+        }
+        self.bus.inner.written.notify_all();
+        Ok(())
+    }
+
+    fn read_packets(&mut self, max: usize, timeout_ms: u32) -> ChannelResult<Vec<CanFrame>> {
+        let deadline = Instant::now() + Duration::from_millis(timeout_ms as u64);
+        let mut state = self.bus.lock();
+        if !self.endpoint(&mut state).open {
+            return Err(ChannelError::InterfaceNotOpen);
+        }
+        let mut read_packets = vec![];
+        loop {
+            let now = Instant::now();
+            state.deliver_due(now);
+            let endpoint = self.endpoint(&mut state);
+            while read_packets.len() < max {
+                match endpoint.rx_queue.pop_front() {
+                    Some(f) if self.is_allowed(f.get_address()) => read_packets.push(f),
+                    Some(_) => {}
+                    None => break,
+                }
+            }
+            if !read_packets.is_empty() {
+                return Ok(read_packets);
+            }
+            if now >= deadline {
+                return Err(ChannelError::BufferEmpty);
+            }
+            // Wait for a frame to be written, or the next pending one to become due
+            let wake = state.next_due().map_or(deadline, |due| due.min(deadline));
+            state = self
+                .bus
+                .inner
+                .written
+                .wait_timeout(state, wake.saturating_duration_since(now))
+                .unwrap()
+                .0;
+        }
+    }
+
+    fn clear_rx_buffer(&mut self) -> ChannelResult<()> {
+        let mut state = self.bus.lock();
+        state.deliver_due(Instant::now());
+        self.endpoint(&mut state).rx_queue.clear();
+        Ok(())
+    }
+
+    fn clear_tx_buffer(&mut self) -> ChannelResult<()> {
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug)]
+/// Device on a [VirtualBus], see [Hardware]
+pub struct VirtualBusDevice {
+    info: HardwareInfo,
+    bus: VirtualBus,
+    can_channel: Arc<AtomicBool>,
+    has_isotp_channel: Arc<AtomicBool>,
+}
+
+impl VirtualBusDevice {
+    /// Creates a new device on `bus`. Its channels attach to the bus when they are created
+    pub fn new(bus: &VirtualBus, name: &str) -> Self {
+        Self {
+            info: virtual_bus_info(name),
+            bus: bus.clone(),
+            can_channel: Arc::new(AtomicBool::new(false)),
+            has_isotp_channel: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Returns the bus the device is on
+    pub fn get_bus(&self) -> &VirtualBus {
+        &self.bus
+    }
+}
+
+impl Hardware for VirtualBusDevice {
+    fn create_iso_tp_channel(
+        &mut self,
+        force_native: bool,
+    ) -> HardwareResult<Box<dyn IsoTPChannel>> {
+        if force_native {
+            return Err(HardwareError::ChannelNotSupported);
+        }
+        let can_channel = self.create_can_channel()?;
+        self.has_isotp_channel.store(true, Ordering::Relaxed);
+        Ok(Box::new(SoftwareIsoTpChannel::new(can_channel)))
+    }
+
+    fn create_native_iso_tp_channel(&mut self) -> HardwareResult<Box<dyn CanChannel>> {
+        self.has_isotp_channel.store(true, Ordering::Relaxed);
+        self.create_can_channel()
+    }
+
+    fn create_can_channel(&mut self) -> HardwareResult<Box<dyn CanChannel>> {
+        if self.can_channel.load(Ordering::Relaxed) {
+            // Already open
+            Err(HardwareError::ConflictingChannel)
+        } else {
+            self.can_channel.store(true, Ordering::Relaxed);
+            let mut channel = self.bus.attach();
+            channel.device_state = Some(self.can_channel.clone());
+            Ok(Box::new(channel))
+        }
+    }
+
+    fn is_iso_tp_channel_open(&self) -> bool {
+        self.has_isotp_channel.load(Ordering::Relaxed)
+    }
+
+    fn is_can_channel_open(&self) -> bool {
+        self.can_channel.load(Ordering::Relaxed)
+    }
+
+    fn read_battery_voltage(&mut self) -> Option<f32> {
+        None
+    }
+
+    fn read_ignition_voltage(&mut self) -> Option<f32> {
+        None
+    }
+
+    fn get_info(&self) -> &HardwareInfo {
+        &self.info
+    }
+
+    fn is_connected(&self) -> bool {
+        true
+    }
+}
+
+fn virtual_bus_info(name: &str) -> HardwareInfo {
+    HardwareInfo {
+        name: name.to_string(),
+        vendor: Some("Virtual CAN bus".to_string()),
+        device_fw_version: None,
+        device_id: None,
+        api_version: None,
+        library_version: None,
+        library_location: None,
+        capabilities: HardwareCapabilities {
+            iso_tp: IsoTpChannelType::Emulated,
+            can: true,
+            kline: false,
+            kline_kwp: false,
+            sae_j1850: false,
+            sci: false,
+            ip: false,
+        },
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use std::time::{Duration, Instant};
+
+    use super::{VirtualBus, VirtualBusDevice};
+    use crate::{
+        core::channel::{CanFrame, ChannelError, FilterPacketChannel, Packet, PacketChannel},
+        hardware::{isotp::IsoTpProtocol, Hardware},
+    };
+
+    #[test]
+    pub fn test_virtual_bus_broadcast() {
+        let bus = VirtualBus::new();
+        let mut a = bus.attach();
+        let mut b = bus.attach();
+        let mut sniffer = bus.attach();
+        let mut closed = bus.attach();
+        for c in [&mut a, &mut b, &mut sniffer] {
+            c.open().unwrap();
+        }
+        assert_eq!(bus.endpoint_count(), 4);
+
+        let f1 = CanFrame::new(0x7E0, &[0x02, 0x3E, 0x00], false);
+        let f2 = CanFrame::new(0x7E8, &[0x02, 0x7E, 0x00], false);
+        a.write_packets(vec![f1], 0).unwrap();
+        b.write_packets(vec![f2], 0).unwrap();
+
+        // Endpoints do not receive their own frames
+        assert_eq!(a.read_packets(10, 0).unwrap(), vec![f2]);
+        assert_eq!(b.read_packets(10, 0).unwrap(), vec![f1]);
+        assert_eq!(sniffer.read_packets(10, 0).unwrap(), vec![f1, f2]);
+        assert!(matches!(
+            closed.read_packets(10, 0),
+            Err(ChannelError::InterfaceNotOpen)
+        ));
+        closed.open().unwrap();
+        assert!(matches!(
+            closed.read_packets(10, 0),
+            Err(ChannelError::BufferEmpty)
+        ));
+
+        sniffer.add_filter(&[0x7E8]).unwrap();
+        a.write_packets(vec![f1], 0).unwrap();
+        b.write_packets(vec![f2], 0).unwrap();
+        assert_eq!(sniffer.read_packets(10, 0).unwrap(), vec![f2]);
+
+        drop(closed);
+        assert_eq!(bus.endpoint_count(), 3);
+    }
+
+    #[test]
+    pub fn test_virtual_bus_arbitration_latency() {
+        let bus = VirtualBus::new();
+        bus.set_arbitration(true);
+        bus.set_latency(Duration::from_millis(50));
+        let mut a = bus.attach();
+        let mut b = bus.attach();
+        a.open().unwrap();
+        b.open().unwrap();
+
+        let frames = vec![
+            CanFrame::new(0x18DAF110, &[1], true),
+            CanFrame::new(0x7E8, &[2], false),
+            CanFrame::new(0x100, &[3], false),
+            // Same base ID as 0x100, standard frame wins
+            CanFrame::new(0x100 << 18, &[4], true),
+        ];
+        let start = Instant::now();
+        a.write_packets(frames, 0).unwrap();
+        assert!(matches!(
+            b.read_packets(10, 0),
+            Err(ChannelError::BufferEmpty)
+        ));
+        let read = b.read_packets(10, 1000).unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(50));
+        let ids: Vec<u32> = read.iter().map(|f| f.get_address()).collect();
+        // 0x18DAF110 has base ID 0x636, so it wins over 0x7E8
+        assert_eq!(ids, [0x100, 0x100 << 18, 0x18DAF110, 0x7E8]);
+    }
+
+    #[test]
+    pub fn test_virtual_bus_device_isotp() {
+        let bus = VirtualBus::new();
+        let mut ecu = bus.attach();
+        let mut sniffer = bus.attach();
+        ecu.open().unwrap();
+        sniffer.open().unwrap();
+
+        let ecu_thread = std::thread::spawn(move || {
+            let req = ecu.read_packets(1, 5000).unwrap();
+            assert_eq!(&req[0].get_data()[..3], &[0x02, 0x3E, 0x00]);
+            ecu.write_packets(vec![CanFrame::new(0x7E8, &[0x02, 0x7E, 0x00], false)], 0)
+                .unwrap();
+        });
+
+        let mut device = VirtualBusDevice::new(&bus, "Tester");
+        assert!(device.create_iso_tp_channel(true).is_err());
+        let mut client = IsoTpProtocol::new_from_hardware(device).unwrap();
+        client.set_rx_id(0x7E8);
+        client.init();
+        assert_eq!(
+            client.send_receive_payload(0x7E0, &[0x3E, 0x00]),
+            [0x7E, 0x00]
+        );
+        ecu_thread.join().unwrap();
+
+        let seen: Vec<u32> = sniffer
+            .read_packets(10, 0)
+            .unwrap()
+            .iter()
+            .map(|f| f.get_address())
+            .collect();
+        assert_eq!(seen, [0x7E0, 0x7E8]);
+    }
+}
@@ -1,5 +1,6 @@
 //!  Provides methods to manipulate the ECUs diagnostic session mode
 
+use crate::core::DiagServerResult;
 use crate::uds::UDSClientSession;
 
 use automotive_diag::uds::{encode_communication_type, CommunicationLevel, UdsCommand};
@@ -12,12 +13,13 @@ impl UDSClientSession {
         communication_type: CommunicationType,
         subnet: Subnet,
         comm_level: CommunicationLevel,
-    ) {
+    ) -> DiagServerResult<()> {
         let level: u8 = comm_level.into();
         let communication_type = encode_communication_type(communication_type, subnet);
         self.send_command_with_response(
             UdsCommand::CommunicationControl,
             &[level, communication_type],
-        );
+        )?;
+        Ok(())
     }
 }
@@ -1,13 +1,15 @@
 //!  Provides methods to manipulate the ECUs diagnostic session mode
 
+use crate::core::DiagServerResult;
 use crate::uds::UDSClientSession;
 
 use automotive_diag::uds::UdsCommand;
 
 impl UDSClientSession {
     /// Requests the ECU to go into a specific diagnostic session mode
-    pub fn uds_control_dtc_setting(&mut self, sub_fcn: DTCSettingSubfcn) {
-        self.send_command_with_response(UdsCommand::ControlDTCSetting, &[sub_fcn as u8]);
+    pub fn uds_control_dtc_setting(&mut self, sub_fcn: DTCSettingSubfcn) -> DiagServerResult<()> {
+        self.send_command_with_response(UdsCommand::ControlDTCSetting, &[sub_fcn as u8])?;
+        Ok(())
     }
 }
 
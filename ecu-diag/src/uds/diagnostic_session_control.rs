@@ -1,38 +1,19 @@
 //!  Provides methods to manipulate the ECUs diagnostic session mode
 
+use crate::core::DiagServerResult;
 use crate::uds::UDSClientSession;
-use crate::uds::UdsSericeResponseDetail;
-use crate::uds::UdsServiceResponse;
 
 use automotive_diag::uds::UdsCommand;
 
 impl UDSClientSession {
     /// Requests the ECU to go into a specific diagnostic session mode
-    pub fn uds_set_session_mode(&mut self, session_mode: UdsSessionType) -> UdsServiceResponse {
-        let resp = self.send_command_with_response(
+    pub fn uds_set_session_mode(&mut self, session_mode: UdsSessionType) -> DiagServerResult<()> {
+        self.send_command_with_response(
             UdsCommand::DiagnosticSessionControl,
             &[session_mode as u8],
-        );
-
-        if !resp.is_empty() {
-            if resp[0] == (UdsCommand::DiagnosticSessionControl as u8 + 0x40) {
-                self.current_diag_mode.mode = session_mode;
-                // Success
-                return UdsServiceResponse::Success(UdsSericeResponseDetail {
-                    console_output: String::from("SUCCESS"),
-                });
-            } else {
-                // Fail
-                return UdsServiceResponse::Fail(UdsSericeResponseDetail {
-                    console_output: String::from("FAIL\nNEGATIVE UDS RESPONSE"),
-                });
-            }
-        }
-
-        // Fail
-        UdsServiceResponse::Fail(UdsSericeResponseDetail {
-            console_output: String::from("FAIL\nEMPTY UDS RESPONSE"),
-        })
+        )?;
+        self.current_diag_mode.mode = session_mode;
+        Ok(())
     }
 }
 
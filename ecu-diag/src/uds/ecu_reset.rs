@@ -1,8 +1,7 @@
 //!  Provides methods to manipulate the ECUs diagnostic session mode
 
+use crate::core::DiagServerResult;
 use crate::uds::UDSClientSession;
-use crate::uds::UdsSericeResponseDetail;
-use crate::uds::UdsServiceResponse;
 
 use automotive_diag::uds::UdsCommand;
 
@@ -10,18 +9,17 @@ use super::security_access::SecurityLevelAccess;
 
 impl UDSClientSession {
     /// Requests the ECU to go into a specific diagnostic session mode
-    pub fn uds_ecu_reset(&mut self, reset_mode: ResetType) -> UdsServiceResponse {
-        self.send_command_no_response(UdsCommand::ECUReset, &[reset_mode as u8]);
-        UdsServiceResponse::Success(UdsSericeResponseDetail {
-            console_output: String::from("SUCCESS"),
-        })
+    pub fn uds_ecu_reset(&mut self, reset_mode: ResetType) -> DiagServerResult<()> {
+        self.send_command_no_response(UdsCommand::ECUReset, &[reset_mode as u8])
     }
 
-    pub fn uds_ecu_reset_setup(&mut self) {
+    pub fn uds_ecu_reset_setup(&mut self) -> DiagServerResult<()> {
         if self.current_diag_mode.sec_level == SecurityLevelAccess::None {
-            let key = self.uds_security_access_request_seed(SecurityLevelAccess::Level1RequestSeed);
-            self.uds_security_access_send_key(SecurityLevelAccess::Level1SendKey, &key);
+            let key =
+                self.uds_security_access_request_seed(SecurityLevelAccess::Level1RequestSeed)?;
+            self.uds_security_access_send_key(SecurityLevelAccess::Level1SendKey, &key)?;
         }
+        Ok(())
     }
 }
 
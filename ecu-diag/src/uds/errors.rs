@@ -1,3 +1,5 @@
+use crate::core::dynamic_diag::EcuNRC;
+
 #[repr(u8)]
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, thiserror::Error)]
 pub enum UdsError {
@@ -139,14 +141,88 @@ pub enum UdsError {
     /// Voltage is too low
     #[error("Voltage is too low")]
     VoltageTooLow = 0x93,
+    /// Negative response code that is not defined above
+    #[error("Unknown negative response code")]
+    Unknown = 0xFF,
+}
+
+impl From<u8> for UdsError {
+    fn from(value: u8) -> Self {
+        use UdsError::*;
+        match value {
+            0x10 => GeneralReject,
+            0x11 => ServiceNotSupported,
+            0x12 => SubFunctionNotSupported,
+            0x13 => IncorrectMessageLengthOrInvalidFormat,
+            0x14 => ResponseTooLong,
+            0x21 => BusyRepeatRequest,
+            0x22 => ConditionsNotCorrect,
+            0x24 => RequestSequenceError,
+            0x25 => NoResponseFromSubnetComponent,
+            0x26 => FailurePreventsExecutionOfRequestedAction,
+            0x31 => RequestOutOfRange,
+            0x33 => SecurityAccessDenied,
+            0x35 => InvalidKey,
+            0x36 => ExceedNumberOfAttempts,
+            0x37 => RequiredTimeDelayNotExpired,
+            0x70 => UploadDownloadNotAccepted,
+            0x71 => TransferDataSuspended,
+            0x72 => GeneralProgrammingFailure,
+            0x73 => WrongBlockSequenceCounter,
+            0x78 => RequestCorrectlyReceivedResponsePending,
+            0x7E => SubFunctionNotSupportedInActiveSession,
+            0x7F => ServiceNotSupportedInActiveSession,
+            0x81 => RpmTooHigh,
+            0x82 => RpmTooLow,
+            0x83 => EngineIsRunning,
+            0x84 => EngineIsNotRunning,
+            0x85 => EngineRunTimeTooLow,
+            0x86 => TemperatureTooHigh,
+            0x87 => TemperatureTooLow,
+            0x88 => VehicleSpeedTooHigh,
+            0x89 => VehicleSpeedTooLow,
+            0x8A => ThrottleTooHigh,
+            0x8B => ThrottleTooLow,
+            0x8C => TransmissionRangeNotInNeutral,
+            0x8D => TransmissionRangeNotInGear,
+            0x8F => BrakeSwitchNotClosed,
+            0x90 => ShifterLeverNotInPark,
+            0x91 => TorqueConverterClutchLocked,
+            0x92 => VoltageTooHigh,
+            0x93 => VoltageTooLow,
+            _ => Unknown,
+        }
+    }
+}
+
+impl EcuNRC for UdsError {
+    fn desc(&self) -> String {
+        self.to_string()
+    }
+
+    fn is_ecu_busy(&self) -> bool {
+        *self == UdsError::RequestCorrectlyReceivedResponsePending
+    }
+
+    fn is_wrong_diag_mode(&self) -> bool {
+        matches!(
+            self,
+            UdsError::SubFunctionNotSupportedInActiveSession
+                | UdsError::ServiceNotSupportedInActiveSession
+        )
+    }
+
+    fn is_repeat_request(&self) -> bool {
+        *self == UdsError::BusyRepeatRequest
+    }
 }
 
 // Function to convert u8 to UdsError
 impl UdsError {
     fn from_u8(value: u8) -> Option<UdsError> {
-        match value {
-            0x10..=0x93 => Some(unsafe { std::mem::transmute(value) }),
-            _ => None,
+        match UdsError::from(value) {
+            UdsError::Unknown => None,
+            e => Some(e),
         }
     }
 }
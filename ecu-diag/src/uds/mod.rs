@@ -1,24 +1,30 @@
 //! Module for [Unified Diagnostic Services](https://en.wikipedia.org/wiki/Unified_Diagnostic_Services) - ISO-14229-1
 //!
 
-use crate::api::UdsServiceProvider;
-use crate::api::{UdsMonitorViewResponse, UdsSericeResponseDetail, UdsServiceResponse};
-use crate::core::channel::CanFrame;
+use crate::api::{UdsMonitorViewResponseDetail, UdsServiceProvider};
+use crate::core::channel::ChannelError;
+use crate::core::channel::{CanBusStatus, ChannelResult, PayloadChannel};
 use crate::core::dynamic_diag::{
-    DiagServerAdvancedOptions, DiagServerBasicOptions, DiagSessionMode, TimeoutConfig,
+    DiagServerAdvancedOptions, DiagServerBasicOptions, DiagServerEmptyLogger, DiagServerLogger,
+    DiagSessionMode, DynamicDiagSession, ServerEvent, TimeoutConfig,
 };
-use crate::hardware::isotp::IsoTpProtocol;
+use crate::core::{DiagError, DiagServerResult};
+use crate::hardware::doip::{DoIpChannel, DoIpSettings};
+use crate::hardware::isotp::{IsoTpAddressing, IsoTpProtocol};
 
 use crate::uds::diagnostic_session_control::UdsSessionType;
 use crate::uds::ecu_reset::ResetType;
-use crate::uds::read_data_by_id::DataId;
-use crate::uds::routine_control::{RoutineControlSubfcn, RoutineId};
+use crate::uds::read_data_by_id::{DataField, DataId, DataValue};
+use crate::uds::routine_control::{RoutineControlSubfcn, RoutineId, RoutineResult};
 use automotive_diag::uds::UdsCommand;
 
 use self::routine_control::ServiceRequest;
 use self::security_access::SecurityLevelAccess;
+use crate::uds::protocol::UdsProtocol;
 use crate::uds::routine_control::ServiceResponse;
 
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex, MutexGuard};
 use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
 
 pub mod communication_control;
@@ -27,15 +33,49 @@ pub mod diagnostic_session_control;
 pub mod ecu_reset;
 pub mod errors;
 pub mod link_control;
+pub(crate) mod protocol;
 pub mod read_data_by_id;
 pub mod read_dtc_info;
 pub mod routine_control;
 pub mod security_access;
 pub mod tester_present;
 
+/// Logger of the diagnostic server, see [UDSClientSession::set_logger]
+#[derive(Clone)]
+struct SessionLogger(Arc<dyn Fn(ServerEvent) + Send + Sync>);
+
+impl SessionLogger {
+    fn new<L: DiagServerLogger + 'static>(logger: L) -> Self {
+        Self(Arc::new(move |evt| logger.on_event(evt)))
+    }
+}
+
+impl DiagServerLogger for SessionLogger {
+    fn on_event(&self, evt: ServerEvent) {
+        (self.0)(evt)
+    }
+}
+
+/// Channel the requests of a [UDSClientSession] are sent over.
+/// Shared with the diagnostic server of the session
+enum ClientChannel {
+    /// ISO-TP over CAN, see [UDSClientSession::protocol]
+    IsoTp(Arc<Mutex<IsoTpProtocol>>),
+    /// Any other payload channel, see [UDSClientSession::new_with_channel]
+    Payload(Arc<Mutex<Box<dyn PayloadChannel>>>),
+}
+
 pub struct UDSClientSession {
     pub current_diag_mode: DiagSessionMode,
-    pub protocol: IsoTpProtocol,
+    channel: ClientChannel,
+    /// Diagnostic server that sends the requests, keeps the session alive with tester present
+    /// and switches the ECU back to the session mode it has dropped out of.
+    /// Started by [UDSClientSession::init]
+    session: Option<DynamicDiagSession>,
+    /// Called while the ECU is busy with a request, see [UDSClientSession::register_waiting_hook]
+    waiting_hook: Option<Arc<dyn Fn() + Send + Sync>>,
+    /// Logger of the diagnostic server, see [UDSClientSession::set_logger]
+    logger: SessionLogger,
     pub basic_option: DiagServerBasicOptions,
     pub advanced_options: DiagServerAdvancedOptions,
     pub rx: UnboundedReceiver<ServiceResponse>,
@@ -46,13 +86,72 @@ pub struct UDSClientSession {
 // unsafe impl Sync for UDSClientSession {}
 
 impl UDSClientSession {
-    async fn async_default(
+    /// Creates a new UDS client session on top of an ISOTP protocol handler.
+    /// The underlying channel is not opened until [UDSClientSession::init] is called
+    pub fn new(
+        mut protocol: IsoTpProtocol,
+        tx: UnboundedSender<ServiceRequest>,
+        rx: UnboundedReceiver<ServiceResponse>,
+    ) -> Self {
+        let addressing = IsoTpAddressing::default();
+        protocol
+            .set_addressing(addressing)
+            .expect("Default addressing is valid");
+        Self::new_with_client_channel(
+            ClientChannel::IsoTp(Arc::new(Mutex::new(protocol))),
+            addressing.tx_id(),
+            addressing.rx_id(),
+            tx,
+            rx,
+        )
+    }
+
+    /// Creates a new UDS client session on top of any payload channel.
+    /// The channel is opened by [UDSClientSession::init]
+    ///
+    /// ## Parameters
+    /// * send_id - ID the ECU listens to requests on (EG: its DoIP logical address)
+    /// * recv_id - ID the ECU responds with
+    pub fn new_with_channel(
+        channel: Box<dyn PayloadChannel>,
+        send_id: u32,
+        recv_id: u32,
+        tx: UnboundedSender<ServiceRequest>,
+        rx: UnboundedReceiver<ServiceResponse>,
+    ) -> Self {
+        Self::new_with_client_channel(
+            ClientChannel::Payload(Arc::new(Mutex::new(channel))),
+            send_id,
+            recv_id,
+            tx,
+            rx,
+        )
+    }
+
+    /// Creates a new UDS client session to the ECU with the logical address `ecu_address`,
+    /// over Diagnostics over IP to the DoIP entity at `addr`
+    pub fn new_doip(
+        addr: SocketAddr,
+        cfg: DoIpSettings,
+        ecu_address: u16,
+        tx: UnboundedSender<ServiceRequest>,
+        rx: UnboundedReceiver<ServiceResponse>,
+    ) -> Self {
+        let channel = Box::new(DoIpChannel::new(addr, cfg));
+        let ecu_address = u32::from(ecu_address);
+        Self::new_with_channel(channel, ecu_address, ecu_address, tx, rx)
+    }
+
+    fn new_with_client_channel(
+        channel: ClientChannel,
+        send_id: u32,
+        recv_id: u32,
         tx: UnboundedSender<ServiceRequest>,
         rx: UnboundedReceiver<ServiceResponse>,
     ) -> Self {
         // If you're using PowerShell, you can set environment variables using the $env: prefix. For example:
         // $env:RUST_LOG="debug"
-        env_logger::init();
+        let _ = env_logger::try_init();
 
         Self {
             current_diag_mode: DiagSessionMode {
@@ -61,10 +160,13 @@ impl UDSClientSession {
                 sec_level: security_access::SecurityLevelAccess::None,
                 name: String::from("UDS Client"),
             },
-            protocol: IsoTpProtocol::new(),
+            channel,
+            session: None,
+            waiting_hook: None,
+            logger: SessionLogger::new(DiagServerEmptyLogger {}),
             basic_option: DiagServerBasicOptions {
-                send_id: 0x784,
-                recv_id: 0x7F0,
+                send_id,
+                recv_id,
                 timeout_cfg: TimeoutConfig {
                     read_timeout_ms: 5000,
                     write_timeout_ms: 5000,
@@ -83,37 +185,142 @@ impl UDSClientSession {
         }
     }
 
+    /// Sets the ISO-TP addressing mode of requests and responses, see [IsoTpProtocol::set_addressing].
+    /// The send and receive IDs of the basic options are updated to match.
+    /// Must be called before [UDSClientSession::init]
+    pub fn set_addressing(&mut self, addressing: IsoTpAddressing) -> ChannelResult<()> {
+        if self.session.is_some() {
+            return Err(ChannelError::ConfigurationError);
+        }
+        self.protocol()
+            .ok_or(ChannelError::UnsupportedRequest)?
+            .set_addressing(addressing)?;
+        self.basic_option.send_id = addressing.tx_id();
+        self.basic_option.recv_id = addressing.rx_id();
+        Ok(())
+    }
+
+    /// Gives access to the ISO-TP protocol handler, for example to manage the hardware or traces.
+    /// Requests are held back while the returned guard is alive.
+    /// Returns None if the session is not over ISO-TP, see [UDSClientSession::new_with_channel]
+    pub fn protocol(&self) -> Option<MutexGuard<'_, IsoTpProtocol>> {
+        match &self.channel {
+            ClientChannel::IsoTp(protocol) => {
+                Some(protocol.lock().unwrap_or_else(|e| e.into_inner()))
+            }
+            ClientChannel::Payload(_) => None,
+        }
+    }
+
+    /// Opens the channel and starts the diagnostic server.
+    /// If the channel cannot be opened, the server is started by the next request instead
     pub fn init(&mut self) {
-        self.protocol = IsoTpProtocol::new();
-        self.protocol.init();
+        if let Some(mut protocol) = self.protocol() {
+            protocol.set_rx_id(self.basic_option.recv_id);
+            protocol.init();
+        }
+        if let Err(e) = self.session() {
+            log::error!("Error: start diagnostic server {e:?}");
+        }
+    }
+
+    /// Returns the diagnostic server, starting it if it is not running yet
+    fn session(&mut self) -> DiagServerResult<&DynamicDiagSession> {
+        if self.session.is_none() {
+            let mut session = match &self.channel {
+                ClientChannel::IsoTp(protocol) => {
+                    let cfg = protocol
+                        .lock()
+                        .unwrap_or_else(|e| e.into_inner())
+                        .get_iso_tp_cfg();
+                    DynamicDiagSession::new_over_iso_tp(
+                        UdsProtocol::default(),
+                        Box::new(protocol.clone()),
+                        cfg,
+                        self.basic_option,
+                        Some(self.advanced_options),
+                        self.logger.clone(),
+                    )?
+                }
+                ClientChannel::Payload(channel) => DynamicDiagSession::new_over_payload_channel(
+                    UdsProtocol::default(),
+                    Box::new(channel.clone()),
+                    self.basic_option,
+                    Some(self.advanced_options),
+                    self.logger.clone(),
+                )?,
+            };
+            if let Some(hook) = self.waiting_hook.clone() {
+                session.register_waiting_hook(move || hook());
+            }
+            self.session = Some(session);
+        }
+        self.session.as_ref().ok_or(DiagError::ServerNotRunning)
+    }
+
+    /// Sets the logger of the diagnostic server, which is told about every payload
+    /// sent to and received from the ECU. Must be called before [UDSClientSession::init]
+    pub fn set_logger<L: DiagServerLogger + 'static>(&mut self, logger: L) -> ChannelResult<()> {
+        if self.session.is_some() {
+            return Err(ChannelError::ConfigurationError);
+        }
+        self.logger = SessionLogger::new(logger);
+        Ok(())
+    }
+
+    /// Registers a hook called whenever the ECU replies that it is busy with a request
+    /// (response pending, NRC 0x78), so the user can be told that the ECU is still working.
+    /// The request keeps waiting for the real response
+    pub fn register_waiting_hook<F: Fn() + Send + Sync + 'static>(&mut self, hook: F) {
+        let hook: Arc<dyn Fn() + Send + Sync> = Arc::new(hook);
+        if let Some(session) = self.session.as_mut() {
+            let hook = hook.clone();
+            session.register_waiting_hook(move || hook());
+        }
+        self.waiting_hook = Some(hook);
     }
 
     pub fn check_can_connection_status(&mut self) -> bool {
-        self.protocol.connection_status
+        match self.protocol() {
+            Some(protocol) => protocol.connection_status,
+            None => self.session.as_ref().is_some_and(|s| s.is_ecu_connected()),
+        }
     }
 
-    /// Send a command to the ECU and await its response
-    pub fn send_command_with_response<T: Into<u8>>(&mut self, cmd: T, args: &[u8]) -> Vec<u8> {
-        let mut data: [u8; 8] = [0, cmd.into(), 0, 0, 0, 0, 0, 0];
-        let arg_len = args.len();
-        data[2..(std::cmp::min(arg_len, 6) + 2)]
-            .copy_from_slice(&args[..std::cmp::min(arg_len, 6)]);
-        data[0] = (arg_len + 1) as u8;
-        let msg = CanFrame::new(0x784, &data, true);
+    /// Checks the health of the CAN bus, see [IsoTpProtocol::check_bus].
+    /// Returns None if the session is not over CAN
+    pub fn check_can_bus_status(&mut self) -> Option<CanBusStatus> {
+        self.protocol()?.check_bus()
+    }
 
-        self.protocol.send_receive(msg)
+    /// Send a command to the ECU and await its positive response.
+    /// A negative response is returned as [DiagError::ECUError], see [DiagError::uds_error]
+    pub fn send_command_with_response<T: Into<u8>>(
+        &mut self,
+        cmd: T,
+        args: &[u8],
+    ) -> DiagServerResult<Vec<u8>> {
+        let mut payload = vec![cmd.into()];
+        payload.extend_from_slice(args);
+
+        let resp = self.session()?.send_byte_array_with_response(&payload)?;
+        match resp.first() {
+            Some(sid) if *sid == payload[0].wrapping_add(0x40) => Ok(resp),
+            Some(_) => Err(DiagError::WrongMessage),
+            None => Err(DiagError::EmptyResponse),
+        }
     }
 
-    /// Send a command to the ECU and await its response
-    pub fn send_command_no_response<T: Into<u8>>(&mut self, cmd: T, args: &[u8]) {
-        let mut data: [u8; 8] = [0, cmd.into(), 0, 0, 0, 0, 0, 0];
-        let arg_len = args.len();
-        data[2..(std::cmp::min(arg_len, 6) + 2)]
-            .copy_from_slice(&args[..std::cmp::min(arg_len, 6)]);
-        data[0] = (arg_len + 1) as u8;
-        let msg = CanFrame::new(0x784, &data, true);
+    /// Send a command to the ECU without waiting for its response
+    pub fn send_command_no_response<T: Into<u8>>(
+        &mut self,
+        cmd: T,
+        args: &[u8],
+    ) -> DiagServerResult<()> {
+        let mut payload = vec![cmd.into()];
+        payload.extend_from_slice(args);
 
-        self.protocol.send(msg)
+        self.session()?.send_byte_array(&payload)
     }
 
     // pub async fn get_service_response(&mut self) -> String {
@@ -133,55 +340,60 @@ impl UdsServiceProvider for UDSClientSession {
     async fn new_uds_client(
         tx: UnboundedSender<ServiceRequest>,
         rx: UnboundedReceiver<ServiceResponse>,
+    ) -> DiagServerResult<UDSClientSession> {
+        let protocol = IsoTpProtocol::new_pcan(None).map_err(Arc::new)?;
+        Ok(Self::new_uds_client_with_protocol(protocol, tx, rx).await)
+    }
+    async fn new_uds_client_with_protocol(
+        protocol: IsoTpProtocol,
+        tx: UnboundedSender<ServiceRequest>,
+        rx: UnboundedReceiver<ServiceResponse>,
     ) -> UDSClientSession {
-        let mut client = UDSClientSession::async_default(tx, rx).await;
+        let mut client = UDSClientSession::new(protocol, tx, rx);
         client.init();
 
-        client.send_command_with_response(UdsCommand::TesterPresent, &[]);
-
-        let resp = client.uds_read_data_by_id(DataId::DiagState);
-        let mut session_state: u8 = 0;
-        let mut security_state: u8 = 0;
-        match resp {
-            UdsServiceResponse::Success(data) => {
-                for line in data.console_output.lines() {
-                    let parts: Vec<&str> = line.split(": ").collect();
-                    if parts.len() == 2 {
-                        match parts[0] {
-                            "Session State" => {
-                                session_state = parts[1].trim().parse().unwrap_or_default();
-                            }
-                            "Security State" => {
-                                security_state = parts[1].trim().parse().unwrap_or_default();
-                            }
-                            _ => {}
-                        }
+        if let Err(e) = client.send_command_with_response(UdsCommand::TesterPresent, &[]) {
+            log::warn!("ECU did not respond to tester present: {e}");
+        }
+
+        match client.uds_read_data_by_id(DataId::DiagState) {
+            Ok(fields) => {
+                let mut session_state: u8 = 0;
+                let mut security_state: u8 = 0;
+                for field in fields {
+                    match (field.name, field.value) {
+                        ("Session State", DataValue::U8(v)) => session_state = v,
+                        ("Security State", DataValue::U8(v)) => security_state = v,
+                        _ => {}
                     }
                 }
 
                 client.current_diag_mode.mode = UdsSessionType::from_byte(session_state);
                 client.current_diag_mode.sec_level = SecurityLevelAccess::from_byte(security_state);
             }
-            UdsServiceResponse::Fail(_fail) => {}
+            Err(e) => log::warn!("Could not read the diagnostic state of the ECU: {e}"),
         }
 
         client
     }
-    fn invoke_read_data_by_id_service(&mut self, data_id: DataId) -> UdsServiceResponse {
+    fn invoke_read_data_by_id_service(
+        &mut self,
+        data_id: DataId,
+    ) -> DiagServerResult<Vec<DataField>> {
         self.uds_read_data_by_id_setup();
         self.uds_read_data_by_id(data_id)
     }
     fn invoke_read_data_by_id_service_return_struct(
         &mut self,
         data_id: DataId,
-    ) -> UdsMonitorViewResponse {
+    ) -> DiagServerResult<UdsMonitorViewResponseDetail> {
         self.uds_read_data_by_id_setup();
         //self.uds_set_session_mode(UdsSessionType::StreamMode);
         self.uds_read_data_by_id_return_struct(data_id)
     }
 
-    fn invoke_reset_ecu_service(&mut self, reset_mode: ResetType) -> UdsServiceResponse {
-        self.uds_ecu_reset_setup();
+    fn invoke_reset_ecu_service(&mut self, reset_mode: ResetType) -> DiagServerResult<()> {
+        self.uds_ecu_reset_setup()?;
         self.uds_ecu_reset(reset_mode)
     }
     async fn invoke_routine_control_service(
@@ -189,8 +401,8 @@ impl UdsServiceProvider for UDSClientSession {
         routine_subfcn: RoutineControlSubfcn,
         routine_id: RoutineId,
         routine_control_option: &[u8],
-    ) -> UdsServiceResponse {
-        self.uds_routine_control_setup();
+    ) -> DiagServerResult<()> {
+        self.uds_routine_control_setup()?;
 
         self.uds_routine_control(
             routine_subfcn,
@@ -204,8 +416,8 @@ impl UdsServiceProvider for UDSClientSession {
         routine_subfcn: RoutineControlSubfcn,
         routine_id: RoutineId,
         routine_control_option: &[u8],
-    ) -> UdsServiceResponse {
-        self.uds_routine_control_setup_get_result();
+    ) -> DiagServerResult<RoutineResult> {
+        self.uds_routine_control_setup_get_result()?;
 
         self.uds_routine_control_get_result(
             routine_subfcn,
@@ -214,7 +426,7 @@ impl UdsServiceProvider for UDSClientSession {
         )
         .await
     }
-    fn invoke_set_session_mode(&mut self, session_mode: UdsSessionType) -> UdsServiceResponse {
+    fn invoke_set_session_mode(&mut self, session_mode: UdsSessionType) -> DiagServerResult<()> {
         self.uds_set_session_mode(session_mode)
     }
 }
@@ -279,3 +491,317 @@ impl UdsServiceProvider for UDSClientSession {
 //     //     client.uds_read_data_by_id(DataId::AdcVoltage);
 //     // }
 // }
+
+#[cfg(test)]
+pub mod test {
+    use super::diagnostic_session_control::UdsSessionType;
+    use super::errors::UdsError;
+    use super::read_data_by_id::{DataId, DataValue};
+    use super::routine_control::{RoutineControlSubfcn, RoutineId, RoutineNrc, RoutineResult};
+    use super::UDSClientSession;
+    use crate::core::channel::{CanFrame, Packet, PacketChannel};
+    use crate::core::dynamic_diag::{DiagServerLogger, ServerEvent};
+    use crate::core::DiagError;
+    use crate::hardware::doip::test::{TestEntity, ENTITY_ADDR};
+    use crate::hardware::doip::DoIpSettings;
+    use crate::hardware::hardware_tests::EmuCanChannel;
+    use crate::hardware::isotp::{IsoTpAddressing, IsoTpProtocol};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{mpsc, Arc};
+    use std::time::{Duration, Instant};
+
+    /// Waits for a single frame request of the tester, skipping tester present
+    /// unless `keep_tester_present` is set
+    fn ecu_recv(ecu: &mut EmuCanChannel, keep_tester_present: bool) -> Vec<u8> {
+        let start = Instant::now();
+        while start.elapsed() < Duration::from_secs(5) {
+            if let Some(f) = ecu.read_packets(1, 0).unwrap().pop() {
+                assert_eq!(f.get_address(), 0x784);
+                let data = f.get_data();
+                let payload = data[1..=usize::from(data[0])].to_vec();
+                if keep_tester_present || payload != [0x3E, 0x80] {
+                    return payload;
+                }
+            }
+            std::thread::yield_now();
+        }
+        panic!("No request from the tester");
+    }
+
+    /// Counts the payloads sent by the diagnostic server
+    #[derive(Clone, Default)]
+    struct SendCounter(Arc<AtomicUsize>);
+
+    impl DiagServerLogger for SendCounter {
+        fn on_event(&self, evt: ServerEvent) {
+            if let ServerEvent::BytesSendState(..) = evt {
+                self.0.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    fn ecu_send(ecu: &mut EmuCanChannel, payload: &[u8]) {
+        let mut data = vec![payload.len() as u8];
+        data.extend_from_slice(payload);
+        data.resize(8, 0);
+        ecu.write_packets(vec![CanFrame::new(0x7F0, &data, false)], 0)
+            .unwrap();
+    }
+
+    #[test]
+    pub fn test_uds_client_over_diag_server() {
+        let (tester_tx, ecu_rx) = mpsc::channel::<CanFrame>();
+        let (ecu_tx, tester_rx) = mpsc::channel::<CanFrame>();
+        let protocol =
+            IsoTpProtocol::new(Box::new(EmuCanChannel::new(tester_tx, tester_rx, "Tester")));
+        let mut ecu = EmuCanChannel::new(ecu_tx, ecu_rx, "ECU");
+        let (tx_req, _rx_req) = tokio::sync::mpsc::unbounded_channel();
+        let (_tx_res, rx_res) = tokio::sync::mpsc::unbounded_channel();
+        let mut client = UDSClientSession::new(protocol, tx_req, rx_res);
+        client.advanced_options.tester_present_interval_ms = 200;
+        let sent = SendCounter::default();
+        client.set_logger(sent.clone()).unwrap();
+        let busy_count = Arc::new(AtomicUsize::new(0));
+        client.register_waiting_hook({
+            let busy_count = busy_count.clone();
+            move || {
+                busy_count.fetch_add(1, Ordering::Relaxed);
+            }
+        });
+
+        let ecu_thread = std::thread::spawn(move || {
+            // Negative response
+            assert_eq!(ecu_recv(&mut ecu, false), [0x22, 0xF1, 0x90]);
+            ecu_send(&mut ecu, &[0x7F, 0x22, 0x31]);
+
+            // Session change, after which the session is kept alive
+            assert_eq!(ecu_recv(&mut ecu, false), [0x10, 0x03]);
+            ecu_send(&mut ecu, &[0x50, 0x03]);
+            assert_eq!(ecu_recv(&mut ecu, true), [0x3E, 0x80]);
+
+            // The ECU dropped back to the default session, the request is
+            // repeated after switching to the extended session again
+            assert_eq!(ecu_recv(&mut ecu, false), [0x22, 0x01, 0x02]);
+            ecu_send(&mut ecu, &[0x7F, 0x22, 0x7F]);
+            assert_eq!(ecu_recv(&mut ecu, false), [0x10, 0x03]);
+            ecu_send(&mut ecu, &[0x50, 0x03]);
+            assert_eq!(ecu_recv(&mut ecu, false), [0x22, 0x01, 0x02]);
+            ecu_send(&mut ecu, &[0x62, 0x01, 0x02, 0xAA]);
+        });
+
+        client.init();
+        assert!(client.check_can_connection_status());
+        assert!(matches!(
+            client.send_command_with_response(0x22, &[0xF1, 0x90]),
+            Err(e) if e.uds_error() == Some(UdsError::RequestOutOfRange)
+        ));
+        assert!(client
+            .uds_set_session_mode(UdsSessionType::Extended)
+            .is_ok());
+        std::thread::sleep(Duration::from_millis(500));
+        assert_eq!(
+            client
+                .send_command_with_response(0x22, &[0x01, 0x02])
+                .unwrap(),
+            [0x62, 0x01, 0x02, 0xAA]
+        );
+        ecu_thread.join().unwrap();
+        // Switching the session back is not the ECU being busy
+        assert_eq!(busy_count.load(Ordering::Relaxed), 0);
+        assert!(sent.0.load(Ordering::Relaxed) >= 5);
+        assert!(client.set_logger(SendCounter::default()).is_err());
+    }
+
+    #[test]
+    pub fn test_uds_client_over_doip() {
+        // Routing activation succeeds, requests are echoed back as positive responses
+        let entity = TestEntity::start(0x10, None, false);
+        let (tx_req, _rx_req) = tokio::sync::mpsc::unbounded_channel();
+        let (_tx_res, rx_res) = tokio::sync::mpsc::unbounded_channel();
+        let mut client = UDSClientSession::new_doip(
+            entity.tcp_addr,
+            DoIpSettings::default(),
+            ENTITY_ADDR,
+            tx_req,
+            rx_res,
+        );
+        assert!(client.protocol().is_none());
+        assert!(client.set_addressing(IsoTpAddressing::default()).is_err());
+
+        client.init();
+        assert!(client.check_can_bus_status().is_none());
+        assert_eq!(
+            client
+                .send_command_with_response(0x22, &[0xF1, 0x90])
+                .unwrap(),
+            [0x62, 0xF1, 0x90]
+        );
+        assert!(client.check_can_connection_status());
+        assert!(client
+            .uds_set_session_mode(UdsSessionType::Extended)
+            .is_ok());
+    }
+
+    #[test]
+    pub fn test_read_data_by_id_records() {
+        let (tester_tx, ecu_rx) = mpsc::channel::<CanFrame>();
+        let (ecu_tx, tester_rx) = mpsc::channel::<CanFrame>();
+        let protocol =
+            IsoTpProtocol::new(Box::new(EmuCanChannel::new(tester_tx, tester_rx, "Tester")));
+        let mut ecu = EmuCanChannel::new(ecu_tx, ecu_rx, "ECU");
+        let (tx_req, _rx_req) = tokio::sync::mpsc::unbounded_channel();
+        let (_tx_res, rx_res) = tokio::sync::mpsc::unbounded_channel();
+        let mut client = UDSClientSession::new(protocol, tx_req, rx_res);
+
+        let ecu_thread = std::thread::spawn(move || {
+            for response in [
+                &[0x62, 0x01, 0x10, 0x03, 0x01][..],
+                &[0x62, 0x01, 0x10, 0x03],
+                &[0x62, 0x01, 0x11, 0x03, 0x01],
+                &[0x7F, 0x22, 0x31],
+            ] {
+                assert_eq!(ecu_recv(&mut ecu, false), [0x22, 0x01, 0x10]);
+                ecu_send(&mut ecu, response);
+            }
+        });
+
+        client.init();
+        let fields = client.uds_read_data_by_id(DataId::DiagState).unwrap();
+        assert_eq!(
+            fields.iter().map(|f| (f.name, f.value)).collect::<Vec<_>>(),
+            [
+                ("Session State", DataValue::U8(0x03)),
+                ("Security State", DataValue::U8(0x01))
+            ]
+        );
+        assert!(matches!(
+            client.uds_read_data_by_id(DataId::DiagState),
+            Err(DiagError::InvalidResponseLength)
+        ));
+        assert!(matches!(
+            client.uds_read_data_by_id(DataId::DiagState),
+            Err(DiagError::MismatchedIdentResponse {
+                want: 0x0110,
+                received: 0x0111
+            })
+        ));
+        assert!(matches!(
+            client.uds_read_data_by_id(DataId::DiagState),
+            Err(e) if e.uds_error() == Some(UdsError::RequestOutOfRange)
+        ));
+        ecu_thread.join().unwrap();
+    }
+
+    #[tokio::test]
+    pub async fn test_routine_control_results() {
+        let (tester_tx, ecu_rx) = mpsc::channel::<CanFrame>();
+        let (ecu_tx, tester_rx) = mpsc::channel::<CanFrame>();
+        let protocol =
+            IsoTpProtocol::new(Box::new(EmuCanChannel::new(tester_tx, tester_rx, "Tester")));
+        let mut ecu = EmuCanChannel::new(ecu_tx, ecu_rx, "ECU");
+        let (tx_req, _rx_req) = tokio::sync::mpsc::unbounded_channel();
+        let (_tx_res, rx_res) = tokio::sync::mpsc::unbounded_channel();
+        let mut client = UDSClientSession::new(protocol, tx_req, rx_res);
+
+        let ecu_thread = std::thread::spawn(move || {
+            assert_eq!(ecu_recv(&mut ecu, false), [0x31, 0x01, 0x02, 0x04, 0x01]);
+            ecu_send(&mut ecu, &[0x7F, 0x31, 0x22]);
+
+            assert_eq!(ecu_recv(&mut ecu, false), [0x31, 0x03, 0x02, 0x04]);
+            ecu_send(&mut ecu, &[0x71, 0x03, 0x02, 0x04, 0x01, 0x02]);
+            assert_eq!(ecu_recv(&mut ecu, false), [0x31, 0x03, 0x02, 0x04]);
+            ecu_send(&mut ecu, &[0x71, 0x03, 0x02]);
+            assert_eq!(ecu_recv(&mut ecu, false), [0x31, 0x03, 0x02, 0x04]);
+            ecu_send(&mut ecu, &[0x7F, 0x31, 0xA0]);
+        });
+
+        client.init();
+        let routine_id = (RoutineId::SimulateInput as u16).to_be_bytes();
+        assert!(matches!(
+            client
+                .uds_routine_control(RoutineControlSubfcn::StartRoutine, &routine_id, &[0x01])
+                .await,
+            Err(e) if e.uds_error() == Some(UdsError::ConditionsNotCorrect)
+        ));
+
+        let get_result = RoutineControlSubfcn::RequestRoutineResults;
+        assert!(matches!(
+            client.uds_routine_control_get_result(get_result, &routine_id, &[]).await,
+            Ok(RoutineResult::Status(status)) if status == [0x01, 0x02]
+        ));
+        assert!(matches!(
+            client
+                .uds_routine_control_get_result(get_result, &routine_id, &[])
+                .await,
+            Err(DiagError::InvalidResponseLength)
+        ));
+        // Vendor specific code, not decoded as a reserved UDS code
+        assert!(matches!(
+            client.uds_routine_control_get_result(get_result, &routine_id, &[]).await,
+            Err(DiagError::ECUError {
+                code: 0xA0,
+                def: Some(def),
+            }) if def == RoutineNrc::RoutineInProgress.to_string()
+        ));
+        ecu_thread.join().unwrap();
+    }
+
+    #[test]
+    pub fn test_response_pending() {
+        let (tester_tx, ecu_rx) = mpsc::channel::<CanFrame>();
+        let (ecu_tx, tester_rx) = mpsc::channel::<CanFrame>();
+        let protocol =
+            IsoTpProtocol::new(Box::new(EmuCanChannel::new(tester_tx, tester_rx, "Tester")));
+        let mut ecu = EmuCanChannel::new(ecu_tx, ecu_rx, "ECU");
+        let (tx_req, _rx_req) = tokio::sync::mpsc::unbounded_channel();
+        let (_tx_res, rx_res) = tokio::sync::mpsc::unbounded_channel();
+        let mut client = UDSClientSession::new(protocol, tx_req, rx_res);
+        let busy_count = Arc::new(AtomicUsize::new(0));
+        client.register_waiting_hook({
+            let busy_count = busy_count.clone();
+            move || {
+                busy_count.fetch_add(1, Ordering::Relaxed);
+            }
+        });
+
+        let ecu_thread = std::thread::spawn(move || {
+            // P2 of 50ms and P2* of 200ms
+            assert_eq!(ecu_recv(&mut ecu, false), [0x10, 0x03]);
+            ecu_send(&mut ecu, &[0x50, 0x03, 0x00, 0x32, 0x00, 0x14]);
+
+            // Each response pending restarts P2*
+            assert_eq!(ecu_recv(&mut ecu, false), [0x31, 0x01, 0x02, 0x03]);
+            for _ in 0..3 {
+                ecu_send(&mut ecu, &[0x7F, 0x31, 0x78]);
+                std::thread::sleep(Duration::from_millis(150));
+            }
+            ecu_send(&mut ecu, &[0x71, 0x01, 0x02, 0x03]);
+
+            // No response after the response pending
+            assert_eq!(ecu_recv(&mut ecu, false), [0x31, 0x01, 0x02, 0x04]);
+            ecu_send(&mut ecu, &[0x7F, 0x31, 0x78]);
+        });
+
+        client.init();
+        assert!(client
+            .uds_set_session_mode(UdsSessionType::Extended)
+            .is_ok());
+        assert_eq!(
+            client
+                .send_command_with_response(0x31, &[0x01, 0x02, 0x03])
+                .unwrap(),
+            [0x71, 0x01, 0x02, 0x03]
+        );
+        assert_eq!(busy_count.load(Ordering::Relaxed), 3);
+
+        let start = Instant::now();
+        assert!(matches!(
+            client.send_command_with_response(0x31, &[0x01, 0x02, 0x04]),
+            Err(DiagError::ChannelError(_))
+        ));
+        // Gave up after P2*, not the read timeout
+        assert!(start.elapsed() < Duration::from_secs(2));
+        assert_eq!(busy_count.load(Ordering::Relaxed), 4);
+        ecu_thread.join().unwrap();
+    }
+}
@@ -0,0 +1,126 @@
+//! UDS description for the dynamic diagnostic server, see [DynamicDiagSession]
+//!
+//! [DynamicDiagSession]: crate::core::dynamic_diag::DynamicDiagSession
+
+use std::collections::HashMap;
+
+use automotive_diag::uds::UdsCommand;
+
+use crate::core::dynamic_diag::{
+    DiagAction, DiagPayload, DiagProtocol, DiagSessionMode, SessionTimings,
+};
+use crate::uds::diagnostic_session_control::UdsSessionType;
+use crate::uds::errors::UdsError;
+use crate::uds::security_access::SecurityLevelAccess;
+
+/// Negative response service ID
+const NEGATIVE_RESPONSE: u8 = 0x7F;
+
+/// UDS (ISO-14229-1) diagnostic protocol
+#[derive(Debug, Clone)]
+pub struct UdsProtocol {
+    session_modes: HashMap<u8, DiagSessionMode>,
+}
+
+impl Default for UdsProtocol {
+    fn default() -> Self {
+        let mut protocol = Self {
+            session_modes: HashMap::new(),
+        };
+        for (mode, tp_require, name) in [
+            (UdsSessionType::Default, false, "Default"),
+            (UdsSessionType::Programming, true, "Programming"),
+            (UdsSessionType::Extended, true, "Extended"),
+            (UdsSessionType::SafetySystem, true, "SafetySystem"),
+            (UdsSessionType::StreamMode, true, "StreamMode"),
+        ] {
+            protocol.register_session_type(DiagSessionMode {
+                mode,
+                tp_require,
+                sec_level: SecurityLevelAccess::None,
+                name: name.into(),
+            });
+        }
+        protocol
+    }
+}
+
+impl DiagProtocol<UdsError> for UdsProtocol {
+    fn get_basic_session_mode(&self) -> Option<DiagSessionMode> {
+        self.session_modes
+            .get(&(UdsSessionType::Default as u8))
+            .cloned()
+    }
+
+    fn get_protocol_name(&self) -> &'static str {
+        "UDS"
+    }
+
+    fn process_req_payload(&self, payload: &[u8]) -> DiagAction {
+        match payload {
+            [sid, mode, ..] if *sid == UdsCommand::DiagnosticSessionControl as u8 => {
+                match self.session_modes.get(mode) {
+                    Some(mode) => DiagAction::SetSessionMode(mode.clone()),
+                    None => {
+                        log::warn!("Unknown UDS session mode 0x{mode:02X}, it will not be tracked");
+                        DiagAction::Other {
+                            sid: *sid,
+                            data: payload[1..].to_vec(),
+                        }
+                    }
+                }
+            }
+            [sid, ..] if *sid == UdsCommand::ECUReset as u8 => DiagAction::EcuReset,
+            [sid, data @ ..] => DiagAction::Other {
+                sid: *sid,
+                data: data.to_vec(),
+            },
+            [] => DiagAction::Other {
+                sid: 0,
+                data: vec![],
+            },
+        }
+    }
+
+    fn make_session_control_msg(&self, mode: &DiagSessionMode) -> Vec<u8> {
+        vec![UdsCommand::DiagnosticSessionControl as u8, mode.mode as u8]
+    }
+
+    fn create_tp_msg(response_required: bool) -> DiagPayload {
+        // Bit 7 of the sub function suppresses the positive response
+        let sub_function = if response_required { 0x00 } else { 0x80 };
+        DiagPayload::new(UdsCommand::TesterPresent as u8, &[sub_function])
+    }
+
+    fn process_ecu_response(r: &[u8]) -> Result<Vec<u8>, (u8, UdsError)> {
+        if r.first() == Some(&NEGATIVE_RESPONSE) {
+            let code = r.get(2).copied().unwrap_or(UdsError::GeneralReject as u8);
+            Err((code, UdsError::from(code)))
+        } else {
+            Ok(r.to_vec())
+        }
+    }
+
+    fn get_diagnostic_session_list(&self) -> HashMap<u8, DiagSessionMode> {
+        self.session_modes.clone()
+    }
+
+    fn register_session_type(&mut self, session: DiagSessionMode) {
+        self.session_modes.insert(session.mode as u8, session);
+    }
+
+    /// The session parameter record of the response has P2 in ms and P2* in units of 10ms
+    fn get_session_timings(&self, r: &[u8]) -> Option<SessionTimings> {
+        match r {
+            [sid, _, p2_hi, p2_lo, p2_star_hi, p2_star_lo, ..]
+                if *sid == UdsCommand::DiagnosticSessionControl as u8 + 0x40 =>
+            {
+                Some(SessionTimings {
+                    p2_ms: u32::from(u16::from_be_bytes([*p2_hi, *p2_lo])),
+                    p2_star_ms: u32::from(u16::from_be_bytes([*p2_star_hi, *p2_star_lo])) * 10,
+                })
+            }
+            _ => None,
+        }
+    }
+}
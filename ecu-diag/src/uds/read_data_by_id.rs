@@ -1,58 +1,43 @@
 //!  Provides methods to read data by identifier
 
-use crate::api::UdsMonitorViewResponse;
 use crate::api::UdsMonitorViewResponseDetail;
+use crate::core::{DiagError, DiagServerResult};
 use crate::uds::UDSClientSession;
-use crate::uds::UdsSericeResponseDetail;
-use crate::uds::UdsServiceResponse;
 
 use automotive_diag::uds::UdsCommand;
+use std::fmt;
 
 impl UDSClientSession {
     /// Requests the ECU to go into a specific diagnostic session mode
-    pub fn uds_read_data_by_id(&mut self, sub_fcn: DataId) -> UdsServiceResponse {
-        // Convert u16 to bytes using big-endian (most significant byte first)
-        let sub_fcn_bytes = sub_fcn.to_bytes();
-
-        // Prepare the payload by concatenating the sub_fcn bytes
-        let payload = vec![sub_fcn_bytes[0], sub_fcn_bytes[1]];
-        let resp = self.send_command_with_response(UdsCommand::ReadDataByIdentifier, &payload);
-
-        if !resp.is_empty() {
-            if resp[0] == (UdsCommand::ReadDataByIdentifier as u8 + 0x40) {
-                // Success
-                return UdsServiceResponse::Success(UdsSericeResponseDetail {
-                    console_output: sub_fcn.parse_result(&resp[3..]),
-                });
-            } else {
-                // Fail
-                return UdsServiceResponse::Fail(UdsSericeResponseDetail {
-                    console_output: String::from("FAIL\nNEGATIVE UDS RESPONSE"),
-                });
-            }
-        }
+    pub fn uds_read_data_by_id(&mut self, sub_fcn: DataId) -> DiagServerResult<Vec<DataField>> {
+        let record = self.uds_read_data_record(sub_fcn)?;
+        sub_fcn.parse_result(&record)
+    }
 
-        // Fail
-        UdsServiceResponse::Fail(UdsSericeResponseDetail {
-            console_output: String::from("FAIL\nEMPTY UDS RESPONSE"),
-        })
+    pub fn uds_read_data_by_id_return_struct(
+        &mut self,
+        sub_fcn: DataId,
+    ) -> DiagServerResult<UdsMonitorViewResponseDetail> {
+        let record = self.uds_read_data_record(sub_fcn)?;
+        sub_fcn.parse_result_to_struct(&record)
     }
 
-    pub fn uds_read_data_by_id_return_struct(&mut self, sub_fcn: DataId) -> UdsMonitorViewResponse {
+    /// Reads the raw data record of `sub_fcn`
+    fn uds_read_data_record(&mut self, sub_fcn: DataId) -> DiagServerResult<Vec<u8>> {
         // Convert u16 to bytes using big-endian (most significant byte first)
         let sub_fcn_bytes = sub_fcn.to_bytes();
 
         // Prepare the payload by concatenating the sub_fcn bytes
         let payload = vec![sub_fcn_bytes[0], sub_fcn_bytes[1]];
-        let resp = self.send_command_with_response(UdsCommand::ReadDataByIdentifier, &payload);
-
-        if resp[0] == (UdsCommand::ReadDataByIdentifier as u8 + 0x40) {
-            // Success
-            let detail = sub_fcn.parse_result_to_struct(&resp[3..]);
-            UdsMonitorViewResponse::Success(detail)
-        } else {
-            // Fail
-            UdsMonitorViewResponse::Fail
+        let resp = self.send_command_with_response(UdsCommand::ReadDataByIdentifier, &payload)?;
+
+        match resp.get(1..3) {
+            Some(id) if id == payload => Ok(resp[3..].to_vec()),
+            Some(id) => Err(DiagError::MismatchedIdentResponse {
+                want: sub_fcn as u16,
+                received: u16::from_be_bytes([id[0], id[1]]),
+            }),
+            None => Err(DiagError::InvalidResponseLength),
         }
     }
 
@@ -64,6 +49,46 @@ impl UDSClientSession {
     }
 }
 
+/// Value of a field of a data record
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DataValue {
+    U8(u8),
+    U16(u16),
+    U32(u32),
+    F32(f32),
+}
+
+impl fmt::Display for DataValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DataValue::U8(v) => write!(f, "{v}"),
+            DataValue::U16(v) => write!(f, "{v}"),
+            DataValue::U32(v) => write!(f, "{v}"),
+            // Two decimals are enough for all sensor values
+            DataValue::F32(v) => write!(f, "{v:.2}"),
+        }
+    }
+}
+
+/// Named field of a data record, see [UDSClientSession::uds_read_data_by_id]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DataField {
+    pub name: &'static str,
+    pub value: DataValue,
+}
+
+impl DataField {
+    fn new(name: &'static str, value: DataValue) -> Self {
+        Self { name, value }
+    }
+}
+
+impl fmt::Display for DataField {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.name, self.value)
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
 pub enum DataId {
     BikeState = 0x0100,
@@ -110,258 +135,231 @@ impl DataId {
     }
 
     #[allow(unreachable_patterns)]
-    fn parse_result(&self, result: &[u8]) -> String {
-        let mut output = String::new();
+    fn parse_result(&self, result: &[u8]) -> DiagServerResult<Vec<DataField>> {
+        let mut fields = Vec::new();
         match self {
             DataId::BikeState => {
-                print!("Bike State len: {}, ", result.len());
                 if result.len() == 2 {
-                    print!("Bike State: {}, ", result[0]);
-                    output.push_str(&format!("Bike State: {}, ", result[0]));
+                    fields.push(DataField::new("Bike State", DataValue::U8(result[0])));
 
-                    println!("Bike Lock: {}", result[1]);
-                    output.push_str(&format!("Bike Lock: {}", result[1]));
+                    fields.push(DataField::new("Bike Lock", DataValue::U8(result[1])));
                 } else {
-                    println!("Invalid data length for BikeState");
-                    output.push_str("Invalid data length for BikeState");
+                    return Err(DiagError::InvalidResponseLength);
                 }
             }
             DataId::SwitchGear => {
-                print!("Switch gear len: {}, ", result.len());
                 if result.len() == 19 {
-                    print!("Right Brake Switch: {}, ", result[0]);
-                    output.push_str(&format!("Right Brake Switch: {}, ", result[0]));
+                    fields.push(DataField::new(
+                        "Right Brake Switch",
+                        DataValue::U8(result[0]),
+                    ));
 
-                    print!("Left Brake Switch: {}, ", result[1]);
-                    output.push_str(&format!("Left Brake Switch: {}, ", result[1]));
+                    fields.push(DataField::new(
+                        "Left Brake Switch",
+                        DataValue::U8(result[1]),
+                    ));
 
-                    print!("Kill Switch: {}, ", result[2]);
-                    output.push_str(&format!("Kill Switch: {}, ", result[2]));
+                    fields.push(DataField::new("Kill Switch", DataValue::U8(result[2])));
 
-                    print!("Power Switch: {}, ", result[3]);
-                    output.push_str(&format!("Power Switch: {}, ", result[3]));
+                    fields.push(DataField::new("Power Switch", DataValue::U8(result[3])));
 
-                    print!("Reverse Switch: {}, ", result[4]);
-                    output.push_str(&format!("Reverse Switch: {}, ", result[4]));
+                    fields.push(DataField::new("Reverse Switch", DataValue::U8(result[4])));
 
                     //print!("Seat Switch: {}, ", result[5]);
                     //output.push_str(&format!("Seat Switch: {}, ", result[5]));
 
-                    print!("Side Stand Switch: {}, ", result[6]);
-                    output.push_str(&format!("Side Stand Switch: {}, ", result[6]));
+                    fields.push(DataField::new(
+                        "Side Stand Switch",
+                        DataValue::U8(result[6]),
+                    ));
 
                     //print!("Trip Switch: {}, ", result[7]);
                     //output.push_str(&format!("Trip Switch: {}, ", result[7]));
 
-                    print!("Ride Mode Switch: {}, ", result[8]);
-                    output.push_str(&format!("Ride Mode Switch: {}, ", result[8]));
+                    fields.push(DataField::new("Ride Mode Switch", DataValue::U8(result[8])));
 
-                    print!("Hazard Switch: {}, ", result[9]);
-                    output.push_str(&format!("Hazard Switch: {}, ", result[9]));
+                    fields.push(DataField::new("Hazard Switch", DataValue::U8(result[9])));
 
-                    print!("Horn Switch: {}, ", result[10]);
-                    output.push_str(&format!("Horn Switch: {}, ", result[10]));
+                    fields.push(DataField::new("Horn Switch", DataValue::U8(result[10])));
 
-                    print!("Right Indicator Switch: {}, ", result[11]);
-                    output.push_str(&format!("Right Indicator Switch: {}, ", result[11]));
+                    fields.push(DataField::new(
+                        "Right Indicator Switch",
+                        DataValue::U8(result[11]),
+                    ));
 
-                    print!("Left Indicator Switch: {}, ", result[12]);
-                    output.push_str(&format!("Left Indicator Switch: {}, ", result[12]));
+                    fields.push(DataField::new(
+                        "Left Indicator Switch",
+                        DataValue::U8(result[12]),
+                    ));
 
                     //print!("Passing Switch: {}, ", result[13]);
                     //output.push_str(&format!("Passing Switch: {}, ", result[13]));
 
-                    print!("High Beam Switch: {}, ", result[14]);
-                    output.push_str(&format!("High Beam Switch: {}, ", result[14]));
+                    fields.push(DataField::new(
+                        "High Beam Switch",
+                        DataValue::U8(result[14]),
+                    ));
 
-                    print!("Start Switch: {}, ", result[15]);
-                    output.push_str(&format!("Start Switch: {}, ", result[15]));
+                    fields.push(DataField::new("Start Switch", DataValue::U8(result[15])));
 
-                    print!("Seat Switch: {}, ", result[16]);
-                    output.push_str(&format!("Seat Switch: {}, ", result[16]));
+                    fields.push(DataField::new("Seat Switch", DataValue::U8(result[16])));
 
-                    print!("Trip Switch: {}, ", result[17]);
-                    output.push_str(&format!("Trip Switch: {}, ", result[17]));
+                    fields.push(DataField::new("Trip Switch", DataValue::U8(result[17])));
 
-                    println!("Down Switch: {}", result[18]);
-                    output.push_str(&format!("Down Switch: {}", result[18]));
+                    fields.push(DataField::new("Down Switch", DataValue::U8(result[18])));
                 } else {
-                    println!("Invalid data length for SwitchGear");
-                    output.push_str("Invalid data length for SwitchGear");
+                    return Err(DiagError::InvalidResponseLength);
                 }
             }
             DataId::ComponentError => {
-                println!("DTC result len: {}", result.len());
-
                 if result.len() == 18 {
-                    print!("System Component: {}, ", result[0]);
-                    output.push_str(&format!("System Component: {}, ", result[0]));
+                    fields.push(DataField::new("System Component", DataValue::U8(result[0])));
 
-                    print!("System Fault Code: {}, ", result[1]);
-                    output.push_str(&format!("System Fault Code: {}, ", result[1]));
+                    fields.push(DataField::new(
+                        "System Fault Code",
+                        DataValue::U8(result[1]),
+                    ));
 
-                    print!("System Level: {}, ", result[2]);
-                    output.push_str(&format!("System Level: {}, ", result[2]));
+                    fields.push(DataField::new("System Level", DataValue::U8(result[2])));
 
-                    print!("BMS Component: {}, ", result[3]);
-                    output.push_str(&format!("BMS Component: {}, ", result[3]));
+                    fields.push(DataField::new("BMS Component", DataValue::U8(result[3])));
 
-                    print!("BMS Fault Code: {}, ", result[4]);
-                    output.push_str(&format!("BMS Fault Code: {}, ", result[4]));
+                    fields.push(DataField::new("BMS Fault Code", DataValue::U8(result[4])));
 
-                    print!("BMS Level: {}, ", result[5]);
-                    output.push_str(&format!("BMS Level: {}, ", result[5]));
+                    fields.push(DataField::new("BMS Level", DataValue::U8(result[5])));
 
-                    print!("MC Component: {}, ", result[6]);
-                    output.push_str(&format!("MC Component: {}, ", result[6]));
+                    fields.push(DataField::new("MC Component", DataValue::U8(result[6])));
 
-                    print!("MC Fault Code: {}, ", result[7]);
-                    output.push_str(&format!("MC Fault Code: {}, ", result[7]));
+                    fields.push(DataField::new("MC Fault Code", DataValue::U8(result[7])));
 
-                    print!("MC Level: {}, ", result[8]);
-                    output.push_str(&format!("MC Level: {}, ", result[8]));
+                    fields.push(DataField::new("MC Level", DataValue::U8(result[8])));
 
-                    print!("OBC Component: {}, ", result[9]);
-                    output.push_str(&format!("OBC Component: {}, ", result[9]));
+                    fields.push(DataField::new("OBC Component", DataValue::U8(result[9])));
 
-                    print!("OBC Fault Code: {}, ", result[10]);
-                    output.push_str(&format!("OBC Fault Code: {}, ", result[10]));
+                    fields.push(DataField::new("OBC Fault Code", DataValue::U8(result[10])));
 
-                    print!("OBC Level: {}, ", result[11]);
-                    output.push_str(&format!("OBC Level: {}, ", result[11]));
+                    fields.push(DataField::new("OBC Level", DataValue::U8(result[11])));
 
-                    print!("Output Component: {}, ", result[12]);
-                    output.push_str(&format!("Output Component: {}, ", result[12]));
+                    fields.push(DataField::new(
+                        "Output Component",
+                        DataValue::U8(result[12]),
+                    ));
 
-                    print!("Output Fault Code: {}, ", result[13]);
-                    output.push_str(&format!("Output Fault Code: {}, ", result[13]));
+                    fields.push(DataField::new(
+                        "Output Fault Code",
+                        DataValue::U8(result[13]),
+                    ));
 
-                    println!("Output Level: {}", result[14]);
-                    output.push_str(&format!("Output Level: {}", result[14]));
+                    fields.push(DataField::new("Output Level", DataValue::U8(result[14])));
 
-                    print!("Feature Component: {}, ", result[15]);
-                    output.push_str(&format!("Feature Component: {}, ", result[15]));
+                    fields.push(DataField::new(
+                        "Feature Component",
+                        DataValue::U8(result[15]),
+                    ));
 
-                    print!("Feature Fault Code: {}, ", result[16]);
-                    output.push_str(&format!("Feature Fault Code: {}, ", result[16]));
+                    fields.push(DataField::new(
+                        "Feature Fault Code",
+                        DataValue::U8(result[16]),
+                    ));
 
-                    println!("Feature Level: {}", result[17]);
-                    output.push_str(&format!("Feature Level: {}", result[17]));
+                    fields.push(DataField::new("Feature Level", DataValue::U8(result[17])));
                 } else {
-                    println!("Invalid data length for Component error");
-                    output.push_str("Invalid data length for Component error");
+                    return Err(DiagError::InvalidResponseLength);
                 }
             }
             DataId::ImuRaw => {
-                println!("IMU len: {}, ", result.len());
                 if result.len() == 24 {
-                    let templates = [
-                        "ACC X: ", "ACC Y: ", "ACC Z: ", "GYR X: ", "GYR Y: ", "GYR Z: ",
-                    ];
-                    let ends = [", ", ", ", ", ", ", ", ", ", "\n"];
+                    let names = ["ACC X", "ACC Y", "ACC Z", "GYR X", "GYR Y", "GYR Z"];
                     for (i, chunk) in result.chunks_exact(4).enumerate() {
                         let mut bytes: [u8; 4] = [0; 4];
                         bytes.copy_from_slice(chunk);
-                        output
-                            .push_str(&format!("{}\n", print_float(templates[i], ends[i], bytes)));
+                        fields.push(DataField::new(names[i], DataValue::F32(to_float(bytes))));
                     }
                 } else {
-                    println!("Invalid data length for IMU");
-                    output.push_str("Invalid data length for IMU");
+                    return Err(DiagError::InvalidResponseLength);
                 }
             }
             DataId::KeyfobState => {
-                print!("keyfob len: {}, ", result.len());
-
                 if result.len() == 3 {
-                    print!("RKE: {}, ", result[0]);
-                    output.push_str(&format!("RKE: {}, ", result[0]));
+                    fields.push(DataField::new("RKE", DataValue::U8(result[0])));
 
-                    print!("PKE: {}, ", result[1]);
-                    output.push_str(&format!("PKE: {}, ", result[1]));
+                    fields.push(DataField::new("PKE", DataValue::U8(result[1])));
 
-                    println!("PKE Distance: {}", result[2]);
-                    output.push_str(&format!("PKE Distance: {}", result[2]));
+                    fields.push(DataField::new("PKE Distance", DataValue::U8(result[2])));
                 } else {
-                    println!("Invalid data length for Keyfob");
-                    output.push_str("Invalid data length for Keyfob");
+                    return Err(DiagError::InvalidResponseLength);
                 }
             }
             DataId::PerformanceCharge => {
-                println!("Performace Charge result len: {}", result.len());
-
                 if result.len() == 12 {
                     let _bytes: [u8; 4] = [0; 4];
                     let mut i = 0;
 
-                    print!("Target Charge SOC PCT: {}, ", result[i]);
-                    output.push_str(&format!("Target Charge SOC PCT: {}\n", result[i]));
-
+                    fields.push(DataField::new(
+                        "Target Charge SOC PCT",
+                        DataValue::U8(result[i]),
+                    ));
                     i += 1;
 
-                    print!("Tartget Charge Hours Rem: {}, ", result[i]);
-                    output.push_str(&format!("Tartget Charge Hours Rem: {}\n", result[i]));
-
+                    fields.push(DataField::new(
+                        "Tartget Charge Hours Rem",
+                        DataValue::U8(result[i]),
+                    ));
                     i += 1;
 
-                    print!("Tartget Charge Min Rem: {}, ", result[i]);
-                    output.push_str(&format!("Tartget Charge Min Rem: {}\n", result[i]));
-
+                    fields.push(DataField::new(
+                        "Tartget Charge Min Rem",
+                        DataValue::U8(result[i]),
+                    ));
                     i += 1;
 
                     let mut bytes_u16: [u8; 2] = [0; 2];
                     bytes_u16.copy_from_slice(&result[i..i + 2]);
-                    output.push_str(&format!(
-                        "{} ",
-                        print_uint16("Tartget Charge Range: ", "\n", bytes_u16)
+                    fields.push(DataField::new(
+                        "Tartget Charge Range",
+                        DataValue::U16(to_uint16(bytes_u16)),
                     ));
                     i += 2;
 
-                    print!("Charge Complete: {}, ", result[i]);
-                    output.push_str(&format!("Charge Complete: {}\n", result[i]));
-
+                    fields.push(DataField::new("Charge Complete", DataValue::U8(result[i])));
                     i += 1;
 
-                    print!("SOC Limit: {}, ", result[i]);
-                    output.push_str(&format!("SOC Limit: {}\n", result[i]));
-
+                    fields.push(DataField::new("SOC Limit", DataValue::U8(result[i])));
                     i += 1;
 
-                    print!("SOC Limit Selection Page: {}, ", result[i]);
-                    output.push_str(&format!("SOC Limit Selection Page: {}\n", result[i]));
-
+                    fields.push(DataField::new(
+                        "SOC Limit Selection Page",
+                        DataValue::U8(result[i]),
+                    ));
                     i += 1;
                     bytes_u16.copy_from_slice(&result[i..i + 2]);
-                    output.push_str(&format!("{} ", print_uint16("VA Limit: ", "\n", bytes_u16)));
+                    fields.push(DataField::new(
+                        "VA Limit",
+                        DataValue::U16(to_uint16(bytes_u16)),
+                    ));
                     i += 2;
 
-                    print!("VA Limit Selection Page: {}, ", result[i]);
-                    output.push_str(&format!("VA Limit Selection Page: {}\n", result[i]));
-
+                    fields.push(DataField::new(
+                        "VA Limit Selection Page",
+                        DataValue::U8(result[i]),
+                    ));
                     i += 1;
 
-                    println!("Store Cable Noti: {}", result[i]);
-                    output.push_str(&format!("Store Cable Noti: {}\n", result[i]));
+                    fields.push(DataField::new("Store Cable Noti", DataValue::U8(result[i])));
                 } else {
-                    println!("Invalid data length for Performance");
-                    output.push_str("Invalid data length for Performance");
+                    return Err(DiagError::InvalidResponseLength);
                 }
             }
             DataId::PerformanceVehicle1 => {
-                println!("Performace Vehicle1 result len: {}", result.len());
-
                 if result.len() == 17 {
                     let mut bytes: [u8; 4] = [0; 4];
                     let mut i = 0;
 
-                    print!("Persist: {}, ", result[i]);
-                    output.push_str(&format!("Persist: {}\n", result[i]));
-
+                    fields.push(DataField::new("Persist", DataValue::U8(result[i])));
                     i += 1;
 
                     bytes.copy_from_slice(&result[i..i + 4]);
-                    output.push_str(&format!("{} ", print_uint32("Odometer: ", "\n", bytes)));
-
+                    fields.push(DataField::new("Odometer", DataValue::U32(to_uint32(bytes))));
                     i += 4;
 
                     // println!("ODOMETER BYTES: ");
@@ -370,26 +368,23 @@ impl DataId {
                     // }
 
                     bytes.copy_from_slice(&result[i..i + 4]);
-                    output.push_str(&format!("{} ", print_uint32("TripA: ", "\n", bytes)));
+                    fields.push(DataField::new("TripA", DataValue::U32(to_uint32(bytes))));
                     i += 4;
 
                     bytes.copy_from_slice(&result[i..i + 4]);
-                    output.push_str(&format!("{} ", print_uint32("TripB: ", "\n", bytes)));
+                    fields.push(DataField::new("TripB", DataValue::U32(to_uint32(bytes))));
                     i += 4;
 
                     bytes.copy_from_slice(&result[i..i + 4]);
-                    output.push_str(&format!("{} ", print_uint32("Last Charge: ", "\n", bytes)));
-                    i += 4;
-
-                    println!("Last index {} ", i);
+                    fields.push(DataField::new(
+                        "Last Charge",
+                        DataValue::U32(to_uint32(bytes)),
+                    ));
                 } else {
-                    println!("Invalid data length for Performance");
-                    output.push_str("Invalid data length for Performance");
+                    return Err(DiagError::InvalidResponseLength);
                 }
             }
             DataId::PerformanceVehicle2 => {
-                println!("Performace Vehicle2 result len: {}", result.len());
-
                 if result.len() == 15 {
                     // println!("Performace Vehicle2 BYTES: ");
                     // for byte in result {
@@ -399,674 +394,622 @@ impl DataId {
                     let mut bytes: [u8; 4] = [0; 4];
                     let mut i = 0;
                     bytes.copy_from_slice(&result[i..i + 4]);
-                    output.push_str(&format!("{} ", print_float("Efficiency: ", "\n", bytes)));
+                    fields.push(DataField::new(
+                        "Efficiency",
+                        DataValue::F32(to_float(bytes)),
+                    ));
                     i += 4;
 
                     bytes.copy_from_slice(&result[i..i + 4]);
-                    output.push_str(&format!("{} ", print_float("Power PCT: ", "\n", bytes)));
+                    fields.push(DataField::new("Power PCT", DataValue::F32(to_float(bytes))));
                     i += 4;
 
                     bytes.copy_from_slice(&result[i..i + 4]);
-                    output.push_str(&format!("{} ", print_float("Speed: ", "\n", bytes)));
-
+                    fields.push(DataField::new("Speed", DataValue::F32(to_float(bytes))));
                     i += 4;
 
-                    print!("TripID: {}, ", result[i]);
-                    output.push_str(&format!("TripID: {}\n", result[i]));
-
+                    fields.push(DataField::new("TripID", DataValue::U8(result[i])));
                     i += 1;
 
-                    print!("Trip Action: {}, ", result[i]);
-                    output.push_str(&format!("Trip Action: {}\n", result[i]));
-
-                    i += 1;
-
-                    print!("Range: {}, ", result[i]);
-                    output.push_str(&format!("Range: {}\n", result[i]));
+                    fields.push(DataField::new("Trip Action", DataValue::U8(result[i])));
                     i += 1;
 
-                    println!("Last index {} ", i);
+                    fields.push(DataField::new("Range", DataValue::U8(result[i])));
                 } else {
-                    println!("Invalid data length for Performance");
-                    output.push_str("Invalid data length for Vehicle Metrics 2");
+                    return Err(DiagError::InvalidResponseLength);
                 }
             }
             DataId::FirmwareVersion => {
-                print!("Firmware len: {}, ", result.len());
                 if result.len() == 6 {
                     let mut bytes_u16: [u8; 2] = [0; 2];
                     bytes_u16.copy_from_slice(&result[0..2]);
-                    output.push_str(&format!("{} ", print_uint16("148 Major Version: ", "\n", bytes_u16)));
+                    fields.push(DataField::new(
+                        "148 Major Version",
+                        DataValue::U16(to_uint16(bytes_u16)),
+                    ));
 
                     bytes_u16.copy_from_slice(&result[2..4]);
-                    output.push_str(&format!("{} ", print_uint16("148 Minor Version: ", "\n", bytes_u16)));
-
-                    output.push_str(&format!("118 Major Version: {}\n", result[4]));
-                    output.push_str(&format!("118 Minor Version: {}\n", result[5]));
+                    fields.push(DataField::new(
+                        "148 Minor Version",
+                        DataValue::U16(to_uint16(bytes_u16)),
+                    ));
 
+                    fields.push(DataField::new(
+                        "118 Major Version",
+                        DataValue::U8(result[4]),
+                    ));
+                    fields.push(DataField::new(
+                        "118 Minor Version",
+                        DataValue::U8(result[5]),
+                    ));
                 } else {
-                    println!("Invalid data length for Firmware Version");
-                    output.push_str("Invalid data length for Firmware Version");
+                    return Err(DiagError::InvalidResponseLength);
                 }
             }
             DataId::AdcVoltage => {
-                print!("ADC len: {}, ", result.len());
-
                 if result.len() == 20 {
-                    let templates = [
-                        "Volt 12V: ",
-                        "Volt 5V: ",
-                        "Volt 3V: ",
-                        "Throttle PCT: ",
-                        "Throttle Filt: ",
+                    let names = [
+                        "Volt 12V",
+                        "Volt 5V",
+                        "Volt 3V",
+                        "Throttle PCT",
+                        "Throttle Filt",
                     ];
-                    let ends = ["\n", "\n", "\n", "\n", "\n"];
                     for (i, chunk) in result.chunks_exact(4).enumerate() {
                         let mut bytes: [u8; 4] = [0; 4];
                         bytes.copy_from_slice(chunk);
-                        output.push_str(&format!("{} ", print_float(templates[i], ends[i], bytes)));
+                        fields.push(DataField::new(names[i], DataValue::F32(to_float(bytes))));
                     }
                 } else {
-                    println!("Invalid data length for ADC");
-                    output.push_str("Invalid data length for ADC");
+                    return Err(DiagError::InvalidResponseLength);
                 }
             }
             DataId::Bms1 => {
-                println!("BMS1 result len: {}", result.len());
                 if result.len() == 18 {
                     let mut bytes: [u8; 4] = [0; 4];
                     let mut i = 0;
 
-                    print!("BMS Status: {}, ", result[i]);
-                    output.push_str(&format!("BMS Status: {}\n", result[i]));
-
+                    fields.push(DataField::new("BMS Status", DataValue::U8(result[i])));
                     i += 1;
 
-                    print!("Pre-discharge relay: {}, ", result[i]);
-                    output.push_str(&format!("Pre-discharge relay: {}\n", result[i]));
-
+                    fields.push(DataField::new(
+                        "Pre-discharge relay",
+                        DataValue::U8(result[i]),
+                    ));
                     i += 1;
 
-                    print!("Discharge relay: {}, ", result[i]);
-                    output.push_str(&format!("Discharge relay: {}\n", result[i]));
-
+                    fields.push(DataField::new("Discharge relay", DataValue::U8(result[i])));
                     i += 1;
 
-                    print!("Charging relay: {}, ", result[i]);
-                    output.push_str(&format!("Charging relay: {}\n", result[i]));
-
+                    fields.push(DataField::new("Charging relay", DataValue::U8(result[i])));
                     i += 1;
 
-                    print!("DC-DC enable: {}, ", result[i]);
-                    output.push_str(&format!("DC-DC enable: {}\n", result[i]));
-
+                    fields.push(DataField::new("DC-DC enable", DataValue::U8(result[i])));
                     i += 1;
 
-                    print!("Charger: {}, ", result[i]);
-                    output.push_str(&format!("Charger: {}\n", result[i]));
-
+                    fields.push(DataField::new("Charger", DataValue::U8(result[i])));
                     i += 1;
 
-                    print!("SOC PCT: {}, ", result[i]);
-                    output.push_str(&format!("SOC PCT: {}\n", result[i]));
-
+                    fields.push(DataField::new("SOC PCT", DataValue::U8(result[i])));
                     i += 1;
 
-                    print!("SOH PCT: {}, ", result[i]);
-                    output.push_str(&format!("SOH PCT: {}\n", result[i]));
-
+                    fields.push(DataField::new("SOH PCT", DataValue::U8(result[i])));
                     i += 1;
 
                     bytes.copy_from_slice(&result[i..i + 4]);
-                    output.push_str(&format!("{} ", print_float("BMS Voltage: ", "\n", bytes)));
+                    fields.push(DataField::new(
+                        "BMS Voltage",
+                        DataValue::F32(to_float(bytes)),
+                    ));
                     i += 4;
 
                     bytes.copy_from_slice(&result[i..i + 4]);
-                    output.push_str(&format!("{} ", print_float("BMS Current: ", "\n", bytes)));
+                    fields.push(DataField::new(
+                        "BMS Current",
+                        DataValue::F32(to_float(bytes)),
+                    ));
                     i += 4;
 
-                    print!("Alive counter: {}\n, ", result[i]);
-                    output.push_str(&format!("Alive counter: {}\n", result[i]));
-                    i += 1;
-
-                    print!("DC-DC enable status: {}\n, ", result[i]);
-                    output.push_str(&format!("DC-DC enable status: {}\n", result[i]));
+                    fields.push(DataField::new("Alive counter", DataValue::U8(result[i])));
                     i += 1;
 
-                    println!("Last index is: {}", i);
+                    fields.push(DataField::new(
+                        "DC-DC enable status",
+                        DataValue::U8(result[i]),
+                    ));
                 } else {
-                    println!("Invalid data length for Bms");
-                    output.push_str("Invalid data length for Bms1");
+                    return Err(DiagError::InvalidResponseLength);
                 }
             }
             DataId::Bms2 => {
-                println!("BMS2 result len: {}", result.len());
                 if result.len() == 8 {
                     let mut i = 0;
 
                     let mut bytes_u16: [u8; 2] = [0; 2];
                     bytes_u16.copy_from_slice(&result[i..i + 2]);
-                    output.push_str(&format!(
-                        "{} ",
-                        print_uint16("Max discharge current: ", "\n", bytes_u16)
+                    fields.push(DataField::new(
+                        "Max discharge current",
+                        DataValue::U16(to_uint16(bytes_u16)),
                     ));
                     i += 2;
 
                     bytes_u16.copy_from_slice(&result[i..i + 2]);
-                    output.push_str(&format!(
-                        "{} ",
-                        print_uint16("Max regen current: ", "\n", bytes_u16)
+                    fields.push(DataField::new(
+                        "Max regen current",
+                        DataValue::U16(to_uint16(bytes_u16)),
                     ));
                     i += 2;
 
                     bytes_u16.copy_from_slice(&result[i..i + 2]);
-                    output.push_str(&format!(
-                        "{} ",
-                        print_uint16("Highest cell voltage: ", "\n", bytes_u16)
+                    fields.push(DataField::new(
+                        "Highest cell voltage",
+                        DataValue::U16(to_uint16(bytes_u16)),
                     ));
                     i += 2;
 
                     bytes_u16.copy_from_slice(&result[i..i + 2]);
-                    output.push_str(&format!(
-                        "{} ",
-                        print_uint16("Lowest cell voltage: ", "\n", bytes_u16)
+                    fields.push(DataField::new(
+                        "Lowest cell voltage",
+                        DataValue::U16(to_uint16(bytes_u16)),
                     ));
-                    i += 2;
-
-                    println!("Last index is: {}", i);
 
                     // This print skip priting errors for now
                 } else {
-                    println!("Invalid data length for Bms");
-                    output.push_str("Invalid data length for Bms");
+                    return Err(DiagError::InvalidResponseLength);
                 }
             }
             DataId::Bms3 => {
-                println!("BMS3 result len: {}", result.len());
                 if result.len() == 6 {
                     let mut i = 0;
                     let mut bytes_u16: [u8; 2] = [0; 2];
 
-                    print!("Max temp: {}, ", result[i]);
-                    output.push_str(&format!("Max temp: {}\n", result[i]));
-
+                    fields.push(DataField::new("Max temp", DataValue::U8(result[i])));
                     i += 1;
 
-                    print!("Max temp number: {}, ", result[i]);
-                    output.push_str(&format!("Max temp number: {}\n", result[i]));
-
+                    fields.push(DataField::new("Max temp number", DataValue::U8(result[i])));
                     i += 1;
 
-                    print!("Min temp: {}, ", result[i]);
-                    output.push_str(&format!("Min temp: {}\n", result[i]));
-
+                    fields.push(DataField::new("Min temp", DataValue::U8(result[i])));
                     i += 1;
 
-                    print!("Min temp number: {}, ", result[i]);
-                    output.push_str(&format!("Min temp number: {}\n", result[i]));
-
+                    fields.push(DataField::new("Min temp number", DataValue::U8(result[i])));
                     i += 1;
 
                     bytes_u16.copy_from_slice(&result[i..i + 2]);
-                    output.push_str(&format!(
-                        "{} ",
-                        print_uint16("Charge discharge cycles: ", "\n", bytes_u16)
+                    fields.push(DataField::new(
+                        "Charge discharge cycles",
+                        DataValue::U16(to_uint16(bytes_u16)),
                     ));
-                    i += 2;
-
-                    println!("Last index is: {}", i);
 
                     // This print skip priting errors for now
                 } else {
-                    println!("Invalid data length for Bms3");
-                    output.push_str("Invalid data length for Bms3");
+                    return Err(DiagError::InvalidResponseLength);
                 }
             }
             DataId::Obc => {
-                println!("OBC result len: {}", result.len());
                 if result.len() == 15 {
                     let mut i = 0;
                     let mut bytes_u16: [u8; 2] = [0; 2];
 
-                    print!("Activation Status: {}, ", result[i]);
-                    output.push_str(&format!("Activation Status: {}\n", result[i]));
+                    fields.push(DataField::new(
+                        "Activation Status",
+                        DataValue::U8(result[i]),
+                    ));
                     i += 1;
 
                     bytes_u16.copy_from_slice(&result[i..i + 2]);
-                    output.push_str(&format!(
-                        "{} ",
-                        print_uint16("Output DC Volt: ", "\n", bytes_u16)
+                    fields.push(DataField::new(
+                        "Output DC Volt",
+                        DataValue::U16(to_uint16(bytes_u16)),
                     ));
                     i += 2;
 
                     bytes_u16.copy_from_slice(&result[i..i + 2]);
-                    output.push_str(&format!(
-                        "{} ",
-                        print_uint16("Output DC Current: ", "\n", bytes_u16)
+                    fields.push(DataField::new(
+                        "Output DC Current",
+                        DataValue::U16(to_uint16(bytes_u16)),
                     ));
                     i += 2;
 
-                    print!("Max Temp: {}, ", result[i]);
-                    output.push_str(&format!("Max Temp: {}\n", result[i]));
-                    i += 1;
-
-                    print!("AC Input Volt: {}, ", result[i]);
-                    output.push_str(&format!("AC Input Volt: {}\n", result[i]));
+                    fields.push(DataField::new("Max Temp", DataValue::U8(result[i])));
                     i += 1;
 
-                    print!("AC Input Current: {}, ", result[i]);
-                    output.push_str(&format!("AC Input Current: {}\n", result[i]));
+                    fields.push(DataField::new("AC Input Volt", DataValue::U8(result[i])));
                     i += 1;
 
-                    print!("Stop tx: {}, ", result[i]);
-                    output.push_str(&format!("Stop tx: {}\n", result[i]));
+                    fields.push(DataField::new("AC Input Current", DataValue::U8(result[i])));
                     i += 1;
 
-                    print!("Alive_counter: {}, ", result[i]);
-                    output.push_str(&format!("Alive_counter: {}\n", result[i]));
+                    fields.push(DataField::new("Stop tx", DataValue::U8(result[i])));
                     i += 1;
 
-                    print!("Error 1 hardware: {}, ", result[i]);
-                    output.push_str(&format!("Error 1 hardware: {}\n", result[i]));
+                    fields.push(DataField::new("Alive_counter", DataValue::U8(result[i])));
                     i += 1;
 
-                    print!("Error 2 temp: {}, ", result[i]);
-                    output.push_str(&format!("Error 2 temp: {}\n", result[i]));
+                    fields.push(DataField::new("Error 1 hardware", DataValue::U8(result[i])));
                     i += 1;
 
-                    print!("Error 3 current: {}, ", result[i]);
-                    output.push_str(&format!("Error 3 current: {}\n", result[i]));
+                    fields.push(DataField::new("Error 2 temp", DataValue::U8(result[i])));
                     i += 1;
 
-                    print!("Error 4 volt in: {}, ", result[i]);
-                    output.push_str(&format!("Error  4 volt in: {}\n", result[i]));
+                    fields.push(DataField::new("Error 3 current", DataValue::U8(result[i])));
                     i += 1;
 
-                    print!("Error 5 comn: {}, ", result[i]);
-                    output.push_str(&format!("Error 5 comn: {}\n", result[i]));
+                    fields.push(DataField::new("Error  4 volt in", DataValue::U8(result[i])));
                     i += 1;
 
-                    println!("Last index is: {}", i);
+                    fields.push(DataField::new("Error 5 comn", DataValue::U8(result[i])));
                 } else {
-                    println!("Invalid data length for Bms3");
-                    output.push_str("Invalid data length for Bms3");
+                    return Err(DiagError::InvalidResponseLength);
                 }
             }
             DataId::Dashboard => {
-                println!("Dashboard result len: {}", result.len());
                 if result.len() == 156 {
                     let mut bytes: [u8; 4] = [0; 4];
 
-                    print!("Bike State: {}, ", result[0]);
-                    output.push_str(&format!("Bike State: {}\n", result[0]));
+                    fields.push(DataField::new("Bike State", DataValue::U8(result[0])));
 
-                    println!("Bike Lock: {}", result[1]);
-                    output.push_str(&format!("Bike Lock: {}\n", result[1]));
+                    fields.push(DataField::new("Bike Lock", DataValue::U8(result[1])));
 
-                    print!("Right Brake Switch: {}, ", result[2]);
-                    output.push_str(&format!("Right Brake Switch: {}, ", result[2]));
+                    fields.push(DataField::new(
+                        "Right Brake Switch",
+                        DataValue::U8(result[2]),
+                    ));
 
-                    print!("Left Brake Switch: {}, ", result[3]);
-                    output.push_str(&format!("Left Brake Switch: {}, ", result[3]));
+                    fields.push(DataField::new(
+                        "Left Brake Switch",
+                        DataValue::U8(result[3]),
+                    ));
 
-                    print!("Kill Switch: {}, ", result[4]);
-                    output.push_str(&format!("Kill Switch: {}, ", result[4]));
+                    fields.push(DataField::new("Kill Switch", DataValue::U8(result[4])));
 
-                    print!("Power Switch: {}, ", result[5]);
-                    output.push_str(&format!("Power Switch: {}, ", result[5]));
+                    fields.push(DataField::new("Power Switch", DataValue::U8(result[5])));
 
-                    print!("Reverse Switch: {}, ", result[6]);
-                    output.push_str(&format!("Reverse Switch: {}, ", result[6]));
+                    fields.push(DataField::new("Reverse Switch", DataValue::U8(result[6])));
 
                     //print!("Seat Switch: {}, ", result[7]);
                     //output.push_str(&format!("Seat Switch: {}, ", result[7]));
 
-                    print!("Side Stand Switch: {}, ", result[8]);
-                    output.push_str(&format!("Side Stand Switch: {}, ", result[8]));
+                    fields.push(DataField::new(
+                        "Side Stand Switch",
+                        DataValue::U8(result[8]),
+                    ));
 
                     //print!("Trip Switch: {}, ", result[9]);
                     //output.push_str(&format!("Trip Switch: {}, ", result[9]));
 
-                    print!("Ride Mode Switch: {}, ", result[10]);
-                    output.push_str(&format!("Ride Mode Switch: {}, ", result[10]));
+                    fields.push(DataField::new(
+                        "Ride Mode Switch",
+                        DataValue::U8(result[10]),
+                    ));
 
-                    print!("Hazard Switch: {}, ", result[11]);
-                    output.push_str(&format!("Hazard Switch: {}, ", result[11]));
+                    fields.push(DataField::new("Hazard Switch", DataValue::U8(result[11])));
 
-                    print!("Horn Switch: {}, ", result[12]);
-                    output.push_str(&format!("Horn Switch: {}, ", result[12]));
+                    fields.push(DataField::new("Horn Switch", DataValue::U8(result[12])));
 
-                    print!("Right Indicator Switch: {}, ", result[13]);
-                    output.push_str(&format!("Right Indicator Switch: {}, ", result[13]));
+                    fields.push(DataField::new(
+                        "Right Indicator Switch",
+                        DataValue::U8(result[13]),
+                    ));
 
-                    print!("Left Indicator Switch: {}, ", result[14]);
-                    output.push_str(&format!("Left Indicator Switch: {}, ", result[14]));
+                    fields.push(DataField::new(
+                        "Left Indicator Switch",
+                        DataValue::U8(result[14]),
+                    ));
 
                     //print!("Passing Switch: {}, ", result[15]);
                     //output.push_str(&format!("Passing Switch: {}, ", result[15]));
 
-                    print!("High Beam Switch: {}, ", result[16]);
-                    output.push_str(&format!("High Beam Switch: {}, ", result[16]));
+                    fields.push(DataField::new(
+                        "High Beam Switch",
+                        DataValue::U8(result[16]),
+                    ));
 
-                    print!("Start Switch: {}, ", result[17]);
-                    output.push_str(&format!("Start Switch: {}, ", result[17]));
+                    fields.push(DataField::new("Start Switch", DataValue::U8(result[17])));
 
-                    print!("Seat Switch: {}, ", result[18]);
-                    output.push_str(&format!("Seat Switch: {}, ", result[18]));
+                    fields.push(DataField::new("Seat Switch", DataValue::U8(result[18])));
 
-                    print!("Trip Switch: {}, ", result[19]);
-                    output.push_str(&format!("Trip Switch: {}, ", result[19]));
+                    fields.push(DataField::new("Trip Switch", DataValue::U8(result[19])));
 
-                    println!("Down Switch: {}", result[20]);
-                    output.push_str(&format!("Down Switch: {}\n", result[20]));
+                    fields.push(DataField::new("Down Switch", DataValue::U8(result[20])));
 
-                    print!("System Component: {}, ", result[21]);
-                    output.push_str(&format!("System Component: {}, ", result[21]));
+                    fields.push(DataField::new(
+                        "System Component",
+                        DataValue::U8(result[21]),
+                    ));
 
-                    print!("System Fault Code: {}, ", result[22]);
-                    output.push_str(&format!("System Fault Code: {}, ", result[22]));
+                    fields.push(DataField::new(
+                        "System Fault Code",
+                        DataValue::U8(result[22]),
+                    ));
 
-                    print!("System Level: {}, ", result[23]);
-                    output.push_str(&format!("System Level: {}, ", result[23]));
+                    fields.push(DataField::new("System Level", DataValue::U8(result[23])));
 
-                    print!("BMS Component: {}, ", result[24]);
-                    output.push_str(&format!("BMS Component: {}, ", result[24]));
+                    fields.push(DataField::new("BMS Component", DataValue::U8(result[24])));
 
-                    print!("BMS Fault Code: {}, ", result[25]);
-                    output.push_str(&format!("BMS Fault Code: {}, ", result[25]));
+                    fields.push(DataField::new("BMS Fault Code", DataValue::U8(result[25])));
 
-                    print!("BMS Level: {}, ", result[26]);
-                    output.push_str(&format!("BMS Level: {}, ", result[26]));
+                    fields.push(DataField::new("BMS Level", DataValue::U8(result[26])));
 
-                    print!("MC Component: {}, ", result[27]);
-                    output.push_str(&format!("MC Component: {}, ", result[27]));
+                    fields.push(DataField::new("MC Component", DataValue::U8(result[27])));
 
-                    print!("MC Fault Code: {}, ", result[28]);
-                    output.push_str(&format!("MC Fault Code: {}, ", result[28]));
+                    fields.push(DataField::new("MC Fault Code", DataValue::U8(result[28])));
 
-                    print!("MC Level: {}, ", result[29]);
-                    output.push_str(&format!("MC Level: {}, ", result[29]));
+                    fields.push(DataField::new("MC Level", DataValue::U8(result[29])));
 
-                    print!("OBC Component: {}, ", result[30]);
-                    output.push_str(&format!("OBC Component: {}, ", result[30]));
+                    fields.push(DataField::new("OBC Component", DataValue::U8(result[30])));
 
-                    print!("OBC Fault Code: {}, ", result[31]);
-                    output.push_str(&format!("OBC Fault Code: {}, ", result[31]));
+                    fields.push(DataField::new("OBC Fault Code", DataValue::U8(result[31])));
 
-                    print!("OBC Level: {}, ", result[32]);
-                    output.push_str(&format!("OBC Level: {}, ", result[32]));
+                    fields.push(DataField::new("OBC Level", DataValue::U8(result[32])));
 
-                    print!("Output Component: {}, ", result[33]);
-                    output.push_str(&format!("Output Component: {}, ", result[33]));
+                    fields.push(DataField::new(
+                        "Output Component",
+                        DataValue::U8(result[33]),
+                    ));
 
-                    print!("Output Fault Code: {}, ", result[34]);
-                    output.push_str(&format!("Output Fault Code: {}, ", result[34]));
+                    fields.push(DataField::new(
+                        "Output Fault Code",
+                        DataValue::U8(result[34]),
+                    ));
 
-                    println!("Output Level: {}", result[35]);
-                    output.push_str(&format!("Output Level: {}", result[35]));
+                    fields.push(DataField::new("Output Level", DataValue::U8(result[35])));
 
-                    print!("Feature Component: {}, ", result[36]);
-                    output.push_str(&format!("Feature Component: {}, ", result[36]));
+                    fields.push(DataField::new(
+                        "Feature Component",
+                        DataValue::U8(result[36]),
+                    ));
 
-                    print!("Feature Fault Code: {}, ", result[37]);
-                    output.push_str(&format!("Feature Fault Code: {}, ", result[37]));
+                    fields.push(DataField::new(
+                        "Feature Fault Code",
+                        DataValue::U8(result[37]),
+                    ));
 
-                    println!("Feature Level: {}", result[38]);
-                    output.push_str(&format!("Feature Level: {}\n", result[38]));
+                    fields.push(DataField::new("Feature Level", DataValue::U8(result[38])));
 
                     let mut i = 39;
 
                     bytes.copy_from_slice(&result[i..i + 4]);
-                    output.push_str(&format!("{} ", print_float("ACC X: ", ", ", bytes)));
+                    fields.push(DataField::new("ACC X", DataValue::F32(to_float(bytes))));
                     i += 4;
 
                     bytes.copy_from_slice(&result[i..i + 4]);
-                    output.push_str(&format!("{} ", print_float("ACC Y: ", ", ", bytes)));
+                    fields.push(DataField::new("ACC Y", DataValue::F32(to_float(bytes))));
                     i += 4;
 
                     bytes.copy_from_slice(&result[i..i + 4]);
-                    output.push_str(&format!("{} ", print_float("ACC Z: ", ", ", bytes)));
+                    fields.push(DataField::new("ACC Z", DataValue::F32(to_float(bytes))));
                     i += 4;
 
                     bytes.copy_from_slice(&result[i..i + 4]);
-                    output.push_str(&format!("{} ", print_float("GYR X: ", ", ", bytes)));
+                    fields.push(DataField::new("GYR X", DataValue::F32(to_float(bytes))));
                     i += 4;
 
                     bytes.copy_from_slice(&result[i..i + 4]);
-                    output.push_str(&format!("{} ", print_float("GYR Y: ", ", ", bytes)));
+                    fields.push(DataField::new("GYR Y", DataValue::F32(to_float(bytes))));
                     i += 4;
 
                     bytes.copy_from_slice(&result[i..i + 4]);
-                    output.push_str(&format!("{} ", print_float("GYR Z: ", ", ", bytes)));
+                    fields.push(DataField::new("GYR Z", DataValue::F32(to_float(bytes))));
                     i += 4;
 
-                    print!("RKE: {}, ", result[i]);
-                    output.push_str(&format!("RKE: {}, ", result[i]));
+                    fields.push(DataField::new("RKE", DataValue::U8(result[i])));
                     i += 1;
 
-                    print!("PKE: {}, ", result[i]);
-                    output.push_str(&format!("PKE: {}, ", result[i]));
+                    fields.push(DataField::new("PKE", DataValue::U8(result[i])));
                     i += 1;
 
-                    println!("PKE Distance: {}", result[i]);
-                    output.push_str(&format!("PKE Distance: {}\n", result[i]));
+                    fields.push(DataField::new("PKE Distance", DataValue::U8(result[i])));
                     i += 1;
 
                     bytes.copy_from_slice(&result[i..i + 4]);
-                    output.push_str(&format!("{} ", print_float("Volt 12V: ", ", ", bytes)));
+                    fields.push(DataField::new("Volt 12V", DataValue::F32(to_float(bytes))));
                     i += 4;
 
                     bytes.copy_from_slice(&result[i..i + 4]);
-                    output.push_str(&format!("{} ", print_float("Volt 5V: ", ", ", bytes)));
+                    fields.push(DataField::new("Volt 5V", DataValue::F32(to_float(bytes))));
                     i += 4;
 
                     bytes.copy_from_slice(&result[i..i + 4]);
-                    output.push_str(&format!("{} ", print_float("Volt 3V: ", ", ", bytes)));
+                    fields.push(DataField::new("Volt 3V", DataValue::F32(to_float(bytes))));
                     i += 4;
 
                     bytes.copy_from_slice(&result[i..i + 4]);
-                    output.push_str(&format!("{} ", print_float("Throttle pct: ", ", ", bytes)));
+                    fields.push(DataField::new(
+                        "Throttle pct",
+                        DataValue::F32(to_float(bytes)),
+                    ));
                     i += 4;
 
                     bytes.copy_from_slice(&result[i..i + 4]);
-                    output.push_str(&format!("{} ", print_float("Throttle filt: ", ", ", bytes)));
+                    fields.push(DataField::new(
+                        "Throttle filt",
+                        DataValue::F32(to_float(bytes)),
+                    ));
                     i += 4;
 
-                    println!("BMS status index: {}, ", i);
-
-                    print!("BMS Status: {}, ", result[i]);
-                    output.push_str(&format!("BMS Status: {}\n", result[i]));
-
+                    fields.push(DataField::new("BMS Status", DataValue::U8(result[i])));
                     i += 1;
 
-                    print!("Pre-discharge relay: {}, ", result[i]);
-                    output.push_str(&format!("Pre-discharge relay: {}\n", result[i]));
-
+                    fields.push(DataField::new(
+                        "Pre-discharge relay",
+                        DataValue::U8(result[i]),
+                    ));
                     i += 1;
 
-                    print!("Discharge relay: {}, ", result[i]);
-                    output.push_str(&format!("Discharge relay: {}\n", result[i]));
-
+                    fields.push(DataField::new("Discharge relay", DataValue::U8(result[i])));
                     i += 1;
 
-                    print!("Charging relay: {}, ", result[i]);
-                    output.push_str(&format!("Charging relay: {}\n", result[i]));
-
+                    fields.push(DataField::new("Charging relay", DataValue::U8(result[i])));
                     i += 1;
 
-                    print!("DC-DC enable: {}, ", result[i]);
-                    output.push_str(&format!("DC-DC enable: {}\n", result[i]));
-
+                    fields.push(DataField::new("DC-DC enable", DataValue::U8(result[i])));
                     i += 1;
 
-                    print!("Charger: {}, ", result[i]);
-                    output.push_str(&format!("Charger: {}\n", result[i]));
-
+                    fields.push(DataField::new("Charger", DataValue::U8(result[i])));
                     i += 1;
 
-                    print!("SOC PCT: {}, ", result[i]);
-                    output.push_str(&format!("SOC PCT: {}\n", result[i]));
-
+                    fields.push(DataField::new("SOC PCT", DataValue::U8(result[i])));
                     i += 1;
 
-                    print!("SOH PCT: {}, ", result[i]);
-                    output.push_str(&format!("SOH PCT: {}\n", result[i]));
-
+                    fields.push(DataField::new("SOH PCT", DataValue::U8(result[i])));
                     i += 1;
 
                     bytes.copy_from_slice(&result[i..i + 4]);
-                    output.push_str(&format!("{} ", print_float("BMS Voltage: ", "\n", bytes)));
+                    fields.push(DataField::new(
+                        "BMS Voltage",
+                        DataValue::F32(to_float(bytes)),
+                    ));
                     i += 4;
 
                     bytes.copy_from_slice(&result[i..i + 4]);
-                    output.push_str(&format!("{} ", print_float("BMS Current: ", "\n", bytes)));
+                    fields.push(DataField::new(
+                        "BMS Current",
+                        DataValue::F32(to_float(bytes)),
+                    ));
                     i += 4;
 
-                    print!("Alive counter: {}\n, ", result[i]);
-                    output.push_str(&format!("Alive counter: {}\n", result[i]));
+                    fields.push(DataField::new("Alive counter", DataValue::U8(result[i])));
                     i += 1;
 
-                    print!("DC-DC enable status: {}\n, ", result[i]);
-                    output.push_str(&format!("DC-DC enable status: {}\n", result[i]));
+                    fields.push(DataField::new(
+                        "DC-DC enable status",
+                        DataValue::U8(result[i]),
+                    ));
                     i += 1;
 
                     let mut bytes_u16: [u8; 2] = [0; 2];
                     bytes_u16.copy_from_slice(&result[i..i + 2]);
-                    output.push_str(&format!(
-                        "{} ",
-                        print_uint16("Max discharge current: ", "\n", bytes_u16)
+                    fields.push(DataField::new(
+                        "Max discharge current",
+                        DataValue::U16(to_uint16(bytes_u16)),
                     ));
                     i += 2;
 
                     bytes_u16.copy_from_slice(&result[i..i + 2]);
-                    output.push_str(&format!(
-                        "{} ",
-                        print_uint16("Max regen current: ", "\n", bytes_u16)
+                    fields.push(DataField::new(
+                        "Max regen current",
+                        DataValue::U16(to_uint16(bytes_u16)),
                     ));
                     i += 2;
 
                     bytes_u16.copy_from_slice(&result[i..i + 2]);
-                    output.push_str(&format!(
-                        "{} ",
-                        print_uint16("Highest cell voltage: ", "\n", bytes_u16)
+                    fields.push(DataField::new(
+                        "Highest cell voltage",
+                        DataValue::U16(to_uint16(bytes_u16)),
                     ));
                     i += 2;
 
                     bytes_u16.copy_from_slice(&result[i..i + 2]);
-                    output.push_str(&format!(
-                        "{} ",
-                        print_uint16("Lowest cell voltage: ", "\n", bytes_u16)
+                    fields.push(DataField::new(
+                        "Lowest cell voltage",
+                        DataValue::U16(to_uint16(bytes_u16)),
                     ));
                     i += 2;
 
-                    print!("Max temp: {}, ", result[i]);
-                    output.push_str(&format!("Max temp: {}\n", result[i]));
+                    fields.push(DataField::new("Max temp", DataValue::U8(result[i])));
                     //output.bms_max_temp = result[i];
                     i += 1;
 
-                    print!("Max temp number: {}, ", result[i]);
-                    output.push_str(&format!("Max temp number: {}\n", result[i]));
+                    fields.push(DataField::new("Max temp number", DataValue::U8(result[i])));
                     //output.bms_max_temp_number = result[i];
                     i += 1;
 
-                    print!("Min temp: {}, ", result[i]);
-                    output.push_str(&format!("Min temp: {}\n", result[i]));
+                    fields.push(DataField::new("Min temp", DataValue::U8(result[i])));
                     //output.bms_min_temp = result[i];
                     i += 1;
 
-                    print!("Min temp number: {}, ", result[i]);
-                    output.push_str(&format!("Min temp number: {}\n", result[i]));
+                    fields.push(DataField::new("Min temp number", DataValue::U8(result[i])));
                     //output.bms_min_temp_number = result[i];
                     i += 1;
 
                     bytes_u16.copy_from_slice(&result[i..i + 2]);
-                    output.push_str(&format!(
-                        "{} ",
-                        print_uint16("Charge discharge cycles: ", "\n", bytes_u16)
+                    fields.push(DataField::new(
+                        "Charge discharge cycles",
+                        DataValue::U16(to_uint16(bytes_u16)),
                     ));
                     //output.bms_charge_discharge_cycles = to_uint16(bytes_u16);
                     i += 2;
 
                     bytes_u16.copy_from_slice(&result[i..i + 2]);
-                    output.push_str(&format!("{} ", print_uint16("fw tm major: ", "\n", bytes_u16)));
+                    fields.push(DataField::new(
+                        "fw tm major",
+                        DataValue::U16(to_uint16(bytes_u16)),
+                    ));
                     //output.fw_rt = to_uint16(bytes_u16);
-                    println!("fw 1: {:?}", bytes_u16);
                     i += 2;
 
                     bytes_u16.copy_from_slice(&result[i..i + 2]);
-                    output.push_str(&format!("{} ", print_uint16("fw tm minor: ", "\n", bytes_u16)));
+                    fields.push(DataField::new(
+                        "fw tm minor",
+                        DataValue::U16(to_uint16(bytes_u16)),
+                    ));
                     //output.fw_tm = to_uint16(bytes_u16);
-                    println!("fw 2: {:?}", bytes_u16);
                     i += 2;
 
-                    println!("fw rt major: {}, ", result[i]);
-                    output.push_str(&format!("fw rt major: {}\n", result[i]));
+                    fields.push(DataField::new("fw rt major", DataValue::U8(result[i])));
                     //output.fw_tm = to_uint16(bytes_u16);
                     i += 1;
 
-                    println!("fw rt minor: {}, ", result[i]);
-                    output.push_str(&format!("fw rt minor: {}\n", result[i]));
+                    fields.push(DataField::new("fw rt minor", DataValue::U8(result[i])));
                     //output.fw_tm = to_uint16(bytes_u16);
                     i += 1;
 
-                    println!("Persist: {}, ", result[i]);
-                    output.push_str(&format!("Persist: {}\n", result[i]));
+                    fields.push(DataField::new("Persist", DataValue::U8(result[i])));
                     //output.vm_persist = result[i];
                     i += 1;
 
                     bytes.copy_from_slice(&result[i..i + 4]);
-                    output.push_str(&format!("{} ", print_uint32("Odometer: ", "\n", bytes)));
+                    fields.push(DataField::new("Odometer", DataValue::U32(to_uint32(bytes))));
                     //output.vm_odometer = to_uint32(bytes);
                     i += 4;
 
                     bytes.copy_from_slice(&result[i..i + 4]);
-                    output.push_str(&format!("{} ", print_uint32("TripA: ", "\n", bytes)));
+                    fields.push(DataField::new("TripA", DataValue::U32(to_uint32(bytes))));
                     //output.vm_tripa = to_uint32(bytes);
                     i += 4;
 
                     bytes.copy_from_slice(&result[i..i + 4]);
-                    output.push_str(&format!("{} ", print_uint32("TripB: ", "\n", bytes)));
+                    fields.push(DataField::new("TripB", DataValue::U32(to_uint32(bytes))));
                     //output.vm_tripb = to_uint32(bytes);
                     i += 4;
 
                     bytes.copy_from_slice(&result[i..i + 4]);
-                    output.push_str(&format!("{} ", print_uint32("Last Charge: ", "\n", bytes)));
+                    fields.push(DataField::new(
+                        "Last Charge",
+                        DataValue::U32(to_uint32(bytes)),
+                    ));
                     //output.vm_last_charge = to_uint32(bytes);
                     i += 4;
 
                     bytes.copy_from_slice(&result[i..i + 4]);
-                    output.push_str(&format!("{} ", print_float("Efficiency: ", "\n", bytes)));
+                    fields.push(DataField::new(
+                        "Efficiency",
+                        DataValue::F32(to_float(bytes)),
+                    ));
                     //output.vm_efficiency = to_float(bytes);
                     i += 4;
 
                     bytes.copy_from_slice(&result[i..i + 4]);
-                    output.push_str(&format!("{} ", print_float("Power PCT: ", "\n", bytes)));
+                    fields.push(DataField::new("Power PCT", DataValue::F32(to_float(bytes))));
                     //output.vm_power_pct = to_float(bytes);
                     i += 4;
 
-                    println!("Speed index: {}, ", i);
-
                     bytes.copy_from_slice(&result[i..i + 4]);
-                    output.push_str(&format!("{} ", print_float("Speed: ", "\n", bytes)));
+                    fields.push(DataField::new("Speed", DataValue::F32(to_float(bytes))));
                     //output.vm_speed = to_float(bytes);
                     i += 4;
 
-                    println!("TripID: {}, ", result[i]);
-                    println!("TripID index: {}, ", i);
-
-                    output.push_str(&format!("TripID: {}\n", result[i]));
+                    fields.push(DataField::new("TripID", DataValue::U8(result[i])));
                     //output.vm_tripid = result[i];
                     i += 1;
 
-                    print!("Trip Action: {}, ", result[i]);
-                    output.push_str(&format!("Trip Action: {}\n", result[i]));
+                    fields.push(DataField::new("Trip Action", DataValue::U8(result[i])));
                     //output.vm_tripaction = result[i];
                     i += 1;
 
-                    print!("Range: {}, ", result[i]);
-                    output.push_str(&format!("Range: {}\n", result[i]));
+                    fields.push(DataField::new("Range", DataValue::U8(result[i])));
                     //output.vm_tripaction = result[i];
-                    i += 1;
 
                     /*print!("Target Charge SOC PCT: {}, ", result[i]);
                     output.push_str(&format!("Target Charge SOC PCT: {}\n", result[i]));
@@ -1225,11 +1168,8 @@ impl DataId {
                     print!("148 CPU Load: {}, ", result[i]);
                     output.push_str(&format!("148 CPU Load: {}\n", result[i]));
                     i += 1; */
-
-                    println!("Last index: {}", i);
                 } else {
-                    println!("Invalid data length for Dashboard");
-                    output.push_str("Invalid data length for Dashboard");
+                    return Err(DiagError::InvalidResponseLength);
                 }
             }
             DataId::TempSensors => {
@@ -1237,168 +1177,128 @@ impl DataId {
                     let mut bytes: [u8; 4] = [0; 4];
                     let mut i = 0;
                     bytes.copy_from_slice(&result[i..i + 4]);
-                    output.push_str(&format!("{} ", print_float("Temp1: ", "\n", bytes)));
+                    fields.push(DataField::new("Temp1", DataValue::F32(to_float(bytes))));
                     i += 4;
 
                     bytes.copy_from_slice(&result[i..i + 4]);
-                    output.push_str(&format!("{} ", print_float("Temp2: ", "\n", bytes)));
+                    fields.push(DataField::new("Temp2", DataValue::F32(to_float(bytes))));
                     i += 4;
 
                     bytes.copy_from_slice(&result[i..i + 4]);
-                    output.push_str(&format!("{} ", print_float("Temp3: ", "\n", bytes)));
+                    fields.push(DataField::new("Temp3", DataValue::F32(to_float(bytes))));
                     i += 4;
 
                     bytes.copy_from_slice(&result[i..i + 4]);
-                    output.push_str(&format!("{} ", print_float("Temp4: ", "\n", bytes)));
+                    fields.push(DataField::new("Temp4", DataValue::F32(to_float(bytes))));
                 } else {
-                    println!("Invalid data length for Temp Sensor");
-                    output.push_str("Invalid data length for Temp Sensor");
+                    return Err(DiagError::InvalidResponseLength);
                 }
             }
             DataId::DiagState => {
                 if result.len() == 2 {
-                    output.push_str(&format!("Session State: {}\n", result[0]));
-                    output.push_str(&format!("Security State: {}\n", result[1]));
+                    fields.push(DataField::new("Session State", DataValue::U8(result[0])));
+                    fields.push(DataField::new("Security State", DataValue::U8(result[1])));
                 } else {
-                    println!("Invalid data length for Temp Sensor");
-                    output.push_str("Invalid data length for Temp Sensor");
+                    return Err(DiagError::InvalidResponseLength);
                 }
             }
             // Add other cases for different DataId variants...
-            _ => {
-                println!("Unknown DataId: {self:?}");
-                output.push_str(&format!("Unknown DataId: {self:?}"));
-            }
+            _ => return Err(DiagError::NotSupported),
         }
-        output
+        Ok(fields)
     }
 
     #[allow(unreachable_patterns)]
-    fn parse_result_to_struct(&self, result: &[u8]) -> UdsMonitorViewResponseDetail {
+    fn parse_result_to_struct(
+        &self,
+        result: &[u8],
+    ) -> DiagServerResult<UdsMonitorViewResponseDetail> {
         let mut output = UdsMonitorViewResponseDetail::default();
 
-        #[allow(clippy::single_match)]
         match self {
             DataId::Dashboard => {
-                println!("Dashboard result len: {}", result.len());
                 if result.len() == 201 {
                     let mut bytes: [u8; 4] = [0; 4];
 
-                    print!("Bike State: {}, ", result[0]);
                     output.bike_status = result[0];
 
-                    println!("Bike Lock: {}", result[1]);
                     output.bike_lock = result[1];
 
-                    print!("Right Brake Switch: {}, ", result[2]);
                     output.right_brake_sw = result[2];
 
-                    print!("Left Brake Switch: {}, ", result[3]);
                     output.left_brake_sw = result[3];
 
-                    print!("Kill Switch: {}, ", result[4]);
                     output.kill_sw = result[4];
 
-                    print!("Power Switch: {}, ", result[5]);
                     output.power_sw = result[5];
 
-                    print!("Reverse Switch: {}, ", result[6]);
                     output.reverse_sw = result[6];
 
                     //print!("Seat Switch: {}, ", result[7]);
                     //output.bike_status = result[7];
 
-                    print!("Side Stand Switch: {}, ", result[8]);
                     output.side_stand_sw = result[8];
 
                     //print!("Trip Switch: {}, ", result[9]);
                     //output.bike_status = result[9];
 
-                    print!("Ride Mode Switch: {}, ", result[10]);
                     output.ride_mode_sw = result[10];
 
-                    print!("Hazard Switch: {}, ", result[11]);
                     output.hazard_sw = result[11];
 
-                    print!("Horn Switch: {}, ", result[12]);
                     output.horn_sw = result[12];
 
-                    print!("Right Indicator Switch: {}, ", result[13]);
                     output.right_indicator_sw = result[13];
 
-                    print!("Left Indicator Switch: {}, ", result[14]);
                     output.left_indicator_sw = result[14];
 
                     //print!("Passing Switch: {}, ", result[15]);
                     //output.bike_status = result[15];
 
-                    print!("High Beam Switch: {}, ", result[16]);
                     output.high_beam_sw = result[16];
 
-                    print!("Start Switch: {}, ", result[17]);
                     output.start_sw = result[17];
 
-                    print!("Seat Switch: {}, ", result[18]);
                     output.seat_sw = result[18];
 
-                    print!("Trip Switch: {}, ", result[19]);
                     output.trip_sw = result[19];
 
-                    println!("Down Switch: {}", result[20]);
                     output.down_sw = result[20];
 
-                    print!("System Component: {}, ", result[21]);
                     //output.down_sw = result[20];
 
-                    print!("System Fault Code: {}, ", result[22]);
                     output.dtc_syscode = result[22];
 
-                    print!("System Level: {}, ", result[23]);
                     //output.down_sw = result[20];
 
-                    print!("BMS Component: {}, ", result[24]);
                     //output.down_sw = result[20];
 
-                    print!("BMS Fault Code: {}, ", result[25]);
                     output.dtc_bmscode = result[25];
 
-                    print!("BMS Level: {}, ", result[26]);
                     //output.down_sw = result[20];
 
-                    print!("MC Component: {}, ", result[27]);
                     //output.down_sw = result[20];
 
-                    print!("MC Fault Code: {}, ", result[28]);
                     output.dtc_mccode = result[28];
 
-                    print!("MC Level: {}, ", result[29]);
                     //output.down_sw = result[20];
 
-                    print!("OBC Component: {}, ", result[30]);
                     //output.down_sw = result[20];
 
-                    print!("OBC Fault Code: {}, ", result[31]);
                     output.dtc_obccode = result[31];
 
-                    print!("OBC Level: {}, ", result[32]);
                     //output.down_sw = result[20];
 
-                    print!("Output Component: {}, ", result[33]);
                     //output.down_sw = result[20];
 
-                    print!("Output Fault Code: {}, ", result[34]);
                     output.dtc_outputcode = result[34];
 
-                    println!("Output Level: {}", result[35]);
                     //output.down_sw = result[20];
 
-                    print!("Feature Component: {}, ", result[36]);
                     //output.push_str(&format!("Feature Component: {}, ", result[15]));
 
-                    print!("Feature Fault Code: {}, ", result[37]);
                     //output.push_str(&format!("Feature Fault Code: {}, ", result[16]));
 
-                    println!("Feature Level: {}", result[38]);
                     //.push_str(&format!("Feature Level: {}", result[17]));
 
                     let mut i = 39;
@@ -1433,17 +1333,14 @@ impl DataId {
                     output.gyr_z = to_float(bytes);
                     i += 4;
 
-                    print!("RKE: {}, ", result[i]);
                     //utput.push_str(&format!("RKE: {}, ", result[i]));
                     output.rke = result[i];
                     i += 1;
 
-                    print!("PKE: {}, ", result[i]);
                     //output.push_str(&format!("PKE: {}, ", result[i]));
                     output.pke = result[i];
                     i += 1;
 
-                    println!("PKE Distance: {}", result[i]);
                     //output.push_str(&format!("PKE Distance: {}", result[i]));
                     output.pke_distance = result[i];
                     i += 1;
@@ -1473,49 +1370,41 @@ impl DataId {
                     output.throttle_filt = to_float(bytes);
                     i += 4;
 
-                    print!("BMS Status: {}, ", result[i]);
                     //output.push_str(&format!("BMS Status: {}\n", result[i]));
                     output.bms_status = result[i];
 
                     i += 1;
 
-                    print!("Pre-discharge relay: {}, ", result[i]);
                     //output.push_str(&format!("Pre-discharge relay: {}\n", result[i]));
                     output.bms_predischarge_relay = result[i];
 
                     i += 1;
 
-                    print!("Discharge relay: {}, ", result[i]);
                     //output.push_str(&format!("Discharge relay: {}\n", result[i]));
                     output.bms_discharge_relay = result[i];
 
                     i += 1;
 
-                    print!("Charging relay: {}, ", result[i]);
                     //output.push_str(&format!("Charging relay: {}\n", result[i]));
                     output.bms_charging_relay = result[i];
 
                     i += 1;
 
-                    print!("DC-DC enable: {}, ", result[i]);
                     //output.push_str(&format!("DC-DC enable: {}\n", result[i]));
                     output.bms_dcdc_enable = result[i];
 
                     i += 1;
 
-                    print!("Charger: {}, ", result[i]);
                     //output.push_str(&format!("Charger: {}\n", result[i]));
                     output.bms_charger = result[i];
 
                     i += 1;
 
-                    print!("SOC PCT: {}, ", result[i]);
                     //output.push_str(&format!("SOC PCT: {}\n", result[i]));
                     output.bms_soc_pct = result[i];
 
                     i += 1;
 
-                    print!("SOH PCT: {}, ", result[i]);
                     //output.push_str(&format!("SOH PCT: {}\n", result[i]));
                     output.bms_soh_pct = result[i];
 
@@ -1531,12 +1420,10 @@ impl DataId {
                     output.bms_current = to_float(bytes);
                     i += 4;
 
-                    print!("Alive counter: {}\n, ", result[i]);
                     //output.push_str(&format!("Alive counter: {}\n", result[i]));
                     output.bms_alive_counter = result[i];
                     i += 1;
 
-                    print!("DC-DC enable status: {}\n, ", result[i]);
                     //output.push_str(&format!("DC-DC enable status: {}\n", result[i]));
                     output.bms_dcdc_enable_status = result[i];
                     i += 1;
@@ -1574,22 +1461,18 @@ impl DataId {
                     output.bms_lowest_cell_volt = to_uint16(bytes_u16);
                     i += 2;
 
-                    print!("Max temp: {}, ", result[i]);
                     //output.push_str(&format!("Max temp: {}\n", result[i]));
                     output.bms_max_temp = result[i];
                     i += 1;
 
-                    print!("Max temp number: {}, ", result[i]);
                     //output.push_str(&format!("Max temp number: {}\n", result[i]));
                     output.bms_max_temp_number = result[i];
                     i += 1;
 
-                    print!("Min temp: {}, ", result[i]);
                     //output.push_str(&format!("Min temp: {}\n", result[i]));
                     output.bms_min_temp = result[i];
                     i += 1;
 
-                    print!("Min temp number: {}, ", result[i]);
                     //output.push_str(&format!("Min temp number: {}\n", result[i]));
                     output.bms_min_temp_number = result[i];
                     i += 1;
@@ -1605,26 +1488,21 @@ impl DataId {
                     bytes_u16.copy_from_slice(&result[i..i + 2]);
 
                     output.fw_tm_major = to_uint16(bytes_u16);
-                    println!("fw tm major: {:?}", bytes_u16);
                     i += 2;
 
                     bytes_u16.copy_from_slice(&result[i..i + 2]);
 
                     output.fw_tm_minor = to_uint16(bytes_u16);
-                    println!("fw tm minor: {:?}", bytes_u16);
                     i += 2;
 
-                    println!("fw rt major: {}, ", result[i]);
                     //output.push_str(&format!("fw rt major: {}\n", result[i]));
                     output.fw_rt_major = result[i];
                     i += 1;
 
-                    println!("fw rt minor: {}, ", result[i]);
                     //output.push_str(&format!("fw rt major: {}\n", result[i]));
                     output.fw_rt_minor = result[i];
                     i += 1;
 
-                    println!("Persist: {}, ", result[i]);
                     //output.push_str(&format!("Persist: {}\n", result[i]));
                     output.vm_persist = result[i];
                     i += 1;
@@ -1664,34 +1542,26 @@ impl DataId {
                     output.vm_speed = to_float(bytes);
                     i += 4;
 
-                    print!("TripID: {}, ", result[i]);
-                    print!("TripID index: {}, ", i);
-
                     //output.push_str(&format!("TripID: {}\n", result[i]));
                     output.vm_tripid = result[i];
                     i += 1;
 
-                    print!("Trip Action: {}, ", result[i]);
                     //output.push_str(&format!("Trip Action: {}\n", result[i]));
                     output.vm_tripaction = result[i];
                     i += 1;
 
-                    print!("Range: {}, ", result[i]);
                     //output.push_str(&format!("Range: {}\n", result[i]));
                     output.vm_tripaction = result[i];
                     i += 1;
 
-                    print!("Target Charge SOC PCT: {}, ", result[i]);
                     //output.push_str(&format!("Target Charge SOC PCT: {}\n", result[i]));
                     output.cm_target_charge_soc_pct = result[i];
                     i += 1;
 
-                    print!("Tartget Charge Hours Rem: {}, ", result[i]);
                     //output.push_str(&format!("Tartget Charge Hours Rem: {}\n", result[i]));
                     output.cm_target_charge_hours_rem = result[i];
                     i += 1;
 
-                    print!("Tartget Charge Min Rem: {}, ", result[i]);
                     //output.push_str(&format!("Tartget Charge Min Rem: {}\n", result[i]));
                     output.cm_target_charge_min_rem = result[i];
                     i += 1;
@@ -1704,17 +1574,14 @@ impl DataId {
                     output.cm_target_charge_range = to_uint16(bytes_u16);
                     i += 2;
 
-                    print!("Charge Complete: {}, ", result[i]);
                     //output.push_str(&format!("Charge Complete: {}\n", result[i]));
                     output.cm_charge_complete = result[i];
                     i += 1;
 
-                    print!("SOC Limit: {}, ", result[i]);
                     //output.push_str(&format!("SOC Limit: {}\n", result[i]));
                     output.cm_soc_limit = result[i];
                     i += 1;
 
-                    print!("SOC Limit Selection Page: {}, ", result[i]);
                     //output.push_str(&format!("SOC Limit Selection Page: {}\n", result[i]));
                     output.cm_soc_limit_selection_page = result[i];
                     i += 1;
@@ -1724,12 +1591,10 @@ impl DataId {
                     output.cm_va_limit = to_uint16(bytes_u16);
                     i += 2;
 
-                    print!("VA Limit Selection Page: {}, ", result[i]);
                     //output.push_str(&format!("VA Limit Selection Page: {}\n", result[i]));
                     output.cm_va_limit_selection_page = result[i];
                     i += 1;
 
-                    println!("Store Cable Noti: {}", result[i]);
                     //output.push_str(&format!("Store Cable Noti: {}\n", result[i]));
                     output.cm_store_cable_noti = result[i];
                     i += 1;
@@ -1737,28 +1602,23 @@ impl DataId {
                     bytes.copy_from_slice(&result[i..i + 4]);
                     //output.push_str(&format!("{} ", print_float("Speed: ", "\n", bytes)));
                     output.temp1 = to_float(bytes);
-                    println!("Temp 1: {}", output.temp1);
                     i += 4;
 
                     bytes.copy_from_slice(&result[i..i + 4]);
                     //output.push_str(&format!("{} ", print_float("Speed: ", "\n", bytes)));
                     output.temp2 = to_float(bytes);
-                    println!("Temp 2: {}", output.temp1);
                     i += 4;
 
                     bytes.copy_from_slice(&result[i..i + 4]);
                     //output.push_str(&format!("{} ", print_float("Speed: ", "\n", bytes)));
                     output.temp3 = to_float(bytes);
-                    println!("Temp 3: {}", output.temp1);
                     i += 4;
 
                     bytes.copy_from_slice(&result[i..i + 4]);
                     //output.push_str(&format!("{} ", print_float("Speed: ", "\n", bytes)));
                     output.temp4 = to_float(bytes);
-                    println!("Temp 4: {}", output.temp1);
                     i += 4;
 
-                    print!("Activation Status: {}, ", result[i]);
                     //output.push_str(&format!("Activation Status: {}\n", result[i]));
                     output.obc_activation_status = result[i];
                     i += 1;
@@ -1779,106 +1639,65 @@ impl DataId {
                     output.obc_output_dc_current = to_uint16(bytes_u16);
                     i += 2;
 
-                    print!("Max Temp: {}, ", result[i]);
                     //output.push_str(&format!("Max Temp: {}\n", result[i]));
                     output.obc_max_temp = result[i];
                     i += 1;
 
-                    print!("AC Input Volt: {}, ", result[i]);
                     //output.push_str(&format!("AC Input Volt: {}\n", result[i]));
                     output.obc_input_volt = result[i];
                     i += 1;
 
-                    print!("AC Input Current: {}, ", result[i]);
                     //output.push_str(&format!("AC Input Current: {}\n", result[i]));
                     output.obc_input_current = result[i];
                     i += 1;
 
-                    print!("Stop tx: {}, ", result[i]);
                     //output.push_str(&format!("Stop tx: {}\n", result[i]));
                     output.obc_stop_tx = result[i];
                     i += 1;
 
-                    print!("Alive_counter: {}, ", result[i]);
                     //output.push_str(&format!("Alive_counter: {}\n", result[i]));
                     output.obc_alive_counter = result[i];
                     i += 1;
 
-                    print!("Error 1 hardware: {}, ", result[i]);
                     //output.push_str(&format!("Error 1 hardware: {}\n", result[i]));
                     output.obc_error1_hw = result[i];
                     i += 1;
 
-                    print!("Error 2 temp: {}, ", result[i]);
                     //output.push_str(&format!("Error 2 temp: {}\n", result[i]));
                     output.obc_error2_temp = result[i];
                     i += 1;
 
-                    print!("Error 3 current: {}, ", result[i]);
                     //output.push_str(&format!("Error 3 current: {}\n", result[i]));
                     output.obc_error3_voltln = result[i];
                     i += 1;
 
-                    print!("Error 4 volt in: {}, ", result[i]);
                     //output.push_str(&format!("Error  4 volt in: {}\n", result[i]));
                     output.obc_error4_current = result[i];
                     i += 1;
 
-                    print!("Error 5 comn: {}, ", result[i]);
                     //output.push_str(&format!("Error 5 comn: {}\n", result[i]));
                     output.obc_error5_comn = result[i];
                     i += 1;
 
-                    print!("118 CPU Load: {}, ", result[i]);
                     output.cpu_118 = result[i];
                     i += 1;
 
-                    print!("148 CPU Load: {}, ", result[i]);
                     output.cpu_148 = result[i];
-                    i += 1;
-
-                    println!("Last index: {}", i);
                 } else {
-                    println!("Invalid data length for Dashboard");
-                    //output.push_str("Invalid data length for Dashboard");
+                    return Err(DiagError::InvalidResponseLength);
                 }
             }
-            _ => {}
+            _ => return Err(DiagError::NotSupported),
         }
-        output
+        Ok(output)
     }
 }
 
-fn print_float(template: &str, end: &str, bytes: [u8; 4]) -> String {
-    let mut output = String::new();
-
-    // Assuming little-endian byte order
-    let bits = u32::from_le_bytes(bytes);
-    let float_value = f32::from_bits(bits);
-    // Format the float with two significant digits
-    let formatted_float = format!("{:.2}", float_value);
-    print!("{template}{formatted_float}{end}");
-    output.push_str(&format!("{template}{formatted_float}{end}"));
-
-    output
-}
-
 fn to_float(bytes: [u8; 4]) -> f32 {
     let bits = u32::from_le_bytes(bytes);
     f32::from_bits(bits)
 }
 
-fn print_uint32(template: &str, end: &str, bytes: [u8; 4]) -> String {
-    let mut output = String::new();
-
-    // Assuming little-endian byte order
-    let bits = u32::from_le_bytes(bytes);
-    print!("{template}{bits}{end}");
-    output.push_str(&format!("{template}{bits}{end}"));
-
-    output
-}
-
 fn to_uint32(bytes: [u8; 4]) -> u32 {
     u32::from_le_bytes(bytes)
 }
@@ -1886,14 +1705,3 @@ fn to_uint32(bytes: [u8; 4]) -> u32 {
 fn to_uint16(bytes: [u8; 2]) -> u16 {
     u16::from_le_bytes(bytes)
 }
-
-fn print_uint16(template: &str, end: &str, bytes: [u8; 2]) -> String {
-    let mut output = String::new();
-
-    // Assuming little-endian byte order
-    let bits = u16::from_le_bytes(bytes);
-    print!("{template}{bits}{end}");
-    output.push_str(&format!("{template}{bits}{end}"));
-
-    output
-}
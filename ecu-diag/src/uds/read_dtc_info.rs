@@ -1,5 +1,6 @@
 //!  Provides methods to read and query DTCs on the ECU, as well as grabbing Env data about each DTC
 
+use crate::core::DiagServerResult;
 use crate::uds::UDSClientSession;
 
 pub use automotive_diag::uds::DtcSubFunction;
@@ -14,30 +15,33 @@ impl UDSClientSession {
     /// 1. (u8) - DTCStatusAvailabilityMask
     /// 2. ([DTCFormatType]) - Format of the DTCs
     /// 3. (u16) - Number of DTCs which match the status mask
-    pub fn uds_get_number_of_dtcs_by_status_mask(&mut self, status_mask: u8) {
+    pub fn uds_get_number_of_dtcs_by_status_mask(
+        &mut self,
+        status_mask: u8,
+    ) -> DiagServerResult<Vec<u8>> {
         self.send_command_with_response(
             UdsCommand::ReadDTCInformation,
             &[
                 DtcSubFunction::ReportNumberOfDtcByStatusMask as u8,
                 status_mask,
             ],
-        );
+        )
     }
 
     /// Returns a list of DTCs stored on the ECU
     /// matching the provided status_mask
-    pub fn uds_get_dtcs_by_status_mask(&mut self, status_mask: u8) {
+    pub fn uds_get_dtcs_by_status_mask(&mut self, status_mask: u8) -> DiagServerResult<Vec<u8>> {
         self.send_command_with_response(
             UdsCommand::ReadDTCInformation,
             &[DtcSubFunction::ReportDtcByStatusMask as u8, status_mask],
-        );
+        )
     }
 
     pub fn uds_get_dtc_snapshot_record_by_dtc_number(
         &mut self,
         dtc_mask_record: u32,
         snapshot_record_number: u8,
-    ) {
+    ) -> DiagServerResult<Vec<u8>> {
         self.send_command_with_response(
             UdsCommand::ReadDTCInformation,
             &[
@@ -47,24 +51,27 @@ impl UDSClientSession {
                 dtc_mask_record as u8,
                 snapshot_record_number,
             ],
-        );
+        )
     }
 
-    pub fn uds_get_dtc_snapshot_identification(&mut self) {
+    pub fn uds_get_dtc_snapshot_identification(&mut self) -> DiagServerResult<Vec<u8>> {
         self.send_command_with_response(
             UdsCommand::ReadDTCInformation,
             &[DtcSubFunction::ReportDtcSnapshotIdentifier as u8],
-        );
+        )
     }
 
-    pub fn uds_get_dtc_snapshot_record_by_record_number(&mut self, snapshot_record_number: u8) {
+    pub fn uds_get_dtc_snapshot_record_by_record_number(
+        &mut self,
+        snapshot_record_number: u8,
+    ) -> DiagServerResult<Vec<u8>> {
         self.send_command_with_response(
             UdsCommand::ReadDTCInformation,
             &[
                 DtcSubFunction::ReportDtcSnapshotRecordByRecordNumber as u8,
                 snapshot_record_number,
             ],
-        );
+        )
     }
 
     /// Returns the DTCExtendedData record(s) associated with the provided DTC mask and record number.
@@ -76,7 +83,7 @@ impl UDSClientSession {
         &mut self,
         dtc: u32,
         extended_data_record_number: u8,
-    ) {
+    ) -> DiagServerResult<Vec<u8>> {
         self.send_command_with_response(
             UdsCommand::ReadDTCInformation,
             &[
@@ -86,7 +93,7 @@ impl UDSClientSession {
                 dtc as u8,         // Low byte
                 extended_data_record_number,
             ],
-        );
+        )
     }
 
     /// Returns the number of DTCs stored on the ECU that match the provided severity and status mask
@@ -94,7 +101,7 @@ impl UDSClientSession {
         &mut self,
         severity_mask: u8,
         status_mask: u8,
-    ) {
+    ) -> DiagServerResult<Vec<u8>> {
         self.send_command_with_response(
             UdsCommand::ReadDTCInformation,
             &[
@@ -102,11 +109,15 @@ impl UDSClientSession {
                 severity_mask,
                 status_mask,
             ],
-        );
+        )
     }
 
     /// Returns a list of DTCs who's severity mask matches the provided mask
-    pub fn uds_get_dtcs_by_severity_mask_record(&mut self, severity_mask: u8, status_mask: u8) {
+    pub fn uds_get_dtcs_by_severity_mask_record(
+        &mut self,
+        severity_mask: u8,
+        status_mask: u8,
+    ) -> DiagServerResult<Vec<u8>> {
         self.send_command_with_response(
             UdsCommand::ReadDTCInformation,
             &[
@@ -114,11 +125,11 @@ impl UDSClientSession {
                 severity_mask,
                 status_mask,
             ],
-        );
+        )
     }
 
     /// Returns the severity status of a provided DTC
-    pub fn uds_get_severity_information_of_dtc(&mut self, dtc: u32) {
+    pub fn uds_get_severity_information_of_dtc(&mut self, dtc: u32) -> DiagServerResult<Vec<u8>> {
         self.send_command_with_response(
             UdsCommand::ReadDTCInformation,
             &[
@@ -127,47 +138,47 @@ impl UDSClientSession {
                 (dtc >> 8) as u8,
                 dtc as u8,
             ],
-        );
+        )
     }
 
     /// Returns a list of all DTCs that the ECU can return
-    pub fn uds_get_supported_dtc(&mut self) {
+    pub fn uds_get_supported_dtc(&mut self) -> DiagServerResult<Vec<u8>> {
         self.send_command_with_response(
             UdsCommand::ReadDTCInformation,
             &[DtcSubFunction::ReportSupportedDtc as u8],
-        );
+        )
     }
 
     /// Returns the first failed DTC to be detected since the last DTC clear operation
-    pub fn uds_get_first_test_failed_dtc(&mut self) {
+    pub fn uds_get_first_test_failed_dtc(&mut self) -> DiagServerResult<Vec<u8>> {
         self.send_command_with_response(
             UdsCommand::ReadDTCInformation,
             &[DtcSubFunction::ReportFirstTestFailedDtc as u8],
-        );
+        )
     }
 
     /// Returns the first confirmed DTC to be detected since the last DTC clear operationn
-    pub fn uds_get_first_confirmed_dtc(&mut self) {
+    pub fn uds_get_first_confirmed_dtc(&mut self) -> DiagServerResult<Vec<u8>> {
         self.send_command_with_response(
             UdsCommand::ReadDTCInformation,
             &[DtcSubFunction::ReportFirstConfirmedDtc as u8],
-        );
+        )
     }
 
     /// Returns the most recent DTC to be detected since the last DTC clear operation
-    pub fn uds_get_most_recent_test_failed_dtc(&mut self) {
+    pub fn uds_get_most_recent_test_failed_dtc(&mut self) -> DiagServerResult<Vec<u8>> {
         self.send_command_with_response(
             UdsCommand::ReadDTCInformation,
             &[DtcSubFunction::ReportMostRecentTestFailedDtc as u8],
-        );
+        )
     }
 
     /// Returns the most recent DTC to be detected since the last DTC clear operation
-    pub fn uds_get_most_recent_confirmed_dtc(&mut self) {
+    pub fn uds_get_most_recent_confirmed_dtc(&mut self) -> DiagServerResult<Vec<u8>> {
         self.send_command_with_response(
             UdsCommand::ReadDTCInformation,
             &[DtcSubFunction::ReportMostRecentConfirmedDtc as u8],
-        );
+        )
     }
 
     /// Returns the current number of 'pre-failed' DTCs on the ECU, which have not yet been confirmed
@@ -177,18 +188,18 @@ impl UDSClientSession {
     /// This function will return a vector of information, where each element is a tuple containing the following values:
     /// 1. (u32) - DTC Code
     /// 2. (u8) - Fault detection counter
-    pub fn uds_get_dtc_fault_detection_counter(&mut self) {
+    pub fn uds_get_dtc_fault_detection_counter(&mut self) -> DiagServerResult<Vec<u8>> {
         self.send_command_with_response(
             UdsCommand::ReadDTCInformation,
             &[DtcSubFunction::ReportDtcFaultDetectionCounter as u8],
-        );
+        )
     }
 
     /// Returns a list of DTCs that have a permanent status
-    pub fn uds_get_dtc_with_permanent_status(&mut self) {
+    pub fn uds_get_dtc_with_permanent_status(&mut self) -> DiagServerResult<Vec<u8>> {
         self.send_command_with_response(
             UdsCommand::ReadDTCInformation,
             &[DtcSubFunction::ReportDtcWithPermanentStatus as u8],
-        );
+        )
     }
 }
@@ -1,15 +1,14 @@
 //!  Provides methods to manipulate the ECUs diagnostic session mode
 
+use crate::core::{DiagError, DiagServerResult};
 use crate::uds::UDSClientSession;
-use crate::uds::UdsSericeResponseDetail;
-use crate::uds::UdsServiceResponse;
 
 use automotive_diag::uds::UdsCommand;
 
 use super::security_access::SecurityLevelAccess;
 use crate::uds::diagnostic_session_control::UdsSessionType;
-use chrono::NaiveDate;
 use std::fmt;
+use tokio::sync::mpsc::error::TryRecvError;
 
 impl UDSClientSession {
     /// Requests the ECU to start a service
@@ -18,7 +17,7 @@ impl UDSClientSession {
         sub_fcn: RoutineControlSubfcn,
         routine_id: &[u8],
         routine_control_option: &[u8],
-    ) -> UdsServiceResponse {
+    ) -> DiagServerResult<()> {
         let mut args: Vec<u8> = Vec::new();
         args.push(sub_fcn as u8);
         args.extend_from_slice(routine_id);
@@ -31,48 +30,26 @@ impl UDSClientSession {
         };
 
         if Self::is_connectivity_service(value, &mut request) {
-            if self.tx.send(request).is_err() {
-                println!("receiver dropped");
-                return UdsServiceResponse::Fail(UdsSericeResponseDetail {
-                    console_output: String::from("FAIL"),
-                });
-            }
-            // Success
-            UdsServiceResponse::Success(UdsSericeResponseDetail {
-                console_output: String::from("SUCCESS"),
-            })
+            self.tx
+                .send(request)
+                .map_err(|_| DiagError::ServerNotRunning)
         } else {
-            let resp = self.send_command_with_response(UdsCommand::RoutineControl, &args);
-
-            if !resp.is_empty() {
-                if resp[0] == (UdsCommand::RoutineControl as u8 + 0x40) {
-                    // Success
-                    return UdsServiceResponse::Success(UdsSericeResponseDetail {
-                        console_output: String::from("SUCCESS"),
-                    });
-                } else {
-                    // Fail
-                    return UdsServiceResponse::Fail(UdsSericeResponseDetail {
-                        console_output: String::from("FAIL\nNEGATIVE UDS RESPONSE"),
-                    });
-                }
-            }
-
-            // Fail
-            UdsServiceResponse::Fail(UdsSericeResponseDetail {
-                console_output: String::from("FAIL\nEMPTY UDS RESPONSE"),
-            })
+            self.send_command_with_response(UdsCommand::RoutineControl, &args)
+                .map(|_| ())
+                .map_err(decode_routine_nrc)
         }
     }
 
-    pub fn uds_routine_control_setup(&mut self) {
+    pub fn uds_routine_control_setup(&mut self) -> DiagServerResult<()> {
         if self.current_diag_mode.mode != UdsSessionType::Programming {
-            self.uds_set_session_mode(UdsSessionType::Programming);
+            self.uds_set_session_mode(UdsSessionType::Programming)?;
         }
         if self.current_diag_mode.sec_level == SecurityLevelAccess::None {
-            let key = self.uds_security_access_request_seed(SecurityLevelAccess::Level1RequestSeed);
-            self.uds_security_access_send_key(SecurityLevelAccess::Level1SendKey, &key);
+            let key =
+                self.uds_security_access_request_seed(SecurityLevelAccess::Level1RequestSeed)?;
+            self.uds_security_access_send_key(SecurityLevelAccess::Level1SendKey, &key)?;
         }
+        Ok(())
     }
 
     /// Requests the ECU to get service results
@@ -81,7 +58,7 @@ impl UDSClientSession {
         sub_fcn: RoutineControlSubfcn,
         routine_id: &[u8],
         routine_control_option: &[u8],
-    ) -> UdsServiceResponse {
+    ) -> DiagServerResult<RoutineResult> {
         let mut args: Vec<u8> = Vec::new();
         args.push(sub_fcn as u8);
         args.extend_from_slice(routine_id);
@@ -94,341 +71,35 @@ impl UDSClientSession {
         };
 
         if Self::is_connectivity_service(value, &mut unused) {
-            #[allow(unused_assignments)]
-            let mut returned_string = "".to_string();
-
-            let res = self.rx.try_recv();
-
-            match res {
-                Ok(message) => {
-                    // Message received successfully
-                    returned_string = message.response;
-                }
-                Err(error) => match error {
-                    tokio::sync::mpsc::error::TryRecvError::Empty => {
-                        // Channel is empty
-                        log::debug!("Channel is empty");
-                        println!("Channel is empty");
-                        returned_string =  "NO RESULT TO SHOW\nNO SERVICE IS RUNNING OR CURRENT SERVICE IS STILL RUNNING".to_string();
-                    }
-                    tokio::sync::mpsc::error::TryRecvError::Disconnected => {
-                        // Channel is empty
-                        log::debug!("Channel is disconnected");
-                        println!("Channel is disconnected");
-                        returned_string =
-                            "NO RESULT: RECEIVING CHANNEL IS DISCONNECTED. TRY RESTART THE APP"
-                                .to_string();
-                    }
-                },
+            match self.rx.try_recv() {
+                Ok(message) => Ok(RoutineResult::Service(message)),
+                Err(TryRecvError::Empty) => Ok(RoutineResult::NotAvailable),
+                Err(TryRecvError::Disconnected) => Err(DiagError::ServerNotRunning),
             }
-
-            // Success
-            UdsServiceResponse::Success(UdsSericeResponseDetail {
-                console_output: format!("SUCCESS\n{}", returned_string),
-            })
         } else {
-            let resp = self.send_command_with_response(UdsCommand::RoutineControl, &args);
-
+            let resp = self
+                .send_command_with_response(UdsCommand::RoutineControl, &args)
+                .map_err(decode_routine_nrc)?;
             log::debug!("RESPONSE FROM SERVICE: {:02X?}", resp);
-            println!("RESPONSE FROM SERVICE: {:02X?}", resp);
-
-            if resp[0] == (UdsCommand::RoutineControl as u8 + 0x40) {
-                let routine_id: i16 = ((resp[2] as i16) << 8) | (resp[3] as i16);
-
-                if routine_id == 0x020D {
-                    let num_scan: u8 = resp[4];
-                    let signal_strength: i16 = ((resp[5] as i16) << 8) | (resp[6] as i16);
-
-                    // Success
-                    // Format the output string
-                    if resp[4] == 0xFF && resp[5] == 0xFF && resp[6] == 0xFF {
-                        let console_output = "SUCCESS\nNO WIFI SCAN RESULT".to_string();
-
-                        UdsServiceResponse::Success(UdsSericeResponseDetail { console_output })
-                    } else {
-                        let console_output = format!(
-                            "SUCCESS\nNumber of wifi Scan: {}\nBest Signal Strength: {}",
-                            num_scan, signal_strength
-                        );
-                        UdsServiceResponse::Success(UdsSericeResponseDetail { console_output })
-                    }
-                } else if routine_id == 0x020E {
-                    if resp.len() == 9
-                        && resp[4] == 0x00
-                        && resp[5] == 0x00
-                        && resp[6] == 0x00
-                        && resp[7] == 0x00
-                        && resp[8] == 0xFF
-                    {
-                        let console_output = "SUCCESS\nNO WIFI IP".to_string();
-                        return UdsServiceResponse::Success(UdsSericeResponseDetail {
-                            console_output,
-                        });
-                    } else {
-                        let console_output = format!(
-                            "SUCCESS\nWIFI IP: {}.{}.{}.{}",
-                            resp[4], resp[5], resp[6], resp[7]
-                        );
-                        return UdsServiceResponse::Success(UdsSericeResponseDetail {
-                            console_output,
-                        });
-                    }
-                } else if routine_id == 0x020F {
-                    /*WIFI RESTART APP IMX SERVICE*/
-                    if resp[4] == 0x01 {
-                        let console_output = "SUCCESS\nWIFI INIT SUCCESS".to_string();
-                        return UdsServiceResponse::Success(UdsSericeResponseDetail {
-                            console_output,
-                        });
-                    } else if resp[4] == 0x02 {
-                        let console_output = "SUCCESS\nWIFI INIT FAIL".to_string();
-                        return UdsServiceResponse::Success(UdsSericeResponseDetail {
-                            console_output,
-                        });
-                    } else if resp[4] == 0x03 {
-                        let console_output = "SUCCESS\nWIFI INIT NO RESULT".to_string();
-                        return UdsServiceResponse::Success(UdsSericeResponseDetail {
-                            console_output,
-                        });
-                    } else {
-                        let console_output = "SUCCESS\nWIFI INIT UNKNOWN RESULT".to_string();
-                        return UdsServiceResponse::Success(UdsSericeResponseDetail {
-                            console_output,
-                        });
-                    }
-                } else if routine_id == 0x0210 {
-                    /*GPS CHECK LOG*/
-                    if resp[4] == 0xFF && resp[5] == 0xFF && resp[6] == 0xFF && resp[7] == 0xFF {
-                        let console_output = "SUCCESS\nNO GPS LOG".to_string();
-                        return UdsServiceResponse::Success(UdsSericeResponseDetail {
-                            console_output,
-                        });
-                    } else {
-                        let year_bytes = &[resp[4], resp[5]]; // Extract 2 bytes for year (big endian)
-                        let month = resp[6]; // Extract 1 byte for month
-                        let day = resp[7]; // Extract 1 byte for day
-                        let hour = resp[8]; // Extract 1 byte for hour
-                        let minute = resp[9]; // Extract 1 byte for minute
-                        let second = resp[10]; // Extract 1 byte for second
-
-                        // Convert bytes to u16 for year
-                        let year = u16::from_be_bytes([year_bytes[0], year_bytes[1]]);
 
-                        // Create a DateTime object
-                        let datetime =
-                            NaiveDate::from_ymd_opt(year as i32, month as u32, day as u32)
-                                .unwrap()
-                                .and_hms_opt(hour as u32, minute as u32, second as u32)
-                                .unwrap();
-
-                        // Format the datetime as RFC3339 string
-                        let datetime_str = datetime.to_string();
-                        let console_output =
-                            format!("SUCCESS\nLAST VALID GPS LOG TIMESTAMP: {}", datetime_str);
-                        return UdsServiceResponse::Success(UdsSericeResponseDetail {
-                            console_output,
-                        });
-                    }
-                } else if routine_id == 0x0211 {
-                    /* LTE CHECK IP */
-                    if resp[4] == 0x00
-                        && resp[5] == 0x00
-                        && resp[6] == 0x00
-                        && resp[7] == 0x00
-                        && resp[8] == 0xFF
-                    {
-                        let console_output = "SUCCESS\nNO LTE IP".to_string();
-                        return UdsServiceResponse::Success(UdsSericeResponseDetail {
-                            console_output,
-                        });
-                    } else {
-                        let console_output = format!(
-                            "SUCCESS\nLTE IP: {}.{}.{}.{}",
-                            resp[4], resp[5], resp[6], resp[7]
-                        );
-                        return UdsServiceResponse::Success(UdsSericeResponseDetail {
-                            console_output,
-                        });
-                    }
-                } else if routine_id == 0x0212 {
-                    /* LTE CHECK PING */
-                    if resp[4] == 0x01 {
-                        let console_output = "SUCCESS\nPING 8.8.8.8 OK".to_string();
-                        return UdsServiceResponse::Success(UdsSericeResponseDetail {
-                            console_output,
-                        });
-                    } else if resp[4] == 0x00 {
-                        let console_output = "SUCCESS\nPING 8.8.8.8 NOT OK".to_string();
-                        return UdsServiceResponse::Success(UdsSericeResponseDetail {
-                            console_output,
-                        });
-                    } else {
-                        let console_output = "SUCCESS\nPING 8.8.8.8 INVALID RESULT".to_string();
-                        return UdsServiceResponse::Success(UdsSericeResponseDetail {
-                            console_output,
-                        });
-                    }
-                } else if routine_id == 0x0213 {
-                    /*LTE CHECK ENABLE SIGNAL*/
-                    if resp[4] == 0x01 {
-                        let console_output = "SUCCESS\nLTE ENABLED SIGNAL ON".to_string();
-                        return UdsServiceResponse::Success(UdsSericeResponseDetail {
-                            console_output,
-                        });
-                    } else if resp[4] == 0x02 {
-                        let console_output = "SUCCESS\nLTE ENABLED SIGNAL OFF".to_string();
-                        return UdsServiceResponse::Success(UdsSericeResponseDetail {
-                            console_output,
-                        });
-                    } else if resp[4] == 0x03 {
-                        let console_output = "SUCCESS\nLTE ENABLED SIGNAL CAN'T READ".to_string();
-                        return UdsServiceResponse::Success(UdsSericeResponseDetail {
-                            console_output,
-                        });
-                    } else {
-                        let console_output =
-                            "SUCCESS\nLTE ENABLED SIGNAL INVALID RESULT".to_string();
-                        return UdsServiceResponse::Success(UdsSericeResponseDetail {
-                            console_output,
-                        });
-                    }
-                } else if routine_id == 0x0214 {
-                    /*LTE GET MODEM INFO*/
-                    if resp[4] == 0xFF && resp[5] == 0xFF && resp[6] == 0xFF {
-                        let console_output = "SUCCESS\nNO LTE MODEM INFO".to_string();
-                        return UdsServiceResponse::Success(UdsSericeResponseDetail {
-                            console_output,
-                        });
-                    } else {
-                        let state_byte = resp[4];
-                        let signal_quality_bytes = [resp[5], resp[6]];
-                        let has_operator = resp[7] == 0x01;
-
-                        let state = match state_byte {
-                            0x00 => "FAILED",
-                            0x01 => "UNKNOWN",
-                            0x02 => "INITIALIZING",
-                            0x03 => "LOCKED",
-                            0x04 => "DISABLED",
-                            0x05 => "DISABLING",
-                            0x06 => "ENABLING",
-                            0x07 => "ENABLED",
-                            0x08 => "SEARCHING",
-                            0x09 => "REGISTERED",
-                            0x0A => "DISCONNECTING",
-                            0x0B => "CONNECTING",
-                            0x0C => "CONNECTED",
-                            // Add other variants as needed
-                            _ => "INVALID STATE", // Invalid state byte
-                        };
-
-                        let signal_quality = u16::from_be_bytes(signal_quality_bytes);
-                        let console_output = format!(
-                            "SUCCESS\nMODEM STATE: {}\nSIGNAL QUALITY: {}\nHAS OPERATOR: {}",
-                            state, signal_quality, has_operator
-                        );
-                        return UdsServiceResponse::Success(UdsSericeResponseDetail {
-                            console_output,
-                        });
-                    }
-                } else if routine_id == 0x0215 {
-                    /*BLE RESTART APP SERVICE*/
-                    if resp[4] == 0x01 {
-                        let console_output = "SUCCESS\nBLE ADVERTISING SUCCESS".to_string();
-                        return UdsServiceResponse::Success(UdsSericeResponseDetail {
-                            console_output,
-                        });
-                    } else if resp[4] == 0x02 {
-                        let console_output = "SUCCESS\nBLE ADVERTISING FAIL".to_string();
-                        return UdsServiceResponse::Success(UdsSericeResponseDetail {
-                            console_output,
-                        });
-                    } else if resp[4] == 0x00 {
-                        let console_output = "SUCCESS\nBLE ADVERTISING NO RESULT".to_string();
-                        return UdsServiceResponse::Success(UdsSericeResponseDetail {
-                            console_output,
-                        });
-                    } else {
-                        let console_output = "SUCCESS\nBLE ADVERTISING INVALID RESULT".to_string();
-                        return UdsServiceResponse::Success(UdsSericeResponseDetail {
-                            console_output,
-                        });
-                    }
-                } else if routine_id == 0x0216 {
-                    /*BLE CHECK PAIR STATUS*/
-                    if resp[4] == 0x01 {
-                        let console_output = "SUCCESS\nBLE PAIR SUCCESS".to_string();
-                        return UdsServiceResponse::Success(UdsSericeResponseDetail {
-                            console_output,
-                        });
-                    } else if resp[4] == 0x02 {
-                        let console_output = "SUCCESS\nBLE PAIR FAIL".to_string();
-                        return UdsServiceResponse::Success(UdsSericeResponseDetail {
-                            console_output,
-                        });
-                    } else if resp[4] == 0x00 {
-                        let console_output = "SUCCESS\nBLE PAIR NO RESULT".to_string();
-                        return UdsServiceResponse::Success(UdsSericeResponseDetail {
-                            console_output,
-                        });
-                    } else {
-                        let console_output = "SUCCESS\nBLE PAIR INVALID RESULT".to_string();
-                        return UdsServiceResponse::Success(UdsSericeResponseDetail {
-                            console_output,
-                        });
-                    }
-                } else if routine_id == 0x0217 {
-                    /* IMX CHECK SERVICE STATUS */
-                    let console_output = format!(
-                        "SUCCESS\nMQTT: {}\nICOM: {}\nSERVICE MANAGER: {}\nLOGGING: {}\nOTA: {}\n",
-                        resp[4] == 0x01,
-                        resp[5] == 0x01,
-                        resp[6] == 0x01,
-                        resp[7] == 0x01,
-                        resp[8] == 0x01
-                    );
-                    return UdsServiceResponse::Success(UdsSericeResponseDetail { console_output });
-                } else {
-                    // Success: Need to handle other services
-                    // Format the output string
-                    let console_output = "SUCCESS".to_string();
-
-                    UdsServiceResponse::Success(UdsSericeResponseDetail { console_output })
-                }
-            } else {
-                // Fail
-                // #define RoutineInProgress (0xA0)
-                // #define IncorrectServiceID (0xA1)
-                // #define InvalidRoutineResult (0xA2)
-                if resp[2] == 0xA0 {
-                    UdsServiceResponse::Fail(UdsSericeResponseDetail {
-                        console_output: String::from("FAIL due to Service In Progress"),
-                    })
-                } else if resp[2] == 0xA1 {
-                    UdsServiceResponse::Fail(UdsSericeResponseDetail {
-                        console_output: String::from("FAIL due to Incorrect Service ID"),
-                    })
-                } else if resp[2] == 0xA2 {
-                    UdsServiceResponse::Fail(UdsSericeResponseDetail {
-                        console_output: String::from("FAIL due to Invalid Routine Result"),
-                    })
-                } else {
-                    UdsServiceResponse::Fail(UdsSericeResponseDetail {
-                        console_output: String::from("FAIL due to unknown error"),
-                    })
-                }
+            // Positive response is the SID, the sub function, the routine ID and the status record
+            match resp.get(4..) {
+                Some(status) => Ok(RoutineResult::Status(status.to_vec())),
+                None => Err(DiagError::InvalidResponseLength),
             }
         }
     }
 
-    pub fn uds_routine_control_setup_get_result(&mut self) {
+    pub fn uds_routine_control_setup_get_result(&mut self) -> DiagServerResult<()> {
         if self.current_diag_mode.mode != UdsSessionType::Programming {
-            self.uds_set_session_mode(UdsSessionType::Programming);
+            self.uds_set_session_mode(UdsSessionType::Programming)?;
         }
         if self.current_diag_mode.sec_level == SecurityLevelAccess::None {
-            let key = self.uds_security_access_request_seed(SecurityLevelAccess::Level1RequestSeed);
-            self.uds_security_access_send_key(SecurityLevelAccess::Level1SendKey, &key);
+            let key =
+                self.uds_security_access_request_seed(SecurityLevelAccess::Level1RequestSeed)?;
+            self.uds_security_access_send_key(SecurityLevelAccess::Level1SendKey, &key)?;
         }
+        Ok(())
     }
 
     fn is_connectivity_service<T>(value: T, req: &mut ServiceRequest) -> bool
@@ -490,6 +161,58 @@ impl UDSClientSession {
     }
 }
 
+/// Result of a routine, see [UDSClientSession::uds_routine_control_get_result]
+#[derive(Debug)]
+pub enum RoutineResult {
+    /// Response of the connectivity service that ran the routine
+    Service(ServiceResponse),
+    /// No connectivity service has finished since its result was last requested
+    NotAvailable,
+    /// Routine status record of the ECU
+    Status(Vec<u8>),
+}
+
+/// Negative response codes of routine control that are specific to this ECU.
+/// ISO 14229-1 reserves 0xA0-0xA2, so they are not part of [UdsError](super::errors::UdsError)
+#[repr(u8)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum RoutineNrc {
+    /// The routine is still running, its result is not available yet
+    #[error("The routine is still running")]
+    RoutineInProgress = 0xA0,
+    /// The routine was requested with a service ID it does not support
+    #[error("The routine does not support the service ID")]
+    IncorrectServiceId = 0xA1,
+    /// The routine finished without a valid result
+    #[error("The routine finished without a valid result")]
+    InvalidRoutineResult = 0xA2,
+}
+
+impl RoutineNrc {
+    /// Returns the routine specific meaning of a negative response code, if it has one
+    pub fn from_code(code: u8) -> Option<Self> {
+        match code {
+            0xA0 => Some(Self::RoutineInProgress),
+            0xA1 => Some(Self::IncorrectServiceId),
+            0xA2 => Some(Self::InvalidRoutineResult),
+            _ => None,
+        }
+    }
+}
+
+/// Replaces the definition of [RoutineNrc] codes in a negative response to a routine control
+fn decode_routine_nrc(e: DiagError) -> DiagError {
+    match e {
+        DiagError::ECUError { code, def } => DiagError::ECUError {
+            code,
+            def: RoutineNrc::from_code(code)
+                .map(|nrc| nrc.to_string())
+                .or(def),
+        },
+        e => e,
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
 pub enum RoutineControlSubfcn {
     StartRoutine = 0x01,
@@ -1,40 +1,41 @@
 //!  Provides methods to access different security levels
 
+use crate::core::{DiagError, DiagServerResult};
 use crate::uds::UDSClientSession;
 
 use automotive_diag::uds::UdsCommand;
 
 impl UDSClientSession {
     /// Requests the ECU to go into a specific diagnostic session mode
-    pub fn uds_security_access_request_seed(&mut self, sub_fcn: SecurityLevelAccess) -> Vec<u8> {
-        let resp = self.send_command_with_response(UdsCommand::SecurityAccess, &[sub_fcn as u8]);
-
-        if !resp.is_empty() && resp[0] == (UdsCommand::SecurityAccess as u8 + 0x40) {
-            // Ensure that the length of `resp` is 8
-            assert_eq!(resp.len(), 7);
-
-            return resp[2..7].to_vec();
+    pub fn uds_security_access_request_seed(
+        &mut self,
+        sub_fcn: SecurityLevelAccess,
+    ) -> DiagServerResult<Vec<u8>> {
+        let resp = self.send_command_with_response(UdsCommand::SecurityAccess, &[sub_fcn as u8])?;
+
+        // SID, security level and a 5 byte seed
+        if resp.len() != 7 {
+            return Err(DiagError::InvalidResponseLength);
         }
-        vec![]
+        Ok(resp[2..7].to_vec())
     }
 
     /// Requests the ECU to go into a specific diagnostic session mode
-    pub fn uds_security_access_send_key(&mut self, sub_fcn: SecurityLevelAccess, key: &[u8]) {
+    pub fn uds_security_access_send_key(
+        &mut self,
+        sub_fcn: SecurityLevelAccess,
+        key: &[u8],
+    ) -> DiagServerResult<()> {
         let mut args: Vec<u8> = Vec::new();
         args.push(sub_fcn as u8);
         args.extend_from_slice(key);
 
-        let resp = self.send_command_with_response(UdsCommand::SecurityAccess, &args);
+        self.send_command_with_response(UdsCommand::SecurityAccess, &args)?;
 
-        if !resp.is_empty() {
-            if resp[0] == (UdsCommand::SecurityAccess as u8 + 0x40) {
-                // Positive response, set security level
-                self.current_diag_mode.sec_level = sub_fcn;
-                self.current_diag_mode.tp_require = true;
-            }
-        } else {
-            println!("Receive empty response for security access");
-        }
+        // Positive response, set security level
+        self.current_diag_mode.sec_level = sub_fcn;
+        self.current_diag_mode.tp_require = true;
+        Ok(())
     }
 }
 
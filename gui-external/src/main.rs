@@ -4,14 +4,36 @@ mod generated_code {
 }
 pub use generated_code::*;
 
-slint::include_modules!();
-
 use chrono::prelude::*;
 use ecu_diag::api::UdsServiceProvider;
-use ecu_diag::api::UdsServiceResponse;
+use ecu_diag::hardware::isotp::IsoTpProtocol;
+use ecu_diag::hardware::pcan_usb::PcanUsbScanner;
+use ecu_diag::hardware::{HardwareResult, HardwareScanner};
 use ecu_diag::uds::read_data_by_id::DataId;
+use ecu_diag::uds::routine_control::{ServiceRequest, ServiceResponse};
 use ecu_diag::uds::UDSClientSession;
-use slint::{Timer, TimerMode};
+use slint::{ModelRc, SharedString, Timer, TimerMode, VecModel};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// Opens a PCAN adapter, by name or index, or the first adapter found. The UDS client
+/// is created on the first adapter opened and moved to the adapters opened after it
+fn open_adapter(
+    client: &mut Option<UDSClientSession>,
+    adapter: Option<&str>,
+) -> HardwareResult<()> {
+    let device = PcanUsbScanner::default().open_adapter(adapter)?;
+    if let Some(mut protocol) = client.as_ref().and_then(UDSClientSession::protocol) {
+        return protocol.switch_hardware(device);
+    }
+    let protocol = IsoTpProtocol::new_from_hardware(device)?;
+    let (tx_req, _rx_req) = tokio::sync::mpsc::unbounded_channel::<ServiceRequest>();
+    let (_tx_res, rx_res) = tokio::sync::mpsc::unbounded_channel::<ServiceResponse>();
+    let mut uds_client = UDSClientSession::new(protocol, tx_req, rx_res);
+    uds_client.init();
+    *client = Some(uds_client);
+    Ok(())
+}
 
 fn main() {
     let ui = MainWindow::new().unwrap();
@@ -31,106 +53,61 @@ fn main() {
         },
     );
 
-    let mut client = UDSClientSession::new_uds_client();
+    // PCAN adapter to start on, by name or index. Defaults to the first adapter found
+    let adapter = std::env::var("ION_CAN_ADAPTER").ok();
+    let adapters: Vec<SharedString> = PcanUsbScanner::default()
+        .list_devices()
+        .into_iter()
+        .map(|info| info.name.into())
+        .collect();
+    let current_adapter = match adapter.as_deref() {
+        Some(a) => match a.parse::<usize>() {
+            Ok(idx) => adapters.get(idx).cloned().unwrap_or_default(),
+            Err(_) => a.into(),
+        },
+        None => adapters.first().cloned().unwrap_or_default(),
+    };
+    ui.set_can_adapters(ModelRc::new(VecModel::from(adapters)));
+    ui.set_can_adapter(current_adapter);
+
+    let client = Rc::new(RefCell::new(None));
+    if let Err(e) = open_adapter(&mut client.borrow_mut(), adapter.as_deref()) {
+        ui.set_status(format!("Cannot open CAN adapter: {e}").into());
+    }
+
+    ui.on_adapter_selected({
+        let client = Rc::clone(&client);
+        let ui_handle = ui.as_weak();
+        move |name| {
+            let status = match open_adapter(&mut client.borrow_mut(), Some(name.as_str())) {
+                Ok(()) => format!("Opened CAN adapter {name}"),
+                Err(e) => format!("Cannot open CAN adapter {name}: {e}"),
+            };
+            ui_handle.unwrap().set_status(status.into());
+        }
+    });
+
     let timer1 = Timer::default();
     let ui_handle1 = ui.as_weak().unwrap();
     timer1.start(
         TimerMode::Repeated,
         std::time::Duration::from_millis(500),
         move || {
-            // Get current time
-            let res = client.invoke_read_data_by_id_service(DataId::Dashboard);
-
-            match res {
-                UdsServiceResponse::Success(mes) => {
-                    let speed_str = mes.console_output.split(", ").find_map(|s| {
-                        let mut parts = s.split(": ");
-                        if let Some(key) = parts.next() {
-                            if key.trim() == "Vehicle Speed" {
-                                return parts.next();
-                            }
-                        }
-                        None
-                    });
-
-                    if let Some(speed) = speed_str {
-                        match speed.parse::<f32>() {
-                            Ok(num) => {
-                                ui_handle1.invoke_update_speed(num);
-                            }
-                            Err(_err) => {}
-                        }
-                    } else {
-                        println!("Speed value not found");
-                    }
-
-                    let cpu_load_str = mes.console_output.split(", ").find_map(|s| {
-                        let mut parts = s.split(": ");
-                        if let Some(key) = parts.next() {
-                            if key.trim() == "CPU Load" {
-                                return parts.next();
-                            }
-                        }
-                        None
-                    });
-
-                    if let Some(cpu_load) = cpu_load_str {
-                        match cpu_load.parse::<i32>() {
-                            Ok(num) => {
-                                ui_handle1.invoke_update_cpu_load(num);
-                            }
-                            Err(_err) => {}
-                        }
-                    } else {
-                        println!("CPU Load value not found");
-                    }
-
-                    let throttle_str = mes.console_output.split(", ").find_map(|s| {
-                        let mut parts = s.split(": ");
-                        if let Some(key) = parts.next() {
-                            if key.trim() == "Throttle" {
-                                return parts.next();
-                            }
-                        }
-                        None
-                    });
-
-                    if let Some(throttle) = throttle_str {
-                        match throttle.parse::<i32>() {
-                            Ok(num) => {
-                                ui_handle1.invoke_update_throttle(num);
-                            }
-                            Err(_err) => {}
-                        }
-                    } else {
-                        println!("Throttle value not found");
-                    }
-
-                    let battery_str = mes.console_output.split(", ").find_map(|s| {
-                        let mut parts = s.split(": ");
-                        if let Some(key) = parts.next() {
-                            if key.trim() == "Battery" {
-                                return parts.next();
-                            }
-                        }
-                        None
-                    });
-
-                    if let Some(battery) = battery_str {
-                        match battery.parse::<i32>() {
-                            Ok(num) => {
-                                ui_handle1.invoke_update_battery(num);
-                            }
-                            Err(_err) => {}
-                        }
-                    } else {
-                        println!("Battery value not found");
-                    }
+            let mut client = client.borrow_mut();
+            let Some(client) = client.as_mut() else {
+                return;
+            };
+            match client.invoke_read_data_by_id_service_return_struct(DataId::Dashboard) {
+                Ok(dashboard) => {
+                    ui_handle1.invoke_update_speed(dashboard.vm_speed);
+                    ui_handle1.invoke_update_cpu_load(i32::from(dashboard.cpu_148));
+                    ui_handle1.invoke_update_throttle(dashboard.throttle_pct as i32);
+                    ui_handle1.invoke_update_battery(i32::from(dashboard.bms_soc_pct));
                 }
-                UdsServiceResponse::Fail(_mes) => {}
+                Err(e) => ui_handle1.set_status(format!("Dashboard not available: {e}").into()),
             }
         },
     );
 
-    ui.run()
+    ui.run().unwrap();
 }
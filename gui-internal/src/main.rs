@@ -5,22 +5,35 @@ mod uds_client;
 mod generated_code {
     slint::include_modules!();
 }
-use ecu_diag::api::{UdsMonitorViewResponse, UdsServiceResponse};
+use ecu_diag::api::{DiagError, DiagServerResult};
 pub use generated_code::*;
 
 use crate::uds_client::{UdsMessage, UdsWorker};
 pub use ecu_diag::uds::diagnostic_session_control::UdsSessionType;
-use slint::{SharedString, Timer, TimerMode};
+use slint::{ModelRc, SharedString, Timer, TimerMode, VecModel};
 
+use std::path::PathBuf;
 use std::sync::Arc;
 use std::thread;
 use std::time::Duration;
 
+use ecu_diag::hardware::pcan_usb::PcanUsbScanner;
 use ecu_diag::hardware::tcp::TcpProtocol;
+use ecu_diag::hardware::{HardwareError, HardwareScanner};
 use ecu_diag::uds::routine_control::{ServiceRequest, ServiceResponse};
 
 use tokio::sync::oneshot;
 
+/// Text of a service result shown in the service output
+fn service_output(res: DiagServerResult<String>) -> String {
+    match res {
+        Ok(output) => output,
+        Err(DiagError::ECUError { def: Some(def), .. }) => format!("FAIL\n{def}"),
+        Err(DiagError::HardwareError(e)) => format!("FAIL\nCAN adapter: {e}"),
+        Err(e) => format!("FAIL\n{e}"),
+    }
+}
+
 #[tokio::main]
 async fn main() {
     let app = AppUi::new().unwrap();
@@ -33,7 +46,100 @@ async fn main() {
     let (stop_tx_monitor, stop_rx_monitor) = oneshot::channel::<()>();
     let (tx_res, rx_res) = tokio::sync::mpsc::unbounded_channel::<ServiceResponse>();
     let (tx_req, rx_req) = tokio::sync::mpsc::unbounded_channel::<ServiceRequest>();
-    let worker = UdsWorker::new(&app, rx_res, tx_req, stop_rx_uds_worker, stop_rx_monitor).await;
+
+    // PCAN adapter to start on, by name or index. Defaults to the first adapter found
+    let adapter = std::env::var("ION_CAN_ADAPTER").ok();
+    let adapters: Vec<SharedString> = PcanUsbScanner::default()
+        .list_devices()
+        .into_iter()
+        .map(|info| info.name.into())
+        .collect();
+    let current_adapter = match adapter.as_deref() {
+        Some(a) => match a.parse::<usize>() {
+            Ok(idx) => adapters.get(idx).cloned().unwrap_or_default(),
+            Err(_) => a.into(),
+        },
+        None => adapters.first().cloned().unwrap_or_default(),
+    };
+    app.set_can_adapters(ModelRc::new(VecModel::from(adapters)));
+    app.set_can_adapter(current_adapter);
+
+    let worker = UdsWorker::new(
+        &app,
+        adapter.as_deref(),
+        rx_res,
+        tx_req,
+        stop_rx_uds_worker,
+        stop_rx_monitor,
+    )
+    .await;
+
+    app.on_adapter_selected({
+        let connection = Arc::clone(&worker.connection);
+        let ui_handle = app.as_weak();
+        move |name| {
+            let connection = Arc::clone(&connection);
+            let ui_handle = ui_handle.clone();
+            tokio::spawn(async move {
+                let res = connection
+                    .lock()
+                    .await
+                    .open_adapter(Some(name.as_str()))
+                    .await;
+                let output =
+                    service_output(res.map(|()| format!("SUCCESS\nOpened CAN adapter {name}")));
+                let _ = ui_handle.upgrade_in_event_loop(move |app| {
+                    app.set_service_output(output.into());
+                });
+            });
+        }
+    });
+
+    app.on_trace_toggled({
+        let connection = Arc::clone(&worker.connection);
+        let ui_handle = app.as_weak();
+        move |enabled| {
+            let connection = Arc::clone(&connection);
+            let ui_handle = ui_handle.clone();
+            tokio::spawn(async move {
+                let mut connection = connection.lock().await;
+                let res = connection.client().and_then(|client| {
+                    let mut protocol = client
+                        .protocol()
+                        .ok_or(Arc::new(HardwareError::ChannelNotSupported))?;
+                    if !enabled {
+                        protocol.stop_trace();
+                        return Ok(None);
+                    }
+                    // Traces are written to ION_CAN_TRACE_DIR, or the working directory
+                    let file = format!(
+                        "ion-trace-{}.asc",
+                        chrono::Local::now().format("%Y%m%d-%H%M%S")
+                    );
+                    let path = std::env::var("ION_CAN_TRACE_DIR")
+                        .map(PathBuf::from)
+                        .unwrap_or_default()
+                        .join(file);
+                    protocol.start_trace(&path).map_err(Arc::new)?;
+                    Ok(Some(path))
+                });
+                let _ = ui_handle.upgrade_in_event_loop(move |app| {
+                    let output = match res {
+                        Ok(Some(path)) => {
+                            app.set_can_trace_file(path.display().to_string().into());
+                            format!("SUCCESS\nRecording CAN trace to {}", path.display())
+                        }
+                        Ok(None) => String::from("SUCCESS\nStopped CAN trace"),
+                        Err(e) => {
+                            app.set_can_trace_enabled(false);
+                            service_output(Err(e))
+                        }
+                    };
+                    app.set_service_output(output.into());
+                });
+            });
+        }
+    });
 
     let tcp_connection = TcpProtocol::new().await;
 
@@ -154,6 +260,7 @@ async fn main() {
     });
 
     let ui_handle1 = app.as_weak();
+    let connection1 = Arc::clone(&worker.connection);
     let timer1 = Timer::default();
 
     #[allow(unused_assignments)]
@@ -161,14 +268,22 @@ async fn main() {
         TimerMode::Repeated,
         std::time::Duration::from_millis(1000),
         move || {
-            let uds_client_clone = Arc::clone(&worker.uds_client);
+            let uds_client_clone = Arc::clone(&connection1);
             let ui_handle1_clone = ui_handle1.clone();
             tokio::spawn(async move {
                 let mut mode = 0;
 
                 //println!("Lock uds client from timer");
-                let mut uds_client = uds_client_clone.lock().await;
+                let mut connection = uds_client_clone.lock().await;
                 //println!("Unlock uds client from timer");
+                let Ok(uds_client) = connection.client() else {
+                    let _ = ui_handle1_clone.upgrade_in_event_loop(|app| {
+                        app.set_can_connection_state(2);
+                        app.set_can_connection_state1(2);
+                        app.set_can_bus_status("No CAN adapter open".into());
+                    });
+                    return;
+                };
 
                 match uds_client.current_diag_mode.mode {
                     UdsSessionType::Default => {
@@ -193,6 +308,10 @@ async fn main() {
 
                 //let uds_client_clone_clone = Arc::clone(&uds_client_clone);
                 let can_status = uds_client.check_can_connection_status();
+                let bus_status = match uds_client.check_can_bus_status() {
+                    Some(status) => status.to_string(),
+                    None => "Not available".to_string(),
+                };
 
                 // if can_status {
                 //     println!("can connected");
@@ -214,11 +333,36 @@ async fn main() {
                         ui_handle1_clone.unwrap().set_can_connection_state(2);
                         ui_handle1_clone.unwrap().set_can_connection_state1(2);
                     }
+                    ui_handle1_clone
+                        .unwrap()
+                        .set_can_bus_status(bus_status.into());
                 });
             });
         },
     );
 
+    let ui_handle_bus = app.as_weak();
+    let timer_bus = Timer::default();
+    timer_bus.start(
+        TimerMode::Repeated,
+        std::time::Duration::from_millis(500),
+        {
+            let connection = Arc::clone(&worker.connection);
+            move || {
+                let connection = Arc::clone(&connection);
+                let ui_handle_bus = ui_handle_bus.clone();
+                tokio::spawn(async move {
+                    let events = connection.lock().await.bus_events();
+                    let _ = ui_handle_bus.upgrade_in_event_loop(move |app| {
+                        for event in events {
+                            app.set_can_bus_event(event.to_string().into());
+                        }
+                    });
+                });
+            }
+        },
+    );
+
     let ui_handle2 = app.as_weak();
     let timer2 = Timer::default();
     timer2.start(
@@ -228,22 +372,9 @@ async fn main() {
             let mut receive_channel = worker.receive_channel;
             move || {
                 if let Ok(res) = receive_channel.try_recv() {
-                    match res {
-                        UdsServiceResponse::Success(success) => {
-                            // log::debug!("Sending OK, awaiting response from ECU");
-                            println!("Received data: {:?}", success.console_output);
-                            ui_handle2
-                                .unwrap()
-                                .set_service_output(success.console_output.into());
-                        }
-                        UdsServiceResponse::Fail(fail) => {
-                            // log::debug!("Sending OK, awaiting response from ECU");
-                            println!("Received data: {:?}", fail.console_output);
-                            ui_handle2
-                                .unwrap()
-                                .set_service_output(fail.console_output.into());
-                        }
-                    }
+                    let output = service_output(res);
+                    println!("Received data: {:?}", output);
+                    ui_handle2.unwrap().set_service_output(output.into());
                 }
             }
         },
@@ -259,7 +390,7 @@ async fn main() {
             move || {
                 if let Ok(res) = receive_channel.try_recv() {
                     match res {
-                        UdsMonitorViewResponse::Success(success) => {
+                        Ok(success) => {
                             // log::debug!("Sending OK, awaiting response from ECU");
                             //println!("Received data: {:?}", success);
                             let app = ui_handle_timer_monitor_view_get_result.unwrap();
@@ -285,10 +416,18 @@ async fn main() {
                                 success.throttle_filt.to_string().into(),
                             );
 
-                            app.set_monitor_view_fw_tm_major(success.fw_tm_major.to_string().into());
-                            app.set_monitor_view_fw_tm_minor(success.fw_tm_minor.to_string().into());
-                            app.set_monitor_view_fw_rt_major(success.fw_rt_major.to_string().into());
-                            app.set_monitor_view_fw_rt_minor(success.fw_rt_minor.to_string().into());
+                            app.set_monitor_view_fw_tm_major(
+                                success.fw_tm_major.to_string().into(),
+                            );
+                            app.set_monitor_view_fw_tm_minor(
+                                success.fw_tm_minor.to_string().into(),
+                            );
+                            app.set_monitor_view_fw_rt_major(
+                                success.fw_rt_major.to_string().into(),
+                            );
+                            app.set_monitor_view_fw_rt_minor(
+                                success.fw_rt_minor.to_string().into(),
+                            );
 
                             app.set_monitor_view_dtc_syscode(
                                 success.dtc_syscode.to_string().into(),
@@ -491,8 +630,14 @@ async fn main() {
                             app.set_seat_sw(success.seat_sw as i32);
                             app.set_trip_sw(success.trip_sw as i32);
                             app.set_down_sw(success.down_sw as i32);
+
+                            app.set_monitor_view_error("".into());
+                        }
+                        Err(e) => {
+                            ui_handle_timer_monitor_view_get_result
+                                .unwrap()
+                                .set_monitor_view_error(format!("FAIL: {e}").into());
                         }
-                        UdsMonitorViewResponse::Fail => {}
                     }
                 }
             }
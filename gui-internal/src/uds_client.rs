@@ -1,5 +1,7 @@
 use super::{Action, AppUi};
-use ecu_diag::api::UdsMonitorViewResponse;
+use ecu_diag::hardware::isotp::{BusRecoveryPolicy, CanBusEvent, IsoTpProtocol};
+use ecu_diag::hardware::pcan_usb::PcanUsbScanner;
+use ecu_diag::hardware::HardwareError;
 use ecu_diag::uds::routine_control::TriggerOutputOption;
 use slint::ComponentHandle;
 use std::sync::Arc;
@@ -7,14 +9,15 @@ use tokio::sync::oneshot;
 use tokio::sync::Mutex;
 
 use crate::{ServiceRequest, ServiceResponse};
-use ecu_diag::api::UdsServiceProvider;
-use ecu_diag::api::UdsServiceResponse;
+use ecu_diag::api::{
+    DiagError, DiagServerResult, UdsMonitorViewResponseDetail, UdsServiceProvider,
+};
 use ecu_diag::uds::diagnostic_session_control::UdsSessionType;
 use ecu_diag::uds::ecu_reset::ResetType;
-use ecu_diag::uds::read_data_by_id::DataId;
+use ecu_diag::uds::read_data_by_id::{DataField, DataId};
 use ecu_diag::uds::routine_control::Domain;
 use ecu_diag::uds::routine_control::SimulateInputOption;
-use ecu_diag::uds::routine_control::{RoutineControlSubfcn, RoutineId};
+use ecu_diag::uds::routine_control::{RoutineControlSubfcn, RoutineId, RoutineResult};
 use ecu_diag::uds::UDSClientSession;
 
 use std::str::FromStr;
@@ -25,6 +28,100 @@ use tokio::task::JoinHandle;
 
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 
+/// Text of a service result shown in the service output of the UI
+trait ConsoleOutput {
+    fn console_output(self) -> String;
+}
+
+impl ConsoleOutput for () {
+    fn console_output(self) -> String {
+        String::from("SUCCESS")
+    }
+}
+
+impl ConsoleOutput for Vec<DataField> {
+    fn console_output(self) -> String {
+        self.iter()
+            .map(DataField::to_string)
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+impl ConsoleOutput for RoutineResult {
+    fn console_output(self) -> String {
+        match self {
+            RoutineResult::Service(response) => format!("SUCCESS\n{}", response.response),
+            RoutineResult::NotAvailable => String::from(
+                "SUCCESS\nNO RESULT TO SHOW\nNO SERVICE IS RUNNING OR CURRENT SERVICE IS STILL RUNNING",
+            ),
+            RoutineResult::Status(status) => format!("SUCCESS\n{status:02X?}"),
+        }
+    }
+}
+
+/// UDS client of the worker, created on the first CAN adapter that opens
+pub struct UdsConnection {
+    client: Option<UDSClientSession>,
+    /// Service channels of the client, until it is created
+    service_channels: Option<(
+        UnboundedSender<ServiceRequest>,
+        UnboundedReceiver<ServiceResponse>,
+    )>,
+    bus_events: Option<std::sync::mpsc::Receiver<CanBusEvent>>,
+    /// Service output of the worker, written while the ECU is busy
+    busy_tx: UnboundedSender<DiagServerResult<String>>,
+}
+
+impl UdsConnection {
+    /// Returns the UDS client, or [HardwareError::DeviceNotOpen] if no CAN adapter is open
+    pub fn client(&mut self) -> DiagServerResult<&mut UDSClientSession> {
+        self.client
+            .as_mut()
+            .ok_or_else(|| Arc::new(HardwareError::DeviceNotOpen).into())
+    }
+
+    /// Bus events received since the last call
+    pub fn bus_events(&self) -> Vec<CanBusEvent> {
+        self.bus_events
+            .iter()
+            .flat_map(|events| events.try_iter())
+            .collect()
+    }
+
+    /// Opens a PCAN adapter, by name or index, or the first adapter found. The UDS client
+    /// is created on the first adapter opened and moved to the adapters opened after it
+    pub async fn open_adapter(&mut self, adapter: Option<&str>) -> DiagServerResult<()> {
+        let device = PcanUsbScanner::default()
+            .open_adapter(adapter)
+            .map_err(Arc::new)?;
+        if let Some(client) = self.client.as_mut() {
+            let mut protocol = client
+                .protocol()
+                .ok_or(Arc::new(HardwareError::ChannelNotSupported))?;
+            return Ok(protocol.switch_hardware(device).map_err(Arc::new)?);
+        }
+        let Some((tx_req, rx_res)) = self.service_channels.take() else {
+            return Err(DiagError::ServerNotRunning);
+        };
+        let mut protocol = IsoTpProtocol::new_from_hardware(device).map_err(Arc::new)?;
+        protocol.set_bus_recovery(BusRecoveryPolicy::Automatic {
+            max_attempts: 3,
+            delay_ms: 500,
+        });
+        self.bus_events = Some(protocol.subscribe_bus_events());
+        let mut client =
+            UDSClientSession::new_uds_client_with_protocol(protocol, tx_req, rx_res).await;
+        // Shown in the service output until the response of the ECU replaces it
+        let busy_tx = self.busy_tx.clone();
+        client.register_waiting_hook(move || {
+            let _ = busy_tx.send(Ok(String::from("ECU BUSY\nWAITING FOR RESPONSE")));
+        });
+        self.client = Some(client);
+        Ok(())
+    }
+}
+
 #[allow(dead_code)]
 pub enum UdsMessage {
     Quit,
@@ -34,12 +131,13 @@ pub enum UdsMessage {
 #[allow(dead_code)]
 pub struct UdsWorker {
     pub channel: UnboundedSender<UdsMessage>,
-    pub receive_channel: UnboundedReceiver<UdsServiceResponse>,
-    pub uds_client: Arc<Mutex<UDSClientSession>>,
+    pub receive_channel: UnboundedReceiver<DiagServerResult<String>>,
+    pub connection: Arc<Mutex<UdsConnection>>,
     worker_thread: JoinHandle<()>,
 
     pub channel_monitor_view: UnboundedSender<UdsMessage>,
-    pub receive_channel_monitor_view: UnboundedReceiver<UdsMonitorViewResponse>,
+    pub receive_channel_monitor_view:
+        UnboundedReceiver<DiagServerResult<UdsMonitorViewResponseDetail>>,
     monitor_view_thread: JoinHandle<()>,
 }
 
@@ -48,6 +146,7 @@ impl UdsWorker {
 
     pub async fn new(
         app_ui: &AppUi,
+        adapter: Option<&str>,
         rx_res: UnboundedReceiver<ServiceResponse>,
         tx_req: UnboundedSender<ServiceRequest>,
         stop_rx_uds_worker: oneshot::Receiver<()>,
@@ -56,13 +155,28 @@ impl UdsWorker {
         let (channel, r) = tokio::sync::mpsc::unbounded_channel();
         let (s, receive_channel) = tokio::sync::mpsc::unbounded_channel();
 
-        let uds_client = UDSClientSession::new_uds_client(tx_req, rx_res).await;
-        app_ui.set_diagnostics_session_state(uds_client.current_diag_mode.mode.into());
+        let mut connection = UdsConnection {
+            client: None,
+            service_channels: Some((tx_req, rx_res)),
+            bus_events: None,
+            busy_tx: s.clone(),
+        };
+        // Without an adapter the worker still runs, another one can be picked in the UI
+        match connection.open_adapter(adapter).await {
+            Ok(()) => {
+                let mode = connection.client().map(|c| c.current_diag_mode.mode);
+                if let Ok(mode) = mode {
+                    app_ui.set_diagnostics_session_state(mode.into());
+                }
+            }
+            Err(e) => {
+                let _ = s.send(Err(e));
+            }
+        }
 
-        // Create a new instance of UDSClientSession
-        let uds_client_arc = Arc::new(Mutex::new(uds_client));
+        let uds_client_arc = Arc::new(Mutex::new(connection));
 
-        // Capture a clone of Arc<Mutex<UDSClientSession>> in the closure
+        // Capture a clone of Arc<Mutex<UdsConnection>> in the closure
         let uds_client_clone = Arc::clone(&uds_client_arc);
 
         let worker_thread = tokio::spawn({
@@ -93,7 +207,7 @@ impl UdsWorker {
         Self {
             channel,
             receive_channel,
-            uds_client: uds_client_arc,
+            connection: uds_client_arc,
             worker_thread,
             channel_monitor_view,
             receive_channel_monitor_view,
@@ -110,9 +224,9 @@ impl UdsWorker {
 
 pub async fn spawn_monitor_view_thread(
     mut r: UnboundedReceiver<UdsMessage>,
-    s: UnboundedSender<UdsMonitorViewResponse>,
+    s: UnboundedSender<DiagServerResult<UdsMonitorViewResponseDetail>>,
     _handle: slint::Weak<AppUi>,
-    client: Arc<Mutex<UDSClientSession>>,
+    client: Arc<Mutex<UdsConnection>>,
     mut stop_rx_monitor: oneshot::Receiver<()>,
 ) {
     tokio::spawn(async move {
@@ -134,7 +248,14 @@ pub async fn spawn_monitor_view_thread(
                                         action.service, action.option1, action.option2, action.option3
                                     );
 
-                                    let mut guard = client.lock().await;
+                                    let mut connection = client.lock().await;
+                                    let guard = match connection.client() {
+                                        Ok(guard) => guard,
+                                        Err(e) => {
+                                            s.send(Err(e)).unwrap();
+                                            continue;
+                                        }
+                                    };
 
                                     if action.service == "Read Monitor View" {
                                         let res = guard
@@ -164,9 +285,9 @@ pub async fn spawn_monitor_view_thread(
 
 pub async fn spawn_worker_thread(
     mut r: UnboundedReceiver<UdsMessage>,
-    s: UnboundedSender<UdsServiceResponse>,
+    s: UnboundedSender<DiagServerResult<String>>,
     _handle: slint::Weak<AppUi>,
-    client: Arc<Mutex<UDSClientSession>>,
+    client: Arc<Mutex<UdsConnection>>,
     mut stop_rx_uds_worker: oneshot::Receiver<()>,
 ) {
     tokio::spawn(async move {
@@ -183,7 +304,14 @@ pub async fn spawn_worker_thread(
                                     return;
                                 }
                                 UdsMessage::Action { action } => {
-                                    let mut guard = client.lock().await;
+                                    let mut connection = client.lock().await;
+                                    let guard = match connection.client() {
+                                        Ok(guard) => guard,
+                                        Err(e) => {
+                                            s.send(Err(e)).unwrap();
+                                            continue;
+                                        }
+                                    };
 
                                     println!(
                                         "Perform action: {}, {}, {}, {}",
@@ -194,72 +322,72 @@ pub async fn spawn_worker_thread(
                                         if action.option1 == "Bike State and Bike Lock" {
                                             let res = guard.invoke_read_data_by_id_service(DataId::BikeState);
 
-                                            s.send(res).unwrap();
+                                            s.send(res.map(ConsoleOutput::console_output)).unwrap();
                                         } else if action.option1 == "Switch Gear" {
                                             let res = guard.invoke_read_data_by_id_service(DataId::SwitchGear);
 
-                                            s.send(res).unwrap();
+                                            s.send(res.map(ConsoleOutput::console_output)).unwrap();
                                         } else if action.option1 == "Error Code" {
                                             let res =
                                                 guard.invoke_read_data_by_id_service(DataId::ComponentError);
 
-                                            s.send(res).unwrap();
+                                            s.send(res.map(ConsoleOutput::console_output)).unwrap();
                                         } else if action.option1 == "Inertial measurement unit (IMU)" {
                                             let res = guard.invoke_read_data_by_id_service(DataId::ImuRaw);
 
-                                            s.send(res).unwrap();
+                                            s.send(res.map(ConsoleOutput::console_output)).unwrap();
                                         } else if action.option1 == "Keyfob Data" {
                                             let res = guard.invoke_read_data_by_id_service(DataId::KeyfobState);
 
-                                            s.send(res).unwrap();
+                                            s.send(res.map(ConsoleOutput::console_output)).unwrap();
                                         } else if action.option1 == "Vehicle Metrics 1" {
                                             let res = guard
                                                 .invoke_read_data_by_id_service(DataId::PerformanceVehicle1);
 
-                                            s.send(res).unwrap();
+                                            s.send(res.map(ConsoleOutput::console_output)).unwrap();
                                         } else if action.option1 == "Vehicle Metrics 2" {
                                             let res = guard
                                                 .invoke_read_data_by_id_service(DataId::PerformanceVehicle2);
 
-                                            s.send(res).unwrap();
+                                            s.send(res.map(ConsoleOutput::console_output)).unwrap();
                                         } else if action.option1 == "Charge Metrics" {
                                             let res =
                                                 guard.invoke_read_data_by_id_service(DataId::PerformanceCharge);
 
-                                            s.send(res).unwrap();
+                                            s.send(res.map(ConsoleOutput::console_output)).unwrap();
                                         } else if action.option1 == "Firmware Version" {
                                             let res =
                                                 guard.invoke_read_data_by_id_service(DataId::FirmwareVersion);
 
-                                            s.send(res).unwrap();
+                                            s.send(res.map(ConsoleOutput::console_output)).unwrap();
                                         } else if action.option1 == "Analog-Digital Converter Voltage" {
                                             let res = guard.invoke_read_data_by_id_service(DataId::AdcVoltage);
 
-                                            s.send(res).unwrap();
+                                            s.send(res.map(ConsoleOutput::console_output)).unwrap();
                                         } else if action.option1 == "Battery management system (BMS) Data1" {
                                             let res = guard.invoke_read_data_by_id_service(DataId::Bms1);
 
-                                            s.send(res).unwrap();
+                                            s.send(res.map(ConsoleOutput::console_output)).unwrap();
                                         } else if action.option1 == "Battery management system (BMS) Data2" {
                                             let res = guard.invoke_read_data_by_id_service(DataId::Bms2);
 
-                                            s.send(res).unwrap();
+                                            s.send(res.map(ConsoleOutput::console_output)).unwrap();
                                         } else if action.option1 == "Battery management system (BMS) Data3" {
                                             let res = guard.invoke_read_data_by_id_service(DataId::Bms3);
 
-                                            s.send(res).unwrap();
+                                            s.send(res.map(ConsoleOutput::console_output)).unwrap();
                                         } else if action.option1 == "Temmperature Sensors" {
                                             let res = guard.invoke_read_data_by_id_service(DataId::TempSensors);
 
-                                            s.send(res).unwrap();
+                                            s.send(res.map(ConsoleOutput::console_output)).unwrap();
                                         } else if action.option1 == "Diag State" {
                                             let res = guard.invoke_read_data_by_id_service(DataId::DiagState);
 
-                                            s.send(res).unwrap();
+                                            s.send(res.map(ConsoleOutput::console_output)).unwrap();
                                         } else if action.option1 == "Dashboard" {
                                             let res = guard.invoke_read_data_by_id_service(DataId::Dashboard);
 
-                                            s.send(res).unwrap();
+                                            s.send(res.map(ConsoleOutput::console_output)).unwrap();
                                         }
                                     } else if action.service == "Routine" {
                                         if action.option1 == "Simulate VCU Input" {
@@ -272,7 +400,7 @@ pub async fn spawn_worker_thread(
                                                             &[SimulateInputOption::RightBrakeSwitch as u8],
                                                         )
                                                         .await;
-                                                    s.send(res).unwrap();
+                                                    s.send(res.map(ConsoleOutput::console_output)).unwrap();
                                                 } else if action.option3 == "Disable" {
                                                     let res = guard
                                                         .invoke_routine_control_service(
@@ -281,7 +409,7 @@ pub async fn spawn_worker_thread(
                                                             &[SimulateInputOption::RightBrakeSwitch as u8],
                                                         )
                                                         .await;
-                                                    s.send(res).unwrap();
+                                                    s.send(res.map(ConsoleOutput::console_output)).unwrap();
                                                 }
                                             } else if action.option2 == "Left Brake Switch" {
                                                 if action.option3 == "Enable" {
@@ -292,7 +420,7 @@ pub async fn spawn_worker_thread(
                                                             &[SimulateInputOption::LeftBrakeSwitch as u8],
                                                         )
                                                         .await;
-                                                    s.send(res).unwrap();
+                                                    s.send(res.map(ConsoleOutput::console_output)).unwrap();
                                                 } else if action.option3 == "Disable" {
                                                     let res = guard
                                                         .invoke_routine_control_service(
@@ -301,7 +429,7 @@ pub async fn spawn_worker_thread(
                                                             &[SimulateInputOption::LeftBrakeSwitch as u8],
                                                         )
                                                         .await;
-                                                    s.send(res).unwrap();
+                                                    s.send(res.map(ConsoleOutput::console_output)).unwrap();
                                                 }
                                             } else if action.option2 == "Kill Switch" {
                                                 if action.option3 == "Enable" {
@@ -312,7 +440,7 @@ pub async fn spawn_worker_thread(
                                                             &[SimulateInputOption::KillSwitch as u8],
                                                         )
                                                         .await;
-                                                    s.send(res).unwrap();
+                                                    s.send(res.map(ConsoleOutput::console_output)).unwrap();
                                                 } else if action.option3 == "Disable" {
                                                     let res = guard
                                                         .invoke_routine_control_service(
@@ -321,7 +449,7 @@ pub async fn spawn_worker_thread(
                                                             &[SimulateInputOption::KillSwitch as u8],
                                                         )
                                                         .await;
-                                                    s.send(res).unwrap();
+                                                    s.send(res.map(ConsoleOutput::console_output)).unwrap();
                                                 }
                                             } else if action.option2 == "Power Switch" {
                                                 if action.option3 == "Enable" {
@@ -332,7 +460,7 @@ pub async fn spawn_worker_thread(
                                                             &[SimulateInputOption::PowerSwitch as u8],
                                                         )
                                                         .await;
-                                                    s.send(res).unwrap();
+                                                    s.send(res.map(ConsoleOutput::console_output)).unwrap();
                                                 } else if action.option3 == "Disable" {
                                                     let res = guard
                                                         .invoke_routine_control_service(
@@ -341,7 +469,7 @@ pub async fn spawn_worker_thread(
                                                             &[SimulateInputOption::PowerSwitch as u8],
                                                         )
                                                         .await;
-                                                    s.send(res).unwrap();
+                                                    s.send(res.map(ConsoleOutput::console_output)).unwrap();
                                                 }
                                             } else if action.option2 == "Reverse Switch" {
                                                 if action.option3 == "Enable" {
@@ -352,7 +480,7 @@ pub async fn spawn_worker_thread(
                                                             &[SimulateInputOption::ReverseSwitch as u8],
                                                         )
                                                         .await;
-                                                    s.send(res).unwrap();
+                                                    s.send(res.map(ConsoleOutput::console_output)).unwrap();
                                                 } else if action.option3 == "Disable" {
                                                     let res = guard
                                                         .invoke_routine_control_service(
@@ -361,7 +489,7 @@ pub async fn spawn_worker_thread(
                                                             &[SimulateInputOption::ReverseSwitch as u8],
                                                         )
                                                         .await;
-                                                    s.send(res).unwrap();
+                                                    s.send(res.map(ConsoleOutput::console_output)).unwrap();
                                                 }
                                             } else if action.option2 == "Side Stand Switch" {
                                                 if action.option3 == "Enable" {
@@ -372,7 +500,7 @@ pub async fn spawn_worker_thread(
                                                             &[SimulateInputOption::SideStandSwitch as u8],
                                                         )
                                                         .await;
-                                                    s.send(res).unwrap();
+                                                    s.send(res.map(ConsoleOutput::console_output)).unwrap();
                                                 } else if action.option3 == "Disable" {
                                                     let res = guard
                                                         .invoke_routine_control_service(
@@ -381,7 +509,7 @@ pub async fn spawn_worker_thread(
                                                             &[SimulateInputOption::SideStandSwitch as u8],
                                                         )
                                                         .await;
-                                                    s.send(res).unwrap();
+                                                    s.send(res.map(ConsoleOutput::console_output)).unwrap();
                                                 }
                                             } else if action.option2 == "Ride Mode Switch" {
                                                 if action.option3 == "Enable" {
@@ -392,7 +520,7 @@ pub async fn spawn_worker_thread(
                                                             &[SimulateInputOption::RideModeSwitch as u8],
                                                         )
                                                         .await;
-                                                    s.send(res).unwrap();
+                                                    s.send(res.map(ConsoleOutput::console_output)).unwrap();
                                                 } else if action.option3 == "Disable" {
                                                     let res = guard
                                                         .invoke_routine_control_service(
@@ -401,7 +529,7 @@ pub async fn spawn_worker_thread(
                                                             &[SimulateInputOption::RideModeSwitch as u8],
                                                         )
                                                         .await;
-                                                    s.send(res).unwrap();
+                                                    s.send(res.map(ConsoleOutput::console_output)).unwrap();
                                                 }
                                             } else if action.option2 == "Hazard Switch" {
                                                 if action.option3 == "Enable" {
@@ -412,7 +540,7 @@ pub async fn spawn_worker_thread(
                                                             &[SimulateInputOption::HazardSwitch as u8],
                                                         )
                                                         .await;
-                                                    s.send(res).unwrap();
+                                                    s.send(res.map(ConsoleOutput::console_output)).unwrap();
                                                 } else if action.option3 == "Disable" {
                                                     let res = guard
                                                         .invoke_routine_control_service(
@@ -421,7 +549,7 @@ pub async fn spawn_worker_thread(
                                                             &[SimulateInputOption::HazardSwitch as u8],
                                                         )
                                                         .await;
-                                                    s.send(res).unwrap();
+                                                    s.send(res.map(ConsoleOutput::console_output)).unwrap();
                                                 }
                                             } else if action.option2 == "Horn Switch" {
                                                 if action.option3 == "Enable" {
@@ -432,7 +560,7 @@ pub async fn spawn_worker_thread(
                                                             &[SimulateInputOption::HornSwitch as u8],
                                                         )
                                                         .await;
-                                                    s.send(res).unwrap();
+                                                    s.send(res.map(ConsoleOutput::console_output)).unwrap();
                                                 } else if action.option3 == "Disable" {
                                                     let res = guard
                                                         .invoke_routine_control_service(
@@ -441,7 +569,7 @@ pub async fn spawn_worker_thread(
                                                             &[SimulateInputOption::HornSwitch as u8],
                                                         )
                                                         .await;
-                                                    s.send(res).unwrap();
+                                                    s.send(res.map(ConsoleOutput::console_output)).unwrap();
                                                 }
                                             } else if action.option2 == "Right Indicator Switch" {
                                                 if action.option3 == "Enable" {
@@ -452,7 +580,7 @@ pub async fn spawn_worker_thread(
                                                             &[SimulateInputOption::RightIndicatorSwitch as u8],
                                                         )
                                                         .await;
-                                                    s.send(res).unwrap();
+                                                    s.send(res.map(ConsoleOutput::console_output)).unwrap();
                                                 } else if action.option3 == "Disable" {
                                                     let res = guard
                                                         .invoke_routine_control_service(
@@ -461,7 +589,7 @@ pub async fn spawn_worker_thread(
                                                             &[SimulateInputOption::RightIndicatorSwitch as u8],
                                                         )
                                                         .await;
-                                                    s.send(res).unwrap();
+                                                    s.send(res.map(ConsoleOutput::console_output)).unwrap();
                                                 }
                                             } else if action.option2 == "Left Indicator Switch" {
                                                 if action.option3 == "Enable" {
@@ -472,7 +600,7 @@ pub async fn spawn_worker_thread(
                                                             &[SimulateInputOption::LeftIndicatorSwitch as u8],
                                                         )
                                                         .await;
-                                                    s.send(res).unwrap();
+                                                    s.send(res.map(ConsoleOutput::console_output)).unwrap();
                                                 } else if action.option3 == "Disable" {
                                                     let res = guard
                                                         .invoke_routine_control_service(
@@ -481,7 +609,7 @@ pub async fn spawn_worker_thread(
                                                             &[SimulateInputOption::LeftIndicatorSwitch as u8],
                                                         )
                                                         .await;
-                                                    s.send(res).unwrap();
+                                                    s.send(res.map(ConsoleOutput::console_output)).unwrap();
                                                 }
                                             } else if action.option2 == "High Beam Switch" {
                                                 if action.option3 == "Enable" {
@@ -492,7 +620,7 @@ pub async fn spawn_worker_thread(
                                                             &[SimulateInputOption::HighBeamSwitch as u8],
                                                         )
                                                         .await;
-                                                    s.send(res).unwrap();
+                                                    s.send(res.map(ConsoleOutput::console_output)).unwrap();
                                                 } else if action.option3 == "Disable" {
                                                     let res = guard
                                                         .invoke_routine_control_service(
@@ -501,7 +629,7 @@ pub async fn spawn_worker_thread(
                                                             &[SimulateInputOption::HighBeamSwitch as u8],
                                                         )
                                                         .await;
-                                                    s.send(res).unwrap();
+                                                    s.send(res.map(ConsoleOutput::console_output)).unwrap();
                                                 }
                                             } else if action.option2 == "Start Switch" {
                                                 if action.option3 == "Enable" {
@@ -512,7 +640,7 @@ pub async fn spawn_worker_thread(
                                                             &[SimulateInputOption::StartSwitch as u8],
                                                         )
                                                         .await;
-                                                    s.send(res).unwrap();
+                                                    s.send(res.map(ConsoleOutput::console_output)).unwrap();
                                                 } else if action.option3 == "Disable" {
                                                     let res = guard
                                                         .invoke_routine_control_service(
@@ -521,7 +649,7 @@ pub async fn spawn_worker_thread(
                                                             &[SimulateInputOption::StartSwitch as u8],
                                                         )
                                                         .await;
-                                                    s.send(res).unwrap();
+                                                    s.send(res.map(ConsoleOutput::console_output)).unwrap();
                                                 }
                                             } else if action.option2 == "Seat Switch" {
                                                 if action.option3 == "Enable" {
@@ -532,7 +660,7 @@ pub async fn spawn_worker_thread(
                                                             &[SimulateInputOption::SeatSwitch as u8],
                                                         )
                                                         .await;
-                                                    s.send(res).unwrap();
+                                                    s.send(res.map(ConsoleOutput::console_output)).unwrap();
                                                 } else if action.option3 == "Disable" {
                                                     let res = guard
                                                         .invoke_routine_control_service(
@@ -541,7 +669,7 @@ pub async fn spawn_worker_thread(
                                                             &[SimulateInputOption::SeatSwitch as u8],
                                                         )
                                                         .await;
-                                                    s.send(res).unwrap();
+                                                    s.send(res.map(ConsoleOutput::console_output)).unwrap();
                                                 }
                                             } else if action.option2 == "Trip Switch" {
                                                 if action.option3 == "Enable" {
@@ -552,7 +680,7 @@ pub async fn spawn_worker_thread(
                                                             &[SimulateInputOption::TripSwitch as u8],
                                                         )
                                                         .await;
-                                                    s.send(res).unwrap();
+                                                    s.send(res.map(ConsoleOutput::console_output)).unwrap();
                                                 } else if action.option3 == "Disable" {
                                                     let res = guard
                                                         .invoke_routine_control_service(
@@ -561,7 +689,7 @@ pub async fn spawn_worker_thread(
                                                             &[SimulateInputOption::TripSwitch as u8],
                                                         )
                                                         .await;
-                                                    s.send(res).unwrap();
+                                                    s.send(res.map(ConsoleOutput::console_output)).unwrap();
                                                 }
                                             } else if action.option2 == "Down Switch" {
                                                 if action.option3 == "Enable" {
@@ -572,7 +700,7 @@ pub async fn spawn_worker_thread(
                                                             &[SimulateInputOption::DownSwitch as u8],
                                                         )
                                                         .await;
-                                                    s.send(res).unwrap();
+                                                    s.send(res.map(ConsoleOutput::console_output)).unwrap();
                                                 } else if action.option3 == "Disable" {
                                                     let res = guard
                                                         .invoke_routine_control_service(
@@ -581,7 +709,7 @@ pub async fn spawn_worker_thread(
                                                             &[SimulateInputOption::DownSwitch as u8],
                                                         )
                                                         .await;
-                                                    s.send(res).unwrap();
+                                                    s.send(res.map(ConsoleOutput::console_output)).unwrap();
                                                 }
                                             } else if action.option2 == "Keyfob Short Press" {
                                                 if action.option3 == "Enable" {
@@ -592,7 +720,7 @@ pub async fn spawn_worker_thread(
                                                             &[SimulateInputOption::KeyfobShortPress as u8],
                                                         )
                                                         .await;
-                                                    s.send(res).unwrap();
+                                                    s.send(res.map(ConsoleOutput::console_output)).unwrap();
                                                 } else if action.option3 == "Disable" {
                                                     let res = guard
                                                         .invoke_routine_control_service(
@@ -601,7 +729,7 @@ pub async fn spawn_worker_thread(
                                                             &[SimulateInputOption::KeyfobShortPress as u8],
                                                         )
                                                         .await;
-                                                    s.send(res).unwrap();
+                                                    s.send(res.map(ConsoleOutput::console_output)).unwrap();
                                                 }
                                             } else if action.option2 == "Keyfob Long Press" {
                                                 if action.option3 == "Enable" {
@@ -612,7 +740,7 @@ pub async fn spawn_worker_thread(
                                                             &[SimulateInputOption::KeyfobLongPress as u8],
                                                         )
                                                         .await;
-                                                    s.send(res).unwrap();
+                                                    s.send(res.map(ConsoleOutput::console_output)).unwrap();
                                                 } else if action.option3 == "Disable" {
                                                     let res = guard
                                                         .invoke_routine_control_service(
@@ -621,7 +749,7 @@ pub async fn spawn_worker_thread(
                                                             &[SimulateInputOption::KeyfobLongPress as u8],
                                                         )
                                                         .await;
-                                                    s.send(res).unwrap();
+                                                    s.send(res.map(ConsoleOutput::console_output)).unwrap();
                                                 }
                                             }
                                         } else if action.option1 == "Trigger VCU Output" {
@@ -634,7 +762,7 @@ pub async fn spawn_worker_thread(
                                                             &[TriggerOutputOption::HssRearRightIndicator as u8],
                                                         )
                                                         .await;
-                                                    s.send(res).unwrap();
+                                                    s.send(res.map(ConsoleOutput::console_output)).unwrap();
                                                 } else if action.option3 == "Disable" {
                                                     let res = guard
                                                         .invoke_routine_control_service(
@@ -643,7 +771,7 @@ pub async fn spawn_worker_thread(
                                                             &[TriggerOutputOption::HssRearRightIndicator as u8],
                                                         )
                                                         .await;
-                                                    s.send(res).unwrap();
+                                                    s.send(res.map(ConsoleOutput::console_output)).unwrap();
                                                 }
                                             } else if action.option2 == "Rear Left Indicator" {
                                                 if action.option3 == "Enable" {
@@ -654,7 +782,7 @@ pub async fn spawn_worker_thread(
                                                             &[TriggerOutputOption::HssRearLeftIndicator as u8],
                                                         )
                                                         .await;
-                                                    s.send(res).unwrap();
+                                                    s.send(res.map(ConsoleOutput::console_output)).unwrap();
                                                 } else if action.option3 == "Disable" {
                                                     let res = guard
                                                         .invoke_routine_control_service(
@@ -663,7 +791,7 @@ pub async fn spawn_worker_thread(
                                                             &[TriggerOutputOption::HssRearLeftIndicator as u8],
                                                         )
                                                         .await;
-                                                    s.send(res).unwrap();
+                                                    s.send(res.map(ConsoleOutput::console_output)).unwrap();
                                                 }
                                             } else if action.option2 == "Brake Light" {
                                                 if action.option3 == "Enable" {
@@ -674,7 +802,7 @@ pub async fn spawn_worker_thread(
                                                             &[TriggerOutputOption::HssBrakeLight as u8],
                                                         )
                                                         .await;
-                                                    s.send(res).unwrap();
+                                                    s.send(res.map(ConsoleOutput::console_output)).unwrap();
                                                 } else if action.option3 == "Disable" {
                                                     let res = guard
                                                         .invoke_routine_control_service(
@@ -683,7 +811,7 @@ pub async fn spawn_worker_thread(
                                                             &[TriggerOutputOption::HssBrakeLight as u8],
                                                         )
                                                         .await;
-                                                    s.send(res).unwrap();
+                                                    s.send(res.map(ConsoleOutput::console_output)).unwrap();
                                                 }
                                             } else if action.option2 == "Horn" {
                                                 if action.option3 == "Enable" {
@@ -694,7 +822,7 @@ pub async fn spawn_worker_thread(
                                                             &[TriggerOutputOption::HssHorn as u8],
                                                         )
                                                         .await;
-                                                    s.send(res).unwrap();
+                                                    s.send(res.map(ConsoleOutput::console_output)).unwrap();
                                                 } else if action.option3 == "Disable" {
                                                     let res = guard
                                                         .invoke_routine_control_service(
@@ -703,7 +831,7 @@ pub async fn spawn_worker_thread(
                                                             &[TriggerOutputOption::HssHorn as u8],
                                                         )
                                                         .await;
-                                                    s.send(res).unwrap();
+                                                    s.send(res.map(ConsoleOutput::console_output)).unwrap();
                                                 }
                                             } else if action.option2 == "High Beam" {
                                                 if action.option3 == "Enable" {
@@ -714,7 +842,7 @@ pub async fn spawn_worker_thread(
                                                             &[TriggerOutputOption::HssHighBeam as u8],
                                                         )
                                                         .await;
-                                                    s.send(res).unwrap();
+                                                    s.send(res.map(ConsoleOutput::console_output)).unwrap();
                                                 } else if action.option3 == "Disable" {
                                                     let res = guard
                                                         .invoke_routine_control_service(
@@ -723,7 +851,7 @@ pub async fn spawn_worker_thread(
                                                             &[TriggerOutputOption::HssHighBeam as u8],
                                                         )
                                                         .await;
-                                                    s.send(res).unwrap();
+                                                    s.send(res.map(ConsoleOutput::console_output)).unwrap();
                                                 }
                                             } else if action.option2 == "Low Beam" {
                                                 if action.option3 == "Enable" {
@@ -734,7 +862,7 @@ pub async fn spawn_worker_thread(
                                                             &[TriggerOutputOption::HssLowBeam as u8],
                                                         )
                                                         .await;
-                                                    s.send(res).unwrap();
+                                                    s.send(res.map(ConsoleOutput::console_output)).unwrap();
                                                 } else if action.option3 == "Disable" {
                                                     let res = guard
                                                         .invoke_routine_control_service(
@@ -743,7 +871,7 @@ pub async fn spawn_worker_thread(
                                                             &[TriggerOutputOption::HssLowBeam as u8],
                                                         )
                                                         .await;
-                                                    s.send(res).unwrap();
+                                                    s.send(res.map(ConsoleOutput::console_output)).unwrap();
                                                 }
                                             } else if action.option2 == "License Plate" {
                                                 if action.option3 == "Enable" {
@@ -754,7 +882,7 @@ pub async fn spawn_worker_thread(
                                                             &[TriggerOutputOption::HssLicensePlate as u8],
                                                         )
                                                         .await;
-                                                    s.send(res).unwrap();
+                                                    s.send(res.map(ConsoleOutput::console_output)).unwrap();
                                                 } else if action.option3 == "Disable" {
                                                     let res = guard
                                                         .invoke_routine_control_service(
@@ -763,7 +891,7 @@ pub async fn spawn_worker_thread(
                                                             &[TriggerOutputOption::HssLicensePlate as u8],
                                                         )
                                                         .await;
-                                                    s.send(res).unwrap();
+                                                    s.send(res.map(ConsoleOutput::console_output)).unwrap();
                                                 }
                                             } else if action.option2 == "Front Left Indicator" {
                                                 if action.option3 == "Enable" {
@@ -774,7 +902,7 @@ pub async fn spawn_worker_thread(
                                                             &[TriggerOutputOption::HssFrontLeftIndicator as u8],
                                                         )
                                                         .await;
-                                                    s.send(res).unwrap();
+                                                    s.send(res.map(ConsoleOutput::console_output)).unwrap();
                                                 } else if action.option3 == "Disable" {
                                                     let res = guard
                                                         .invoke_routine_control_service(
@@ -783,7 +911,7 @@ pub async fn spawn_worker_thread(
                                                             &[TriggerOutputOption::HssFrontLeftIndicator as u8],
                                                         )
                                                         .await;
-                                                    s.send(res).unwrap();
+                                                    s.send(res.map(ConsoleOutput::console_output)).unwrap();
                                                 }
                                             } else if action.option2 == "Front Right Indicator" {
                                                 if action.option3 == "Enable" {
@@ -795,7 +923,7 @@ pub async fn spawn_worker_thread(
                                                                 as u8],
                                                         )
                                                         .await;
-                                                    s.send(res).unwrap();
+                                                    s.send(res.map(ConsoleOutput::console_output)).unwrap();
                                                 } else if action.option3 == "Disable" {
                                                     let res = guard
                                                         .invoke_routine_control_service(
@@ -805,7 +933,7 @@ pub async fn spawn_worker_thread(
                                                                 as u8],
                                                         )
                                                         .await;
-                                                    s.send(res).unwrap();
+                                                    s.send(res.map(ConsoleOutput::console_output)).unwrap();
                                                 }
                                             } else if action.option2 == "Tail Light" {
                                                 if action.option3 == "Enable" {
@@ -816,7 +944,7 @@ pub async fn spawn_worker_thread(
                                                             &[TriggerOutputOption::HssTailLight as u8],
                                                         )
                                                         .await;
-                                                    s.send(res).unwrap();
+                                                    s.send(res.map(ConsoleOutput::console_output)).unwrap();
                                                 } else if action.option3 == "Disable" {
                                                     let res = guard
                                                         .invoke_routine_control_service(
@@ -825,7 +953,7 @@ pub async fn spawn_worker_thread(
                                                             &[TriggerOutputOption::HssTailLight as u8],
                                                         )
                                                         .await;
-                                                    s.send(res).unwrap();
+                                                    s.send(res.map(ConsoleOutput::console_output)).unwrap();
                                                 }
                                             } else if action.option2 == "Seat Lock" {
                                                 if action.option3 == "Enable" {
@@ -836,7 +964,7 @@ pub async fn spawn_worker_thread(
                                                             &[TriggerOutputOption::HssSeatLock as u8],
                                                         )
                                                         .await;
-                                                    s.send(res).unwrap();
+                                                    s.send(res.map(ConsoleOutput::console_output)).unwrap();
                                                 } else if action.option3 == "Disable" {
                                                     let res = guard
                                                         .invoke_routine_control_service(
@@ -845,7 +973,7 @@ pub async fn spawn_worker_thread(
                                                             &[TriggerOutputOption::HssSeatLock as u8],
                                                         )
                                                         .await;
-                                                    s.send(res).unwrap();
+                                                    s.send(res.map(ConsoleOutput::console_output)).unwrap();
                                                 }
                                             } else if action.option2 == "BMS Enable" {
                                                 if action.option3 == "Enable" {
@@ -856,7 +984,7 @@ pub async fn spawn_worker_thread(
                                                             &[TriggerOutputOption::HssBmsEnable as u8],
                                                         )
                                                         .await;
-                                                    s.send(res).unwrap();
+                                                    s.send(res.map(ConsoleOutput::console_output)).unwrap();
                                                 } else if action.option3 == "Disable" {
                                                     let res = guard
                                                         .invoke_routine_control_service(
@@ -865,7 +993,7 @@ pub async fn spawn_worker_thread(
                                                             &[TriggerOutputOption::HssBmsEnable as u8],
                                                         )
                                                         .await;
-                                                    s.send(res).unwrap();
+                                                    s.send(res.map(ConsoleOutput::console_output)).unwrap();
                                                 }
                                             } else if action.option2 == "Motor Enable" {
                                                 if action.option3 == "Enable" {
@@ -876,7 +1004,7 @@ pub async fn spawn_worker_thread(
                                                             &[TriggerOutputOption::HssMotorEnable as u8],
                                                         )
                                                         .await;
-                                                    s.send(res).unwrap();
+                                                    s.send(res.map(ConsoleOutput::console_output)).unwrap();
                                                 } else if action.option3 == "Disable" {
                                                     let res = guard
                                                         .invoke_routine_control_service(
@@ -885,7 +1013,7 @@ pub async fn spawn_worker_thread(
                                                             &[TriggerOutputOption::HssMotorEnable as u8],
                                                         )
                                                         .await;
-                                                    s.send(res).unwrap();
+                                                    s.send(res.map(ConsoleOutput::console_output)).unwrap();
                                                 }
                                             } else if action.option2 == "Steer Lock" {
                                                 if action.option3 == "Enable" {
@@ -896,7 +1024,7 @@ pub async fn spawn_worker_thread(
                                                             &[TriggerOutputOption::HssSteerLock as u8],
                                                         )
                                                         .await;
-                                                    s.send(res).unwrap();
+                                                    s.send(res.map(ConsoleOutput::console_output)).unwrap();
                                                 } else if action.option3 == "Disable" {
                                                     let res = guard
                                                         .invoke_routine_control_service(
@@ -905,7 +1033,7 @@ pub async fn spawn_worker_thread(
                                                             &[TriggerOutputOption::HssSteerLock as u8],
                                                         )
                                                         .await;
-                                                    s.send(res).unwrap();
+                                                    s.send(res.map(ConsoleOutput::console_output)).unwrap();
                                                 }
                                             } else if action.option2 == "DRL" {
                                                 if action.option3 == "Enable" {
@@ -916,7 +1044,7 @@ pub async fn spawn_worker_thread(
                                                             &[TriggerOutputOption::HssDrl as u8],
                                                         )
                                                         .await;
-                                                    s.send(res).unwrap();
+                                                    s.send(res.map(ConsoleOutput::console_output)).unwrap();
                                                 } else if action.option3 == "Disable" {
                                                     let res = guard
                                                         .invoke_routine_control_service(
@@ -925,7 +1053,7 @@ pub async fn spawn_worker_thread(
                                                             &[TriggerOutputOption::HssDrl as u8],
                                                         )
                                                         .await;
-                                                    s.send(res).unwrap();
+                                                    s.send(res.map(ConsoleOutput::console_output)).unwrap();
                                                 }
                                             } else if action.option2 == "Tire Pressure Monitoring System (TPMS)"
                                             {
@@ -937,7 +1065,7 @@ pub async fn spawn_worker_thread(
                                                             &[TriggerOutputOption::HssTpms as u8],
                                                         )
                                                         .await;
-                                                    s.send(res).unwrap();
+                                                    s.send(res.map(ConsoleOutput::console_output)).unwrap();
                                                 } else if action.option3 == "Disable" {
                                                     let res = guard
                                                         .invoke_routine_control_service(
@@ -946,7 +1074,7 @@ pub async fn spawn_worker_thread(
                                                             &[TriggerOutputOption::HssTpms as u8],
                                                         )
                                                         .await;
-                                                    s.send(res).unwrap();
+                                                    s.send(res.map(ConsoleOutput::console_output)).unwrap();
                                                 }
                                             } else if action.option2 == "Side Stand Power" {
                                                 if action.option3 == "Enable" {
@@ -957,7 +1085,7 @@ pub async fn spawn_worker_thread(
                                                             &[TriggerOutputOption::HssSideStandPower as u8],
                                                         )
                                                         .await;
-                                                    s.send(res).unwrap();
+                                                    s.send(res.map(ConsoleOutput::console_output)).unwrap();
                                                 } else if action.option3 == "Disable" {
                                                     let res = guard
                                                         .invoke_routine_control_service(
@@ -966,7 +1094,7 @@ pub async fn spawn_worker_thread(
                                                             &[TriggerOutputOption::HssSideStandPower as u8],
                                                         )
                                                         .await;
-                                                    s.send(res).unwrap();
+                                                    s.send(res.map(ConsoleOutput::console_output)).unwrap();
                                                 }
                                             }
                                         } else if action.option1 == "Open Debug Screen" {
@@ -977,7 +1105,7 @@ pub async fn spawn_worker_thread(
                                                     &[],
                                                 )
                                                 .await;
-                                            s.send(res).unwrap();
+                                            s.send(res.map(ConsoleOutput::console_output)).unwrap();
                                         } else if action.option1 == "Close Debug Screen" {
                                             let res = guard
                                                 .invoke_routine_control_service(
@@ -986,7 +1114,7 @@ pub async fn spawn_worker_thread(
                                                     &[],
                                                 )
                                                 .await;
-                                            s.send(res).unwrap();
+                                            s.send(res.map(ConsoleOutput::console_output)).unwrap();
                                         } else if action.option1 == "Toggle Off BMS Voltage" {
                                             let res = guard
                                                 .invoke_routine_control_service(
@@ -995,7 +1123,7 @@ pub async fn spawn_worker_thread(
                                                     &[],
                                                 )
                                                 .await;
-                                            s.send(res).unwrap();
+                                            s.send(res.map(ConsoleOutput::console_output)).unwrap();
                                         } else if action.option1 == "Toggle On BMS Voltage" {
                                             let res = guard
                                                 .invoke_routine_control_service(
@@ -1004,7 +1132,7 @@ pub async fn spawn_worker_thread(
                                                     &[],
                                                 )
                                                 .await;
-                                            s.send(res).unwrap();
+                                            s.send(res.map(ConsoleOutput::console_output)).unwrap();
                                         } else if action.option1 == "Bike Force Unlock" {
                                             let res = guard
                                                 .invoke_routine_control_service(
@@ -1013,7 +1141,7 @@ pub async fn spawn_worker_thread(
                                                     &[],
                                                 )
                                                 .await;
-                                            s.send(res).unwrap();
+                                            s.send(res.map(ConsoleOutput::console_output)).unwrap();
                                         } else if action.option1 == "Bike Force Lock" {
                                             let res = guard
                                                 .invoke_routine_control_service(
@@ -1022,7 +1150,7 @@ pub async fn spawn_worker_thread(
                                                     &[],
                                                 )
                                                 .await;
-                                            s.send(res).unwrap();
+                                            s.send(res.map(ConsoleOutput::console_output)).unwrap();
                                         }
                                     } else if action.service == "Connectivity" {
                                         if action.option1 == "Wifi Scan" {
@@ -1033,7 +1161,7 @@ pub async fn spawn_worker_thread(
                                                     &[],
                                                 )
                                                 .await;
-                                            s.send(res).unwrap();
+                                            s.send(res.map(ConsoleOutput::console_output)).unwrap();
                                         } else if action.option1 == "Wifi Scan Get Result" {
                                             let res = guard
                                                 .invoke_routine_control_service_get_result(
@@ -1042,7 +1170,7 @@ pub async fn spawn_worker_thread(
                                                     &[],
                                                 )
                                                 .await;
-                                            s.send(res).unwrap();
+                                            s.send(res.map(ConsoleOutput::console_output)).unwrap();
                                         } else if action.option1 == "Wifi Check IP" {
                                             let res = guard
                                                 .invoke_routine_control_service(
@@ -1051,7 +1179,7 @@ pub async fn spawn_worker_thread(
                                                     &[],
                                                 )
                                                 .await;
-                                            s.send(res).unwrap();
+                                            s.send(res.map(ConsoleOutput::console_output)).unwrap();
                                         } else if action.option1 == "Wifi Check IP Get Result" {
                                             let res = guard
                                                 .invoke_routine_control_service_get_result(
@@ -1060,7 +1188,7 @@ pub async fn spawn_worker_thread(
                                                     &[],
                                                 )
                                                 .await;
-                                            s.send(res).unwrap();
+                                            s.send(res.map(ConsoleOutput::console_output)).unwrap();
                                         } else if action.option1 == "Wifi Restart App" {
                                             let res = guard
                                                 .invoke_routine_control_service(
@@ -1069,7 +1197,7 @@ pub async fn spawn_worker_thread(
                                                     &[],
                                                 )
                                                 .await;
-                                            s.send(res).unwrap();
+                                            s.send(res.map(ConsoleOutput::console_output)).unwrap();
                                         } else if action.option1 == "Wifi Restart App Get Result" {
                                             let res = guard
                                                 .invoke_routine_control_service_get_result(
@@ -1078,7 +1206,7 @@ pub async fn spawn_worker_thread(
                                                     &[],
                                                 )
                                                 .await;
-                                            s.send(res).unwrap();
+                                            s.send(res.map(ConsoleOutput::console_output)).unwrap();
                                         } else if action.option1 == "GPS Check Log" {
                                             let res = guard
                                                 .invoke_routine_control_service(
@@ -1087,7 +1215,7 @@ pub async fn spawn_worker_thread(
                                                     &[],
                                                 )
                                                 .await;
-                                            s.send(res).unwrap();
+                                            s.send(res.map(ConsoleOutput::console_output)).unwrap();
                                         } else if action.option1 == "GPS Check Log Get Result" {
                                             let res = guard
                                                 .invoke_routine_control_service_get_result(
@@ -1096,7 +1224,7 @@ pub async fn spawn_worker_thread(
                                                     &[],
                                                 )
                                                 .await;
-                                            s.send(res).unwrap();
+                                            s.send(res.map(ConsoleOutput::console_output)).unwrap();
                                         } else if action.option1 == "LTE Check IP" {
                                             let res = guard
                                                 .invoke_routine_control_service(
@@ -1105,7 +1233,7 @@ pub async fn spawn_worker_thread(
                                                     &[],
                                                 )
                                                 .await;
-                                            s.send(res).unwrap();
+                                            s.send(res.map(ConsoleOutput::console_output)).unwrap();
                                         } else if action.option1 == "LTE Check IP Get Result" {
                                             let res = guard
                                                 .invoke_routine_control_service_get_result(
@@ -1114,7 +1242,7 @@ pub async fn spawn_worker_thread(
                                                     &[],
                                                 )
                                                 .await;
-                                            s.send(res).unwrap();
+                                            s.send(res.map(ConsoleOutput::console_output)).unwrap();
                                         } else if action.option1 == "LTE Check Ping" {
                                             let res = guard
                                                 .invoke_routine_control_service(
@@ -1123,7 +1251,7 @@ pub async fn spawn_worker_thread(
                                                     &[],
                                                 )
                                                 .await;
-                                            s.send(res).unwrap();
+                                            s.send(res.map(ConsoleOutput::console_output)).unwrap();
                                         } else if action.option1 == "LTE Check Ping Get Result" {
                                             let res = guard
                                                 .invoke_routine_control_service_get_result(
@@ -1132,7 +1260,7 @@ pub async fn spawn_worker_thread(
                                                     &[],
                                                 )
                                                 .await;
-                                            s.send(res).unwrap();
+                                            s.send(res.map(ConsoleOutput::console_output)).unwrap();
                                         } else if action.option1 == "LTE Check Enable Signal" {
                                             let res = guard
                                                 .invoke_routine_control_service(
@@ -1141,7 +1269,7 @@ pub async fn spawn_worker_thread(
                                                     &[],
                                                 )
                                                 .await;
-                                            s.send(res).unwrap();
+                                            s.send(res.map(ConsoleOutput::console_output)).unwrap();
                                         } else if action.option1 == "LTE Check Enable Signal Get Result" {
                                             let res = guard
                                                 .invoke_routine_control_service_get_result(
@@ -1150,7 +1278,7 @@ pub async fn spawn_worker_thread(
                                                     &[],
                                                 )
                                                 .await;
-                                            s.send(res).unwrap();
+                                            s.send(res.map(ConsoleOutput::console_output)).unwrap();
                                         } else if action.option1 == "LTE Get Modem Info" {
                                             let res = guard
                                                 .invoke_routine_control_service(
@@ -1159,7 +1287,7 @@ pub async fn spawn_worker_thread(
                                                     &[],
                                                 )
                                                 .await;
-                                            s.send(res).unwrap();
+                                            s.send(res.map(ConsoleOutput::console_output)).unwrap();
                                         } else if action.option1 == "LTE Get Modem Info Get Result" {
                                             let res = guard
                                                 .invoke_routine_control_service_get_result(
@@ -1168,7 +1296,7 @@ pub async fn spawn_worker_thread(
                                                     &[],
                                                 )
                                                 .await;
-                                            s.send(res).unwrap();
+                                            s.send(res.map(ConsoleOutput::console_output)).unwrap();
                                         } else if action.option1 == "LTE Get Signal Strength" {
                                             let res = guard
                                                 .invoke_routine_control_service(
@@ -1177,7 +1305,7 @@ pub async fn spawn_worker_thread(
                                                     &[],
                                                 )
                                                 .await;
-                                            s.send(res).unwrap();
+                                            s.send(res.map(ConsoleOutput::console_output)).unwrap();
                                         } else if action.option1 == "LTE Get Signal Strength Get Result" {
                                             let res = guard
                                                 .invoke_routine_control_service_get_result(
@@ -1186,7 +1314,7 @@ pub async fn spawn_worker_thread(
                                                     &[],
                                                 )
                                                 .await;
-                                            s.send(res).unwrap();
+                                            s.send(res.map(ConsoleOutput::console_output)).unwrap();
                                         } else if action.option1 == "LTE Enable LTE/GPS" {
                                             let res = guard
                                                 .invoke_routine_control_service(
@@ -1195,7 +1323,7 @@ pub async fn spawn_worker_thread(
                                                     &[],
                                                 )
                                                 .await;
-                                            s.send(res).unwrap();
+                                            s.send(res.map(ConsoleOutput::console_output)).unwrap();
                                         } else if action.option1 == "LTE Disable LTE/GPS" {
                                             let res = guard
                                                 .invoke_routine_control_service(
@@ -1204,7 +1332,7 @@ pub async fn spawn_worker_thread(
                                                     &[],
                                                 )
                                                 .await;
-                                            s.send(res).unwrap();
+                                            s.send(res.map(ConsoleOutput::console_output)).unwrap();
                                         } else if action.option1 == "BLE Restart App" {
                                             let res = guard
                                                 .invoke_routine_control_service(
@@ -1213,7 +1341,7 @@ pub async fn spawn_worker_thread(
                                                     &[],
                                                 )
                                                 .await;
-                                            s.send(res).unwrap();
+                                            s.send(res.map(ConsoleOutput::console_output)).unwrap();
                                         } else if action.option1 == "BLE Restart App Get Result" {
                                             let res = guard
                                                 .invoke_routine_control_service_get_result(
@@ -1222,7 +1350,7 @@ pub async fn spawn_worker_thread(
                                                     &[],
                                                 )
                                                 .await;
-                                            s.send(res).unwrap();
+                                            s.send(res.map(ConsoleOutput::console_output)).unwrap();
                                         } else if action.option1 == "BLE Check Pair" {
                                             let res = guard
                                                 .invoke_routine_control_service(
@@ -1231,7 +1359,7 @@ pub async fn spawn_worker_thread(
                                                     &[],
                                                 )
                                                 .await;
-                                            s.send(res).unwrap();
+                                            s.send(res.map(ConsoleOutput::console_output)).unwrap();
                                         } else if action.option1 == "BLE Check Pair Get Result" {
                                             let res = guard
                                                 .invoke_routine_control_service_get_result(
@@ -1240,7 +1368,7 @@ pub async fn spawn_worker_thread(
                                                     &[],
                                                 )
                                                 .await;
-                                            s.send(res).unwrap();
+                                            s.send(res.map(ConsoleOutput::console_output)).unwrap();
                                         } else if action.option1 == "IMX Check Service Status" {
                                             let res = guard
                                                 .invoke_routine_control_service(
@@ -1249,7 +1377,7 @@ pub async fn spawn_worker_thread(
                                                     &[],
                                                 )
                                                 .await;
-                                            s.send(res).unwrap();
+                                            s.send(res.map(ConsoleOutput::console_output)).unwrap();
                                         } else if action.option1 == "IMX Check Service Status Get Result" {
                                             let res = guard
                                                 .invoke_routine_control_service_get_result(
@@ -1258,45 +1386,45 @@ pub async fn spawn_worker_thread(
                                                     &[],
                                                 )
                                                 .await;
-                                            s.send(res).unwrap();
+                                            s.send(res.map(ConsoleOutput::console_output)).unwrap();
                                         }
                                     } else if action.service == "Reset" {
                                         if action.option1 == "Realtime 118" {
                                             let res = guard.invoke_reset_ecu_service(ResetType::RealtimeReset);
-                                            s.send(res).unwrap();
+                                            s.send(res.map(ConsoleOutput::console_output)).unwrap();
                                         } else if action.option1 == "Telematic 148" {
                                             let res = guard.invoke_reset_ecu_service(ResetType::TelematicReset);
-                                            s.send(res).unwrap();
+                                            s.send(res.map(ConsoleOutput::console_output)).unwrap();
                                         } else if action.option1 == "IMX" {
                                             let res = guard.invoke_reset_ecu_service(ResetType::ImxReset);
-                                            s.send(res).unwrap();
+                                            s.send(res.map(ConsoleOutput::console_output)).unwrap();
                                         } else if action.option1 == "ESP32 WIFI" {
                                             let res = guard.invoke_reset_ecu_service(ResetType::Esp32WifiReset);
-                                            s.send(res).unwrap();
+                                            s.send(res.map(ConsoleOutput::console_output)).unwrap();
                                         } else if action.option1 == "ESP32 BLE" {
                                             let res = guard.invoke_reset_ecu_service(ResetType::Esp32BleReset);
-                                            s.send(res).unwrap();
+                                            s.send(res.map(ConsoleOutput::console_output)).unwrap();
                                         } else if action.option1 == "QUECTEL" {
                                             let res = guard.invoke_reset_ecu_service(ResetType::QuectelReset);
-                                            s.send(res).unwrap();
+                                            s.send(res.map(ConsoleOutput::console_output)).unwrap();
                                         } else if action.option1 == "LIZARD" {
                                             let res = guard.invoke_reset_ecu_service(ResetType::LizardReset);
-                                            s.send(res).unwrap();
+                                            s.send(res.map(ConsoleOutput::console_output)).unwrap();
                                         } else if action.option1 == "CENDRIC" {
                                             let res = guard.invoke_reset_ecu_service(ResetType::CendricReset);
-                                            s.send(res).unwrap();
+                                            s.send(res.map(ConsoleOutput::console_output)).unwrap();
                                         }
                                     } else if action.service == "Set Mode" {
                                         if action.option1 == "User Mode" {
                                             let res = guard.invoke_set_session_mode(UdsSessionType::Default);
-                                            s.send(res).unwrap();
+                                            s.send(res.map(ConsoleOutput::console_output)).unwrap();
                                         } else if action.option1 == "Debug Mode" {
                                             let res =
                                                 guard.invoke_set_session_mode(UdsSessionType::Programming);
-                                            s.send(res).unwrap();
+                                            s.send(res.map(ConsoleOutput::console_output)).unwrap();
                                         } else if action.option1 == "Stream Mode" {
                                             let res = guard.invoke_set_session_mode(UdsSessionType::StreamMode);
-                                            s.send(res).unwrap();
+                                            s.send(res.map(ConsoleOutput::console_output)).unwrap();
                                         }
                                     }
                                 }